@@ -0,0 +1,244 @@
+//! HTTP API: a tiny HTTP server (configured with the `[api]` section)
+//! through which container platforms can health-check the whole bundle
+//! of processes.
+//!
+//! `GET /healthz` answers `200 OK` once startup has completed if every
+//! daemon is running (and passing its health check, if it has one), and
+//! `503 Service Unavailable` otherwise. `GET /status` answers with a JSON
+//! description of every process (with the same status code).
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use color_eyre::eyre::{self, WrapErr};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+
+/// Longest request head (request line and headers) that is accepted.
+const MAX_REQUEST_LEN: u64 = 8192;
+
+/// Longest amount of time to wait for a client to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Status of every process, which is the body of the `/status` response.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct ApiStatus {
+    /// True if startup has completed and every daemon is healthy.
+    pub(crate) healthy: bool,
+
+    /// True until startup has completed (at which point `processes`
+    /// lists every process).
+    pub(crate) starting: bool,
+
+    /// Status of every process, in startup order.
+    pub(crate) processes: Vec<ProcessStatus>,
+}
+
+/// Status of a single process.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct ProcessStatus {
+    /// Name of the process.
+    pub(crate) name: String,
+
+    /// State of the process (as in the control socket's `status`).
+    pub(crate) state: &'static str,
+
+    /// True if the process is a one-shot process, or if its daemon is
+    /// running and has not failed its health check.
+    pub(crate) healthy: bool,
+
+    /// PID of the daemon, if the daemon is running.
+    pub(crate) pid: Option<i32>,
+
+    /// Number of seconds that the daemon has been running, if it is
+    /// running.
+    pub(crate) uptime_secs: Option<u64>,
+
+    /// Owner of the process, if the config names one.
+    pub(crate) owner: Option<String>,
+
+    /// Description of the process, if the config has one.
+    pub(crate) description: Option<String>,
+
+    /// TCP ports that the process declares.
+    pub(crate) ports: Vec<u16>,
+}
+
+/// Request for the status of every process, which is answered through
+/// `reply`.
+#[derive(Debug)]
+pub(crate) struct ApiRequest {
+    pub(crate) reply: oneshot::Sender<ApiStatus>,
+}
+
+/// Serves the HTTP API, sending a request to `requests` for every HTTP
+/// request once startup has completed (requests made during startup are
+/// answered without one). Dropping the returned server stops serving
+/// requests.
+#[derive(Debug)]
+pub(crate) struct ApiServer {
+    started: Arc<AtomicBool>,
+    task: JoinHandle<()>,
+}
+
+impl ApiServer {
+    /// Starts listening for HTTP requests on the given address.
+    pub(crate) fn start(
+        listen: SocketAddr,
+        requests: mpsc::UnboundedSender<ApiRequest>,
+    ) -> eyre::Result<Self> {
+        let listener = std::net::TcpListener::bind(listen)
+            .and_then(|listener| {
+                listener.set_nonblocking(true)?;
+                TcpListener::from_std(listener)
+            })
+            .wrap_err_with(|| format!("Failed to listen for API requests on {listen}"))?;
+        tracing::info!(%listen, "Listening for API requests");
+
+        let started = Arc::new(AtomicBool::new(false));
+        let task = tokio::spawn({
+            let started = started.clone();
+            async move {
+                loop {
+                    match listener.accept().await {
+                        Ok((stream, _)) => {
+                            tokio::spawn(serve_connection(
+                                stream,
+                                started.load(Ordering::SeqCst),
+                                requests.clone(),
+                            ));
+                        }
+                        Err(err) => {
+                            tracing::warn!(?err, "Failed to accept API connection");
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(ApiServer { started, task })
+    }
+
+    /// Notes that startup has completed, after which requests are sent
+    /// to be answered with the status of every process.
+    pub(crate) fn startup_completed(&self) {
+        self.started.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Reads the request of a single connection, gets the status of every
+/// process (if startup has completed), and writes the response.
+async fn serve_connection(
+    stream: TcpStream,
+    started: bool,
+    requests: mpsc::UnboundedSender<ApiRequest>,
+) {
+    let mut stream = BufReader::new(stream);
+    let (status, content_type, body) =
+        match tokio::time::timeout(REQUEST_TIMEOUT, read_request_line(&mut stream)).await {
+            Ok(Some(line)) => respond(&line, started, &requests).await,
+            Ok(None) => (400, "text/plain", String::from("bad request\n")),
+            Err(_) => (408, "text/plain", String::from("request timeout\n")),
+        };
+
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        _ => "Service Unavailable",
+    };
+    let response = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    if let Err(err) = stream.get_mut().write_all(response.as_bytes()).await {
+        tracing::debug!(?err, "Failed to write API response");
+    }
+}
+
+/// Reads the head of the request (ignoring the headers), returning the
+/// request line, or `None` if the head could not be read.
+async fn read_request_line(stream: &mut BufReader<TcpStream>) -> Option<String> {
+    let mut head = stream.take(MAX_REQUEST_LEN);
+    let mut request_line = String::new();
+    head.read_line(&mut request_line).await.ok()?;
+    loop {
+        let mut header = String::new();
+        match head.read_line(&mut header).await {
+            Ok(0) | Err(_) => return None,
+            Ok(_) if header.trim_end().is_empty() => return Some(request_line),
+            Ok(_) => {}
+        }
+    }
+}
+
+/// Returns the status code, content type, and body of the response to
+/// the request with the given request line.
+async fn respond(
+    request_line: &str,
+    started: bool,
+    requests: &mpsc::UnboundedSender<ApiRequest>,
+) -> (u16, &'static str, String) {
+    let mut words = request_line.split_whitespace();
+    let (method, path) = match (words.next(), words.next()) {
+        (Some(method), Some(path)) => (method, path),
+        _ => return (400, "text/plain", String::from("bad request\n")),
+    };
+    let path = path.split('?').next().unwrap_or_default();
+    if path != "/healthz" && path != "/status" {
+        return (404, "text/plain", String::from("not found\n"));
+    }
+    if method != "GET" {
+        return (405, "text/plain", String::from("method not allowed\n"));
+    }
+
+    let status = if started {
+        let (reply, status) = oneshot::channel();
+        let _ = requests.send(ApiRequest { reply });
+        status.await.unwrap_or_default()
+    } else {
+        ApiStatus {
+            starting: true,
+            ..ApiStatus::default()
+        }
+    };
+    let code = if status.healthy { 200 } else { 503 };
+
+    if path == "/healthz" {
+        let body = match (status.healthy, status.starting) {
+            (true, _) => "ok\n",
+            (false, true) => "starting\n",
+            (false, false) => "unhealthy\n",
+        };
+        (code, "text/plain", String::from(body))
+    } else {
+        match serde_json::to_string(&status) {
+            Ok(json) => (code, "application/json", json + "\n"),
+            Err(err) => {
+                tracing::warn!(?err, "Failed to serialize API status");
+                (503, "text/plain", String::from("unavailable\n"))
+            }
+        }
+    }
+}
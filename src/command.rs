@@ -1,18 +1,51 @@
 //! Runs commands and monitors their completion.
 
-use std::{env, process::Stdio};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    env,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    iter,
+    os::unix::{
+        fs::{OpenOptionsExt, PermissionsExt},
+        process::ExitStatusExt,
+    },
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::Duration,
+};
 
 use color_eyre::eyre::{self, eyre, WrapErr};
 use command_group::{AsyncCommandGroup, AsyncGroupChild};
-use nix::unistd::Pid;
+use nix::{
+    errno::Errno,
+    sys::resource::{getrlimit, setrlimit, Resource},
+    unistd::{chown, Gid, Pid, Uid},
+};
 use once_cell::sync::Lazy;
 use regex::{Captures, Regex};
+use sha2::{Digest, Sha256};
 use tokio::{
     io::{AsyncBufReadExt, BufReader},
     sync::oneshot,
 };
+use tracing::Instrument;
+use users::os::unix::UserExt;
+
+use crate::config::{CommandConfig, EnvPreset};
+
+/// Number of lines of output that are kept for each command (see
+/// `CommandControl::recent_output`).
+const RECENT_OUTPUT_LINES: usize = 20;
 
-use crate::config::CommandConfig;
+/// Longest line of output (in characters) that is kept for each command
+/// (longer lines are truncated).
+const RECENT_OUTPUT_LINE_LEN: usize = 256;
 
 /// Exit status returned by a command.
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -20,18 +53,150 @@ pub(crate) enum ExitStatus {
     /// Command exited with the given exit code.
     Exited(i32),
 
-    /// Command was killed before it could exit.
-    Killed,
+    /// Command was killed (by the given signal, if known) before it
+    /// could exit.
+    Killed(Option<i32>),
+}
+
+/// Environment variables available to a command.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CommandEnv {
+    /// Snapshot of the environment in which to run the command, or
+    /// `None` to use Ground Control's environment (plus `config_env`) at
+    /// the time that the command is run.
+    pub(crate) snapshot: Option<HashMap<String, String>>,
+
+    /// Additional variables that are always passed to the command
+    /// (regardless of the command's `only_env` setting).
+    pub(crate) extra: HashMap<String, String>,
+
+    /// Only expand template variables that the command is allowed to
+    /// see (as determined by the command's `only_env` setting), instead
+    /// of every variable in the environment.
+    pub(crate) restrict_expansion: bool,
+
+    /// Preset of standard variables to add to the command's environment
+    /// (see `preset_vars`).
+    pub(crate) preset: Option<EnvPreset>,
+
+    /// Variables from the config's `env` table, which are added to
+    /// Ground Control's environment (without changing it, since every
+    /// spec run by a `Supervisor` shares that environment), and which
+    /// keep their configured values instead of being replaced by the
+    /// preset (or user) variables.
+    pub(crate) config_env: HashMap<String, String>,
+}
+
+impl CommandEnv {
+    /// Looks up an environment variable, preferring the additional
+    /// variables over the snapshot (or the config's `env` table and the
+    /// process environment).
+    pub(crate) fn var(&self, key: &str) -> Option<String> {
+        self.extra
+            .get(key)
+            .cloned()
+            .or_else(|| match &self.snapshot {
+                Some(snapshot) => snapshot.get(key).cloned(),
+                None => self
+                    .config_env
+                    .get(key)
+                    .cloned()
+                    .or_else(|| env::var(key).ok()),
+            })
+    }
+
+    /// Returns a copy of the environment in which the values of
+    /// sensitive variables (see `is_sensitive`) have been redacted.
+    fn redacted(&self) -> CommandEnv {
+        self.captured(true)
+    }
+
+    /// Returns a copy of the environment with a snapshot of Ground
+    /// Control's environment (if the environment has no snapshot of its
+    /// own), redacting the values of sensitive variables if requested.
+    fn captured(&self, redact_sensitive: bool) -> CommandEnv {
+        let redact = |(key, value): (String, String)| {
+            let value = if redact_sensitive && is_sensitive(&key) {
+                String::from(REDACTED)
+            } else {
+                value
+            };
+            (key, value)
+        };
+
+        let snapshot = match &self.snapshot {
+            Some(snapshot) => snapshot.clone(),
+            None => snapshot_env(&self.config_env),
+        };
+        CommandEnv {
+            snapshot: Some(snapshot.into_iter().map(redact).collect()),
+            extra: self.extra.clone().into_iter().map(redact).collect(),
+            restrict_expansion: self.restrict_expansion,
+            preset: self.preset,
+            config_env: self.config_env.clone().into_iter().map(redact).collect(),
+        }
+    }
+
+    /// Returns the environment to use for template expansion in a
+    /// command with the given `only_env` setting: the environment
+    /// itself, unless expansion is restricted, in which case only the
+    /// allowed variables (plus `PATH` and the additional variables) are
+    /// available.
+    fn for_expansion(&self, only_env: Option<&HashSet<String>>) -> Cow<'_, CommandEnv> {
+        match only_env {
+            Some(only_env) if self.restrict_expansion => {
+                let snapshot = only_env
+                    .iter()
+                    .map(String::as_str)
+                    .chain(iter::once("PATH"))
+                    .filter_map(|key| self.var(key).map(|value| (key.to_owned(), value)))
+                    .collect();
+                Cow::Owned(CommandEnv {
+                    snapshot: Some(snapshot),
+                    extra: self.extra.clone(),
+                    restrict_expansion: true,
+                    preset: self.preset,
+                    config_env: self.config_env.clone(),
+                })
+            }
+            Some(_) | None => Cow::Borrowed(self),
+        }
+    }
+}
+
+/// Takes a snapshot of Ground Control's environment (skipping any
+/// variables that are not valid Unicode, which cannot be used in
+/// environment variable expansion anyway), with the variables from the
+/// config's `env` table added to it.
+pub(crate) fn snapshot_env(config_env: &HashMap<String, String>) -> HashMap<String, String> {
+    let mut snapshot: HashMap<String, String> = env::vars_os()
+        .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+        .collect();
+    snapshot.extend(config_env.clone());
+    snapshot
 }
 
 /// Control handle for a Command, used to send signals to the command.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub(crate) struct CommandControl {
     name: String,
     pid: Pid,
+    output: RecentOutput,
 }
 
 impl CommandControl {
+    /// Returns the PID of the process.
+    pub(crate) fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// Returns the buffer of the most recent lines of output (from both
+    /// stdout and stderr, in the order in which they were read) of the
+    /// process.
+    pub(crate) fn recent_output(&self) -> RecentOutput {
+        self.output.clone()
+    }
+
     /// Sends a signal to the process.
     pub(crate) fn kill(&self, signal: nix::sys::signal::Signal) -> eyre::Result<()> {
         nix::sys::signal::kill(self.pid, signal).wrap_err_with(|| {
@@ -39,6 +204,60 @@ impl CommandControl {
         })?;
         Ok(())
     }
+
+    /// Sends a signal to every process in the process's process group
+    /// (which is led by the process, since every command is spawned in
+    /// its own group).
+    pub(crate) fn kill_group(&self, signal: nix::sys::signal::Signal) -> eyre::Result<()> {
+        nix::sys::signal::killpg(self.pid, signal).wrap_err_with(|| {
+            format!(
+                "Error sending {signal} signal to process group of \"{}\"",
+                self.name
+            )
+        })?;
+        Ok(())
+    }
+}
+
+/// Ring buffer of the most recent lines of a command's output.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RecentOutput {
+    lines: Arc<Mutex<VecDeque<String>>>,
+
+    /// Number of output streams (stdout and stderr) that have not yet
+    /// been read to the end.
+    open_streams: Arc<AtomicUsize>,
+}
+
+impl RecentOutput {
+    /// Adds a line of output, discarding the oldest line if the buffer
+    /// is full.
+    fn push(&self, line: &str) {
+        let mut lines = self.lines.lock().unwrap_or_else(PoisonError::into_inner);
+        if lines.len() == RECENT_OUTPUT_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line.chars().take(RECENT_OUTPUT_LINE_LEN).collect());
+    }
+
+    /// Returns the lines in the buffer, oldest first, after waiting
+    /// (briefly) for the output of a command that just exited to be read
+    /// to the end.
+    pub(crate) async fn lines(&self) -> Vec<String> {
+        for _ in 0..10 {
+            if self.open_streams.load(Ordering::SeqCst) == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        self.lines
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .cloned()
+            .collect()
+    }
 }
 
 /// Monitoring handle for a Command, used to wait for the Command to
@@ -57,68 +276,160 @@ impl CommandMonitor {
     }
 }
 
-/// Runs the command and returns the control and monitor handles.
+/// Runs the command and returns the control and monitor handles. `env`
+/// provides the environment in which the command is run (and which is
+/// used for environment variable expansion). Standard input is read from
+/// the `stdin` file if provided, and is empty otherwise. `core_limit`
+/// sets the maximum size of the command's core dumps (capped at the hard
+/// limit), otherwise the command inherits Ground Control's limit.
 pub(crate) fn run(
     name: &str,
     config: &CommandConfig,
+    env: &CommandEnv,
+    stdin: Option<&Path>,
+    core_limit: Option<u64>,
 ) -> eyre::Result<(CommandControl, CommandMonitor)> {
     tracing::debug!(%name, ?config, "Running command");
 
-    // Initialize the command.
-    let mut command = tokio::process::Command::new(&config.program);
-
-    // Add the arguments, and perform environment variable substitution.
-    match config
-        .args
-        .iter()
-        .map(substitute_env_var)
-        .collect::<eyre::Result<Vec<String>>>()
-    {
-        Ok(args) => command.args(args),
-        Err(err) => {
-            return Err(err.wrap_err(format!(
+    // Look up the user, if provided.
+    let user = config
+        .user
+        .as_ref()
+        .map(|username| {
+            users::get_user_by_name(username)
+                .ok_or_else(|| eyre!("Unknown username \"{username}\""))
+        })
+        .transpose()?;
+
+    // Write the inline script (if any) to a temporary file, which then
+    // becomes the program to execute.
+    let script = config
+        .script
+        .as_ref()
+        .map(|script| ScriptFile::create(script, user.as_ref()))
+        .transpose()?;
+    let config = match &script {
+        Some(script) => Cow::Owned(CommandConfig {
+            program: script.path.to_string_lossy().into_owned(),
+            ..config.clone()
+        }),
+        None => Cow::Borrowed(config),
+    };
+
+    // Perform environment variable substitution on the arguments.
+    let args = expand_args(&config.args, &env.for_expansion(config.only_env.as_ref()))
+        .wrap_err_with(|| {
+            format!(
                 "Environment variable expansion failed for command \"{}\"",
                 config.program
-            )))
+            )
+        })?;
+
+    // Verify the integrity of the program before running it, if an
+    // expected hash was provided.
+    if let Some(sha256) = &config.sha256 {
+        verify_program(&config.program, sha256, env).wrap_err_with(|| {
+            format!("Integrity check failed for command \"{}\"", config.program)
+        })?;
+    }
+
+    // Initialize the command, running it through the user's login shell
+    // (which sources the user's profile) if requested. The program and
+    // its arguments are passed to the shell as positional parameters so
+    // that they do not need to be quoted.
+    let login_user = if config.login_shell {
+        Some(user_or_current(user.as_ref())?)
+    } else {
+        None
+    };
+
+    let mut command = match &login_user {
+        Some(login_user) => {
+            let mut command = tokio::process::Command::new(login_user.shell());
+            command
+                .args(["-l", "-c", r#"exec "$0" "$@""#, &config.program])
+                .args(args);
+            command
+        }
+        None => {
+            let mut command = tokio::process::Command::new(&config.program);
+            command.args(args);
+            command
         }
     };
 
     // Clear the environment if `only_env` was provided, then add back
-    // in `PATH` and any other allowed environment variables.
+    // in `PATH` and any other allowed environment variables. Otherwise
+    // replace the environment with the snapshot (if there is one).
     if let Some(only_env) = &config.only_env {
         command.env_clear();
 
-        if let Ok(path) = env::var("PATH") {
+        if let Some(path) = env.var("PATH") {
             command.env("PATH", path);
         }
 
         for key in only_env {
             command.env(
                 key,
-                env::var(key).map_err(|_| eyre!("Unknown environment variable \"{key}\""))?,
+                env.var(key)
+                    .ok_or_else(|| eyre!("Unknown environment variable \"{key}\""))?,
             );
         }
+    } else if let Some(snapshot) = &env.snapshot {
+        command.env_clear().envs(snapshot);
+    } else {
+        command.envs(&env.config_env);
+    }
+
+    // Add the variables that describe the user that runs the command
+    // (which override any inherited values).
+    command.envs(user_vars(user.as_ref(), env)?);
+
+    // Add the additional environment variables.
+    command.envs(&env.extra);
+
+    // Login shells get the same identity variables that `su -l` would
+    // provide (the shell's profile then takes care of `PATH`, etc.).
+    if let Some(login_user) = &login_user {
+        command
+            .env("HOME", login_user.home_dir())
+            .env("SHELL", login_user.shell())
+            .env("USER", login_user.name())
+            .env("LOGNAME", login_user.name());
     }
 
     // Set the uid and gid if provided.
-    if let Some(username) = &config.user {
-        let user = users::get_user_by_name(username)
-            .ok_or_else(|| eyre!("Unknown username \"{username}\""))?;
+    if let Some(user) = &user {
         command.uid(user.uid()).gid(user.primary_group_id());
     };
 
-    // Disable stdin, and pipe stdout and stderr so that we can read
-    // and process the output.
+    // Connect stdin to the requested file (or disable stdin), and pipe
+    // stdout and stderr so that we can read and process the output.
+    let stdin = match stdin {
+        Some(path) => Stdio::from(File::open(path).wrap_err_with(|| {
+            format!(
+                "Failed to open stdin file \"{}\" for command \"{}\"",
+                path.display(),
+                config.program
+            )
+        })?),
+        None => Stdio::null(),
+    };
     command
-        .stdin(Stdio::null())
+        .stdin(stdin)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
 
-    // Run the command.
-    let mut child = command
-        .group_spawn()
+    // Run the command, explaining why the program could not be found
+    // (or executed) if the spawn fails. Login shells look up the
+    // program themselves, so there is nothing to explain here.
+    let mut child = spawn(&mut command, core_limit)
+        .map_err(|err| match login_user {
+            Some(_) => err,
+            None => explain_spawn_failure(err, &config.program, env, config.only_env.is_some()),
+        })
         .wrap_err_with(|| format!("Error starting command \"{}\"", config.program))?;
-    let pid = nix::unistd::Pid::from_raw(child.id().ok_or_else(|| {
+    let pid = Pid::from_raw(child.id().ok_or_else(|| {
         eyre!(
             "Failed to get PID of just-started command \"{}\"",
             config.program
@@ -128,7 +439,10 @@ pub(crate) fn run(
     tracing::debug!(%name, %pid, "Command running");
 
     // Read stdout and stderr and send them to the console via
-    // specially-targeted `tracing` events.
+    // specially-targeted `tracing` events (keeping the most recent lines
+    // for the exit history).
+    let output = RecentOutput::default();
+    output.open_streams.store(2, Ordering::SeqCst);
     let stdout = child
         .inner()
         .stdout
@@ -136,13 +450,17 @@ pub(crate) fn run(
         .expect("failed to get stdout from child process");
     let mut reader = BufReader::new(stdout).lines();
     let process = name.to_string();
-    tokio::task::spawn({
+    let stdout_output = output.clone();
+    tokio::task::spawn(
         async move {
             while let Ok(Some(line)) = reader.next_line().await {
+                stdout_output.push(&line);
                 tracing::info!(target: "stdout", %process, output = line);
             }
+            stdout_output.open_streams.fetch_sub(1, Ordering::SeqCst);
         }
-    });
+        .in_current_span(),
+    );
 
     let stderr = child
         .inner()
@@ -151,29 +469,549 @@ pub(crate) fn run(
         .expect("failed to get stderr from child process");
     let mut reader = BufReader::new(stderr).lines();
     let process = name.to_string();
-    tokio::task::spawn({
+    let stderr_output = output.clone();
+    tokio::task::spawn(
         async move {
             while let Ok(Some(line)) = reader.next_line().await {
+                stderr_output.push(&line);
                 tracing::info!(target: "stderr", %process, output = line);
             }
+            stderr_output.open_streams.fetch_sub(1, Ordering::SeqCst);
         }
-    });
+        .in_current_span(),
+    );
 
     // Listen for the command to complete.
     let (sender, receiver) = oneshot::channel();
-    monitor_process(name.to_owned(), pid, child, sender);
+    monitor_process(name.to_owned(), pid, child, script, sender);
 
     // Return the Command Control and Monitor.
     Ok((
         CommandControl {
             name: name.to_owned(),
             pid,
+            output,
         },
         CommandMonitor { monitor: receiver },
     ))
 }
 
-fn substitute_env_var(s: impl AsRef<str>) -> eyre::Result<String> {
+/// Command as it would be executed by `run`, for auditing purposes.
+/// The values of sensitive environment variables are redacted, both in
+/// the environment and in the (expanded) arguments.
+#[derive(Debug)]
+pub(crate) struct ResolvedCommand {
+    /// Program and arguments, after environment variable expansion (and
+    /// wrapped in the login shell, if requested).
+    pub(crate) argv: Vec<String>,
+
+    /// User that the command runs as, if not Ground Control's user.
+    pub(crate) user: Option<String>,
+
+    /// Working directory of the command (which is always Ground
+    /// Control's working directory).
+    pub(crate) cwd: PathBuf,
+
+    /// Environment of the command.
+    pub(crate) env: BTreeMap<String, String>,
+}
+
+/// Resolves the command exactly as `run` would, but without running it.
+pub(crate) fn resolve(config: &CommandConfig, env: &CommandEnv) -> eyre::Result<ResolvedCommand> {
+    resolve_captured(config, &env.redacted())
+}
+
+/// Resolves the command in an environment that has already been
+/// captured (see `CommandEnv::captured`).
+fn resolve_captured(config: &CommandConfig, env: &CommandEnv) -> eyre::Result<ResolvedCommand> {
+    let args = expand_args(&config.args, &env.for_expansion(config.only_env.as_ref()))
+        .wrap_err_with(|| {
+            format!(
+                "Environment variable expansion failed for command \"{}\"",
+                config.program
+            )
+        })?;
+
+    // Build the environment in the same order as `run`: the allowed (or
+    // snapshotted) variables, the user variables, the additional
+    // variables, and then the login shell's identity variables.
+    let mut resolved_env: BTreeMap<String, String> = match &config.only_env {
+        Some(only_env) => only_env
+            .iter()
+            .map(String::as_str)
+            .chain(iter::once("PATH"))
+            .filter_map(|key| env.var(key).map(|value| (key.to_owned(), value)))
+            .collect(),
+        None => env
+            .snapshot
+            .clone()
+            .unwrap_or_default()
+            .into_iter()
+            .collect(),
+    };
+    let user = match &config.user {
+        Some(_) => Some(command_user(config)?),
+        None => None,
+    };
+    resolved_env.extend(user_vars(user.as_ref(), env)?);
+    resolved_env.extend(env.extra.clone());
+
+    // Inline scripts are not written to a file until they are run.
+    let program = match &config.script {
+        Some(_) => String::from("<script>"),
+        None => config.program.clone(),
+    };
+
+    let argv = if config.login_shell {
+        let user = command_user(config)?;
+        let name = user.name().to_string_lossy().into_owned();
+        resolved_env.insert(String::from("HOME"), user.home_dir().display().to_string());
+        resolved_env.insert(String::from("SHELL"), user.shell().display().to_string());
+        resolved_env.insert(String::from("USER"), name.clone());
+        resolved_env.insert(String::from("LOGNAME"), name);
+
+        [
+            user.shell().display().to_string(),
+            String::from("-l"),
+            String::from("-c"),
+            String::from(r#"exec "$0" "$@""#),
+            program,
+        ]
+        .into_iter()
+        .chain(args)
+        .collect()
+    } else {
+        iter::once(program).chain(args).collect()
+    };
+
+    Ok(ResolvedCommand {
+        argv,
+        user: config.user.clone(),
+        cwd: env::current_dir().wrap_err("Failed to get the current directory")?,
+        env: resolved_env,
+    })
+}
+
+/// Inputs of one run of a command -- its argv and environment, both as
+/// run and with sensitive values redacted -- which are compared between
+/// the runs of a daemon to show what changed when the daemon restarts.
+#[derive(Debug)]
+pub(crate) struct RunInputs {
+    actual: ResolvedCommand,
+    redacted: ResolvedCommand,
+}
+
+impl RunInputs {
+    /// Resolves the inputs that the command would be run with.
+    pub(crate) fn resolve(config: &CommandConfig, env: &CommandEnv) -> eyre::Result<Self> {
+        Ok(RunInputs {
+            actual: resolve_captured(config, &env.captured(false))?,
+            redacted: resolve(config, env)?,
+        })
+    }
+
+    /// Describes how these inputs differ from those of the previous
+    /// run, without revealing the values of sensitive variables (or the
+    /// arguments into which they were expanded). Returns an empty list
+    /// if nothing changed.
+    pub(crate) fn changes_since(&self, previous: &RunInputs) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        if self.actual.argv != previous.actual.argv {
+            changes.push(format!(
+                "argv: {:?} -> {:?}",
+                previous.redacted.argv, self.redacted.argv
+            ));
+        }
+
+        let keys: BTreeSet<&String> = previous
+            .actual
+            .env
+            .keys()
+            .chain(self.actual.env.keys())
+            .collect();
+        for key in keys {
+            match (previous.actual.env.get(key), self.actual.env.get(key)) {
+                (Some(old), Some(new)) if old != new => changes.push(format!(
+                    "{key}: {:?} -> {:?}",
+                    previous.redacted.env[key], self.redacted.env[key]
+                )),
+                (Some(_), None) => changes.push(format!("{key}: removed")),
+                (None, Some(_)) => {
+                    changes.push(format!("{key}: added {:?}", self.redacted.env[key]));
+                }
+                _ => {}
+            }
+        }
+
+        changes
+    }
+}
+
+/// Returns the given user, or the user running Ground Control if no
+/// user was given.
+fn user_or_current(user: Option<&users::User>) -> eyre::Result<users::User> {
+    match user {
+        Some(user) => Ok(user.clone()),
+        None => users::get_user_by_uid(users::get_current_uid())
+            .ok_or_else(|| eyre!("Unable to find the current user")),
+    }
+}
+
+/// Looks up the user that runs the command (the command's `user`, or
+/// the user running Ground Control).
+fn command_user(config: &CommandConfig) -> eyre::Result<users::User> {
+    match &config.user {
+        Some(username) => users::get_user_by_name(username)
+            .ok_or_else(|| eyre!("Unknown username \"{username}\"")),
+        None => user_or_current(None),
+    }
+}
+
+/// Returns the variables that describe the user that runs the command:
+/// the variables in the environment preset (if any), otherwise the
+/// `HOME`, `USER`, and `LOGNAME` of the command's `user` (if any, since
+/// many programs misbehave if those do not match the user). Variables
+/// that were set by the config's `env` table keep their configured
+/// values.
+fn user_vars(user: Option<&users::User>, env: &CommandEnv) -> eyre::Result<Vec<(String, String)>> {
+    let vars = match (env.preset, user) {
+        (Some(preset), user) => preset_vars(preset, &user_or_current(user)?, env),
+        (None, Some(user)) => preset_vars(EnvPreset::Minimal, user, env),
+        (None, None) => Vec::new(),
+    };
+
+    Ok(vars
+        .into_iter()
+        .map(|(key, value)| {
+            let value = if env.config_env.contains_key(&key) {
+                env.var(&key).unwrap_or(value)
+            } else {
+                value
+            };
+            (key, value)
+        })
+        .collect())
+}
+
+/// Returns the variables in the environment preset for a command that
+/// runs as the given user. Locale and temporary directory variables
+/// keep Ground Control's values (from `env`), if it has any.
+fn preset_vars(preset: EnvPreset, user: &users::User, env: &CommandEnv) -> Vec<(String, String)> {
+    let name = user.name().to_string_lossy().into_owned();
+    let mut vars = vec![
+        (String::from("HOME"), user.home_dir().display().to_string()),
+        (String::from("USER"), name.clone()),
+        (String::from("LOGNAME"), name),
+    ];
+
+    if matches!(preset, EnvPreset::Locale | EnvPreset::Full) {
+        vars.push((
+            String::from("LANG"),
+            env.var("LANG").unwrap_or_else(|| String::from("C.UTF-8")),
+        ));
+    }
+
+    if preset == EnvPreset::Full {
+        vars.push((
+            String::from("TMPDIR"),
+            env.var("TMPDIR").unwrap_or_else(|| String::from("/tmp")),
+        ));
+        vars.push((String::from("SHELL"), user.shell().display().to_string()));
+    }
+
+    vars
+}
+
+/// Replacement for the values of sensitive environment variables.
+const REDACTED: &str = "<redacted>";
+
+/// Returns true if the name of the environment variable suggests that
+/// its value is a secret.
+fn is_sensitive(key: &str) -> bool {
+    const SENSITIVE: [&str; 8] = [
+        "AUTH",
+        "CREDENTIAL",
+        "KEY",
+        "PASSPHRASE",
+        "PASSWD",
+        "PASSWORD",
+        "SECRET",
+        "TOKEN",
+    ];
+
+    let key = key.to_ascii_uppercase();
+    SENSITIVE.iter().any(|sensitive| key.contains(sensitive))
+}
+
+/// Lock held while spawning commands (see `spawn`) and while writing
+/// scripts (see `ScriptFile::create`).
+static SPAWN_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// PIDs of the commands whose exit is being monitored (see
+/// `monitor_process`), which are reaped by their monitors, and must not
+/// be reaped by the zombie reaper (see `reaper`).
+static MONITORED: Lazy<Mutex<HashSet<Pid>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Calls `f` with the PIDs of the commands whose exit is being
+/// monitored, holding the spawn lock so that no command can be spawned
+/// (and exit) before its monitor knows about it.
+pub(crate) fn with_monitored<T>(f: impl FnOnce(&HashSet<Pid>) -> T) -> T {
+    let _guard = SPAWN_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let monitored = MONITORED.lock().unwrap_or_else(PoisonError::into_inner);
+    f(&monitored)
+}
+
+/// Spawns the command, setting its core dump limit (if provided).
+///
+/// Commands inherit Ground Control's own resource limits, so the core
+/// dump limit is set by temporarily changing Ground Control's soft limit
+/// while the command is spawned. Every spawn holds a lock so that no
+/// other command inherits the temporary limit (and so that the command
+/// is added to the monitored commands before the zombie reaper can see
+/// it exit).
+fn spawn(
+    command: &mut tokio::process::Command,
+    core_limit: Option<u64>,
+) -> eyre::Result<AsyncGroupChild> {
+    let _guard = SPAWN_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
+    let child = match core_limit {
+        Some(core_limit) => {
+            let (soft, hard) = getrlimit(Resource::RLIMIT_CORE)?;
+            setrlimit(Resource::RLIMIT_CORE, core_limit.min(hard), hard)
+                .wrap_err("Failed to set core dump limit")?;
+            let child = command.group_spawn();
+            setrlimit(Resource::RLIMIT_CORE, soft, hard)
+                .wrap_err("Failed to restore core dump limit")?;
+            child?
+        }
+        None => command.group_spawn()?,
+    };
+
+    if let Some(id) = child.id() {
+        MONITORED
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(Pid::from_raw(id as i32));
+    }
+
+    Ok(child)
+}
+
+/// Inline script that has been written to a temporary file, which is
+/// removed when the script file is dropped.
+#[derive(Debug)]
+struct ScriptFile {
+    path: PathBuf,
+}
+
+impl ScriptFile {
+    /// Writes the script to a new, executable file that is only
+    /// accessible to the given user (or Ground Control's user).
+    ///
+    /// The file is written while holding the spawn lock, since a
+    /// command that is spawned while the file is open for writing would
+    /// inherit the open file, and executing the script would then fail
+    /// with `ETXTBSY`.
+    fn create(script: &str, user: Option<&users::User>) -> eyre::Result<Self> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+        let path = env::temp_dir().join(format!(
+            "groundcontrol-{}-script-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let _guard = SPAWN_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o700)
+            .open(&path)
+            .wrap_err_with(|| format!("Failed to create script file \"{}\"", path.display()))?;
+        let script_file = Self { path };
+
+        file.write_all(script.trim_start().as_bytes())
+            .wrap_err_with(|| {
+                format!(
+                    "Failed to write script file \"{}\"",
+                    script_file.path.display()
+                )
+            })?;
+        if let Some(user) = user {
+            chown(
+                &script_file.path,
+                Some(Uid::from_raw(user.uid())),
+                Some(Gid::from_raw(user.primary_group_id())),
+            )
+            .wrap_err_with(|| {
+                format!(
+                    "Failed to change the owner of script file \"{}\"",
+                    script_file.path.display()
+                )
+            })?;
+        }
+
+        Ok(script_file)
+    }
+}
+
+impl Drop for ScriptFile {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            tracing::warn!(path = %self.path.display(), ?err, "Failed to remove script file");
+        }
+    }
+}
+
+/// Verifies that the SHA-256 hash of the program (found in the `PATH`
+/// if the program is not a path itself) matches the expected hash.
+fn verify_program(program: &str, expected: &str, env: &CommandEnv) -> eyre::Result<()> {
+    let path = if program.contains('/') {
+        Some(PathBuf::from(program))
+    } else {
+        env.var("PATH").and_then(|path| {
+            env::split_paths(&path)
+                .map(|dir| dir.join(program))
+                .find(|candidate| {
+                    fs::metadata(candidate)
+                        .map(|metadata| {
+                            metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+                        })
+                        .unwrap_or(false)
+                })
+        })
+    };
+    let path = path.ok_or_else(|| eyre!("\"{program}\" was not found in `PATH`"))?;
+
+    let mut file =
+        File::open(&path).wrap_err_with(|| format!("Failed to open \"{}\"", path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)
+        .wrap_err_with(|| format!("Failed to read \"{}\"", path.display()))?;
+    let actual = format!("{:x}", hasher.finalize());
+
+    if actual != expected {
+        return Err(eyre!(
+            "SHA-256 hash of \"{}\" is {actual}, not the expected {expected}",
+            path.display()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Adds an explanation to errors caused by a program that could not be
+/// found (or could not be executed), including the `PATH` that was
+/// searched for programs that are not paths themselves.
+fn explain_spawn_failure(
+    err: eyre::Report,
+    program: &str,
+    env: &CommandEnv,
+    only_env: bool,
+) -> eyre::Report {
+    let errno = err
+        .chain()
+        .filter_map(|err| err.downcast_ref::<std::io::Error>())
+        .find_map(std::io::Error::raw_os_error)
+        .map(Errno::from_i32);
+    if !matches!(errno, Some(Errno::ENOENT | Errno::EACCES)) {
+        return err;
+    }
+
+    // Programs that contain a slash are executed directly.
+    if program.contains('/') {
+        let explanation = match fs::metadata(program) {
+            Err(_) => format!("\"{program}\" does not exist"),
+            Ok(metadata) if metadata.is_dir() => format!("\"{program}\" is a directory"),
+            Ok(metadata) if metadata.permissions().mode() & 0o111 == 0 => {
+                format!("\"{program}\" exists, but is not executable")
+            }
+            Ok(_) if errno == Some(Errno::ENOENT) => format!(
+                "\"{program}\" exists, but its interpreter (`#!` line) or dynamic loader does not"
+            ),
+            Ok(_) => return err,
+        };
+        return err.wrap_err(explanation);
+    }
+
+    // Everything else is looked up in the `PATH` (which, for `only-env`
+    // commands, is the only variable that survives the cleared
+    // environment).
+    let hint = if only_env {
+        " (`only-env` clears the environment of this command, but passes through Ground Control's `PATH`)"
+    } else {
+        ""
+    };
+    let path = match env.var("PATH") {
+        Some(path) => path,
+        None => {
+            return err.wrap_err(format!(
+                "\"{program}\" cannot be found because `PATH` is not set{hint}"
+            ))
+        }
+    };
+
+    let not_executable: Vec<String> = env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .filter(|candidate| {
+            fs::metadata(candidate)
+                .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 == 0)
+                .unwrap_or(false)
+        })
+        .map(|candidate| format!("\"{}\"", candidate.display()))
+        .collect();
+    if not_executable.is_empty() {
+        err.wrap_err(format!(
+            "\"{program}\" was not found in `PATH` \"{path}\"{hint}"
+        ))
+    } else {
+        err.wrap_err(format!(
+            "\"{program}\" was found in `PATH` \"{path}\", but is not executable: {}{hint}",
+            not_executable.join(", ")
+        ))
+    }
+}
+
+/// Performs environment variable expansion on every argument, replacing
+/// arguments that consist entirely of a `{{@VAR}}` expression with the
+/// zero or more arguments found by splitting the value of the variable
+/// using shell quoting rules (unset variables produce zero arguments).
+fn expand_args(args: &[String], env: &CommandEnv) -> eyre::Result<Vec<String>> {
+    static SPLIT_VAR_REGEX: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"\{\{ *@([A-Za-z0-9_]+) *\}\}").expect("regex should be valid"));
+
+    let mut expanded = Vec::with_capacity(args.len());
+    for arg in args {
+        match SPLIT_VAR_REGEX.captures(arg) {
+            Some(caps) if caps[0].len() == arg.len() => {
+                if let Some(value) = env.var(&caps[1]) {
+                    expanded.extend(shell_words::split(&value).wrap_err_with(|| {
+                        format!(
+                            "Unable to split environment variable \"{}\" into arguments",
+                            &caps[1]
+                        )
+                    })?);
+                }
+            }
+            Some(_) => {
+                return Err(eyre!(
+                    "Argument splitting expression must be the entire argument: \"{arg}\""
+                ))
+            }
+            None => expanded.push(substitute_env_var(arg, env)?),
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// Replaces every `{{VAR}}` template expression in the string with the
+/// value of the environment variable in `env`, returning an error if any
+/// of the variables are unknown.
+pub(crate) fn substitute_env_var(s: impl AsRef<str>, env: &CommandEnv) -> eyre::Result<String> {
     static TEMPLATE_VAR_REGEX: Lazy<Regex> =
         Lazy::new(|| Regex::new(r"\{\{ *([A-Za-z0-9_]+) *\}\}").expect("regex should be valid"));
 
@@ -185,14 +1023,15 @@ fn substitute_env_var(s: impl AsRef<str>) -> eyre::Result<String> {
     TEMPLATE_VAR_REGEX
         .captures_iter(s.as_ref())
         .map(|caps| {
-            std::env::var(&caps[1])
-                .map_err(|_| eyre!("Unknown environment variable \"{}\"", &caps[1]))
+            env.var(&caps[1])
+                .ok_or_else(|| eyre!("Unknown environment variable \"{}\"", &caps[1]))
         })
         .collect::<eyre::Result<String>>()?;
 
     Ok(TEMPLATE_VAR_REGEX
         .replace_all(s.as_ref(), |caps: &Captures| {
-            std::env::var(&caps[1]).expect("Unable to find environment variable")
+            env.var(&caps[1])
+                .expect("Unable to find environment variable")
         })
         .into_owned())
 }
@@ -201,13 +1040,26 @@ fn monitor_process(
     name: String,
     pid: Pid,
     mut child: AsyncGroupChild,
+    script: Option<ScriptFile>,
     sender: oneshot::Sender<ExitStatus>,
 ) {
-    tokio::spawn(async move {
-        match child.wait().await {
+    let monitor = async move {
+        let exit_status = child.wait().await;
+
+        // The command has been reaped, so the zombie reaper no longer
+        // needs to leave it alone.
+        MONITORED
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&pid);
+
+        // The script is no longer needed once the command has exited.
+        drop(script);
+
+        match exit_status {
             Err(err) => {
                 tracing::error!(%name, ?err, "Error waiting for command to exit");
-                let _ = sender.send(ExitStatus::Killed);
+                let _ = sender.send(ExitStatus::Killed(None));
             }
             Ok(exit_status) => match exit_status.code() {
                 Some(exit_code) => {
@@ -220,10 +1072,122 @@ fn monitor_process(
                     let _ = sender.send(ExitStatus::Exited(exit_code));
                 }
                 None => {
-                    tracing::debug!(%name, %pid, "Command was killed");
-                    let _ = sender.send(ExitStatus::Killed);
+                    tracing::debug!(%name, %pid, signal = ?exit_status.signal(), "Command was killed");
+                    let _ = sender.send(ExitStatus::Killed(exit_status.signal()));
                 }
             },
         }
-    });
+    };
+    tokio::spawn(monitor.in_current_span());
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn recent_output_keeps_last_lines() {
+        let output = RecentOutput::default();
+        for line in 0..RECENT_OUTPUT_LINES + 5 {
+            output.push(&line.to_string());
+        }
+        output.push(&"x".repeat(RECENT_OUTPUT_LINE_LEN + 1));
+
+        let lines = output.lines().await;
+        assert_eq!(RECENT_OUTPUT_LINES, lines.len());
+        assert_eq!("6", lines[0]);
+        assert_eq!("x".repeat(RECENT_OUTPUT_LINE_LEN), lines[lines.len() - 1]);
+    }
+
+    #[test]
+    fn resolve_redacts_sensitive_values() {
+        let env = CommandEnv {
+            snapshot: Some(HashMap::from([
+                (String::from("PATH"), String::from("/bin")),
+                (String::from("DB_PASSWORD"), String::from("hunter2")),
+                (String::from("GREETING"), String::from("hello")),
+            ])),
+            extra: HashMap::from([(String::from("API_TOKEN"), String::from("abc123"))]),
+            restrict_expansion: false,
+            preset: None,
+            config_env: HashMap::new(),
+        };
+        let config = CommandConfig {
+            user: None,
+            only_env: Some(HashSet::from([String::from("DB_PASSWORD")])),
+            login_shell: false,
+            program: String::from("/app/server"),
+            args: vec![
+                String::from("--password={{DB_PASSWORD}}"),
+                String::from("{{GREETING}}"),
+            ],
+            sha256: None,
+            script: None,
+        };
+
+        let resolved = resolve(&config, &env).unwrap();
+        assert_eq!(
+            vec!["/app/server", "--password=<redacted>", "hello"],
+            resolved.argv
+        );
+        assert_eq!(
+            BTreeMap::from([
+                (String::from("API_TOKEN"), String::from("<redacted>")),
+                (String::from("DB_PASSWORD"), String::from("<redacted>")),
+                (String::from("PATH"), String::from("/bin")),
+            ]),
+            resolved.env
+        );
+    }
+
+    #[test]
+    fn run_inputs_changes_are_redacted() {
+        let env = |token: &str, greeting: Option<&str>| CommandEnv {
+            snapshot: Some(
+                [
+                    Some((String::from("PATH"), String::from("/bin"))),
+                    Some((String::from("API_TOKEN"), String::from(token))),
+                    greeting.map(|greeting| (String::from("GREETING"), String::from(greeting))),
+                ]
+                .into_iter()
+                .flatten()
+                .collect(),
+            ),
+            ..CommandEnv::default()
+        };
+        let config = CommandConfig {
+            user: None,
+            only_env: None,
+            login_shell: false,
+            program: String::from("/app/server"),
+            args: vec![String::from("--token={{API_TOKEN}}")],
+            sha256: None,
+            script: None,
+        };
+
+        let previous = RunInputs::resolve(&config, &env("abc123", Some("hello"))).unwrap();
+        let unchanged = RunInputs::resolve(&config, &env("abc123", Some("hello"))).unwrap();
+        assert!(unchanged.changes_since(&previous).is_empty());
+
+        let rotated = RunInputs::resolve(&config, &env("def456", None)).unwrap();
+        assert_eq!(
+            vec![
+                r#"argv: ["/app/server", "--token=<redacted>"] -> ["/app/server", "--token=<redacted>"]"#,
+                r#"API_TOKEN: "<redacted>" -> "<redacted>""#,
+                "GREETING: removed",
+            ],
+            rotated.changes_since(&previous)
+        );
+        assert_eq!(
+            vec![
+                r#"argv: ["/app/server", "--token=<redacted>"] -> ["/app/server", "--token=<redacted>"]"#,
+                r#"API_TOKEN: "<redacted>" -> "<redacted>""#,
+                r#"GREETING: added "hello""#,
+            ],
+            previous.changes_since(&rotated)
+        );
+    }
 }
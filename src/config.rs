@@ -1,371 +1,4531 @@
 //! Configuration structs.
 
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use serde::Deserialize;
+use color_eyre::eyre::{self, eyre, WrapErr};
+use serde::{
+    de::{self, DeserializeOwned, MapAccess, SeqAccess, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use sha2::{Digest, Sha256};
+
+use crate::{locations::KeyLocations, quiesce, ready, schedule::Schedule, wait};
 
 /// Ground Control configuration.
-#[derive(Clone, Debug, Deserialize)]
-#[serde(deny_unknown_fields)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct Config {
     /// Suppress the timestamp field from the log output (useful on
     /// systems that prepend the log output with their own, timestamped
     /// log output).
-    #[serde(default)]
+    #[serde(
+        default,
+        alias = "suppress_timestamps",
+        skip_serializing_if = "is_false"
+    )]
     pub suppress_timestamps: bool,
 
+    /// Maximum number of independent one-shot processes that can run
+    /// their `pre` commands at the same time during startup. Defaults
+    /// to one, which starts every process in sequence.
+    #[serde(
+        default = "default_startup_concurrency",
+        skip_serializing_if = "is_default_startup_concurrency"
+    )]
+    pub startup_concurrency: usize,
+
+    /// Optional limit on the time that the entire shutdown (stopping
+    /// every process in the reverse order in which they were started)
+    /// can take. The daemons of the processes that have not been stopped
+    /// when the limit expires are killed with SIGKILL.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_optional_duration",
+        serialize_with = "serialize_optional_duration",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub shutdown_timeout: Option<Duration>,
+
+    /// Forward every signal received by Ground Control to the (single)
+    /// daemon process, instead of using SIGINT and SIGTERM to trigger a
+    /// graceful shutdown. Ground Control exits when the daemon exits,
+    /// with the daemon's exit code.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub forward_signals: bool,
+
+    /// File descriptors (other than stdin, stdout, and stderr) that
+    /// Ground Control inherited and passes on to every command. Every
+    /// other inherited file descriptor is closed when a command is
+    /// executed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pass_fds: Vec<i32>,
+
+    /// Restrict `{{VAR}}` template expansion in commands that have an
+    /// `only-env` allow-list to the variables in that list (plus `PATH`),
+    /// instead of every variable in Ground Control's environment.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub restrict_expansion: bool,
+
+    /// Optional directory that Ground Control watches for control files
+    /// (creating a file named `shutdown` in the directory triggers a
+    /// graceful shutdown).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub control_dir: Option<PathBuf>,
+
+    /// Optional path of the Unix domain socket on which Ground Control
+    /// serves the `status`, `start`, `stop`, and `restart` subcommands.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub control_socket: Option<PathBuf>,
+
+    /// Optional HTTP API (`/healthz` and `/status`) through which
+    /// container platforms can health-check the whole bundle.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api: Option<ApiConfig>,
+
+    /// Optional file to which every lifecycle event (every command that
+    /// is started or exits, every daemon exit, and every stop that is
+    /// issued) is appended as a line of JSON.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_log: Option<PathBuf>,
+
+    /// Optional directory in which Ground Control keeps state that
+    /// outlives a single run (such as the history of daemon exits).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_dir: Option<PathBuf>,
+
     /// Optional list of additional variables to add to the environment.
-    #[serde(default)]
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "serialize_sorted_map"
+    )]
     pub env: HashMap<String, String>,
 
+    /// *Ordered* list of setup steps (creating directories, writing
+    /// files, etc.) to perform before any process is started.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub setup: Vec<SetupConfig>,
+
+    /// Optional directory of additional process definitions (one
+    /// process per `*.toml` file), which are added to the end of the
+    /// list of processes by `load_processes_dir`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub processes_dir: Option<PathBuf>,
+
     /// *Ordered* list of processes to start.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub processes: Vec<ProcessConfig>,
+
+    /// Path of the file from which the config was read, if known (this
+    /// is not a setting in the config file; it is only used to describe
+    /// the running config in the startup banner and status file).
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+
+    /// Path of the file to which the startup profile (the time that each
+    /// process took to start, and the critical path of dependencies) is
+    /// written as JSON once startup has completed (this is not a setting
+    /// in the config file; it is set by `--startup-profile`).
+    #[serde(skip)]
+    pub startup_profile: Option<PathBuf>,
+
+    /// Locations of the keys in the config file (when the config was
+    /// parsed with `from_toml`), which are included in validation
+    /// errors.
+    #[serde(skip)]
+    pub(crate) locations: KeyLocations,
 }
 
-/// Process configuration.
-#[derive(Clone, Debug, Deserialize)]
-#[serde(rename_all = "kebab-case", deny_unknown_fields)]
-pub struct ProcessConfig {
-    /// Name of the process (used in logging/monitoring).
-    pub name: String,
+/// HTTP API that Ground Control serves.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ApiConfig {
+    /// Address (and port) on which the API listens, such as
+    /// `"0.0.0.0:8080"` or `"[::]:8080"`.
+    pub listen: SocketAddr,
+}
 
-    /// Optional command to run *before* the `run` command.
-    #[serde(default)]
-    pub pre: Option<CommandConfig>,
+/// Dependency of a process on another process, along with the location
+/// of the dependency in the config (for use in error messages).
+struct Dependency {
+    /// Index of the process that is depended on.
+    index: usize,
 
-    /// Optional `run` command; if present, this process is considered a
-    /// "daemon process" and Ground Control will monitor the run
-    /// command, shutting down all of the processes if any run command
-    /// exits.
-    #[serde(default)]
-    pub run: Option<CommandConfig>,
+    /// Index of the process whose config contains the dependency (which
+    /// is the depended-on process for `before`).
+    declared_by: usize,
 
-    /// Mechanism for stopping the process *if this is a daemon process*
-    /// (ignored if the process does not have a `run` command).
-    #[serde(default)]
-    pub stop: StopMechanism,
+    /// Key in which the dependency was found (`depends-on`, `wants`,
+    /// `after`, or `before`).
+    key: &'static str,
 
-    /// Optional command to run after the process has been stopped.
-    #[serde(default)]
-    pub post: Option<CommandConfig>,
+    /// Position of the dependency in the list of dependencies.
+    position: usize,
 }
 
-/// Mechanism used to stop a daemon process.
-#[derive(Clone, Eq, PartialEq, Debug, Deserialize)]
-#[serde(untagged)]
-pub enum StopMechanism {
-    /// Stop the process using a signal.
-    Signal(SignalConfig),
+/// Deserializes a TOML document (whose keys are at the given locations),
+/// including the full key path (for example, `processes[2].stop`) and
+/// the line and column in any errors.
+fn deserialize_toml<T: DeserializeOwned>(text: &str, locations: &KeyLocations) -> eyre::Result<T> {
+    let mut deserializer = toml::Deserializer::new(text);
+    serde_path_to_error::deserialize(&mut deserializer).map_err(|err| {
+        let path = err.path().to_string();
+        let err = err.into_inner();
 
-    /// Stop the process by running a command.
-    Command(CommandConfig),
+        // The TOML error message includes its own (less precise) key
+        // and location; we replace those with our own.
+        let message = err.to_string();
+        let message = message
+            .split(" for key `")
+            .next()
+            .and_then(|message| message.split(" at line ").next())
+            .unwrap_or_default();
+
+        // TOML reports some errors (such as unknown fields) at the start
+        // of the table that contains the key, in which case the location
+        // of the key itself is more precise.
+        let location = match (err.line_col(), locations.get(&path)) {
+            (Some((line, _)), Some(key)) if key.0 > line + 1 => Some(key),
+            (Some((line, col)), _) => Some((line + 1, col + 1)),
+            (None, _) => locations.find(&path),
+        };
+        let location = match location {
+            Some((line, col)) => format!(" (line {line}, column {col})"),
+            None => String::new(),
+        };
+
+        if path == "." {
+            eyre!("{message}{location}")
+        } else {
+            eyre!("`{path}`{location}: {message}")
+        }
+    })
 }
 
-impl Default for StopMechanism {
-    fn default() -> Self {
-        StopMechanism::Signal(SignalConfig::SIGTERM)
-    }
+/// Returns true if the name could be a time zone in the tz database: one
+/// or more slash-separated components of letters, digits, `_`, `-`, and
+/// `+` (which also keeps the name from escaping the database directory).
+fn is_valid_time_zone_name(name: &str) -> bool {
+    name.split('/').all(|component| {
+        !component.is_empty()
+            && component != "."
+            && component != ".."
+            && component
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+' | '.'))
+    })
 }
 
-/// Signals used to stop a daemon process.
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize)]
-pub enum SignalConfig {
-    /// SIGINT
-    SIGINT,
+/// Returns the path of the time zone in the tz database (in the
+/// directory named by `TZDIR`, or `/usr/share/zoneinfo`).
+pub fn time_zone_path(name: &str) -> PathBuf {
+    let tz_dir = std::env::var_os("TZDIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/usr/share/zoneinfo"));
+    tz_dir.join(name)
+}
 
-    /// SIGQUIT
-    SIGQUIT,
+fn default_startup_concurrency() -> usize {
+    1
+}
 
-    /// SIGTERM
-    SIGTERM,
+fn is_default_startup_concurrency(startup_concurrency: &usize) -> bool {
+    *startup_concurrency == default_startup_concurrency()
 }
 
-impl From<SignalConfig> for nix::sys::signal::Signal {
-    fn from(signal: SignalConfig) -> Self {
-        match signal {
-            SignalConfig::SIGINT => Self::SIGINT,
-            SignalConfig::SIGQUIT => Self::SIGQUIT,
-            SignalConfig::SIGTERM => Self::SIGTERM,
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Serializes a map in key order (so that serialized configs are
+/// stable).
+fn serialize_sorted_map<S>(map: &HashMap<String, String>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+impl Config {
+    /// Parses a Ground Control config file. Parse errors include the
+    /// full key path (for example, `processes[2].stop`) and the line
+    /// and column at which the error was found; `validate` errors of the
+    /// parsed config include the line and column of the offending key.
+    pub fn from_toml(text: &str) -> eyre::Result<Self> {
+        let locations = KeyLocations::scan(text);
+        let mut config: Config = deserialize_toml(text, &locations)?;
+        config.locations = locations;
+        Ok(config)
+    }
+
+    /// Serializes the config as a Ground Control config file, which
+    /// parses (with `from_toml`) back into an identical config. Optional
+    /// and default settings are omitted, and commands are always written
+    /// as arrays (or detailed tables), since the string form cannot
+    /// represent arguments that contain spaces. Processes that were
+    /// added by `load_processes_dir` are included in the output.
+    pub fn to_toml(&self) -> eyre::Result<String> {
+        // Convert to a TOML value first, which orders the plain values
+        // of every table before its subtables (as TOML requires).
+        let value = toml::Value::try_from(self).wrap_err("Failed to serialize config")?;
+        toml::to_string(&value).wrap_err("Failed to serialize config")
+    }
+
+    /// Returns a fingerprint of the config: the SHA-256 hash (as a hex
+    /// string) of the config as serialized by `to_toml`. The fingerprint
+    /// only depends on the parsed settings, so formatting changes and
+    /// comments in the config file do not change the fingerprint.
+    pub fn fingerprint(&self) -> eyre::Result<String> {
+        let mut hasher = Sha256::new();
+        hasher.update(self.to_toml()?);
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Adds the processes defined in the `processes-dir` directory (if
+    /// any) to the end of the list of processes. Every `*.toml` file in
+    /// the directory defines a single process; the files are added in
+    /// the order of their file names.
+    pub fn load_processes_dir(&mut self) -> eyre::Result<()> {
+        let processes_dir = match &self.processes_dir {
+            Some(processes_dir) => processes_dir,
+            None => return Ok(()),
+        };
+
+        let mut paths = std::fs::read_dir(processes_dir)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect::<std::io::Result<Vec<_>>>()
+            })
+            .wrap_err_with(|| {
+                format!(
+                    "Failed to read processes directory \"{}\"",
+                    processes_dir.display()
+                )
+            })?;
+        paths.retain(|path| {
+            path.extension()
+                .map_or(false, |extension| extension == "toml")
+        });
+        paths.sort();
+
+        for path in paths {
+            let text = std::fs::read_to_string(&path)
+                .wrap_err_with(|| format!("Failed to read process file \"{}\"", path.display()))?;
+            let process: ProcessConfig = deserialize_toml(&text, &KeyLocations::scan(&text))
+                .wrap_err_with(|| format!("Failed to parse process file \"{}\"", path.display()))?;
+            self.processes.push(process);
         }
+
+        Ok(())
     }
-}
 
-impl From<&SignalConfig> for nix::sys::signal::Signal {
-    fn from(signal: &SignalConfig) -> Self {
-        match signal {
-            SignalConfig::SIGINT => Self::SIGINT,
-            SignalConfig::SIGQUIT => Self::SIGQUIT,
-            SignalConfig::SIGTERM => Self::SIGTERM,
+    /// Returns the location of the key with the given path (such as
+    /// `processes[2].stop`, or else the nearest key that contains it) in
+    /// the config file, formatted for the end of an error message, or an
+    /// empty string if the location is not known.
+    fn location(&self, path: &str) -> String {
+        match self.locations.find(path) {
+            Some((line, col)) => format!(" (line {line}, column {col})"),
+            None => String::new(),
         }
     }
-}
 
-/// Configuration for a command, its arguments, and any execution
-/// properties (such as the user under which to run the command, or the
-/// environment variables to pass through to the command).
-#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
-#[serde(from = "CommandLineConfig")]
-pub struct CommandConfig {
-    /// User to run this command as, otherwise run the command as the
-    /// user that executed Ground Control (most likely `root`).
-    pub user: Option<String>,
+    /// Returns the key path, followed by the line and column of the key
+    /// in the config file if known (such as `processes[2].after[0] at
+    /// line 9, column 1`).
+    fn key_path_at(&self, path: &str) -> String {
+        match self.locations.find(path) {
+            Some((line, col)) => format!("{path} at line {line}, column {col}"),
+            None => path.to_owned(),
+        }
+    }
 
-    /// If present, then only the given list of environment variables
-    /// will be passed through to the command (all other variables will
-    /// be removed from the command's environment). Note that `PATH` is
-    /// always allowed. All environment variables will be allowed if
-    /// this value is `None`. If provided, but empty, then no variables
-    /// other than `PATH` will be allowed.
-    pub only_env: Option<HashSet<String>>,
+    /// Returns the location of a key of the process (see `location`).
+    fn process_location(&self, process: &ProcessConfig, key: &str) -> String {
+        match self
+            .processes
+            .iter()
+            .position(|candidate| std::ptr::eq(candidate, process))
+        {
+            Some(index) => self.location(&format!("processes[{index}].{key}")),
+            None => String::new(),
+        }
+    }
 
-    /// Program to execute.
-    pub program: String,
+    /// Validates the configuration, returning an error if the
+    /// configuration is internally inconsistent (duplicate process
+    /// names, references to unknown processes, dependency cycles, etc.).
+    pub fn validate(&self) -> eyre::Result<()> {
+        if self.startup_concurrency == 0 {
+            return Err(eyre!(
+                "`startup-concurrency` must be at least 1{}",
+                self.location("startup-concurrency")
+            ));
+        }
 
-    /// Arguments to pass to the program.
-    pub args: Vec<String>,
-}
+        if let Some(fd) = self.pass_fds.iter().find(|fd| **fd <= 2) {
+            return Err(eyre!(
+                "`pass-fds` cannot include file descriptor {fd} (stdin, stdout, and stderr are always passed){}",
+                self.location("pass-fds")
+            ));
+        }
 
-#[derive(Clone, Eq, PartialEq, Debug, Deserialize)]
-#[serde(untagged)]
-enum CommandLineConfig {
-    Simple(CommandLine),
+        // Process names are used as file names (of runtime directories,
+        // for example), so they must be a single path component.
+        for process in &self.processes {
+            let name = process.name.as_str();
+            if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\0']) {
+                return Err(eyre!(
+                    "Process name \"{name}\" must be a single path component (not empty, `.`, or `..`, and without `/`){}",
+                    self.process_location(process, "name")
+                ));
+            }
+        }
 
-    Detailed(DetailedCommandLine),
-}
+        let processes = self.startup_order()?;
+        self.standby_processes()?;
+        self.validate_ports()?;
 
-impl From<CommandLineConfig> for CommandConfig {
-    fn from(config: CommandLineConfig) -> Self {
-        match config {
-            CommandLineConfig::Simple(config) => {
-                let (program, args) = config.program_and_args();
-                Self {
-                    user: None,
-                    only_env: None,
-                    program,
-                    args,
+        for process in &self.processes {
+            if let Some(RuntimeDirConfig::Path(path)) = &process.runtime_dir {
+                if !path.is_absolute() {
+                    return Err(eyre!(
+                        "`runtime-dir` in process \"{}\" must be an absolute path{}",
+                        process.name,
+                        self.process_location(process, "runtime-dir")
+                    ));
                 }
             }
-            CommandLineConfig::Detailed(config) => {
-                let (program, args) = config.command.program_and_args();
-                Self {
-                    user: config.user,
-                    only_env: config.only_env,
-                    program,
-                    args,
+
+            if let Some(wait_for) = &process.wait_for {
+                let conditions = [
+                    wait_for.dns.is_some(),
+                    wait_for.connect.is_some(),
+                    wait_for.mount.is_some(),
+                ];
+                if conditions.iter().filter(|condition| **condition).count() != 1 {
+                    return Err(eyre!(
+                        "`wait-for` in process \"{}\" must specify exactly one condition (`dns`, `connect`, or `mount`){}",
+                        process.name,
+                        self.process_location(process, "wait-for")
+                    ));
+                }
+
+                if let Some(url) = &wait_for.connect {
+                    wait::validate_connect_url(url).wrap_err_with(|| {
+                        format!(
+                            "Invalid `wait-for` in process \"{}\"{}",
+                            process.name,
+                            self.process_location(process, "wait-for")
+                        )
+                    })?;
+                }
+            }
+
+            if let StopMechanism::Http(http) = &process.stop {
+                quiesce::validate_url(&http.http).wrap_err_with(|| {
+                    format!(
+                        "Invalid `stop` in process \"{}\"{}",
+                        process.name,
+                        self.process_location(process, "stop")
+                    )
+                })?;
+            }
+
+            if let Some(ready) = &process.ready {
+                if process.run.is_none() {
+                    return Err(eyre!(
+                        "Process \"{}\" has a `ready` probe, but no `run` command{}",
+                        process.name,
+                        self.process_location(process, "ready")
+                    ));
+                }
+
+                let probes = [
+                    ready.tcp.is_some(),
+                    ready.http.is_some(),
+                    ready.exec.is_some(),
+                    ready.file.is_some(),
+                ];
+                let count = probes.iter().filter(|probe| **probe).count();
+                if count > 1 || (count == 0 && process.ports.is_empty()) {
+                    return Err(eyre!(
+                        "`ready` in process \"{}\" must specify exactly one probe (`tcp`, `http`, `exec`, or `file`){}",
+                        process.name,
+                        self.process_location(process, "ready")
+                    ));
+                }
+
+                if let Some(address) = &ready.tcp {
+                    ready::validate_tcp_address(address).wrap_err_with(|| {
+                        format!(
+                            "Invalid `ready` in process \"{}\"{}",
+                            process.name,
+                            self.process_location(process, "ready")
+                        )
+                    })?;
+                }
+                if let Some(url) = &ready.http {
+                    quiesce::validate_url(url).wrap_err_with(|| {
+                        format!(
+                            "Invalid `ready` in process \"{}\"{}",
+                            process.name,
+                            self.process_location(process, "ready")
+                        )
+                    })?;
                 }
             }
         }
-    }
-}
 
-#[derive(Clone, Eq, PartialEq, Debug, Deserialize)]
-#[serde(untagged)]
-enum CommandLine {
-    CommandString(String),
+        for process in &self.processes {
+            if let Some(dependency) = process
+                .depends_on
+                .iter()
+                .find(|name| self.is_optional(name))
+            {
+                return Err(eyre!(
+                    "Process \"{}\" depends on optional process \"{dependency}\" (use `wants` instead){}",
+                    process.name,
+                    self.process_location(process, "depends-on")
+                ));
+            }
+        }
 
-    CommandVector(Vec<String>),
-}
+        for process in &self.processes {
+            if let Some(target_name) = &process.bound_to {
+                self.validate_binding(process, target_name)?;
+            }
+        }
 
-impl CommandLine {
-    /// Parse the Command Line into the program to execute, and the
-    /// arguments to that program.
-    fn program_and_args(&self) -> (String, Vec<String>) {
-        match self {
-            CommandLine::CommandString(line) => {
-                // TODO: This won't handle quoted arguments with spaces
-                // (for example), so really we should parse this using a
-                // more correct, shell-like parser. OTOH, we could just
-                // say that anything complicated needs to use the vector
-                // format...
-                let mut elems = line.split(' ');
+        if let Some(process) = self.processes.iter().find(|process| {
+            process.restart_on_exit_codes.is_some() && process.restart_except_exit_codes.is_some()
+        }) {
+            return Err(eyre!(
+                "Process \"{}\" cannot use both `restart-on-exit-codes` and `restart-except-exit-codes`{}",
+                process.name,
+                self.process_location(process, "restart-on-exit-codes")
+            ));
+        }
 
-                let program = elems
-                    .next()
-                    .expect("Command line must not be empty")
-                    .to_string();
-                let args = elems.map(|s| s.to_string()).collect();
+        for process in &self.processes {
+            if process.restart == RestartPolicy::Never {
+                continue;
+            }
+            if process.restart_on_exit_codes.is_some()
+                || process.restart_except_exit_codes.is_some()
+            {
+                return Err(eyre!(
+                    "Process \"{}\" cannot use both `restart` and `restart-on-exit-codes` or `restart-except-exit-codes`{}",
+                    process.name,
+                    self.process_location(process, "restart")
+                ));
+            }
+            if process.run.is_none() {
+                return Err(eyre!(
+                    "Process \"{}\" has a `restart` policy, but no `run` command{}",
+                    process.name,
+                    self.process_location(process, "restart")
+                ));
+            }
+        }
 
-                (program, args)
+        for process in &self.processes {
+            match process.watchdog {
+                Some(_) if process.run.is_none() => {
+                    return Err(eyre!(
+                        "Process \"{}\" has a `watchdog`, but no `run` command{}",
+                        process.name,
+                        self.process_location(process, "watchdog")
+                    ));
+                }
+                Some(_) if process.runtime_dir_path().is_none() => {
+                    return Err(eyre!(
+                        "Process \"{}\" has a `watchdog`, but no `runtime-dir` (which holds the watchdog file and socket){}",
+                        process.name,
+                        self.process_location(process, "watchdog")
+                    ));
+                }
+                Some(watchdog) if watchdog.is_zero() => {
+                    return Err(eyre!(
+                        "`watchdog` in process \"{}\" must be greater than zero{}",
+                        process.name,
+                        self.process_location(process, "watchdog")
+                    ));
+                }
+                _ => {}
             }
+        }
 
-            CommandLine::CommandVector(v) => {
-                let program = v[0].to_string();
-                let args = v[1..].to_vec();
+        for process in &self.processes {
+            if let Some(backoff) = &process.restart_backoff {
+                if backoff.initial.is_zero() || backoff.initial > backoff.max {
+                    return Err(eyre!(
+                        "`restart-backoff` in process \"{}\" must have an `initial` delay that is greater than zero and no greater than its `max` delay{}",
+                        process.name,
+                        self.process_location(process, "restart-backoff")
+                    ));
+                }
+            }
+            if let Some(limit) = &process.restart_limit {
+                if limit.window.is_zero() {
+                    return Err(eyre!(
+                        "`restart-limit` in process \"{}\" must have a `window` that is greater than zero{}",
+                        process.name,
+                        self.process_location(process, "restart-limit")
+                    ));
+                }
+            }
+        }
 
-                (program, args)
+        for process in &self.processes {
+            if process.shutdown_class != ShutdownClass::Normal && self.shutdown_timeout.is_none() {
+                return Err(eyre!(
+                    "Process \"{}\" has a `shutdown-class`, but there is no `shutdown-timeout`{}",
+                    process.name,
+                    self.process_location(process, "shutdown-class")
+                ));
             }
         }
-    }
-}
 
-#[derive(Clone, Eq, PartialEq, Debug, Deserialize)]
-#[serde(deny_unknown_fields, rename_all = "kebab-case")]
-struct DetailedCommandLine {
-    #[serde(default)]
-    user: Option<String>,
+        for process in &self.processes {
+            match process.max_runtime {
+                Some(_) if process.run.is_none() => {
+                    return Err(eyre!(
+                        "Process \"{}\" has a `max-runtime`, but no `run` command{}",
+                        process.name,
+                        self.process_location(process, "max-runtime")
+                    ));
+                }
+                Some(max_runtime) if max_runtime.is_zero() => {
+                    return Err(eyre!(
+                        "`max-runtime` in process \"{}\" must be greater than zero{}",
+                        process.name,
+                        self.process_location(process, "max-runtime")
+                    ));
+                }
+                _ => {}
+            }
+        }
 
-    #[serde(default)]
-    only_env: Option<HashSet<String>>,
+        for process in &self.processes {
+            if let Some(restart_schedule) = &process.restart_schedule {
+                if process.run.is_none() {
+                    return Err(eyre!(
+                        "Process \"{}\" has a `restart-schedule`, but no `run` command{}",
+                        process.name,
+                        self.process_location(process, "restart-schedule")
+                    ));
+                }
+                Schedule::parse(restart_schedule).wrap_err_with(|| {
+                    format!(
+                        "Invalid `restart-schedule` in process \"{}\"{}",
+                        process.name,
+                        self.process_location(process, "restart-schedule")
+                    )
+                })?;
+            }
+        }
 
-    command: CommandLine,
-}
+        for process in &self.processes {
+            for (key, hook) in [
+                ("post-start", &process.post_start),
+                ("pre-stop", &process.pre_stop),
+            ] {
+                if hook.is_some() && process.run.is_none() {
+                    return Err(eyre!(
+                        "Process \"{}\" has a `{key}` command, but no `run` command{}",
+                        process.name,
+                        self.process_location(process, key)
+                    ));
+                }
+            }
+        }
 
-#[cfg(test)]
-#[allow(clippy::unwrap_used)]
-mod tests {
-    use serde::Deserialize;
+        for process in &self.processes {
+            if let Some(health_check) = &process.health_check {
+                self.validate_health_check(process, health_check)?;
+            }
+        }
 
-    use super::*;
+        for process in &self.processes {
+            if process.success_marker.is_some() && (process.run.is_some() || process.pre.is_none())
+            {
+                return Err(eyre!(
+                    "Process \"{}\" has a `success-marker`, but is not a one-shot process with a `pre` command{}",
+                    process.name,
+                    self.process_location(process, "success-marker")
+                ));
+            }
+        }
 
-    #[derive(Debug, Deserialize, PartialEq)]
-    struct StopMechanismTest {
-        stop: StopMechanism,
-    }
+        for process in &self.processes {
+            if let Some(timezone) = &process.timezone {
+                if !is_valid_time_zone_name(timezone) {
+                    return Err(eyre!(
+                        "Process \"{}\" has an invalid `timezone` \"{timezone}\" (expected a tz database name such as \"America/New_York\"){}",
+                        process.name,
+                        self.process_location(process, "timezone")
+                    ));
+                }
+            }
+        }
 
-    #[test]
-    fn supports_signal_names_in_stop() {
-        let toml = r#"stop = "SIGTERM""#;
-        let decoded: StopMechanismTest = toml::from_str(toml).expect("Failed to parse test TOML");
-        assert_eq!(StopMechanism::Signal(SignalConfig::SIGTERM), decoded.stop);
-    }
+        #[cfg(not(feature = "consul"))]
+        if let Some(process) = self
+            .processes
+            .iter()
+            .find(|process| process.service.is_some())
+        {
+            return Err(eyre!(
+                "Process \"{}\" has a `service`, but Ground Control was built without the `consul` feature{}",
+                process.name,
+                self.process_location(process, "service")
+            ));
+        }
 
-    #[derive(Debug, Deserialize, PartialEq)]
-    struct CommandConfigTest {
-        run: CommandConfig,
+        let main_processes: Vec<&ProcessConfig> = self
+            .processes
+            .iter()
+            .filter(|process| process.main)
+            .collect();
+        if let Some(process) = main_processes.iter().find(|process| process.run.is_none()) {
+            return Err(eyre!(
+                "Process \"{}\" is the `main` process, but has no `run` command{}",
+                process.name,
+                self.process_location(process, "main")
+            ));
+        }
+        if main_processes.len() > 1 {
+            return Err(eyre!(
+                "Only one process can be the `main` process (found {}){}",
+                main_processes.len(),
+                self.process_location(main_processes[1], "main")
+            ));
+        }
+        if self.forward_signals && !main_processes.is_empty() {
+            return Err(eyre!(
+                "`main` cannot be combined with `forward-signals` (which forwards every signal to the only daemon){}",
+                self.location("forward-signals")
+            ));
+        }
+
+        if self.forward_signals {
+            let daemons = processes
+                .iter()
+                .filter(|process| process.run.is_some())
+                .count();
+            if daemons != 1 {
+                return Err(eyre!(
+                    "`forward-signals` requires exactly one daemon process (found {daemons}){}",
+                    self.location("forward-signals")
+                ));
+            }
+        }
+
+        Ok(())
     }
 
-    #[test]
-    fn supports_whitespace_separated_command_lines() {
+    /// Returns the processes in the order in which they should be
+    /// started: dependencies (`depends-on` and `wants`) and ordering
+    /// hints (`after` and `before`) are always started before the
+    /// processes that reference them, otherwise
+    /// processes are started in the order they are found in the config
+    /// file. Disabled processes are not included.
+    pub fn startup_order(&self) -> eyre::Result<Vec<ProcessConfig>> {
+        // Map every process name to its position in the config file,
+        // rejecting duplicate names (since dependencies would then be
+        // ambiguous).
+        let mut indexes: HashMap<&str, usize> = HashMap::new();
+        for (index, process) in self.processes.iter().enumerate() {
+            if indexes.insert(&process.name, index).is_some() {
+                return Err(eyre!(
+                    "Duplicate process name \"{}\"{}",
+                    process.name,
+                    self.process_location(process, "name")
+                ));
+            }
+        }
+
+        // Map every barrier name to the (enabled) processes that join
+        // the barrier. Barriers share the namespace of processes, since
+        // both are referenced in the same way.
+        let mut barriers: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (index, process) in self.processes.iter().enumerate() {
+            if let Some(barrier) = &process.joins {
+                if indexes.contains_key(barrier.as_str()) {
+                    return Err(eyre!(
+                        "Process \"{}\" joins barrier \"{barrier}\", which has the same name as a process{}",
+                        process.name,
+                        self.process_location(process, "joins")
+                    ));
+                }
+                if process.standby_for.is_some() {
+                    return Err(eyre!(
+                        "Standby process \"{}\" cannot join barrier \"{barrier}\"{}",
+                        process.name,
+                        self.process_location(process, "joins")
+                    ));
+                }
+
+                let joiners = barriers.entry(barrier).or_default();
+                if !process.disabled {
+                    joiners.push(index);
+                }
+            }
+        }
+
+        // Resolve the dependencies of every process into the indexes of
+        // those dependencies (references to a barrier resolve to every
+        // process that joins the barrier). `before` is the inverse of
+        // `after`, and so adds a dependency to the *referenced* process.
+        let mut dependencies: Vec<Vec<Dependency>> =
+            (0..self.processes.len()).map(|_| Vec::new()).collect();
+        for (process_index, process) in self.processes.iter().enumerate() {
+            let bound_to: &[String] = match &process.bound_to {
+                Some(target) => std::slice::from_ref(target),
+                None => &[],
+            };
+            for (key, names) in [
+                ("depends-on", process.depends_on.as_slice()),
+                ("wants", process.wants.as_slice()),
+                ("after", process.after.as_slice()),
+                ("before", process.before.as_slice()),
+                ("bound-to", bound_to),
+            ] {
+                for (position, name) in names.iter().enumerate() {
+                    let location =
+                        self.key_path_at(&format!("processes[{process_index}].{key}[{position}]"));
+
+                    if let Some(joiners) = barriers.get(name.as_str()) {
+                        if key == "before" {
+                            return Err(eyre!(
+                                "Process \"{}\" lists barrier \"{name}\" in `before` (use `joins` instead) ({location})",
+                                process.name
+                            ));
+                        }
+                        dependencies[process_index].extend(joiners.iter().map(|&index| {
+                            Dependency {
+                                index,
+                                declared_by: process_index,
+                                key,
+                                position,
+                            }
+                        }));
+                        continue;
+                    }
+
+                    let index = *indexes.get(name.as_str()).ok_or_else(|| {
+                        eyre!(
+                            "Process \"{}\" references unknown process \"{name}\" ({location})",
+                            process.name
+                        )
+                    })?;
+
+                    // Standby processes are only started when their
+                    // primary fails, and so cannot be dependencies.
+                    if self.processes[index].standby_for.is_some() {
+                        return Err(eyre!(
+                            "Process \"{}\" references standby process \"{name}\" ({location})",
+                            process.name
+                        ));
+                    }
+
+                    // Disabled processes can be wanted, but not
+                    // required.
+                    if key == "depends-on" && !process.disabled && self.processes[index].disabled {
+                        return Err(eyre!(
+                            "Process \"{}\" depends on disabled process \"{name}\" ({location})",
+                            process.name
+                        ));
+                    }
+
+                    let (dependent, index) = if key == "before" {
+                        (index, process_index)
+                    } else {
+                        (process_index, index)
+                    };
+                    dependencies[dependent].push(Dependency {
+                        index,
+                        declared_by: process_index,
+                        key,
+                        position,
+                    });
+                }
+            }
+        }
+
+        // Repeatedly pick the first process (in config file order)
+        // whose dependencies have all been started. This is a stable
+        // topological sort, which means that configs without any
+        // dependencies start in exactly the order they are written.
+        let mut started = vec![false; self.processes.len()];
+        let mut order = Vec::with_capacity(self.processes.len());
+        while order.len() < self.processes.len() {
+            let next = (0..self.processes.len()).find(|&index| {
+                !started[index] && dependencies[index].iter().all(|dep| started[dep.index])
+            });
+
+            match next {
+                Some(index) => {
+                    started[index] = true;
+                    order.push(self.processes[index].clone());
+                }
+                None => return Err(self.dependency_cycle_error(&dependencies, &started)),
+            }
+        }
+
+        // Disabled processes are never started, and standby processes
+        // are not started until they are needed.
+        order.retain(|process| !process.disabled && process.standby_for.is_none());
+
+        Ok(order)
+    }
+
+    /// Generates an error that describes a dependency cycle, given the
+    /// dependencies of every process and the processes that could be
+    /// started before the cycle was found.
+    fn dependency_cycle_error(
+        &self,
+        dependencies: &[Vec<Dependency>],
+        started: &[bool],
+    ) -> eyre::Report {
+        // Every process that could not be started has at least one
+        // dependency that could not be started, so following those
+        // dependencies from any such process must eventually revisit a
+        // process, which closes the cycle.
+        let mut path: Vec<(usize, &Dependency)> = Vec::new();
+        let mut index = started
+            .iter()
+            .position(|started| !started)
+            .expect("cycle should contain at least one process");
+        let cycle_start = loop {
+            if let Some(position) = path.iter().position(|(visited, _)| *visited == index) {
+                break position;
+            }
+
+            let dep = dependencies[index]
+                .iter()
+                .find(|dep| !started[dep.index])
+                .expect("unstarted process should have an unstarted dependency");
+            path.push((index, dep));
+            index = dep.index;
+        };
+        let cycle = &path[cycle_start..];
+
+        let names: Vec<&str> = cycle
+            .iter()
+            .map(|(index, _)| self.processes[*index].name.as_str())
+            .chain(std::iter::once(self.processes[cycle[0].0].name.as_str()))
+            .collect();
+        let entries: Vec<String> = cycle
+            .iter()
+            .map(|(index, dep)| {
+                // `before` is declared by the depended-on process.
+                let referenced = if dep.declared_by == *index {
+                    dep.index
+                } else {
+                    *index
+                };
+                format!(
+                    "{} (\"{}\" {} \"{}\")",
+                    self.key_path_at(&format!(
+                        "processes[{}].{}[{}]",
+                        dep.declared_by, dep.key, dep.position
+                    )),
+                    self.processes[dep.declared_by].name,
+                    dep.key,
+                    self.processes[referenced].name
+                )
+            })
+            .collect();
+
+        eyre!(
+            "Dependency cycle detected: {}\n{}",
+            names.join(" → "),
+            entries
+                .iter()
+                .map(|entry| format!("  - {entry}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        )
+    }
+
+    /// Verifies the ports of the (enabled) processes: no process can
+    /// declare port 0, or a port more than once, and no two processes can
+    /// declare the same port, unless one is the standby process of the
+    /// other (since they never run at the same time).
+    fn validate_ports(&self) -> eyre::Result<()> {
+        let is_standby_pair = |a: &ProcessConfig, b: &ProcessConfig| {
+            a.standby_for.as_deref() == Some(b.name.as_str())
+                || b.standby_for.as_deref() == Some(a.name.as_str())
+        };
+
+        let mut owners: HashMap<u16, &ProcessConfig> = HashMap::new();
+        for process in self.processes.iter().filter(|process| !process.disabled) {
+            for (position, port) in process.ports.iter().enumerate() {
+                if *port == 0 {
+                    return Err(eyre!(
+                        "Process \"{}\" cannot declare port 0{}",
+                        process.name,
+                        self.process_location(process, "ports")
+                    ));
+                }
+                if process.ports[..position].contains(port) {
+                    return Err(eyre!(
+                        "Process \"{}\" declares port {port} more than once{}",
+                        process.name,
+                        self.process_location(process, "ports")
+                    ));
+                }
+
+                match owners.get(port) {
+                    Some(owner) if !is_standby_pair(owner, process) => {
+                        return Err(eyre!(
+                            "Processes \"{}\" and \"{}\" both declare port {port}{}",
+                            owner.name,
+                            process.name,
+                            self.process_location(process, "ports")
+                        ));
+                    }
+                    Some(_) => {}
+                    None => {
+                        owners.insert(*port, process);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Verifies the health check of the process: the process must be a
+    /// daemon, the check must specify exactly one probe (or none, if the
+    /// process declares `ports`), and its interval, timeout, and
+    /// thresholds must be greater than zero.
+    fn validate_health_check(
+        &self,
+        process: &ProcessConfig,
+        health_check: &HealthCheckConfig,
+    ) -> eyre::Result<()> {
+        if process.run.is_none() {
+            return Err(eyre!(
+                "Process \"{}\" has a `health-check`, but no `run` command{}",
+                process.name,
+                self.process_location(process, "health-check")
+            ));
+        }
+
+        let probes = [
+            health_check.tcp.is_some(),
+            health_check.http.is_some(),
+            health_check.exec.is_some(),
+        ];
+        let count = probes.iter().filter(|probe| **probe).count();
+        if count > 1 || (count == 0 && process.ports.is_empty()) {
+            return Err(eyre!(
+                "`health-check` in process \"{}\" must specify exactly one probe (`tcp`, `http`, or `exec`){}",
+                process.name,
+                self.process_location(process, "health-check")
+            ));
+        }
+
+        if let Some(address) = &health_check.tcp {
+            ready::validate_tcp_address(address).wrap_err_with(|| {
+                format!(
+                    "Invalid `health-check` in process \"{}\"{}",
+                    process.name,
+                    self.process_location(process, "health-check")
+                )
+            })?;
+        }
+        if let Some(url) = &health_check.http {
+            quiesce::validate_url(url).wrap_err_with(|| {
+                format!(
+                    "Invalid `health-check` in process \"{}\"{}",
+                    process.name,
+                    self.process_location(process, "health-check")
+                )
+            })?;
+        }
+
+        if health_check.interval.is_zero()
+            || health_check.timeout.is_zero()
+            || health_check.failure_threshold == 0
+            || health_check.success_threshold == 0
+        {
+            return Err(eyre!(
+                "`health-check` in process \"{}\" must have an `interval`, `timeout`, `failure-threshold`, and `success-threshold` that are greater than zero{}",
+                process.name,
+                self.process_location(process, "health-check")
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Verifies that the process can be bound to the target process
+    /// (`bound-to`): both processes must be daemons, and the target must
+    /// be an enabled, required process that is not itself bound to
+    /// another process.
+    fn validate_binding(&self, process: &ProcessConfig, target_name: &str) -> eyre::Result<()> {
+        let target = self
+            .processes
+            .iter()
+            .find(|target| target.name == target_name)
+            .ok_or_else(|| {
+                eyre!(
+                    "Process \"{}\" is bound to unknown process \"{target_name}\"{}",
+                    process.name,
+                    self.process_location(process, "bound-to")
+                )
+            })?;
+
+        let problem = if process.run.is_none() {
+            Some("but has no `run` command")
+        } else if process.standby_for.is_some() {
+            Some("but is a standby process")
+        } else if target.name == process.name {
+            Some("which is itself")
+        } else if target.run.is_none() || target.standby_for.is_some() {
+            Some("which is not a daemon process")
+        } else if target.optional {
+            Some("which is optional")
+        } else if target.disabled && !process.disabled {
+            Some("which is disabled")
+        } else if target.bound_to.is_some() {
+            Some("which is itself bound to another process")
+        } else {
+            None
+        };
+
+        match problem {
+            Some(problem) => Err(eyre!(
+                "Process \"{}\" is bound to process \"{target_name}\", {problem}{}",
+                process.name,
+                self.process_location(process, "bound-to")
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the (enabled) standby processes, keyed by the name of
+    /// their primary process.
+    pub fn standby_processes(&self) -> eyre::Result<HashMap<String, ProcessConfig>> {
+        let mut standbys = HashMap::new();
+        for (process, primary_name) in self
+            .processes
+            .iter()
+            .filter(|process| !process.disabled)
+            .filter_map(|process| Some((process, process.standby_for.as_ref()?)))
+        {
+            let primary = self
+                .processes
+                .iter()
+                .find(|primary| &primary.name == primary_name)
+                .ok_or_else(|| {
+                    eyre!(
+                        "Process \"{}\" is a standby for unknown process \"{primary_name}\"{}",
+                        process.name,
+                        self.process_location(process, "standby-for")
+                    )
+                })?;
+            if primary.run.is_none() || primary.standby_for.is_some() {
+                return Err(eyre!(
+                    "Process \"{}\" is a standby for \"{primary_name}\", which is not a daemon process{}",
+                    process.name,
+                    self.process_location(process, "standby-for")
+                ));
+            }
+
+            if standbys
+                .insert(primary_name.clone(), process.clone())
+                .is_some()
+            {
+                return Err(eyre!(
+                    "Process \"{primary_name}\" has more than one standby process{}",
+                    self.process_location(process, "standby-for")
+                ));
+            }
+        }
+
+        Ok(standbys)
+    }
+
+    /// Disables the process with the given name, returning an error if
+    /// there is no such process.
+    pub fn disable_process(&mut self, name: &str) -> eyre::Result<()> {
+        let process = self
+            .processes
+            .iter_mut()
+            .find(|process| process.name == name)
+            .ok_or_else(|| eyre!("Unknown process \"{name}\""))?;
+        process.disabled = true;
+        Ok(())
+    }
+
+    /// Returns true if the given process is marked as `optional`.
+    pub(crate) fn is_optional(&self, name: &str) -> bool {
+        self.processes
+            .iter()
+            .any(|process| process.name == name && process.optional)
+    }
+
+    /// Returns true if a failure to start the given process should be
+    /// logged and ignored instead of aborting startup: the process must
+    /// be *wanted* by at least one other process, and must not be a
+    /// hard dependency (`depends-on`) of any process, or have processes
+    /// bound to it.
+    pub(crate) fn is_failure_tolerated(&self, name: &str) -> bool {
+        let process = match self.processes.iter().find(|process| process.name == name) {
+            Some(process) => process,
+            None => return false,
+        };
+
+        self.processes
+            .iter()
+            .any(|other| process.is_referenced_by(&other.wants))
+            && !self.processes.iter().any(|other| {
+                process.is_referenced_by(&other.depends_on)
+                    || other.bound_to.as_ref() == Some(&process.name)
+            })
+    }
+}
+
+/// Process configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProcessConfig {
+    /// Name of the process (used in logging/monitoring).
+    pub name: String,
+
+    /// Optional description of what the process does, which is included
+    /// in status output and in the logs of failures (so that whoever is
+    /// paged knows what failed).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// Optional owner of the process (a team, or a person), which is
+    /// included in status output and in the logs of failures.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+
+    /// Disables the process, which will then not be started (this
+    /// allows processes to be turned off without removing them from
+    /// the config file).
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub disabled: bool,
+
+    /// Marks the daemon as the main process of the container, to which
+    /// Ground Control forwards every signal that it receives other than
+    /// SIGINT and SIGTERM (which still trigger a graceful shutdown), and
+    /// whose exit code becomes Ground Control's exit code.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub main: bool,
+
+    /// Name of the daemon process for which this process is a standby:
+    /// standby processes are not started during startup, but are
+    /// instead started if their primary daemon fails.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub standby_for: Option<String>,
+
+    /// Allows the process to fail to start (its `pre` or `run` command
+    /// fails) without aborting startup; the failure is logged and the
+    /// process is skipped.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub optional: bool,
+
+    /// Names of processes that must start successfully before this
+    /// process is started; startup is aborted if any of them fail.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+
+    /// Names of processes that should be started before this process,
+    /// but whose failure to start is logged and then ignored (useful
+    /// for optional sidecars).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wants: Vec<String>,
+
+    /// Names of processes that must be started before this process
+    /// (ordering only: unlike `depends-on` and `wants`, this does not
+    /// change how a failure of those processes is handled).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+
+    /// Names of processes that must be started after this process
+    /// (ordering only; the inverse of `after`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub before: Vec<String>,
+
+    /// Optional name of a barrier that this process joins. Barriers are
+    /// referenced like processes (in `depends-on`, `wants`, and `after`),
+    /// and processes that reference a barrier start only once every
+    /// process that joins the barrier has started (which, for one-shot
+    /// processes, means that they have completed).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub joins: Option<String>,
+
+    /// Optional name of a daemon process to which this (daemon) process
+    /// is bound, as a sidecar: this process starts after that process,
+    /// is stopped as soon as the daemon of that process stops, and is
+    /// restarted whenever that daemon is restarted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bound_to: Option<String>,
+
+    /// Optional command to run *before* the `run` command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre: Option<CommandConfig>,
+
+    /// Optional `run` command; if present, this process is considered a
+    /// "daemon process" and Ground Control will monitor the run
+    /// command, shutting down all of the processes if any run command
+    /// exits.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run: Option<CommandConfig>,
+
+    /// Optional command to run every time the daemon has been started
+    /// (and is ready, if the process has a `ready` probe), such as one
+    /// that warms a cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_start: Option<CommandConfig>,
+
+    /// Optional command to run right before the daemon is stopped (before
+    /// its `stop` command/signal), such as one that flushes buffers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_stop: Option<CommandConfig>,
+
+    /// Mechanism for stopping the process *if this is a daemon process*
+    /// (ignored if the process does not have a `run` command).
+    #[serde(default, skip_serializing_if = "is_default_stop")]
+    pub stop: StopMechanism,
+
+    /// What to do if the `stop` command/signal fails (defaults to
+    /// `continue`).
+    #[serde(default, skip_serializing_if = "is_default_on_stop_failure")]
+    pub on_stop_failure: OnStopFailure,
+
+    /// Optional delay between deregistering the daemon's service (which
+    /// takes the daemon out of rotation) and stopping the daemon, which
+    /// gives load balancers time to stop sending it new connections.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_optional_duration",
+        serialize_with = "serialize_optional_duration",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub pre_stop_delay: Option<Duration>,
+
+    /// How much of the `shutdown-timeout` the process can use to stop
+    /// (defaults to `normal`).
+    #[serde(default, skip_serializing_if = "is_default_shutdown_class")]
+    pub shutdown_class: ShutdownClass,
+
+    /// Optional command to run after the process has been stopped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post: Option<CommandConfig>,
+
+    /// Optional marker file of a one-shot process, which is created once
+    /// the `pre` command has succeeded. The `pre` command is skipped on
+    /// every later start if the marker file exists, which makes the
+    /// process run only once, ever.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub success_marker: Option<PathBuf>,
+
+    /// Optional runtime directory to create before the process starts
+    /// (and remove after the process stops). The path of the directory
+    /// is provided to every command in the `GC_RUNTIME_DIR`
+    /// environment variable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime_dir: Option<RuntimeDirConfig>,
+
+    /// Optional source for the standard input of the process's `run`
+    /// command (or `pre` command, for one-shot processes). Standard
+    /// input is empty if this is not provided.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdin: Option<StdinConfig>,
+
+    /// Resolve the environment separately for every command, instead of
+    /// using the environment captured when the process was started for
+    /// all of the process's commands.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub fresh_env: bool,
+
+    /// Optional preset of standard variables (such as `HOME` and `LANG`)
+    /// to provide to every one of the process's commands, based on the
+    /// user that runs the command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env_preset: Option<EnvPreset>,
+
+    /// Optional time zone (a name from the tz database, such as
+    /// `America/New_York`) that is provided to every one of the
+    /// process's commands as `TZ`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+
+    /// TCP ports that the process binds. No two processes can declare
+    /// the same port (other than a daemon and its standby process), and
+    /// `ready` and `health-check` probes without a probe of their own
+    /// check that the first port accepts connections.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<u16>,
+
+    /// Optional service to register with the local Consul agent once
+    /// the process has started (and deregister when the process is
+    /// stopped). Requires the `consul` feature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<ServiceConfig>,
+
+    /// Optional hold on the startup of the process: startup pauses
+    /// before this process until the hold is released by an external
+    /// approval.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hold: Option<HoldConfig>,
+
+    /// Optional condition to wait for before the process is started
+    /// (and thus before any of the processes that depend on it are
+    /// started).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wait_for: Option<WaitForConfig>,
+
+    /// Optional readiness probe for the daemon: once the daemon has been
+    /// started, the processes that are started after this process wait
+    /// for the probe to succeed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ready: Option<ReadyConfig>,
+
+    /// When the daemon is restarted after it exits, instead of shutting
+    /// down Ground Control: never (unless its exit code is listed in
+    /// `restart_on_exit_codes` or not listed in
+    /// `restart_except_exit_codes`), after every failure, or always
+    /// (even after a clean exit).
+    #[serde(default, skip_serializing_if = "is_default_restart_policy")]
+    pub restart: RestartPolicy,
+
+    /// Exit codes that cause the daemon to be restarted, instead of
+    /// shutting down Ground Control. Daemons that are killed by a signal
+    /// use the shell convention of 128 plus the signal number.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart_on_exit_codes: Option<Vec<i32>>,
+
+    /// Inverse of `restart_on_exit_codes`: the daemon is restarted after
+    /// every failure *except* these exit codes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart_except_exit_codes: Option<Vec<i32>>,
+
+    /// Optional core dump handling for the daemon, which enables core
+    /// dumps and runs a hook when the daemon is killed by a signal.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core_dump: Option<CoreDumpConfig>,
+
+    /// Optional throttling of restarts while the system is under memory
+    /// pressure (which prevents OOM-kill/restart feedback loops).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart_throttle: Option<RestartThrottleConfig>,
+
+    /// Optional exponential backoff between restarts of the daemon
+    /// (instead of the fixed one-second delay).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart_backoff: Option<RestartBackoffConfig>,
+
+    /// Optional limit on the number of restarts of the daemon within a
+    /// window of time, after which a failure of the daemon shuts down
+    /// Ground Control (as if the daemon had no restart policy).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart_limit: Option<RestartLimitConfig>,
+
+    /// Number of times to retry starting the `run` command if it fails
+    /// with a transient error (`ETXTBSY` or `EAGAIN`). Defaults to 3.
+    #[serde(
+        default = "default_spawn_retries",
+        skip_serializing_if = "is_default_spawn_retries"
+    )]
+    pub spawn_retries: u32,
+
+    /// Optional watchdog timeout: the daemon must touch the watchdog
+    /// file (or send a datagram to the watchdog socket) in its runtime
+    /// directory at least this often, otherwise it is killed (and then
+    /// restarted or shut down, as with any other failure).
+    #[serde(
+        default,
+        deserialize_with = "deserialize_optional_duration",
+        serialize_with = "serialize_optional_duration",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub watchdog: Option<Duration>,
+
+    /// Optional maximum runtime of the daemon, after which the daemon is
+    /// stopped (as it would be at shutdown, but without running the
+    /// `post` command) and then restarted if its `restart` policy is
+    /// `on-failure` or `always`, or shuts down Ground Control otherwise.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_optional_duration",
+        serialize_with = "serialize_optional_duration",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_runtime: Option<Duration>,
+
+    /// Optional cron schedule (`minute hour day-of-month month
+    /// day-of-week`, in UTC) on which the daemon is proactively
+    /// restarted: its service is deregistered, the daemon is stopped (as
+    /// it would be at shutdown, but without running the `post` command)
+    /// and restarted, and then its service is registered again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart_schedule: Option<String>,
+
+    /// Optional periodic health check of the running daemon, which
+    /// restarts the daemon (or shuts down Ground Control) once too many
+    /// consecutive checks have failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_check: Option<HealthCheckConfig>,
+}
+
+fn default_spawn_retries() -> u32 {
+    3
+}
+
+fn is_default_spawn_retries(spawn_retries: &u32) -> bool {
+    *spawn_retries == default_spawn_retries()
+}
+
+fn is_default_stop(stop: &StopMechanism) -> bool {
+    *stop == StopMechanism::default()
+}
+
+fn is_default_on_stop_failure(on_stop_failure: &OnStopFailure) -> bool {
+    *on_stop_failure == OnStopFailure::default()
+}
+
+fn is_default_shutdown_class(shutdown_class: &ShutdownClass) -> bool {
+    *shutdown_class == ShutdownClass::default()
+}
+
+fn is_default_restart_policy(restart: &RestartPolicy) -> bool {
+    *restart == RestartPolicy::default()
+}
+
+impl ProcessConfig {
+    /// Returns the path of the process's runtime directory, if any.
+    pub fn runtime_dir_path(&self) -> Option<PathBuf> {
+        match &self.runtime_dir {
+            Some(RuntimeDirConfig::Enabled(true)) => Some(Path::new("/run").join(&self.name)),
+            Some(RuntimeDirConfig::Enabled(false)) | None => None,
+            Some(RuntimeDirConfig::Path(path)) => Some(path.clone()),
+        }
+    }
+
+    /// Returns every command of the process, along with the phase in
+    /// which the command runs (`pre`, `run`, `stop`, `post`, or
+    /// `on-crash`). The `stop` command is only included for daemons.
+    pub fn commands(&self) -> Vec<(&'static str, &CommandConfig)> {
+        let stop = match &self.stop {
+            StopMechanism::Command(stop) if self.run.is_some() => Some(stop),
+            StopMechanism::Command(_) | StopMechanism::Signal(_) | StopMechanism::Http(_) => None,
+        };
+        let on_crash = self
+            .core_dump
+            .as_ref()
+            .and_then(|core_dump| core_dump.on_crash.as_ref());
+
+        [
+            ("pre", self.pre.as_ref()),
+            ("run", self.run.as_ref()),
+            ("post-start", self.post_start.as_ref()),
+            ("pre-stop", self.pre_stop.as_ref()),
+            ("stop", stop),
+            ("post", self.post.as_ref()),
+            ("on-crash", on_crash),
+            (
+                "ready",
+                self.ready.as_ref().and_then(|ready| ready.exec.as_ref()),
+            ),
+            (
+                "health-check",
+                self.health_check
+                    .as_ref()
+                    .and_then(|health_check| health_check.exec.as_ref()),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(phase, command)| command.map(|command| (phase, command)))
+        .collect()
+    }
+
+    /// Returns true if the daemon should be restarted after failing
+    /// with the given exit code (128 plus the signal number for daemons
+    /// that were killed by a signal).
+    pub fn restarts_after(&self, exit_code: i32) -> bool {
+        match (&self.restart_on_exit_codes, &self.restart_except_exit_codes) {
+            (Some(codes), _) => codes.contains(&exit_code),
+            (None, Some(codes)) => !codes.contains(&exit_code),
+            (None, None) => self.restart != RestartPolicy::Never,
+        }
+    }
+
+    /// Returns true if the daemon should be restarted after exiting
+    /// cleanly (with exit code 0).
+    pub fn restarts_after_clean_exit(&self) -> bool {
+        self.restart == RestartPolicy::Always
+    }
+
+    /// Returns true if the daemon should be restarted after it was
+    /// stopped for exceeding its `max-runtime`.
+    pub fn restarts_after_max_runtime(&self) -> bool {
+        self.restart != RestartPolicy::Never
+    }
+
+    /// Returns true if this process must be started after the given
+    /// process: this process lists the other process (or a barrier that
+    /// the other process joins) as one of its dependencies (`depends-on`
+    /// or `wants`) or in `after`, or is bound to the other process, or the
+    /// other process lists this process in `before`.
+    pub(crate) fn starts_after(&self, other: &ProcessConfig) -> bool {
+        [&self.depends_on, &self.wants, &self.after]
+            .into_iter()
+            .any(|names| other.is_referenced_by(names))
+            || other.before.contains(&self.name)
+            || self.bound_to.as_ref() == Some(&other.name)
+    }
+
+    /// Returns true if the list of names references this process, either
+    /// by its name or by the barrier that it joins.
+    fn is_referenced_by(&self, names: &[String]) -> bool {
+        names
+            .iter()
+            .any(|name| *name == self.name || self.joins.as_ref() == Some(name))
+    }
+}
+
+/// Filesystem setup step.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(tag = "action", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum SetupConfig {
+    /// Creates a directory (and any missing parent directories).
+    Directory {
+        /// Path of the directory.
+        path: PathBuf,
+
+        /// Ownership and permissions of the directory.
+        #[serde(flatten)]
+        attributes: FileAttributes,
+    },
+
+    /// Changes the owner and/or group of a file or directory.
+    Chown {
+        /// Path of the file or directory.
+        path: PathBuf,
+
+        /// Name of the new owner.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        user: Option<String>,
+
+        /// Name of the new group.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        group: Option<String>,
+    },
+
+    /// Changes the permissions of a file or directory.
+    Chmod {
+        /// Path of the file or directory.
+        path: PathBuf,
+
+        /// New permissions (an octal string, such as `"0640"`).
+        #[serde(
+            deserialize_with = "deserialize_mode",
+            serialize_with = "serialize_mode"
+        )]
+        mode: u32,
+    },
+
+    /// Creates (or replaces) a symbolic link.
+    Symlink {
+        /// Path of the symbolic link.
+        path: PathBuf,
+
+        /// Path that the symbolic link points to.
+        target: PathBuf,
+    },
+
+    /// Copies a file.
+    Copy {
+        /// Path of the file to copy.
+        source: PathBuf,
+
+        /// Path of the new file.
+        path: PathBuf,
+
+        /// Ownership and permissions of the new file.
+        #[serde(flatten)]
+        attributes: FileAttributes,
+    },
+
+    /// Writes a file, performing environment variable expansion on the
+    /// contents.
+    Write {
+        /// Path of the file.
+        path: PathBuf,
+
+        /// Contents of the file (which can use `{{VAR}}` templates).
+        contents: String,
+
+        /// Ownership and permissions of the file.
+        #[serde(flatten)]
+        attributes: FileAttributes,
+    },
+
+    /// Renders a template file, performing environment variable
+    /// expansion on the contents of the template.
+    Render {
+        /// Path of the template file.
+        template: PathBuf,
+
+        /// Path of the rendered file.
+        path: PathBuf,
+
+        /// Ownership and permissions of the rendered file.
+        #[serde(flatten)]
+        attributes: FileAttributes,
+    },
+}
+
+/// Optional ownership and permissions of a file or directory created by
+/// a setup step.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FileAttributes {
+    /// Name of the owner (defaults to the user running Ground Control).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+
+    /// Name of the group (defaults to the primary group of the owner).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+
+    /// Permissions (an octal string, such as `"0750"`).
+    #[serde(
+        default,
+        deserialize_with = "deserialize_optional_mode",
+        serialize_with = "serialize_optional_mode",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub mode: Option<u32>,
+}
+
+fn deserialize_mode<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mode = String::deserialize(deserializer)?;
+    u32::from_str_radix(&mode, 8).map_err(|_| {
+        de::Error::custom(format!(
+            "invalid file mode \"{mode}\" (expected an octal string such as \"0750\")"
+        ))
+    })
+}
+
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let duration = String::deserialize(deserializer)?;
+    humantime::parse_duration(&duration).map_err(|_| {
+        de::Error::custom(format!(
+            "invalid duration \"{duration}\" (expected a duration such as \"30s\" or \"5m\")"
+        ))
+    })
+}
+
+fn deserialize_optional_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_duration(deserializer).map(Some)
+}
+
+fn deserialize_optional_mode<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_mode(deserializer).map(Some)
+}
+
+fn serialize_mode<S>(mode: &u32, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&format!("{mode:04o}"))
+}
+
+fn serialize_optional_mode<S>(mode: &Option<u32>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match mode {
+        Some(mode) => serialize_mode(mode, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn serialize_duration<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&humantime::format_duration(*duration).to_string())
+}
+
+fn serialize_optional_duration<S>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match duration {
+        Some(duration) => serialize_duration(duration, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Runtime directory configuration: either `true` (which creates
+/// `/run/<name>`) or the path to the directory.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum RuntimeDirConfig {
+    /// Create (or do not create) the default runtime directory.
+    Enabled(bool),
+
+    /// Create the runtime directory at the given path.
+    Path(PathBuf),
+}
+
+/// Condition to wait for before starting a process.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct WaitForConfig {
+    /// Hostname that must resolve to at least one address.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns: Option<String>,
+
+    /// URL of a service (`postgres://`, `mysql://`, or `redis://`) that
+    /// must accept a protocol-level connection.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect: Option<String>,
+
+    /// Path that must be a mount point (for example, a persistent volume
+    /// that is attached after the container starts).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mount: Option<PathBuf>,
+
+    /// How long to wait for the condition before giving up (and
+    /// failing to start the process). Defaults to 30 seconds.
+    #[serde(
+        default = "default_wait_for_timeout",
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub timeout: Duration,
+}
+
+fn default_wait_for_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+/// Hold on the startup of a process, which is released when the release
+/// file appears, when a `release.<name>` file is dropped into the control
+/// directory, or through the `Supervisor`.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct HoldConfig {
+    /// Path of the file whose appearance releases the hold.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+
+    /// How long to wait for the hold to be released before giving up
+    /// (and aborting startup). Waits forever if not provided.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_optional_duration",
+        serialize_with = "serialize_optional_duration",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub timeout: Option<Duration>,
+}
+
+/// Periodic health check of a running daemon.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct HealthCheckConfig {
+    /// Address (`host:port`, or `[address]:port` for an IPv6 address)
+    /// that must accept TCP connections.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tcp: Option<String>,
+
+    /// `http://` URL that must respond to a `GET` request with a `2xx`
+    /// status.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<String>,
+
+    /// Command that must exit successfully (it is run with the same
+    /// environment as the process's other commands).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exec: Option<CommandConfig>,
+
+    /// Time between checks (the first check is made one interval after
+    /// the daemon starts). Defaults to 10 seconds.
+    #[serde(
+        default = "default_health_check_interval",
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub interval: Duration,
+
+    /// How long a single check may take before it counts as a failure.
+    /// Defaults to 5 seconds.
+    #[serde(
+        default = "default_health_check_timeout",
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub timeout: Duration,
+
+    /// Number of consecutive failed checks after which the daemon is
+    /// considered to be unhealthy. Defaults to 3.
+    #[serde(default = "default_health_check_failure_threshold")]
+    pub failure_threshold: u32,
+
+    /// Number of consecutive passed checks after which an unhealthy
+    /// daemon is considered to be healthy again (only used with
+    /// `on-failure = "report"`). Defaults to 1.
+    #[serde(default = "default_health_check_success_threshold")]
+    pub success_threshold: u32,
+
+    /// What to do once the daemon is unhealthy (defaults to `restart`).
+    #[serde(default)]
+    pub on_failure: OnHealthCheckFailure,
+}
+
+fn default_health_check_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_health_check_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_health_check_failure_threshold() -> u32 {
+    3
+}
+
+fn default_health_check_success_threshold() -> u32 {
+    1
+}
+
+/// Action taken when a daemon fails its health check.
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnHealthCheckFailure {
+    /// Stop the daemon (as it would be at shutdown, but without running
+    /// the `post` command) and restart it.
+    Restart,
+
+    /// Stop the daemon and shut down Ground Control, as if the daemon
+    /// had failed (or start the daemon's standby process, if it has
+    /// one).
+    Shutdown,
+
+    /// Leave the daemon running, but report it as unhealthy (in the
+    /// API's `/healthz` and `/status`) until it is healthy again.
+    Report,
+}
+
+impl Default for OnHealthCheckFailure {
+    fn default() -> Self {
+        OnHealthCheckFailure::Restart
+    }
+}
+
+/// Probe that must succeed before a daemon is considered to be ready.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ReadyConfig {
+    /// Address (`host:port`, or `[address]:port` for an IPv6 address)
+    /// that must accept TCP connections.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tcp: Option<String>,
+
+    /// `http://` URL that must respond to a `GET` request with a `2xx`
+    /// status.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<String>,
+
+    /// Command that must exit successfully (it is run with the same
+    /// environment as the process's other commands).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exec: Option<CommandConfig>,
+
+    /// Path of a file that must exist (for example, a file that the
+    /// daemon creates once it has finished starting).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+
+    /// How long to wait for the probe to succeed before giving up (and
+    /// failing to start the process). Defaults to 30 seconds.
+    #[serde(
+        default = "default_wait_for_timeout",
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub timeout: Duration,
+}
+
+/// Service registration for a process.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ServiceConfig {
+    /// Name of the service; defaults to the name of the process.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Port on which the service is listening.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+
+    /// Tags to attach to the service.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+
+    /// Optional HTTP endpoint that the Consul agent uses to check the
+    /// health of the service.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<String>,
+}
+
+/// Core dump handling for a daemon process.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CoreDumpConfig {
+    /// Maximum size (in bytes) of the daemon's core dumps (`RLIMIT_CORE`);
+    /// defaults to the hard limit, which is usually unlimited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+
+    /// Directory into which the kernel writes core dumps (which must
+    /// match the system's `core_pattern`); the newest core dump in the
+    /// directory is provided to the `on-crash` command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+
+    /// Command to run after the daemon is killed by a signal (before the
+    /// daemon's failure is handled), usually to archive the core dump
+    /// and the daemon's binary.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_crash: Option<CommandConfig>,
+}
+
+/// Delays the restart of a failed daemon while the system is under
+/// memory pressure.
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RestartThrottleConfig {
+    /// Maximum memory pressure (the percentage of time that at least one
+    /// task was stalled on memory over the last 10 seconds, from
+    /// `/proc/pressure/memory`) at which the daemon is restarted.
+    /// Defaults to 10%.
+    #[serde(default = "default_max_memory_pressure")]
+    pub max_pressure: f64,
+
+    /// Minimum percentage of memory that must be available (from
+    /// `/proc/meminfo`) for the daemon to be restarted. Defaults to 5%.
+    #[serde(default = "default_min_memory_available")]
+    pub min_available: f64,
+
+    /// Longest amount of time to delay the restart, after which the
+    /// daemon is restarted regardless of memory pressure. Defaults to 5
+    /// minutes.
+    #[serde(
+        default = "default_max_restart_delay",
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub max_delay: Duration,
+}
+
+fn default_max_memory_pressure() -> f64 {
+    10.0
+}
+
+fn default_min_memory_available() -> f64 {
+    5.0
+}
+
+fn default_max_restart_delay() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+/// Exponential backoff between restarts of a failed daemon: the delay
+/// before each restart doubles, up to a maximum.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RestartBackoffConfig {
+    /// Delay before the first restart. Defaults to 1 second.
+    #[serde(
+        default = "default_initial_restart_backoff",
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub initial: Duration,
+
+    /// Longest delay before a restart. A daemon that runs for at least
+    /// this long before failing again is restarted after the `initial`
+    /// delay. Defaults to 1 minute.
+    #[serde(
+        default = "default_max_restart_backoff",
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub max: Duration,
+}
+
+fn default_initial_restart_backoff() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_max_restart_backoff() -> Duration {
+    Duration::from_secs(60)
+}
+
+/// Limit on the number of times that a failed daemon is restarted within
+/// a window of time.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RestartLimitConfig {
+    /// Maximum number of restarts within the window.
+    pub restarts: u32,
+
+    /// Length of the window. Defaults to 1 minute.
+    #[serde(
+        default = "default_restart_limit_window",
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub window: Duration,
+}
+
+fn default_restart_limit_window() -> Duration {
+    Duration::from_secs(60)
+}
+
+/// Source of a process's standard input.
+#[derive(Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct StdinConfig {
+    /// File whose contents are provided as the standard input.
+    pub file: PathBuf,
+}
+
+/// Mechanism used to stop a daemon process.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum StopMechanism {
+    /// Stop the process using a signal.
+    Signal(SignalConfig),
+
+    /// Stop the process by running a command.
+    Command(CommandConfig),
+
+    /// Ask the process to quiesce with an HTTP request, falling back to
+    /// a signal.
+    Http(HttpStopConfig),
+}
+
+/// HTTP request (to an admin endpoint) that asks a daemon to drain its
+/// work and exit.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct HttpStopConfig {
+    /// `http://` URL to which a `POST` request is sent.
+    pub http: String,
+
+    /// Signal that is sent to the daemon if the request fails, or if
+    /// the daemon has not exited once the `timeout` expires (defaults to
+    /// `SIGTERM`).
+    #[serde(default = "default_then_signal")]
+    pub then_signal: SignalConfig,
+
+    /// How long to wait for the daemon to exit after a successful
+    /// request. Defaults to 10 seconds.
+    #[serde(
+        default = "default_http_stop_timeout",
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration"
+    )]
+    pub timeout: Duration,
+}
+
+fn default_then_signal() -> SignalConfig {
+    SignalConfig::SIGTERM
+}
+
+fn default_http_stop_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+impl Default for StopMechanism {
+    fn default() -> Self {
+        StopMechanism::Signal(SignalConfig::SIGTERM)
+    }
+}
+
+impl<'de> Deserialize<'de> for StopMechanism {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(StopMechanismVisitor)
+    }
+}
+
+impl Serialize for StopMechanism {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            StopMechanism::Signal(signal) => signal.serialize(serializer),
+            StopMechanism::Command(command) => command.serialize(serializer),
+            StopMechanism::Http(http) => http.serialize(serializer),
+        }
+    }
+}
+
+struct StopMechanismVisitor;
+
+impl<'de> Visitor<'de> for StopMechanismVisitor {
+    type Value = StopMechanism;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(
+            "a signal name (SIGINT, SIGQUIT, or SIGTERM), a command, or an `http` request",
+        )
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        // Anything that looks like a signal name is treated as a signal
+        // (so that typos and unsupported signals are reported, instead
+        // of being executed as a command).
+        if value.starts_with("SIG") && !value.contains(' ') {
+            return SignalConfig::from_name(value)
+                .map(StopMechanism::Signal)
+                .ok_or_else(|| {
+                    E::custom(format!(
+                        "unsupported stop signal \"{value}\", expected one of SIGINT, SIGQUIT, or SIGTERM"
+                    ))
+                });
+        }
+
+        CommandLineConfigVisitor
+            .visit_str(value)
+            .map(|config| StopMechanism::Command(config.into()))
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        CommandLineConfigVisitor
+            .visit_seq(seq)
+            .map(|config| StopMechanism::Command(config.into()))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        // Both HTTP requests and detailed commands are tables, which are
+        // told apart by the `http` key.
+        let mut table = toml::value::Table::new();
+        while let Some((key, value)) = map.next_entry::<String, toml::Value>()? {
+            table.insert(key, value);
+        }
+
+        if table.contains_key("http") {
+            HttpStopConfig::deserialize(toml::Value::Table(table))
+                .map(StopMechanism::Http)
+                .map_err(de::Error::custom)
+        } else {
+            CommandLineConfig::deserialize(toml::Value::Table(table))
+                .map(|config| StopMechanism::Command(config.into()))
+                .map_err(de::Error::custom)
+        }
+    }
+}
+
+/// Preset of standard variables provided to a process's commands (in
+/// addition to the variables that pass through `only-env`).
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EnvPreset {
+    /// `HOME`, `USER`, and `LOGNAME` of the user that runs the command.
+    Minimal,
+
+    /// `minimal`, plus `LANG` (Ground Control's value, or `C.UTF-8`).
+    Locale,
+
+    /// `locale`, plus `TMPDIR` (Ground Control's value, or `/tmp`) and
+    /// the `SHELL` of the user that runs the command.
+    Full,
+}
+
+/// Action taken when a daemon's `stop` command/signal fails.
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnStopFailure {
+    /// Log the failure, then run the `post` command and continue
+    /// stopping the other processes (the daemon is probably still
+    /// running).
+    Continue,
+
+    /// Kill every process in the daemon's process group with `SIGKILL`,
+    /// then wait for the daemon to exit and continue as usual.
+    KillGroup,
+
+    /// Stop immediately without running the `post` command or stopping
+    /// any of the remaining processes, and return an error.
+    Abort,
+}
+
+impl Default for OnStopFailure {
+    fn default() -> Self {
+        OnStopFailure::Continue
+    }
+}
+
+/// How much of the `shutdown-timeout` a process can use to stop, which
+/// preserves the time needed to stop the critical (stateful) processes.
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ShutdownClass {
+    /// The daemon can use the whole `shutdown-timeout` to stop.
+    Critical,
+
+    /// The daemon can use the `shutdown-timeout` to stop, except for the
+    /// last half, which is kept for the critical processes that have not
+    /// yet been stopped (if any); the daemon is killed if it has not
+    /// stopped by then.
+    Normal,
+
+    /// The daemon is killed if it has not stopped within a second (or as
+    /// a `normal` process, if that is sooner).
+    BestEffort,
+}
+
+impl Default for ShutdownClass {
+    fn default() -> Self {
+        ShutdownClass::Normal
+    }
+}
+
+/// When a daemon is restarted after it exits.
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// Never restart the daemon (unless `restart-on-exit-codes` or
+    /// `restart-except-exit-codes` asks for a restart); its exit shuts
+    /// down Ground Control.
+    Never,
+
+    /// Restart the daemon after every failure (a non-zero exit code, or
+    /// being killed by a signal); a clean exit shuts down Ground Control.
+    OnFailure,
+
+    /// Restart the daemon whenever it exits, even after a clean exit.
+    Always,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy::Never
+    }
+}
+
+/// Signals used to stop a daemon process.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+pub enum SignalConfig {
+    /// SIGINT
+    SIGINT,
+
+    /// SIGQUIT
+    SIGQUIT,
+
+    /// SIGTERM
+    SIGTERM,
+}
+
+impl SignalConfig {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "SIGINT" => Some(Self::SIGINT),
+            "SIGQUIT" => Some(Self::SIGQUIT),
+            "SIGTERM" => Some(Self::SIGTERM),
+            _ => None,
+        }
+    }
+}
+
+impl From<SignalConfig> for nix::sys::signal::Signal {
+    fn from(signal: SignalConfig) -> Self {
+        match signal {
+            SignalConfig::SIGINT => Self::SIGINT,
+            SignalConfig::SIGQUIT => Self::SIGQUIT,
+            SignalConfig::SIGTERM => Self::SIGTERM,
+        }
+    }
+}
+
+impl From<&SignalConfig> for nix::sys::signal::Signal {
+    fn from(signal: &SignalConfig) -> Self {
+        match signal {
+            SignalConfig::SIGINT => Self::SIGINT,
+            SignalConfig::SIGQUIT => Self::SIGQUIT,
+            SignalConfig::SIGTERM => Self::SIGTERM,
+        }
+    }
+}
+
+/// Configuration for a command, its arguments, and any execution
+/// properties (such as the user under which to run the command, or the
+/// environment variables to pass through to the command).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(from = "CommandLineConfig")]
+pub struct CommandConfig {
+    /// User to run this command as, otherwise run the command as the
+    /// user that executed Ground Control (most likely `root`).
+    pub user: Option<String>,
+
+    /// If present, then only the given list of environment variables
+    /// will be passed through to the command (all other variables will
+    /// be removed from the command's environment). Note that `PATH` is
+    /// always allowed. All environment variables will be allowed if
+    /// this value is `None`. If provided, but empty, then no variables
+    /// other than `PATH` will be allowed.
+    pub only_env: Option<HashSet<String>>,
+
+    /// Run the command through the login shell of the user (sourcing
+    /// the user's profile, as with `su -l`), instead of executing the
+    /// program directly.
+    pub login_shell: bool,
+
+    /// Program to execute (empty if this is an inline `script`).
+    pub program: String,
+
+    /// Arguments to pass to the program.
+    pub args: Vec<String>,
+
+    /// Expected SHA-256 hash of the program (as lowercase hex); the
+    /// command is not started if the program's hash does not match.
+    pub sha256: Option<String>,
+
+    /// Inline script (including its `#!` interpreter line) to run
+    /// instead of a program; the script is written to a temporary file
+    /// for as long as the command is running.
+    pub script: Option<String>,
+}
+
+/// Serializes the command as an array (or as a detailed table, if any of
+/// the execution properties are set), since the string form cannot
+/// represent arguments that contain spaces.
+impl Serialize for CommandConfig {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let command: Vec<&str> = std::iter::once(self.program.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .collect();
+        if self.user.is_none()
+            && self.only_env.is_none()
+            && !self.login_shell
+            && self.sha256.is_none()
+            && self.script.is_none()
+        {
+            return command.serialize(serializer);
+        }
+
+        let mut map = serializer.serialize_map(None)?;
+        if let Some(user) = &self.user {
+            map.serialize_entry("user", user)?;
+        }
+        if let Some(only_env) = &self.only_env {
+            map.serialize_entry("only-env", &only_env.iter().collect::<BTreeSet<_>>())?;
+        }
+        if self.login_shell {
+            map.serialize_entry("login-shell", &self.login_shell)?;
+        }
+        match &self.script {
+            Some(script) => map.serialize_entry("script", script)?,
+            None => map.serialize_entry("command", &command)?,
+        }
+        if let Some(sha256) = &self.sha256 {
+            map.serialize_entry("sha256", sha256)?;
+        }
+        map.end()
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum CommandLineConfig {
+    Simple(CommandLine),
+
+    Detailed(DetailedCommandLine),
+}
+
+impl From<CommandLineConfig> for CommandConfig {
+    fn from(config: CommandLineConfig) -> Self {
+        match config {
+            CommandLineConfig::Simple(config) => {
+                let (program, args) = config.program_and_args();
+                Self {
+                    user: None,
+                    only_env: None,
+                    login_shell: false,
+                    program,
+                    args,
+                    sha256: None,
+                    script: None,
+                }
+            }
+            CommandLineConfig::Detailed(config) => {
+                let (program, args, script) = match config.command {
+                    DetailedCommand::Command(command) => {
+                        let (program, args) = command.program_and_args();
+                        (program, args, None)
+                    }
+                    DetailedCommand::Script(script) => (String::new(), Vec::new(), Some(script)),
+                };
+                Self {
+                    user: config.user,
+                    only_env: config.only_env,
+                    login_shell: config.login_shell,
+                    program,
+                    args,
+                    sha256: config.sha256,
+                    script,
+                }
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CommandLineConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(CommandLineConfigVisitor)
+    }
+}
+
+/// Deserializes the three command formats (string, array, and detailed
+/// table) by hand, so that errors describe what was wrong with the
+/// command instead of "data did not match any variant".
+struct CommandLineConfigVisitor;
+
+const DETAILED_COMMAND_FIELDS: &[&str] = &[
+    "user",
+    "only-env",
+    "login-shell",
+    "command",
+    "script",
+    "sha256",
+];
+
+impl<'de> Visitor<'de> for CommandLineConfigVisitor {
+    type Value = CommandLineConfig;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a command string, an array of strings, or a detailed command table")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        CommandLineVisitor
+            .visit_str(value)
+            .map(CommandLineConfig::Simple)
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        CommandLineVisitor
+            .visit_seq(seq)
+            .map(CommandLineConfig::Simple)
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut user = None;
+        let mut only_env = None;
+        let mut login_shell = None;
+        let mut command = None;
+        let mut script: Option<String> = None;
+        let mut sha256: Option<String> = None;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "user" => {
+                    if user.is_some() {
+                        return Err(de::Error::duplicate_field("user"));
+                    }
+                    user = Some(map.next_value()?);
+                }
+                "only-env" => {
+                    if only_env.is_some() {
+                        return Err(de::Error::duplicate_field("only-env"));
+                    }
+                    only_env = Some(map.next_value()?);
+                }
+                "login-shell" => {
+                    if login_shell.is_some() {
+                        return Err(de::Error::duplicate_field("login-shell"));
+                    }
+                    login_shell = Some(map.next_value()?);
+                }
+                "command" => {
+                    if command.is_some() {
+                        return Err(de::Error::duplicate_field("command"));
+                    }
+                    command = Some(map.next_value()?);
+                }
+                "script" => {
+                    if script.is_some() {
+                        return Err(de::Error::duplicate_field("script"));
+                    }
+                    let value: String = map.next_value()?;
+                    if !value.trim_start().starts_with("#!") {
+                        return Err(de::Error::custom(
+                            "`script` must start with an interpreter line (for example, `#!/bin/sh`)",
+                        ));
+                    }
+                    script = Some(value);
+                }
+                "sha256" => {
+                    if sha256.is_some() {
+                        return Err(de::Error::duplicate_field("sha256"));
+                    }
+                    let hash: String = map.next_value()?;
+                    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                        return Err(de::Error::custom(format!(
+                            "`sha256` must be 64 hexadecimal digits, not \"{hash}\""
+                        )));
+                    }
+                    sha256 = Some(hash.to_ascii_lowercase());
+                }
+                _ => return Err(de::Error::unknown_field(&key, DETAILED_COMMAND_FIELDS)),
+            }
+        }
+
+        let command = match (command, script) {
+            (Some(command), None) => DetailedCommand::Command(command),
+            (None, Some(script)) => {
+                if sha256.is_some() {
+                    return Err(de::Error::custom(
+                        "`sha256` cannot be used with an inline `script`",
+                    ));
+                }
+                DetailedCommand::Script(script)
+            }
+            (Some(_), Some(_)) => {
+                return Err(de::Error::custom(
+                    "detailed command cannot have both a `command` and a `script`",
+                ))
+            }
+            (None, None) => {
+                return Err(de::Error::custom(
+                    "detailed command is missing required `command` (or `script`) field",
+                ))
+            }
+        };
+
+        Ok(CommandLineConfig::Detailed(DetailedCommandLine {
+            user,
+            only_env,
+            login_shell: login_shell.unwrap_or_default(),
+            command,
+            sha256,
+        }))
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum CommandLine {
+    CommandString(String),
+
+    CommandVector(Vec<String>),
+}
+
+impl<'de> Deserialize<'de> for CommandLine {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(CommandLineVisitor)
+    }
+}
+
+struct CommandLineVisitor;
+
+impl<'de> Visitor<'de> for CommandLineVisitor {
+    type Value = CommandLine;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a command string or an array of strings")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        if value.trim().is_empty() {
+            return Err(E::custom("command must not be empty"));
+        }
+
+        Ok(CommandLine::CommandString(value.to_string()))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut elems = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(elem) = seq.next_element::<String>()? {
+            elems.push(elem);
+        }
+
+        if elems.is_empty() {
+            return Err(de::Error::custom(
+                "command array must contain at least the program to execute",
+            ));
+        }
+
+        Ok(CommandLine::CommandVector(elems))
+    }
+}
+
+impl CommandLine {
+    /// Parse the Command Line into the program to execute, and the
+    /// arguments to that program.
+    fn program_and_args(&self) -> (String, Vec<String>) {
+        match self {
+            CommandLine::CommandString(line) => {
+                // TODO: This won't handle quoted arguments with spaces
+                // (for example), so really we should parse this using a
+                // more correct, shell-like parser. OTOH, we could just
+                // say that anything complicated needs to use the vector
+                // format...
+                let mut elems = line.split(' ');
+
+                let program = elems
+                    .next()
+                    .expect("Command line must not be empty")
+                    .to_string();
+                let args = elems.map(|s| s.to_string()).collect();
+
+                (program, args)
+            }
+
+            CommandLine::CommandVector(v) => {
+                let program = v[0].to_string();
+                let args = v[1..].to_vec();
+
+                (program, args)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct DetailedCommandLine {
+    user: Option<String>,
+
+    only_env: Option<HashSet<String>>,
+
+    login_shell: bool,
+
+    command: DetailedCommand,
+
+    sha256: Option<String>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum DetailedCommand {
+    Command(CommandLine),
+
+    Script(String),
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use indoc::indoc;
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct StopMechanismTest {
+        stop: StopMechanism,
+    }
+
+    #[test]
+    fn supports_signal_names_in_stop() {
+        let toml = r#"stop = "SIGTERM""#;
+        let decoded: StopMechanismTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(StopMechanism::Signal(SignalConfig::SIGTERM), decoded.stop);
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct CommandConfigTest {
+        run: CommandConfig,
+    }
+
+    #[test]
+    fn supports_whitespace_separated_command_lines() {
         let toml = r#"run = "/app/run-me.sh using these args""#;
         let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
         assert_eq!(
-            CommandConfig {
-                user: None,
-                only_env: None,
-                program: String::from("/app/run-me.sh"),
-                args: vec![
-                    String::from("using"),
-                    String::from("these"),
-                    String::from("args"),
-                ]
-            },
-            decoded.run
+            CommandConfig {
+                user: None,
+                only_env: None,
+                login_shell: false,
+                program: String::from("/app/run-me.sh"),
+                args: vec![
+                    String::from("using"),
+                    String::from("these"),
+                    String::from("args"),
+                ],
+                sha256: None,
+                script: None,
+            },
+            decoded.run
+        );
+    }
+
+    #[test]
+    fn supports_command_vectors() {
+        let toml = r#"run = ["/app/run-me.sh", "using", "these", "args"]"#;
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            CommandConfig {
+                user: None,
+                only_env: None,
+                login_shell: false,
+                program: String::from("/app/run-me.sh"),
+                args: vec![
+                    String::from("using"),
+                    String::from("these"),
+                    String::from("args"),
+                ],
+                sha256: None,
+                script: None,
+            },
+            decoded.run
+        );
+    }
+
+    #[test]
+    fn supports_detailed_whitespace_separated_command_lines() {
+        let toml = r#"run = { command = "/app/run-me.sh using these args" }"#;
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            CommandConfig {
+                user: None,
+                only_env: None,
+                login_shell: false,
+                program: String::from("/app/run-me.sh"),
+                args: vec![
+                    String::from("using"),
+                    String::from("these"),
+                    String::from("args"),
+                ],
+                sha256: None,
+                script: None,
+            },
+            decoded.run
+        );
+
+        let toml = r#"run = { user = "app", command = "/app/run-me.sh using these args" }"#;
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            CommandConfig {
+                user: Some(String::from("app")),
+                only_env: None,
+                login_shell: false,
+                program: String::from("/app/run-me.sh"),
+                args: vec![
+                    String::from("using"),
+                    String::from("these"),
+                    String::from("args"),
+                ],
+                sha256: None,
+                script: None,
+            },
+            decoded.run
+        );
+    }
+
+    #[test]
+    fn supports_detailed_command_vectors() {
+        let toml = r#"run = { command = ["/app/run-me.sh", "using", "these", "args"] }"#;
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            CommandConfig {
+                user: None,
+                only_env: None,
+                login_shell: false,
+                program: String::from("/app/run-me.sh"),
+                args: vec![
+                    String::from("using"),
+                    String::from("these"),
+                    String::from("args"),
+                ],
+                sha256: None,
+                script: None,
+            },
+            decoded.run
+        );
+
+        let toml = r#"run = { user = "app", only-env = [], command = ["/app/run-me.sh", "using", "these", "args"] }"#;
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            CommandConfig {
+                user: Some(String::from("app")),
+                only_env: Some(HashSet::new()),
+                login_shell: false,
+                program: String::from("/app/run-me.sh"),
+                args: vec![
+                    String::from("using"),
+                    String::from("these"),
+                    String::from("args"),
+                ],
+                sha256: None,
+                script: None,
+            },
+            decoded.run
+        );
+
+        let toml = r#"run = { user = "app", only-env = ["USER", "HOME"], command = ["/app/run-me.sh", "using", "these", "args"] }"#;
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            CommandConfig {
+                user: Some(String::from("app")),
+                only_env: Some(HashSet::from(["USER".into(), "HOME".into()])),
+                login_shell: false,
+                program: String::from("/app/run-me.sh"),
+                args: vec![
+                    String::from("using"),
+                    String::from("these"),
+                    String::from("args"),
+                ],
+                sha256: None,
+                script: None,
+            },
+            decoded.run
+        );
+    }
+
+    #[test]
+    fn requires_command_in_detailed_command() {
+        let toml = r#"run = { }"#;
+        let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
+        assert_eq!(
+            "detailed command is missing required `command` (or `script`) field for key `run` at line 1 column 7",
+            error.to_string(),
+        );
+
+        let toml = r#"run = { user = "app" }"#;
+        let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
+        assert_eq!(
+            "detailed command is missing required `command` (or `script`) field for key `run` at line 1 column 7",
+            error.to_string(),
+        );
+    }
+
+    #[test]
+    fn supports_login_shell_in_detailed_command() {
+        let toml = r#"run = { user = "app", login-shell = true, command = "/app/run-me.sh" }"#;
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            CommandConfig {
+                user: Some(String::from("app")),
+                only_env: None,
+                login_shell: true,
+                program: String::from("/app/run-me.sh"),
+                args: vec![],
+                sha256: None,
+                script: None,
+            },
+            decoded.run
+        );
+    }
+
+    #[test]
+    fn supports_sha256_in_detailed_command() {
+        let toml = r#"run = { sha256 = "CE6DEAE998540E350BAD276A776876550A950ACF9C8DFF0D190B829147CBC636", command = "/app/run-me.sh" }"#;
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            Some(String::from(
+                "ce6deae998540e350bad276a776876550a950acf9c8dff0d190b829147cbc636"
+            )),
+            decoded.run.sha256
+        );
+
+        let toml = r#"run = { sha256 = "abc123", command = "/app/run-me.sh" }"#;
+        let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
+        assert_eq!(
+            "`sha256` must be 64 hexadecimal digits, not \"abc123\" for key `run` at line 1 column 7",
+            error.to_string(),
+        );
+    }
+
+    #[test]
+    fn supports_script_in_detailed_command() {
+        let toml = indoc! {r#"
+            run = { user = "app", script = """
+            #!/bin/sh
+            exec /app/server
+            """ }
+            "#};
+        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            CommandConfig {
+                user: Some(String::from("app")),
+                only_env: None,
+                login_shell: false,
+                program: String::new(),
+                args: vec![],
+                sha256: None,
+                script: Some(String::from("#!/bin/sh\nexec /app/server\n")),
+            },
+            decoded.run
+        );
+
+        let toml = r#"run = { script = "echo hi" }"#;
+        let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
+        assert_eq!(
+            "`script` must start with an interpreter line (for example, `#!/bin/sh`) for key `run` at line 1 column 7",
+            error.to_string(),
+        );
+
+        let toml = r##"run = { script = "#!/bin/sh", command = "/app/server" }"##;
+        let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
+        assert_eq!(
+            "detailed command cannot have both a `command` and a `script` for key `run` at line 1 column 7",
+            error.to_string(),
+        );
+    }
+
+    #[test]
+    fn explains_invalid_commands() {
+        let toml = r#"run = { command = "app", group = "app" }"#;
+        let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
+        assert_eq!("unknown field `group`, expected one of `user`, `only-env`, `login-shell`, `command`, `script`, `sha256` for key `run` at line 1 column 7", error.to_string(),);
+
+        let toml = r#"run = [ ]"#;
+        let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
+        assert_eq!("command array must contain at least the program to execute for key `run` at line 1 column 1", error.to_string(),);
+
+        let toml = r#"run = """#;
+        let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
+        assert_eq!(
+            "command must not be empty for key `run` at line 1 column 7",
+            error.to_string(),
+        );
+
+        let toml = r#"run = 5"#;
+        let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
+        assert_eq!("invalid type: integer `5`, expected a command string, an array of strings, or a detailed command table for key `run` at line 1 column 7", error.to_string(),);
+    }
+
+    #[test]
+    fn supports_commands_in_stop() {
+        let toml = r#"stop = "/usr/sbin/nginx -s quit""#;
+        let decoded: StopMechanismTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            StopMechanism::Command(CommandConfig {
+                user: None,
+                only_env: None,
+                login_shell: false,
+                program: "/usr/sbin/nginx".into(),
+                args: vec!["-s".into(), "quit".into()],
+                sha256: None,
+                script: None,
+            }),
+            decoded.stop
+        );
+    }
+
+    #[test]
+    fn supports_http_requests_in_stop() {
+        let toml = r#"stop = { http = "http://127.0.0.1:9000/quitquitquit" }"#;
+        let decoded: StopMechanismTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            StopMechanism::Http(HttpStopConfig {
+                http: "http://127.0.0.1:9000/quitquitquit".into(),
+                then_signal: SignalConfig::SIGTERM,
+                timeout: Duration::from_secs(10),
+            }),
+            decoded.stop
+        );
+
+        let toml =
+            r#"stop = { http = "http://127.0.0.1:9000/quitquitquit", then-signal = "SIGKILL" }"#;
+        assert!(toml::from_str::<StopMechanismTest>(toml).is_err());
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "app"
+            run = "/app/server"
+            stop = { http = "https://127.0.0.1:9000/quitquitquit" }
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Invalid `stop` in process \"app\"",
+            config.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_stop_signals() {
+        let toml = r#"stop = "SIGKILL""#;
+        let error = toml::from_str::<StopMechanismTest>(toml).unwrap_err();
+        assert_eq!("unsupported stop signal \"SIGKILL\", expected one of SIGINT, SIGQUIT, or SIGTERM for key `stop` at line 1 column 8", error.to_string(),);
+    }
+
+    fn startup_names(toml: &str) -> eyre::Result<Vec<String>> {
+        let config: Config = toml::from_str(toml)?;
+        Ok(config
+            .startup_order()?
+            .into_iter()
+            .map(|process| process.name)
+            .collect())
+    }
+
+    #[test]
+    fn parse_errors_include_key_path_and_location() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "a"
+
+            [[processes]]
+            name = "b"
+            disabled = "yes"
+            "#};
+        assert_eq!(
+            "`processes[1].disabled` (line 6, column 12): invalid type: string \"yes\", expected a boolean",
+            Config::from_toml(toml).unwrap_err().to_string()
+        );
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "a"
+            bogus = true
+            "#};
+        assert_eq!(
+            "`processes[0].bogus` (line 3, column 1): unknown field `bogus`, expected one of `name`, `description`, `owner`, `disabled`, `main`, `standby-for`, `optional`, `depends-on`, `wants`, `after`, `before`, `joins`, `bound-to`, `pre`, `run`, `post-start`, `pre-stop`, `stop`, `on-stop-failure`, `pre-stop-delay`, `shutdown-class`, `post`, `success-marker`, `runtime-dir`, `stdin`, `fresh-env`, `env-preset`, `timezone`, `ports`, `service`, `hold`, `wait-for`, `ready`, `restart`, `restart-on-exit-codes`, `restart-except-exit-codes`, `core-dump`, `restart-throttle`, `restart-backoff`, `restart-limit`, `spawn-retries`, `watchdog`, `max-runtime`, `restart-schedule`, `health-check`",
+            Config::from_toml(toml).unwrap_err().to_string()
+        );
+
+        let toml = indoc! {r#"
+            [[processes]
+            name = "a"
+            "#};
+        assert_eq!(
+            "expected a right bracket, found a newline (line 1, column 13)",
+            Config::from_toml(toml).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn validation_errors_include_location() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "db"
+            run = "/app/db"
+
+            [[processes]]
+            name = "app"
+            pre = "/app/migrate.sh"
+              max-runtime = "1h"
+            "#};
+        assert_eq!(
+            "Process \"app\" has a `max-runtime`, but no `run` command (line 8, column 3)",
+            Config::from_toml(toml)
+                .unwrap()
+                .validate()
+                .unwrap_err()
+                .to_string()
+        );
+
+        // Errors in inline tables are reported at the key of the table.
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "db"
+            run = "/app/db"
+            ready = { tcp = "127.0.0.1:5432", file = "/run/db.ready" }
+            "#};
+        assert_eq!(
+            "`ready` in process \"db\" must specify exactly one probe (`tcp`, `http`, `exec`, or `file`) (line 4, column 1)",
+            Config::from_toml(toml)
+                .unwrap()
+                .validate()
+                .unwrap_err()
+                .to_string()
+        );
+
+        let toml = indoc! {r#"
+            startup-concurrency = 0
+            "#};
+        assert_eq!(
+            "`startup-concurrency` must be at least 1 (line 1, column 1)",
+            Config::from_toml(toml)
+                .unwrap()
+                .validate()
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn pass_fds_excludes_standard_streams() {
+        let toml = indoc! {r#"
+            pass-fds = [ 3, 4 ]
+
+            [[processes]]
+            name = "app"
+            run = "/app/run.sh"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert!(config.validate().is_ok());
+
+        let toml = indoc! {r#"
+            pass-fds = [ 3, 1 ]
+
+            [[processes]]
+            name = "app"
+            run = "/app/run.sh"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "`pass-fds` cannot include file descriptor 1 (stdin, stdout, and stderr are always passed)",
+            config.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn validates_main_process() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "setup"
+            pre = "/app/setup.sh"
+
+            [[processes]]
+            name = "app"
+            main = true
+            run = "/app/run.sh"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert!(config.validate().is_ok());
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "setup"
+            main = true
+            pre = "/app/setup.sh"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Process \"setup\" is the `main` process, but has no `run` command",
+            config.validate().unwrap_err().to_string()
+        );
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "app"
+            main = true
+            run = "/app/run.sh"
+
+            [[processes]]
+            name = "worker"
+            main = true
+            run = "/app/worker.sh"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Only one process can be the `main` process (found 2)",
+            config.validate().unwrap_err().to_string()
+        );
+
+        let toml = indoc! {r#"
+            forward-signals = true
+
+            [[processes]]
+            name = "app"
+            main = true
+            run = "/app/run.sh"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "`main` cannot be combined with `forward-signals` (which forwards every signal to the only daemon)",
+            config.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn forward_signals_requires_one_daemon() {
+        let toml = indoc! {r#"
+            forward-signals = true
+
+            [[processes]]
+            name = "setup"
+            pre = "/app/setup.sh"
+
+            [[processes]]
+            name = "app"
+            run = "/app/run.sh"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert!(config.validate().is_ok());
+
+        let toml = indoc! {r#"
+            forward-signals = true
+
+            [[processes]]
+            name = "app"
+            run = "/app/run.sh"
+
+            [[processes]]
+            name = "worker"
+            run = "/app/worker.sh"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "`forward-signals` requires exactly one daemon process (found 2)",
+            config.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[cfg(not(feature = "consul"))]
+    #[test]
+    fn service_requires_consul_feature() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "web"
+            service = { port = 8080 }
+            run = "/app/web.sh"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Process \"web\" has a `service`, but Ground Control was built without the `consul` feature",
+            config.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn validates_ports() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "web"
+            run = "/bin/true"
+            ports = [8080, 9090]
+
+            [[processes]]
+            name = "metrics"
+            run = "/bin/true"
+            ports = [9090]
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Processes \"web\" and \"metrics\" both declare port 9090",
+            config.validate().unwrap_err().to_string()
+        );
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "web"
+            run = "/bin/true"
+            ports = [8080, 8080]
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Process \"web\" declares port 8080 more than once",
+            config.validate().unwrap_err().to_string()
+        );
+
+        // Disabled processes, and standby processes, do not conflict.
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "web"
+            run = "/bin/true"
+            ports = [8080]
+
+            [[processes]]
+            name = "web-standby"
+            standby-for = "web"
+            run = "/bin/true"
+            ports = [8080]
+
+            [[processes]]
+            name = "old-web"
+            disabled = true
+            run = "/bin/true"
+            ports = [8080]
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert!(config.validate().is_ok());
+
+        // Probes default to the first declared port.
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "web"
+            run = "/bin/true"
+            ports = [8080]
+            ready = {}
+            health-check = {}
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert!(config.validate().is_ok());
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "web"
+            run = "/bin/true"
+            ready = {}
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "`ready` in process \"web\" must specify exactly one probe (`tcp`, `http`, `exec`, or `file`)",
+            config.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn validates_ready() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "db"
+            pre = "/bin/true"
+            ready = { file = "/run/db.ready" }
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Process \"db\" has a `ready` probe, but no `run` command",
+            config.validate().unwrap_err().to_string()
+        );
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "db"
+            run = "/bin/true"
+            ready = { tcp = "127.0.0.1:5432", file = "/run/db.ready" }
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "`ready` in process \"db\" must specify exactly one probe (`tcp`, `http`, `exec`, or `file`)",
+            config.validate().unwrap_err().to_string()
+        );
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "db"
+            run = "/bin/true"
+            ready = { tcp = "127.0.0.1" }
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Invalid `ready` in process \"db\": `tcp` address \"127.0.0.1\" must be of the form \"host:port\" (or \"[ipv6-address]:port\")",
+            format!("{:#}", config.validate().unwrap_err())
+        );
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "web"
+            run = "/bin/true"
+            ready = { http = "https://127.0.0.1:8080/healthz", timeout = "1m" }
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Invalid `ready` in process \"web\": Unsupported `http` URL scheme \"https\" (expected http)",
+            format!("{:#}", config.validate().unwrap_err())
+        );
+    }
+
+    #[test]
+    fn validates_lifecycle_hooks() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "migrate"
+            pre = "/app/migrate.sh"
+            pre-stop = "/app/flush.sh"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Process \"migrate\" has a `pre-stop` command, but no `run` command",
+            config.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn validates_health_check() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "web"
+            run = "/app/web.sh"
+            health-check = { http = "http://127.0.0.1:8080/healthz" }
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        config.validate().unwrap();
+        let health_check = config.processes[0].health_check.as_ref().unwrap();
+        assert_eq!(Duration::from_secs(10), health_check.interval);
+        assert_eq!(Duration::from_secs(5), health_check.timeout);
+        assert_eq!(3, health_check.failure_threshold);
+        assert_eq!(1, health_check.success_threshold);
+        assert_eq!(OnHealthCheckFailure::Restart, health_check.on_failure);
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "migrate"
+            pre = "/app/migrate.sh"
+            health-check = { tcp = "127.0.0.1:5432" }
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Process \"migrate\" has a `health-check`, but no `run` command",
+            config.validate().unwrap_err().to_string()
+        );
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "web"
+            run = "/app/web.sh"
+            health-check = { interval = "5s" }
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "`health-check` in process \"web\" must specify exactly one probe (`tcp`, `http`, or `exec`)",
+            config.validate().unwrap_err().to_string()
+        );
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "web"
+            run = "/app/web.sh"
+            health-check = { tcp = "127.0.0.1:8080", failure-threshold = 0 }
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "`health-check` in process \"web\" must have an `interval`, `timeout`, `failure-threshold`, and `success-threshold` that are greater than zero",
+            config.validate().unwrap_err().to_string()
+        );
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "web"
+            run = "/app/web.sh"
+            health-check = { tcp = "127.0.0.1:8080", success-threshold = 0, on-failure = "report" }
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "`health-check` in process \"web\" must have an `interval`, `timeout`, `failure-threshold`, and `success-threshold` that are greater than zero",
+            config.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn validates_wait_for() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "dns"
+            wait-for = { dns = "db.internal", timeout = "soon" }
+            "#};
+        assert_eq!(
+            "`processes[0].wait-for.timeout` (line 3, column 12): invalid duration \"soon\" (expected a duration such as \"30s\" or \"5m\")",
+            Config::from_toml(toml).unwrap_err().to_string()
+        );
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "dns"
+            wait-for = { timeout = "5s" }
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "`wait-for` in process \"dns\" must specify exactly one condition (`dns`, `connect`, or `mount`)",
+            config.validate().unwrap_err().to_string()
+        );
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "db"
+            wait-for = { connect = "mongodb://db.internal" }
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Invalid `wait-for` in process \"db\": Unsupported `connect` URL scheme \"mongodb\" (expected one of postgres, mysql, redis)",
+            format!("{:#}", config.validate().unwrap_err())
+        );
+    }
+
+    #[test]
+    fn loads_processes_dir() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join("20-worker.toml"),
+            "name = \"worker\"\nrun = \"/app/worker\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("10-api.toml"),
+            "name = \"api\"\nrun = \"/app/api\"\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("README"), "not a process").unwrap();
+
+        let toml = format!(
+            indoc! {r#"
+                processes-dir = "{}"
+
+                [[processes]]
+                name = "db"
+                run = "/app/db"
+                "#},
+            dir.path().display()
+        );
+        let mut config = Config::from_toml(&toml).unwrap();
+        config.load_processes_dir().unwrap();
+        assert_eq!(
+            vec!["db", "api", "worker"],
+            config
+                .processes
+                .iter()
+                .map(|process| process.name.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        std::fs::write(
+            dir.path().join("30-bad.toml"),
+            "name = \"bad\"\nbogus = 1\n",
+        )
+        .unwrap();
+        let mut config = Config::from_toml(&toml).unwrap();
+        let err = config.load_processes_dir().unwrap_err();
+        assert_eq!(
+            format!(
+                "Failed to parse process file \"{}\": `bogus` (line 2, column 1): unknown field `bogus`",
+                dir.path().join("30-bad.toml").display()
+            ),
+            format!("{err:#}")
+                .split(", expected one of")
+                .next()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn restart_exit_codes() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "only"
+            run = "/app/only"
+            restart-on-exit-codes = [1, 137]
+
+            [[processes]]
+            name = "except"
+            run = "/app/except"
+            restart-except-exit-codes = [2]
+
+            [[processes]]
+            name = "never"
+            run = "/app/never"
+
+            [[processes]]
+            name = "on-failure"
+            run = "/app/on-failure"
+            restart = "on-failure"
+
+            [[processes]]
+            name = "always"
+            run = "/app/always"
+            restart = "always"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        config.validate().unwrap();
+
+        let restarts: Vec<Vec<bool>> = config
+            .processes
+            .iter()
+            .map(|process| {
+                [1, 2, 137]
+                    .map(|code| process.restarts_after(code))
+                    .into_iter()
+                    .chain([process.restarts_after_clean_exit()])
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                vec![true, false, true, false],
+                vec![true, false, true, false],
+                vec![false, false, false, false],
+                vec![true, true, true, false],
+                vec![true, true, true, true],
+            ],
+            restarts
+        );
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "both"
+            run = "/app/both"
+            restart-on-exit-codes = [1]
+            restart-except-exit-codes = [2]
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Process \"both\" cannot use both `restart-on-exit-codes` and `restart-except-exit-codes`",
+            config.validate().unwrap_err().to_string()
+        );
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "both"
+            run = "/app/both"
+            restart = "on-failure"
+            restart-on-exit-codes = [1]
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Process \"both\" cannot use both `restart` and `restart-on-exit-codes` or `restart-except-exit-codes`",
+            config.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn validates_restart_backoff_and_limit() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "app"
+            run = "/app/server"
+            restart = "on-failure"
+            restart-backoff = {}
+            restart-limit = { restarts = 3 }
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        config.validate().unwrap();
+        assert_eq!(
+            Some(RestartBackoffConfig {
+                initial: Duration::from_secs(1),
+                max: Duration::from_secs(60),
+            }),
+            config.processes[0].restart_backoff
+        );
+        assert_eq!(
+            Some(RestartLimitConfig {
+                restarts: 3,
+                window: Duration::from_secs(60),
+            }),
+            config.processes[0].restart_limit
+        );
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "app"
+            run = "/app/server"
+            restart-backoff = { initial = "2m", max = "1m" }
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "`restart-backoff` in process \"app\" must have an `initial` delay that is greater than zero and no greater than its `max` delay",
+            config.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn validates_optional_processes() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "proxy"
+            optional = true
+            run = "/app/proxy"
+
+            [[processes]]
+            name = "app"
+            depends-on = ["proxy"]
+            run = "/app/server"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Process \"app\" depends on optional process \"proxy\" (use `wants` instead)",
+            config.validate().unwrap_err().to_string()
         );
     }
 
     #[test]
-    fn supports_command_vectors() {
-        let toml = r#"run = ["/app/run-me.sh", "using", "these", "args"]"#;
-        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+    fn validates_success_marker() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "app"
+            run = "/app/server"
+            success-marker = "/var/lib/app/started"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
         assert_eq!(
-            CommandConfig {
-                user: None,
-                only_env: None,
-                program: String::from("/app/run-me.sh"),
-                args: vec![
-                    String::from("using"),
-                    String::from("these"),
-                    String::from("args"),
-                ]
-            },
-            decoded.run
+            "Process \"app\" has a `success-marker`, but is not a one-shot process with a `pre` command",
+            config.validate().unwrap_err().to_string()
         );
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "migrate"
+            pre = "/app/migrate.sh"
+            success-marker = "/var/lib/app/migrated-v42"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            Some(PathBuf::from("/var/lib/app/migrated-v42")),
+            config.processes[0].success_marker
+        );
+        config.validate().unwrap();
     }
 
     #[test]
-    fn supports_detailed_whitespace_separated_command_lines() {
-        let toml = r#"run = { command = "/app/run-me.sh using these args" }"#;
-        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+    fn validates_watchdog() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "app"
+            run = "/app/server"
+            watchdog = "15s"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
         assert_eq!(
-            CommandConfig {
-                user: None,
-                only_env: None,
-                program: String::from("/app/run-me.sh"),
-                args: vec![
-                    String::from("using"),
-                    String::from("these"),
-                    String::from("args"),
-                ]
-            },
-            decoded.run
+            "Process \"app\" has a `watchdog`, but no `runtime-dir` (which holds the watchdog file and socket)",
+            config.validate().unwrap_err().to_string()
         );
 
-        let toml = r#"run = { user = "app", command = "/app/run-me.sh using these args" }"#;
-        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "app"
+            run = "/app/server"
+            runtime-dir = true
+            watchdog = "15s"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(Some(Duration::from_secs(15)), config.processes[0].watchdog);
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn validates_runtime_dir() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = ".."
+            run = "/app/server"
+            runtime-dir = true
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
         assert_eq!(
-            CommandConfig {
-                user: Some(String::from("app")),
-                only_env: None,
-                program: String::from("/app/run-me.sh"),
-                args: vec![
-                    String::from("using"),
-                    String::from("these"),
-                    String::from("args"),
-                ]
-            },
-            decoded.run
+            "Process name \"..\" must be a single path component (not empty, `.`, or `..`, and without `/`)",
+            config.validate().unwrap_err().to_string()
+        );
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "app/worker"
+            run = "/app/server"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Process name \"app/worker\" must be a single path component (not empty, `.`, or `..`, and without `/`)",
+            config.validate().unwrap_err().to_string()
+        );
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "app"
+            run = "/app/server"
+            runtime-dir = "run/app"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "`runtime-dir` in process \"app\" must be an absolute path",
+            config.validate().unwrap_err().to_string()
         );
     }
 
     #[test]
-    fn supports_detailed_command_vectors() {
-        let toml = r#"run = { command = ["/app/run-me.sh", "using", "these", "args"] }"#;
-        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+    fn validates_max_runtime() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "migrate"
+            pre = "/app/migrate.sh"
+            max-runtime = "2h"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
         assert_eq!(
-            CommandConfig {
-                user: None,
-                only_env: None,
-                program: String::from("/app/run-me.sh"),
-                args: vec![
-                    String::from("using"),
-                    String::from("these"),
-                    String::from("args"),
-                ]
-            },
-            decoded.run
+            "Process \"migrate\" has a `max-runtime`, but no `run` command",
+            config.validate().unwrap_err().to_string()
         );
 
-        let toml = r#"run = { user = "app", only-env = [], command = ["/app/run-me.sh", "using", "these", "args"] }"#;
-        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "app"
+            run = "/app/server"
+            max-runtime = "2h 30m"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
         assert_eq!(
-            CommandConfig {
-                user: Some(String::from("app")),
-                only_env: Some(HashSet::new()),
-                program: String::from("/app/run-me.sh"),
-                args: vec![
-                    String::from("using"),
-                    String::from("these"),
-                    String::from("args"),
-                ]
-            },
-            decoded.run
+            Some(Duration::from_secs(9000)),
+            config.processes[0].max_runtime
         );
+        config.validate().unwrap();
+    }
 
-        let toml = r#"run = { user = "app", only-env = ["USER", "HOME"], command = ["/app/run-me.sh", "using", "these", "args"] }"#;
-        let decoded: CommandConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+    #[test]
+    fn validates_shutdown_class() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "db"
+            run = "/app/db"
+            shutdown-class = "critical"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
         assert_eq!(
-            CommandConfig {
-                user: Some(String::from("app")),
-                only_env: Some(HashSet::from(["USER".into(), "HOME".into()])),
-                program: String::from("/app/run-me.sh"),
-                args: vec![
-                    String::from("using"),
-                    String::from("these"),
-                    String::from("args"),
-                ]
-            },
-            decoded.run
+            "Process \"db\" has a `shutdown-class`, but there is no `shutdown-timeout`",
+            config.validate().unwrap_err().to_string()
         );
+
+        let toml = indoc! {r#"
+            shutdown-timeout = "30s"
+
+            [[processes]]
+            name = "cache"
+            run = "/app/cache"
+            shutdown-class = "best-effort"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            ShutdownClass::BestEffort,
+            config.processes[0].shutdown_class
+        );
+        config.validate().unwrap();
     }
 
     #[test]
-    fn requires_command_in_detailed_command() {
-        let toml = r#"run = { }"#;
-        let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
-        assert_eq!("data did not match any variant of untagged enum CommandLineConfig for key `run` at line 1 column 1", error.to_string(),);
+    fn validates_restart_schedule() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "migrate"
+            pre = "/app/migrate.sh"
+            restart-schedule = "0 4 * * *"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Process \"migrate\" has a `restart-schedule`, but no `run` command",
+            config.validate().unwrap_err().to_string()
+        );
 
-        let toml = r#"run = { user = "app" }"#;
-        let error = toml::from_str::<CommandConfigTest>(toml).unwrap_err();
-        assert_eq!("data did not match any variant of untagged enum CommandLineConfig for key `run` at line 1 column 1", error.to_string(),);
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "app"
+            run = "/app/server"
+            restart-schedule = "0 25 * * *"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Invalid `restart-schedule` in process \"app\"",
+            config.validate().unwrap_err().to_string()
+        );
+
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "app"
+            run = "/app/server"
+            restart-schedule = "0 4 * * 1-5"
+            "#};
+        let config: Config = toml::from_str(toml).unwrap();
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn validates_timezone() {
+        for timezone in ["America/New_York", "UTC", "Etc/GMT+5"] {
+            let toml = format!(
+                "[[processes]]\nname = \"app\"\nrun = \"/app/server\"\ntimezone = \"{timezone}\"\n"
+            );
+            let config: Config = toml::from_str(&toml).unwrap();
+            config.validate().unwrap();
+        }
+
+        for timezone in [
+            "",
+            "/etc/passwd",
+            "../../etc/passwd",
+            "America//New_York",
+            "EST 5",
+        ] {
+            let toml = format!(
+                "[[processes]]\nname = \"app\"\nrun = \"/app/server\"\ntimezone = \"{timezone}\"\n"
+            );
+            let config: Config = toml::from_str(&toml).unwrap();
+            assert_eq!(
+                format!("Process \"app\" has an invalid `timezone` \"{timezone}\" (expected a tz database name such as \"America/New_York\")"),
+                config.validate().unwrap_err().to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn startup_order_defaults_to_config_order() {
+        let toml = r#"
+            [[processes]]
+            name = "a"
+
+            [[processes]]
+            name = "b"
+
+            [[processes]]
+            name = "c"
+            "#;
+        assert_eq!(vec!["a", "b", "c"], startup_names(toml).unwrap());
+    }
+
+    #[test]
+    fn startup_order_starts_dependencies_first() {
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            depends-on = ["db"]
+            wants = ["metrics"]
+
+            [[processes]]
+            name = "other"
+
+            [[processes]]
+            name = "metrics"
+
+            [[processes]]
+            name = "db"
+            "#;
+        assert_eq!(
+            vec!["other", "metrics", "db", "app"],
+            startup_names(toml).unwrap()
+        );
+    }
+
+    #[test]
+    fn startup_order_skips_disabled_processes() {
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            wants = ["metrics"]
+
+            [[processes]]
+            name = "metrics"
+            disabled = true
+            "#;
+        assert_eq!(vec!["app"], startup_names(toml).unwrap());
+
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            depends-on = ["db"]
+
+            [[processes]]
+            name = "db"
+            disabled = true
+            "#;
+        assert_eq!(
+            "Process \"app\" depends on disabled process \"db\" (processes[0].depends-on[0])",
+            startup_names(toml).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn startup_order_follows_ordering_hints() {
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            after = ["network-setup"]
+
+            [[processes]]
+            name = "network-setup"
+
+            [[processes]]
+            name = "migrate"
+            before = ["app"]
+            "#;
+        assert_eq!(
+            vec!["network-setup", "migrate", "app"],
+            startup_names(toml).unwrap()
+        );
+
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            after = ["proxy"]
+            before = ["cache"]
+
+            [[processes]]
+            name = "proxy"
+            after = ["cache"]
+
+            [[processes]]
+            name = "cache"
+            "#;
+        assert_eq!(
+            indoc! {r#"
+                Dependency cycle detected: app → proxy → cache → app
+                  - processes[0].after[0] ("app" after "proxy")
+                  - processes[1].after[0] ("proxy" after "cache")
+                  - processes[0].before[0] ("app" before "cache")"#},
+            startup_names(toml).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn startup_order_waits_for_barriers() {
+        let toml = r#"
+            [[processes]]
+            name = "api"
+            depends-on = ["migrations-done"]
+
+            [[processes]]
+            name = "migrate-users"
+            joins = "migrations-done"
+
+            [[processes]]
+            name = "worker"
+            after = ["migrations-done"]
+
+            [[processes]]
+            name = "migrate-billing"
+            joins = "migrations-done"
+            "#;
+        assert_eq!(
+            vec!["migrate-users", "migrate-billing", "api", "worker"],
+            startup_names(toml).unwrap()
+        );
+
+        let toml = r#"
+            [[processes]]
+            name = "migrate"
+            joins = "migrate"
+            "#;
+        assert_eq!(
+            "Process \"migrate\" joins barrier \"migrate\", which has the same name as a process",
+            startup_names(toml).unwrap_err().to_string()
+        );
+
+        let toml = r#"
+            [[processes]]
+            name = "migrate"
+            joins = "migrations-done"
+
+            [[processes]]
+            name = "seed"
+            before = ["migrations-done"]
+            "#;
+        assert_eq!(
+            "Process \"seed\" lists barrier \"migrations-done\" in `before` (use `joins` instead) (processes[1].before[0])",
+            startup_names(toml).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn validates_standby_processes() {
+        let toml = r#"
+            [[processes]]
+            name = "primary"
+            pre = "/bin/true"
+
+            [[processes]]
+            name = "standby"
+            standby-for = "primary"
+            run = "/bin/true"
+            "#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Process \"standby\" is a standby for \"primary\", which is not a daemon process",
+            config.validate().unwrap_err().to_string()
+        );
+
+        let toml = r#"
+            [[processes]]
+            name = "primary"
+            run = "/bin/true"
+
+            [[processes]]
+            name = "standby"
+            standby-for = "primary"
+            run = "/bin/true"
+
+            [[processes]]
+            name = "app"
+            wants = ["standby"]
+            "#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Process \"app\" references standby process \"standby\" (processes[2].wants[0])",
+            config.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn validates_bound_processes() {
+        let toml = r#"
+            [[processes]]
+            name = "main"
+            pre = "/bin/true"
+
+            [[processes]]
+            name = "sidecar"
+            bound-to = "main"
+            run = "/bin/true"
+            "#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Process \"sidecar\" is bound to process \"main\", which is not a daemon process",
+            config.validate().unwrap_err().to_string()
+        );
+
+        let toml = r#"
+            [[processes]]
+            name = "main"
+            run = "/bin/true"
+            optional = true
+
+            [[processes]]
+            name = "sidecar"
+            bound-to = "main"
+            run = "/bin/true"
+            "#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Process \"sidecar\" is bound to process \"main\", which is optional",
+            config.validate().unwrap_err().to_string()
+        );
+
+        let toml = r#"
+            [[processes]]
+            name = "sidecar"
+            bound-to = "main"
+            run = "/bin/true"
+            "#;
+        let config: Config = toml::from_str(toml).unwrap();
+        assert_eq!(
+            "Process \"sidecar\" references unknown process \"main\" (processes[0].bound-to[0])",
+            config.validate().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn startup_order_rejects_invalid_dependencies() {
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            depends-on = ["db"]
+            "#;
+        assert_eq!(
+            "Process \"app\" references unknown process \"db\" (processes[0].depends-on[0])",
+            startup_names(toml).unwrap_err().to_string()
+        );
+
+        let toml = r#"
+            [[processes]]
+            name = "app"
+
+            [[processes]]
+            name = "app"
+            "#;
+        assert_eq!(
+            "Duplicate process name \"app\"",
+            startup_names(toml).unwrap_err().to_string()
+        );
+
+        let toml = r#"
+            [[processes]]
+            name = "a"
+            depends-on = ["b"]
+
+            [[processes]]
+            name = "b"
+            wants = ["a"]
+            "#;
+        assert_eq!(
+            indoc! {r#"
+                Dependency cycle detected: a → b → a
+                  - processes[0].depends-on[0] ("a" depends-on "b")
+                  - processes[1].wants[0] ("b" wants "a")"#},
+            startup_names(toml).unwrap_err().to_string()
+        );
+
+        let toml = r#"
+            [[processes]]
+            name = "app"
+            depends-on = ["a"]
+
+            [[processes]]
+            name = "a"
+            depends-on = ["b"]
+
+            [[processes]]
+            name = "b"
+            depends-on = ["c"]
+
+            [[processes]]
+            name = "c"
+            wants = ["a"]
+            "#;
+        assert_eq!(
+            indoc! {r#"
+                Dependency cycle detected: a → b → c → a
+                  - processes[1].depends-on[0] ("a" depends-on "b")
+                  - processes[2].depends-on[0] ("b" depends-on "c")
+                  - processes[3].wants[0] ("c" wants "a")"#},
+            startup_names(toml).unwrap_err().to_string()
+        );
+
+        // Configs that are parsed from a file include the location of
+        // every entry in the cycle.
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "a"
+            after = ["b"]
+
+            [[processes]]
+            name = "b"
+              depends-on = ["a"]
+            "#};
+        assert_eq!(
+            indoc! {r#"
+                Dependency cycle detected: a → b → a
+                  - processes[0].after[0] at line 3, column 1 ("a" after "b")
+                  - processes[1].depends-on[0] at line 7, column 3 ("b" depends-on "a")"#},
+            Config::from_toml(toml)
+                .unwrap()
+                .startup_order()
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct SetupConfigTest {
+        setup: Vec<SetupConfig>,
+    }
+
+    #[test]
+    fn supports_setup_steps() {
+        let toml = r#"
+            [[setup]]
+            action = "directory"
+            path = "/data/app"
+            user = "app"
+            mode = "0750"
+
+            [[setup]]
+            action = "symlink"
+            path = "/app/current"
+            target = "/app/releases/v2"
+            "#;
+        let decoded: SetupConfigTest = toml::from_str(toml).expect("Failed to parse test TOML");
+        assert_eq!(
+            vec![
+                SetupConfig::Directory {
+                    path: PathBuf::from("/data/app"),
+                    attributes: FileAttributes {
+                        user: Some(String::from("app")),
+                        group: None,
+                        mode: Some(0o750),
+                    },
+                },
+                SetupConfig::Symlink {
+                    path: PathBuf::from("/app/current"),
+                    target: PathBuf::from("/app/releases/v2"),
+                },
+            ],
+            decoded.setup
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_file_modes() {
+        let toml = r#"
+            [[setup]]
+            action = "chmod"
+            path = "/data/app"
+            mode = "rwx"
+            "#;
+        let error = toml::from_str::<SetupConfigTest>(toml).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("invalid file mode \"rwx\" (expected an octal string such as \"0750\")"));
+    }
+
+    #[test]
+    fn round_trips_through_toml() {
+        let toml = indoc! {r##"
+            suppress-timestamps = true
+            startup-concurrency = 4
+            restrict-expansion = true
+            control-dir = "/run/groundcontrol"
+            control-socket = "/run/groundcontrol.sock"
+            api = { listen = "[::]:8080" }
+            event-log = "/var/log/groundcontrol/events.jsonl"
+            state-dir = "/var/lib/groundcontrol"
+            env = { PGDATA = "/data/pg", LANG = "C.UTF-8" }
+
+            [[setup]]
+            action = "directory"
+            path = "/data/pg"
+            user = "postgres"
+            mode = "0700"
+
+            [[setup]]
+            action = "chown"
+            path = "/data"
+            group = "app"
+
+            [[setup]]
+            action = "chmod"
+            path = "/data/app"
+            mode = "0640"
+
+            [[setup]]
+            action = "write"
+            path = "/etc/app.conf"
+            contents = "url = {{DATABASE_URL}}"
+
+            [[processes]]
+            name = "postgres"
+            joins = "storage-ready"
+            pre = "/app/init-db.sh --if-needed"
+            run = { user = "postgres", only-env = ["PGDATA", "LANG"], command = ["/usr/bin/postgres", "-D", "/data/pg"] }
+            stop = "SIGINT"
+            on-stop-failure = "kill-group"
+            runtime-dir = true
+            wait-for = { mount = "/data", timeout = "1m 30s" }
+            restart-on-exit-codes = [1, 137]
+            restart-throttle = { max-pressure = 20.5, max-delay = "2m" }
+            core-dump = { dir = "/cores", on-crash = ["/app/archive-core.sh", "with spaces"] }
+
+            [[processes]]
+            name = "app"
+            disabled = true
+            optional = true
+            depends-on = ["postgres"]
+            wants = ["metrics"]
+            run = { login-shell = true, command = "/app/server", sha256 = "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855" }
+            stop = { user = "app", command = ["/app/server", "--stop"] }
+            pre-stop-delay = "10s"
+            post = "/app/cleanup.sh"
+            runtime-dir = "/run/app"
+            stdin = { file = "/app/input.txt" }
+            fresh-env = true
+            env-preset = "locale"
+            timezone = "America/New_York"
+            ports = [8080, 9090]
+            service = { port = 8080, tags = ["web"] }
+            restart-except-exit-codes = [2]
+            restart-backoff = { initial = "500ms", max = "30s" }
+            restart-limit = { restarts = 5, window = "10m" }
+            spawn-retries = 0
+            watchdog = "15s"
+            max-runtime = "2h"
+
+            [[processes]]
+            name = "metrics"
+            standby-for = "app"
+            run = ["/app/metrics"]
+            stop = { http = "http://127.0.0.1:9000/quitquitquit", then-signal = "SIGINT", timeout = "30s" }
+            restart = "always"
+            post = { script = "#!/bin/sh\necho \"metrics stopped\"\n" }
+            restart-schedule = "0 4 * * *"
+
+            [[processes]]
+            name = "migrate"
+            pre = "/app/migrate.sh"
+            success-marker = "/var/lib/app/migrated-v42"
+            "##};
+        let config = Config::from_toml(toml).unwrap();
+
+        let serialized = config.to_toml().unwrap();
+        assert_eq!(config, Config::from_toml(&serialized).unwrap());
+    }
+
+    #[test]
+    fn serializes_minimal_settings() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "app"
+            pre = "/app/migrate.sh --all"
+            run = { command = "/app/server" }
+            "#};
+        let config = Config::from_toml(toml).unwrap();
+
+        assert_eq!(
+            indoc! {r#"
+                [[processes]]
+                name = "app"
+                pre = ["/app/migrate.sh", "--all"]
+                run = ["/app/server"]
+            "#},
+            config.to_toml().unwrap()
+        );
+    }
+
+    #[test]
+    fn fingerprint_ignores_formatting() {
+        let original = Config::from_toml(indoc! {r#"
+            [[processes]]
+            name = "app"
+            run = "/app/server --port 8080"
+            "#})
+        .unwrap();
+        let reformatted = Config::from_toml(indoc! {r#"
+            # The application server.
+            [[processes]]
+            name = "app"
+            run = ["/app/server", "--port", "8080"]
+            "#})
+        .unwrap();
+        let changed = Config::from_toml(indoc! {r#"
+            [[processes]]
+            name = "app"
+            run = "/app/server --port 8081"
+            "#})
+        .unwrap();
+
+        assert_eq!(
+            original.fingerprint().unwrap(),
+            reformatted.fingerprint().unwrap()
+        );
+        assert_ne!(
+            original.fingerprint().unwrap(),
+            changed.fingerprint().unwrap()
+        );
     }
 }
@@ -0,0 +1,81 @@
+//! Registers processes as services with the local Consul agent.
+
+use color_eyre::eyre::{self, WrapErr};
+use serde_json::{json, Value};
+
+use crate::{command::CommandEnv, config::ServiceConfig};
+
+/// Address of the Consul agent's HTTP API, unless overridden by the
+/// `CONSUL_HTTP_ADDR` environment variable (which is also used by the
+/// Consul CLI) in the process's environment.
+const DEFAULT_CONSUL_HTTP_ADDR: &str = "http://127.0.0.1:8500";
+
+/// Registers the process as a service, using the name of the process as
+/// the ID of the service.
+pub(crate) async fn register(
+    process_name: &str,
+    service: &ServiceConfig,
+    env: &CommandEnv,
+) -> eyre::Result<()> {
+    let mut registration = json!({
+        "ID": process_name,
+        "Name": service.name.as_deref().unwrap_or(process_name),
+        "Tags": service.tags,
+    });
+    if let Some(port) = service.port {
+        registration["Port"] = json!(port);
+    }
+    if let Some(health) = &service.health {
+        registration["Check"] = json!({
+            "HTTP": health,
+            "Interval": "10s",
+            "DeregisterCriticalServiceAfter": "1m",
+        });
+    }
+
+    put("/v1/agent/service/register", Some(registration), env)
+        .await
+        .wrap_err_with(|| format!("Failed to register service for process \"{process_name}\""))
+}
+
+/// Deregisters the service that was registered for the process.
+pub(crate) async fn deregister(process_name: &str, env: &CommandEnv) -> eyre::Result<()> {
+    put(
+        &format!("/v1/agent/service/deregister/{process_name}"),
+        None,
+        env,
+    )
+    .await
+    .wrap_err_with(|| format!("Failed to deregister service for process \"{process_name}\""))
+}
+
+/// Sends a `PUT` request to the Consul agent, which is found (and
+/// authenticated to) using the variables in `env`. The request is
+/// performed on a blocking thread, since the HTTP client is synchronous.
+async fn put(path: &str, body: Option<Value>, env: &CommandEnv) -> eyre::Result<()> {
+    let addr = env
+        .var("CONSUL_HTTP_ADDR")
+        .unwrap_or_else(|| DEFAULT_CONSUL_HTTP_ADDR.to_string());
+    let url = if addr.contains("://") {
+        format!("{}{path}", addr.trim_end_matches('/'))
+    } else {
+        format!("http://{}{path}", addr.trim_end_matches('/'))
+    };
+    let token = env.var("CONSUL_HTTP_TOKEN");
+
+    tokio::task::spawn_blocking(move || {
+        let mut request = ureq::put(&url);
+        if let Some(token) = &token {
+            request = request.set("X-Consul-Token", token);
+        }
+
+        match body {
+            Some(body) => request.send_json(body),
+            None => request.call(),
+        }
+        .map(|_| ())
+        .map_err(|err| eyre::Report::new(err).wrap_err(format!("Consul request to {url} failed")))
+    })
+    .await
+    .wrap_err("Consul request task failed")?
+}
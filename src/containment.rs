@@ -0,0 +1,199 @@
+//! Contains panics in the tasks that supervise a single daemon (waiting
+//! for the daemon to exit, checking its health, enforcing its watchdog,
+//! and sampling its resource usage): a panic in one of those tasks fails
+//! that daemon, which then restarts (or shuts down Ground Control)
+//! according to its restart policy, while every other process keeps
+//! running. Panics anywhere else still abort Ground Control (see
+//! `install_panic_hook`).
+
+use std::{
+    any::Any,
+    cell::Cell,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use nix::{
+    sys::signal::{killpg, Signal},
+    unistd::Pid,
+};
+
+use crate::history::FailureNotes;
+
+thread_local! {
+    /// Set while a contained task is being polled on this thread.
+    static CONTAINED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Installs a panic hook that aborts Ground Control on a panic anywhere
+/// (including in a background task), since a panic means that something
+/// is very wrong, *except* in the tasks that supervise a single daemon,
+/// whose panics only fail that daemon.
+pub fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        if CONTAINED.with(Cell::get) {
+            eprintln!("Process supervision task panicked: {info}");
+        } else {
+            eprintln!("Process panicked: {info}");
+            std::process::abort();
+        }
+    }));
+}
+
+/// Spawns the tasks that supervise a daemon.
+#[derive(Clone, Debug)]
+pub(crate) struct DaemonTasks {
+    process_name: String,
+    pid: Pid,
+    exited: Arc<AtomicBool>,
+    stopping: Arc<AtomicBool>,
+    failure: FailureNotes,
+}
+
+impl DaemonTasks {
+    /// Creates the spawner for the tasks of the daemon with the given
+    /// PID, which is failed (if a task panics) unless it has already
+    /// `exited` or is `stopping`. Panic messages are noted in the
+    /// `failure` notes of the daemon's process.
+    pub(crate) fn new(
+        process_name: String,
+        pid: Pid,
+        exited: Arc<AtomicBool>,
+        stopping: Arc<AtomicBool>,
+        failure: FailureNotes,
+    ) -> Self {
+        DaemonTasks {
+            process_name,
+            pid,
+            exited,
+            stopping,
+            failure,
+        }
+    }
+
+    /// Spawns a task that supervises the daemon, killing the daemon if
+    /// the task panics (the daemon's exit is then reported as a
+    /// failure), unless the daemon has already exited or is being
+    /// stopped.
+    pub(crate) fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+        let tasks = self.clone();
+        self.spawn_with(task, move || {
+            if !tasks.exited.load(Ordering::SeqCst) && !tasks.is_stopping() {
+                tasks.kill_daemon();
+            }
+        });
+    }
+
+    /// Spawns a task that supervises the daemon, calling `on_panic` if
+    /// the task panics (after the panic has been noted for the exit
+    /// history).
+    pub(crate) fn spawn_with(
+        &self,
+        task: impl Future<Output = ()> + Send + 'static,
+        on_panic: impl FnOnce() + Send + 'static,
+    ) {
+        let process_name = self.process_name.clone();
+        let failure = self.failure.clone();
+        let task = tokio::spawn(Contained(Box::pin(task)));
+        tokio::spawn(async move {
+            let err = match task.await {
+                Err(err) if err.is_panic() => err,
+                _ => return,
+            };
+
+            let message = panic_message(err.into_panic());
+            tracing::error!(process = %process_name, panic = %message, "Process supervision task panicked; failing the process");
+            failure.note_panic(message);
+            on_panic();
+        });
+    }
+
+    /// Returns true if Ground Control is stopping the daemon (in which
+    /// case there is nothing to fail).
+    pub(crate) fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst)
+    }
+
+    /// Kills the daemon (and everything else in its process group) with
+    /// SIGKILL.
+    pub(crate) fn kill_daemon(&self) {
+        if let Err(err) = killpg(self.pid, Signal::SIGKILL) {
+            tracing::warn!(process = %self.process_name, ?err, "Failed to kill daemon");
+        }
+    }
+}
+
+/// Future that marks the thread on which it is polled as running a
+/// contained task (see `install_panic_hook`).
+struct Contained<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for Contained<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let _guard = ContainedGuard(CONTAINED.with(|contained| contained.replace(true)));
+        self.0.as_mut().poll(cx)
+    }
+}
+
+/// Restores the previous value of the thread's contained flag when
+/// dropped, including when the contained task panics.
+struct ContainedGuard(bool);
+
+impl Drop for ContainedGuard {
+    fn drop(&mut self) {
+        CONTAINED.with(|contained| contained.set(self.0));
+    }
+}
+
+/// Returns the message of a panic (which is almost always a string).
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => match panic.downcast::<&str>() {
+            Ok(message) => (*message).to_owned(),
+            Err(_) => String::from("<unknown panic>"),
+        },
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use tokio::sync::oneshot;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn panics_are_contained() {
+        let failure = FailureNotes::default();
+        let tasks = DaemonTasks::new(
+            String::from("contained"),
+            Pid::from_raw(i32::MAX),
+            Arc::new(AtomicBool::new(true)),
+            Arc::new(AtomicBool::new(false)),
+            failure.clone(),
+        );
+
+        let (sender, receiver) = oneshot::channel();
+        tasks.spawn_with(
+            async {
+                panic!("supervision failed");
+            },
+            move || sender.send(()).unwrap(),
+        );
+
+        receiver.await.unwrap();
+        assert_eq!(
+            Some(String::from("supervision failed")),
+            failure.take().panic
+        );
+        assert_eq!(None, failure.take().panic);
+    }
+}
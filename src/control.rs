@@ -0,0 +1,331 @@
+//! File-based control channel: Ground Control watches a directory for
+//! "drop files" that trigger actions (`shutdown`, `pause` and `resume`,
+//! and `pause.<name>` and `resume.<name>` for individual processes,
+//! `drain.<name>`, `release.<name>`, and `log-level` and
+//! `log-level.<name>`), which allows Ground Control to be controlled in
+//! environments without a socket client. Ground Control also writes a
+//! `status` file that describes the spec that it is running.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use color_eyre::eyre::{self, WrapErr};
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::{BuildInfo, ShutdownReason};
+
+/// Time between checks for new drop files.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Name of the drop file that triggers a graceful shutdown.
+const SHUTDOWN_FILE: &str = "shutdown";
+
+/// Name of the drop file that pauses every process.
+const PAUSE_FILE: &str = "pause";
+
+/// Name of the drop file that resumes every process.
+const RESUME_FILE: &str = "resume";
+
+/// Prefix of the drop files that pause a process (`pause.<name>`).
+const PAUSE_PREFIX: &str = "pause.";
+
+/// Prefix of the drop files that resume a process (`resume.<name>`).
+const RESUME_PREFIX: &str = "resume.";
+
+/// Prefix of the drop files that drain a process (`drain.<name>`).
+const DRAIN_PREFIX: &str = "drain.";
+
+/// Prefix of the drop files that release the startup hold of a process
+/// (`release.<name>`).
+const RELEASE_PREFIX: &str = "release.";
+
+/// Name of the drop file that changes the global log level.
+const LOG_LEVEL_FILE: &str = "log-level";
+
+/// Prefix of the drop files that change the log level of a process
+/// (`log-level.<name>`).
+const LOG_LEVEL_PREFIX: &str = "log-level.";
+
+/// Request (other than a shutdown) made through a drop file.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ControlRequest {
+    /// Pause the daemon of the named process.
+    Pause(String),
+
+    /// Resume the (paused) daemon of the named process.
+    Resume(String),
+
+    /// Pause the daemon of every process.
+    PauseAll,
+
+    /// Resume the daemon of every process.
+    ResumeAll,
+
+    /// Mark the daemon of the named process as not ready, without
+    /// stopping it.
+    Drain(String),
+
+    /// Release the startup hold of the named process.
+    Release(String),
+
+    /// Change the log level (globally, or for the named process) to the
+    /// contents of the drop file.
+    LogLevel {
+        /// Name of the process, or `None` for the global log level.
+        process: Option<String>,
+
+        /// New log level (empty to restore the default level).
+        level: String,
+    },
+}
+
+impl ControlRequest {
+    /// Parses the name of a drop file into a request, if the file is a
+    /// pause, resume, drain, release, or log level request (the level of
+    /// a log level request is read from the file by `watch`).
+    fn from_file_name(file_name: &str) -> Option<Self> {
+        if file_name == LOG_LEVEL_FILE {
+            Some(Self::LogLevel {
+                process: None,
+                level: String::new(),
+            })
+        } else if let Some(name) = file_name.strip_prefix(LOG_LEVEL_PREFIX) {
+            Some(Self::LogLevel {
+                process: Some(name.to_string()),
+                level: String::new(),
+            })
+        } else if file_name == PAUSE_FILE {
+            Some(Self::PauseAll)
+        } else if file_name == RESUME_FILE {
+            Some(Self::ResumeAll)
+        } else if let Some(name) = file_name.strip_prefix(DRAIN_PREFIX) {
+            Some(Self::Drain(name.to_string()))
+        } else if let Some(name) = file_name.strip_prefix(RELEASE_PREFIX) {
+            Some(Self::Release(name.to_string()))
+        } else if let Some(name) = file_name.strip_prefix(PAUSE_PREFIX) {
+            Some(Self::Pause(name.to_string()))
+        } else {
+            file_name
+                .strip_prefix(RESUME_PREFIX)
+                .map(|name| Self::Resume(name.to_string()))
+        }
+    }
+}
+
+/// Name of the file that describes the running spec.
+const STATUS_FILE: &str = "status";
+
+/// Contents of the status file (written as TOML).
+#[derive(Debug, Serialize)]
+pub(crate) struct Status {
+    /// Metadata about the Ground Control build.
+    #[serde(flatten)]
+    pub(crate) build: BuildInfo,
+
+    /// Path of the config file (empty if unknown).
+    pub(crate) config: String,
+
+    /// Fingerprint of the config (see `Config::fingerprint`).
+    pub(crate) fingerprint: String,
+
+    /// Number of processes in the config.
+    pub(crate) processes: usize,
+}
+
+/// Creates the control directory (if necessary), and removes any drop
+/// files left over from a previous run (which would otherwise trigger
+/// their action immediately).
+pub(crate) async fn prepare(control_dir: &Path) -> eyre::Result<()> {
+    tokio::fs::create_dir_all(control_dir)
+        .await
+        .wrap_err_with(|| {
+            format!(
+                "Failed to create control directory \"{}\"",
+                control_dir.display()
+            )
+        })?;
+
+    let shutdown_file = control_dir.join(SHUTDOWN_FILE);
+    let mut stale_files = vec![shutdown_file.clone()];
+    stale_files.extend(
+        request_files(control_dir)
+            .await?
+            .into_iter()
+            .map(|(path, _)| path),
+    );
+    for stale_file in stale_files {
+        if tokio::fs::metadata(&stale_file).await.is_ok() {
+            tokio::fs::remove_file(&stale_file)
+                .await
+                .wrap_err_with(|| {
+                    format!(
+                        "Failed to remove stale control file \"{}\"",
+                        stale_file.display()
+                    )
+                })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the paths of the request (pause, resume, drain, release, and
+/// log level) drop files in the control directory, along with their
+/// requests.
+async fn request_files(control_dir: &Path) -> eyre::Result<Vec<(PathBuf, ControlRequest)>> {
+    let mut entries = tokio::fs::read_dir(control_dir).await.wrap_err_with(|| {
+        format!(
+            "Failed to read control directory \"{}\"",
+            control_dir.display()
+        )
+    })?;
+
+    let mut files = Vec::new();
+    while let Some(entry) = entries.next_entry().await.wrap_err_with(|| {
+        format!(
+            "Failed to read control directory \"{}\"",
+            control_dir.display()
+        )
+    })? {
+        let request = entry
+            .file_name()
+            .to_str()
+            .and_then(ControlRequest::from_file_name);
+        if let Some(request) = request {
+            files.push((entry.path(), request));
+        }
+    }
+
+    // Handle the requests in a consistent order.
+    files.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(files)
+}
+
+/// Removes the drop file that releases the startup hold of the process,
+/// returning true if it was present. Holds are released during startup,
+/// before the control directory is watched.
+pub(crate) async fn take_release_file(control_dir: &Path, process_name: &str) -> bool {
+    let release_file = control_dir.join(format!("{RELEASE_PREFIX}{process_name}"));
+    if tokio::fs::metadata(&release_file).await.is_err() {
+        return false;
+    }
+
+    tracing::info!(path = %release_file.display(), "Release requested by control file");
+    if let Err(err) = tokio::fs::remove_file(&release_file).await {
+        tracing::warn!(?err, "Failed to remove control file");
+    }
+    true
+}
+
+/// Writes the status file into the control directory, replacing the
+/// status file of any previous run.
+pub(crate) async fn write_status(control_dir: &Path, status: &Status) -> eyre::Result<()> {
+    let status_file = control_dir.join(STATUS_FILE);
+    let contents = toml::to_string(status).wrap_err("Failed to serialize status")?;
+    tokio::fs::write(&status_file, contents)
+        .await
+        .wrap_err_with(|| format!("Failed to write status file \"{}\"", status_file.display()))
+}
+
+/// Watches the control directory, sending a graceful shutdown request
+/// once the `shutdown` file appears, and sending the other requests as
+/// their files appear.
+pub(crate) fn watch(
+    control_dir: PathBuf,
+    shutdown_sender: mpsc::UnboundedSender<ShutdownReason>,
+    request_sender: mpsc::UnboundedSender<ControlRequest>,
+) {
+    tokio::spawn(async move {
+        let shutdown_file = control_dir.join(SHUTDOWN_FILE);
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+
+            match request_files(&control_dir).await {
+                Ok(files) => {
+                    for (path, mut request) in files {
+                        tracing::info!(path = %path.display(), "Request made by control file");
+
+                        if let ControlRequest::LogLevel { level, .. } = &mut request {
+                            match tokio::fs::read_to_string(&path).await {
+                                Ok(contents) => *level = contents.trim().to_string(),
+                                Err(err) => tracing::warn!(?err, "Failed to read control file"),
+                            }
+                        }
+
+                        if let Err(err) = tokio::fs::remove_file(&path).await {
+                            tracing::warn!(?err, "Failed to remove control file");
+                        }
+
+                        let _ = request_sender.send(request);
+                    }
+                }
+                Err(err) => tracing::warn!(?err, "Failed to check for control files"),
+            }
+
+            if tokio::fs::metadata(&shutdown_file).await.is_ok() {
+                tracing::info!(path = %shutdown_file.display(), "Shutdown requested by control file");
+
+                if let Err(err) = tokio::fs::remove_file(&shutdown_file).await {
+                    tracing::warn!(?err, "Failed to remove control file");
+                }
+
+                let _ = shutdown_sender.send(ShutdownReason::ControlShutdown);
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn parses_request_file_names() {
+        assert_eq!(
+            Some(ControlRequest::Pause(String::from("worker"))),
+            ControlRequest::from_file_name("pause.worker")
+        );
+        assert_eq!(
+            Some(ControlRequest::Resume(String::from("worker"))),
+            ControlRequest::from_file_name("resume.worker")
+        );
+        assert_eq!(
+            Some(ControlRequest::PauseAll),
+            ControlRequest::from_file_name("pause")
+        );
+        assert_eq!(
+            Some(ControlRequest::ResumeAll),
+            ControlRequest::from_file_name("resume")
+        );
+        assert_eq!(
+            Some(ControlRequest::LogLevel {
+                process: None,
+                level: String::new()
+            }),
+            ControlRequest::from_file_name("log-level")
+        );
+        assert_eq!(
+            Some(ControlRequest::LogLevel {
+                process: Some(String::from("worker")),
+                level: String::new()
+            }),
+            ControlRequest::from_file_name("log-level.worker")
+        );
+        assert_eq!(
+            Some(ControlRequest::Drain(String::from("worker"))),
+            ControlRequest::from_file_name("drain.worker")
+        );
+        assert_eq!(
+            Some(ControlRequest::Release(String::from("worker"))),
+            ControlRequest::from_file_name("release.worker")
+        );
+        assert_eq!(None, ControlRequest::from_file_name("shutdown"));
+        assert_eq!(None, ControlRequest::from_file_name("status"));
+    }
+}
@@ -0,0 +1,288 @@
+//! Inspects the runtime environment for problems that would prevent
+//! Ground Control (or a specification) from working.
+
+use std::{
+    collections::BTreeSet,
+    fmt,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use nix::{
+    sys::utsname::uname,
+    unistd::{access, getpid, AccessFlags},
+};
+
+use crate::config::{self, Config, SetupConfig};
+
+/// Severity of a diagnostic finding.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Severity {
+    /// Everything is as expected.
+    Ok,
+
+    /// Something may not work as expected, depending on how Ground
+    /// Control is used.
+    Warning,
+
+    /// Something will not work.
+    Error,
+}
+
+/// Result of one of the diagnostic checks.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Finding {
+    /// Severity of the finding.
+    pub severity: Severity,
+
+    /// Description of what was found (and what to do about it).
+    pub message: String,
+}
+
+impl Finding {
+    fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Ok => "ok",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{severity:<8}{}", self.message)
+    }
+}
+
+/// Inspects the runtime environment (and the specification, if one is
+/// provided) and returns the findings of every check.
+pub fn diagnose(config: Option<&Config>) -> Vec<Finding> {
+    let mut findings = vec![
+        check_pid(),
+        check_proc(),
+        check_cgroups(),
+        check_subreaper(),
+    ];
+
+    if let Some(config) = config {
+        findings.extend(check_directories(config));
+        findings.extend(check_users(config));
+        findings.extend(check_programs(config));
+        findings.extend(check_time_zones(config));
+    }
+
+    findings
+}
+
+fn check_pid() -> Finding {
+    let pid = getpid();
+    if pid.as_raw() == 1 {
+        Finding::new(Severity::Ok, "Running as PID 1")
+    } else {
+        Finding::new(
+            Severity::Warning,
+            format!("Not running as PID 1 (PID {pid}); signals sent to the container (by `docker stop`, for example) go to PID 1, not to Ground Control"),
+        )
+    }
+}
+
+fn check_proc() -> Finding {
+    if Path::new("/proc/self/stat").exists() {
+        Finding::new(Severity::Ok, "/proc is mounted")
+    } else {
+        Finding::new(
+            Severity::Error,
+            "/proc is not mounted; resource usage, `mount` conditions, and core dump handling require /proc",
+        )
+    }
+}
+
+fn check_cgroups() -> Finding {
+    if Path::new("/sys/fs/cgroup/cgroup.controllers").exists() {
+        Finding::new(Severity::Ok, "cgroup v2 is mounted at /sys/fs/cgroup")
+    } else if Path::new("/sys/fs/cgroup/memory").exists() {
+        Finding::new(Severity::Ok, "cgroup v1 is mounted at /sys/fs/cgroup")
+    } else {
+        Finding::new(
+            Severity::Warning,
+            "No cgroup filesystem is mounted at /sys/fs/cgroup; memory and CPU limits cannot be inspected",
+        )
+    }
+}
+
+fn check_subreaper() -> Finding {
+    let release = match uname() {
+        Ok(uname) => uname.release().to_string_lossy().into_owned(),
+        Err(err) => {
+            return Finding::new(
+                Severity::Warning,
+                format!("Unable to determine the kernel version: {err}"),
+            )
+        }
+    };
+
+    // Child subreapers (`PR_SET_CHILD_SUBREAPER`) were added in Linux 3.4.
+    let mut version = release
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|part| part.parse::<u32>().unwrap_or_default());
+    let (major, minor) = (
+        version.next().unwrap_or_default(),
+        version.next().unwrap_or_default(),
+    );
+    if (major, minor) >= (3, 4) {
+        Finding::new(
+            Severity::Ok,
+            format!("Kernel {release} supports child subreapers"),
+        )
+    } else {
+        Finding::new(
+            Severity::Warning,
+            format!("Kernel {release} does not support child subreapers (Linux 3.4 or later is required); orphaned processes are reparented to PID 1"),
+        )
+    }
+}
+
+/// Checks that every directory that Ground Control writes to can be
+/// created (or already exists) and is writable.
+fn check_directories(config: &Config) -> Vec<Finding> {
+    let mut directories: Vec<(String, PathBuf)> = Vec::new();
+    if let Some(control_dir) = &config.control_dir {
+        directories.push((String::from("`control-dir`"), control_dir.clone()));
+    }
+    if let Some(state_dir) = &config.state_dir {
+        directories.push((String::from("`state-dir`"), state_dir.clone()));
+    }
+    for process in config.processes.iter().filter(|process| !process.disabled) {
+        if let Some(runtime_dir) = process.runtime_dir_path() {
+            directories.push((
+                format!("runtime directory of process \"{}\"", process.name),
+                runtime_dir,
+            ));
+        }
+    }
+
+    directories
+        .into_iter()
+        .map(|(description, path)| {
+            // Directories that do not exist yet are created by Ground
+            // Control, so their nearest existing ancestor must be
+            // writable instead.
+            let existing = path
+                .ancestors()
+                .find(|ancestor| ancestor.exists())
+                .unwrap_or_else(|| Path::new("/"));
+            if access(existing, AccessFlags::W_OK).is_ok() {
+                Finding::new(
+                    Severity::Ok,
+                    format!("{description} \"{}\" is writable", path.display()),
+                )
+            } else {
+                Finding::new(
+                    Severity::Error,
+                    format!(
+                        "{description} \"{}\" is not writable (\"{}\" is read-only or owned by another user)",
+                        path.display(),
+                        existing.display()
+                    ),
+                )
+            }
+        })
+        .collect()
+}
+
+/// Checks that every user and group in the specification exists.
+fn check_users(config: &Config) -> Vec<Finding> {
+    let mut users = BTreeSet::new();
+    let mut groups = BTreeSet::new();
+    for process in config.processes.iter().filter(|process| !process.disabled) {
+        for (_, command) in process.commands() {
+            users.extend(command.user.clone());
+        }
+    }
+    for step in &config.setup {
+        if let SetupConfig::Chown { user, group, .. } = step {
+            users.extend(user.clone());
+            groups.extend(group.clone());
+        }
+    }
+
+    let users = users.into_iter().map(|user| {
+        if users::get_user_by_name(&user).is_some() {
+            Finding::new(Severity::Ok, format!("User \"{user}\" exists"))
+        } else {
+            Finding::new(
+                Severity::Error,
+                format!("User \"{user}\" does not exist (check /etc/passwd in the image)"),
+            )
+        }
+    });
+    let groups = groups.into_iter().map(|group| {
+        if users::get_group_by_name(&group).is_some() {
+            Finding::new(Severity::Ok, format!("Group \"{group}\" exists"))
+        } else {
+            Finding::new(
+                Severity::Error,
+                format!("Group \"{group}\" does not exist (check /etc/group in the image)"),
+            )
+        }
+    });
+    users.chain(groups).collect()
+}
+
+/// Checks that every program that is specified as a path exists and is
+/// executable (programs that are looked up in the `PATH` are not
+/// checked, since the `PATH` may be different when the command runs).
+fn check_programs(config: &Config) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for process in config.processes.iter().filter(|process| !process.disabled) {
+        for (phase, command) in process.commands() {
+            if !command.program.contains('/') {
+                continue;
+            }
+
+            let problem = match std::fs::metadata(&command.program) {
+                Err(_) => "does not exist",
+                Ok(metadata) if metadata.is_dir() => "is a directory",
+                Ok(metadata) if metadata.permissions().mode() & 0o111 == 0 => "is not executable",
+                Ok(_) => continue,
+            };
+            findings.push(Finding::new(
+                Severity::Error,
+                format!(
+                    "Program \"{}\" (`{phase}` command of process \"{}\") {problem}",
+                    command.program, process.name
+                ),
+            ));
+        }
+    }
+    findings
+}
+
+/// Checks that the time zone of every process exists in the tz database.
+fn check_time_zones(config: &Config) -> Vec<Finding> {
+    let timezones: BTreeSet<&str> = config
+        .processes
+        .iter()
+        .filter(|process| !process.disabled)
+        .filter_map(|process| process.timezone.as_deref())
+        .collect();
+
+    timezones
+        .into_iter()
+        .map(|timezone| {
+            if config::time_zone_path(timezone).is_file() {
+                Finding::new(Severity::Ok, format!("Time zone \"{timezone}\" exists"))
+            } else {
+                Finding::new(
+                    Severity::Error,
+                    format!("Time zone \"{timezone}\" does not exist (install the tz database in the image, or check `TZDIR`)"),
+                )
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,127 @@
+//! Lifecycle event log: every command that Ground Control starts, every
+//! daemon exit, every stop that Ground Control issues, and every change
+//! in the health of a daemon, written as
+//! JSON lines to the `event-log` file, so that post-mortems can see
+//! exactly what Ground Control did, and when.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+    time::SystemTime,
+};
+
+use color_eyre::eyre::{self, WrapErr};
+use serde::Serialize;
+
+use crate::command::ExitStatus;
+
+/// Lifecycle event of a process.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub(crate) enum Event<'a> {
+    /// One of the process's commands (other than `run`) was started.
+    #[serde(rename_all = "kebab-case")]
+    CommandStarted { phase: &'a str, pid: i32 },
+
+    /// One of the process's commands (other than `run`) exited.
+    #[serde(rename_all = "kebab-case")]
+    CommandExited {
+        phase: &'a str,
+        pid: i32,
+        exit_code: Option<i32>,
+        signal: Option<i32>,
+    },
+
+    /// The daemon (the `run` command) of the process was spawned.
+    #[serde(rename_all = "kebab-case")]
+    DaemonSpawned { pid: i32 },
+
+    /// Ground Control asked the daemon of the process to stop, with the
+    /// given signal (or `command` or `http`).
+    #[serde(rename_all = "kebab-case")]
+    StopIssued { pid: i32, stop: &'a str },
+
+    /// The daemon of the process exited; `expected` is true if Ground
+    /// Control had asked the daemon to stop.
+    #[serde(rename_all = "kebab-case")]
+    DaemonExited {
+        pid: i32,
+        exit_code: Option<i32>,
+        signal: Option<i32>,
+        expected: bool,
+    },
+
+    /// The daemon of the process became unhealthy (or healthy again),
+    /// according to its health check; `reason` describes the checks
+    /// that changed its state.
+    #[serde(rename_all = "kebab-case")]
+    HealthChanged {
+        pid: i32,
+        healthy: bool,
+        reason: &'a str,
+    },
+}
+
+/// Returns the exit code and signal (for an event) of the exit status.
+pub(crate) fn exit_fields(exit_status: ExitStatus) -> (Option<i32>, Option<i32>) {
+    match exit_status {
+        ExitStatus::Exited(exit_code) => (Some(exit_code), None),
+        ExitStatus::Killed(signal) => (None, signal),
+    }
+}
+
+/// Line in the event log.
+#[derive(Debug, Serialize)]
+struct EventLine<'a> {
+    timestamp: String,
+    process: &'a str,
+    #[serde(flatten)]
+    event: Event<'a>,
+}
+
+/// Writer of the lifecycle event log (which does nothing if there is no
+/// `event-log`).
+#[derive(Clone, Debug, Default)]
+pub(crate) struct EventLog(Option<Arc<Mutex<File>>>);
+
+impl EventLog {
+    /// Opens the event log at the given path, appending to the events of
+    /// any previous run.
+    pub(crate) fn open(path: &Path) -> eyre::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .wrap_err_with(|| format!("Failed to open event log \"{}\"", path.display()))?;
+        Ok(EventLog(Some(Arc::new(Mutex::new(file)))))
+    }
+
+    /// Writes an event of the given process to the log. Errors are
+    /// logged, but otherwise ignored, since the log is only
+    /// informational.
+    pub(crate) fn record(&self, process: &str, event: Event<'_>) {
+        let file = match &self.0 {
+            Some(file) => file,
+            None => return,
+        };
+
+        let line = EventLine {
+            timestamp: humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+            process,
+            event,
+        };
+        let result = serde_json::to_string(&line)
+            .map_err(eyre::Report::new)
+            .and_then(|line| {
+                file.lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .write_all(format!("{line}\n").as_bytes())
+                    .map_err(eyre::Report::new)
+            });
+        if let Err(err) = result {
+            tracing::warn!(?err, "Failed to write event log");
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! Exit codes of the `groundcontrol` CLI, which wrapper scripts and
+//! orchestrators can branch on (see `Error::exit_code` and
+//! `ShutdownReason::exit_code`).
+//!
+//! The codes are stable: new codes may be added, but existing codes will
+//! not change. Ground Control's own codes avoid the codes that shells
+//! use (2, and 126 and above) and the codes of `sysexits.h` (64 to 78),
+//! other than `CONFIG_ERROR`, which is `EX_CONFIG`. Configs that mirror
+//! the exit code of a daemon (`forward-signals`, or a `main` process)
+//! exit with that daemon's exit code instead.
+
+/// Ground Control shut down cleanly.
+pub const SUCCESS: i32 = 0;
+
+/// Ground Control failed for a reason that does not have a more
+/// specific exit code.
+pub const FAILURE: i32 = 1;
+
+/// The config file could not be read, parsed, or validated.
+pub const CONFIG_ERROR: i32 = 78;
+
+/// A process failed to start, so startup was aborted (and every process
+/// that had already started was stopped).
+pub const STARTUP_ABORTED: i32 = 80;
+
+/// One or more daemons failed, which shut down Ground Control.
+pub const ABNORMAL_SHUTDOWN: i32 = 81;
+
+/// A daemon could not be stopped and its `on-stop-failure` is `abort`,
+/// so the remaining processes were not stopped.
+pub const SHUTDOWN_ABORTED: i32 = 82;
+
+/// The shutdown took longer than the `shutdown-timeout`, so the
+/// remaining daemons were killed.
+pub const SHUTDOWN_TIMEOUT: i32 = 83;
+
+/// The shutdown was requested through the control directory (with the
+/// `shutdown` file) and completed cleanly.
+pub const CONTROL_SHUTDOWN: i32 = 84;
@@ -0,0 +1,333 @@
+//! Exports a Ground Control specification to other process managers.
+
+use std::fmt::Write;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::config::{CommandConfig, Config, ProcessConfig, RestartPolicy, StopMechanism};
+
+/// Converts every (enabled) process into a systemd service unit,
+/// returning the file name and contents of each unit.
+///
+/// Dependencies become `Requires=`/`Wants=` and `After=`/`Before=`
+/// relationships, and Ground Control's `{{VAR}}` expressions become
+/// systemd `${VAR}` expressions. Settings that have no systemd
+/// equivalent are listed in a comment at the top of the unit. Barriers
+/// become target units that require (and are ordered after) every
+/// process that joins them.
+pub fn systemd_units(config: &Config) -> Vec<(String, String)> {
+    let mut units: Vec<(String, String)> = config
+        .processes
+        .iter()
+        .filter(|process| !process.disabled)
+        .map(|process| {
+            (
+                unit_name(config, &process.name),
+                systemd_unit(config, process),
+            )
+        })
+        .collect();
+
+    let mut barriers: Vec<&str> = config
+        .processes
+        .iter()
+        .filter_map(|process| process.joins.as_deref())
+        .collect();
+    barriers.sort_unstable();
+    barriers.dedup();
+    units.extend(
+        barriers
+            .into_iter()
+            .map(|barrier| (unit_name(config, barrier), barrier_unit(config, barrier))),
+    );
+
+    units
+}
+
+/// Returns the name of the unit of the process (or barrier) with the
+/// given name.
+fn unit_name(config: &Config, name: &str) -> String {
+    if is_barrier(config, name) {
+        format!("{name}.target")
+    } else {
+        format!("{name}.service")
+    }
+}
+
+fn is_barrier(config: &Config, name: &str) -> bool {
+    config
+        .processes
+        .iter()
+        .any(|process| process.joins.as_deref() == Some(name))
+}
+
+fn barrier_unit(config: &Config, barrier: &str) -> String {
+    let mut unit = String::new();
+
+    let joiners: Vec<String> = config
+        .processes
+        .iter()
+        .filter(|process| !process.disabled && process.joins.as_deref() == Some(barrier))
+        .map(|process| process.name.clone())
+        .collect();
+
+    let _ = writeln!(unit, "[Unit]");
+    let _ = writeln!(
+        unit,
+        "Description={} (barrier exported from Ground Control)",
+        escape_specifiers(barrier)
+    );
+    if !joiners.is_empty() {
+        let joiners = unit_names(config, &joiners);
+        let _ = writeln!(unit, "Requires={joiners}");
+        let _ = writeln!(unit, "After={joiners}");
+    }
+
+    unit
+}
+
+fn systemd_unit(config: &Config, process: &ProcessConfig) -> String {
+    let mut unit = String::new();
+
+    let unsupported: Vec<&str> = [
+        ("main", process.main),
+        ("owner", process.owner.is_some()),
+        ("standby-for", process.standby_for.is_some()),
+        ("hold", process.hold.is_some()),
+        ("wait-for", process.wait_for.is_some()),
+        ("ready", process.ready.is_some()),
+        ("service", process.service.is_some()),
+        ("core-dump", process.core_dump.is_some()),
+        ("success-marker", process.success_marker.is_some()),
+        ("pre-stop-delay", process.pre_stop_delay.is_some()),
+        ("restart-backoff", process.restart_backoff.is_some()),
+        ("restart-schedule", process.restart_schedule.is_some()),
+        ("health-check", process.health_check.is_some()),
+        ("stop.http", matches!(process.stop, StopMechanism::Http(_))),
+        (
+            "script",
+            process
+                .commands()
+                .iter()
+                .any(|(_, command)| command.script.is_some()),
+        ),
+        (
+            "restart-on-exit-codes",
+            process.restart_on_exit_codes.is_some() || process.restart_except_exit_codes.is_some(),
+        ),
+    ]
+    .into_iter()
+    .filter(|(_, present)| *present)
+    .map(|(key, _)| key)
+    .collect();
+    if !unsupported.is_empty() {
+        let _ = writeln!(
+            unit,
+            "# Not exported (no systemd equivalent): {}",
+            unsupported.join(", ")
+        );
+    }
+
+    // Dependencies.
+    let _ = writeln!(unit, "[Unit]");
+    let description = match &process.description {
+        Some(description) => format!("{}: {description}", process.name),
+        None => process.name.clone(),
+    };
+    let _ = writeln!(
+        unit,
+        "Description={} (exported from Ground Control)",
+        escape_specifiers(&description)
+    );
+    for (key, names) in [("Requires", &process.depends_on), ("Wants", &process.wants)] {
+        if !names.is_empty() {
+            let _ = writeln!(unit, "{key}={}", unit_names(config, names));
+        }
+    }
+    if let Some(target) = &process.bound_to {
+        let _ = writeln!(
+            unit,
+            "BindsTo={}",
+            unit_names(config, std::slice::from_ref(target))
+        );
+    }
+    let ordering: Vec<String> = process
+        .depends_on
+        .iter()
+        .chain(&process.wants)
+        .chain(&process.after)
+        .chain(&process.bound_to)
+        .cloned()
+        .collect();
+    if !ordering.is_empty() {
+        let _ = writeln!(unit, "After={}", unit_names(config, &ordering));
+    }
+    if !process.before.is_empty() {
+        let _ = writeln!(unit, "Before={}", unit_names(config, &process.before));
+    }
+
+    // systemd's start limit counts every start, including the first one.
+    if let Some(limit) = &process.restart_limit {
+        let _ = writeln!(
+            unit,
+            "StartLimitIntervalSec={}",
+            humantime::format_duration(limit.window)
+        );
+        let _ = writeln!(unit, "StartLimitBurst={}", limit.restarts.saturating_add(1));
+    }
+
+    // Commands. One-shot processes run their `pre` command as the
+    // service's only command, and then remain "active" so that services
+    // that depend on them can start.
+    let _ = writeln!(unit, "\n[Service]");
+    let main_command = match (&process.run, &process.pre) {
+        (Some(run), pre) => {
+            let _ = writeln!(unit, "Type=simple");
+            if let Some(pre) = pre {
+                let _ = writeln!(unit, "ExecStartPre={}", exec_line(pre));
+            }
+            Some(run)
+        }
+        (None, pre) => {
+            let _ = writeln!(unit, "Type=oneshot");
+            let _ = writeln!(unit, "RemainAfterExit=yes");
+            pre.as_ref()
+        }
+    };
+
+    if let Some(command) = main_command {
+        if let Some(user) = &command.user {
+            let _ = writeln!(unit, "User={user}");
+        }
+        let _ = writeln!(unit, "ExecStart={}", exec_line(command));
+    } else {
+        let _ = writeln!(unit, "ExecStart=/bin/true");
+    }
+    if let Some(post_start) = &process.post_start {
+        let _ = writeln!(unit, "ExecStartPost={}", exec_line(post_start));
+    }
+
+    // systemd runs every `ExecStop` command (in order) before it sends
+    // the `KillSignal` to whatever is still running.
+    if process.run.is_some() {
+        if let Some(pre_stop) = &process.pre_stop {
+            let _ = writeln!(unit, "ExecStop={}", exec_line(pre_stop));
+        }
+        match &process.stop {
+            StopMechanism::Signal(signal) => {
+                let _ = writeln!(
+                    unit,
+                    "KillSignal={}",
+                    nix::sys::signal::Signal::from(signal).as_str()
+                );
+            }
+            StopMechanism::Command(command) => {
+                let _ = writeln!(unit, "ExecStop={}", exec_line(command));
+            }
+            StopMechanism::Http(http) => {
+                let _ = writeln!(
+                    unit,
+                    "KillSignal={}",
+                    nix::sys::signal::Signal::from(http.then_signal).as_str()
+                );
+            }
+        }
+    }
+
+    if let Some(max_runtime) = process.max_runtime {
+        let _ = writeln!(
+            unit,
+            "RuntimeMaxSec={}",
+            humantime::format_duration(max_runtime)
+        );
+    }
+
+    match process.restart {
+        RestartPolicy::Never => {}
+        RestartPolicy::OnFailure => {
+            let _ = writeln!(unit, "Restart=on-failure");
+        }
+        RestartPolicy::Always => {
+            let _ = writeln!(unit, "Restart=always");
+        }
+    }
+
+    if let Some(post) = &process.post {
+        let _ = writeln!(unit, "ExecStopPost={}", exec_line(post));
+    }
+
+    if let Some(runtime_dir) = process.runtime_dir_path() {
+        if let Ok(relative) = runtime_dir.strip_prefix("/run") {
+            let _ = writeln!(unit, "RuntimeDirectory={}", relative.display());
+        }
+    }
+
+    if let Some(stdin) = &process.stdin {
+        let _ = writeln!(unit, "StandardInput=file:{}", stdin.file.display());
+    }
+
+    // Environment (sorted, so that the output is stable), including the
+    // process's time zone.
+    let mut env: Vec<(&str, &str)> = config
+        .env
+        .iter()
+        .filter(|(key, _)| process.timezone.is_none() || key.as_str() != "TZ")
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect();
+    env.extend(process.timezone.as_deref().map(|timezone| ("TZ", timezone)));
+    env.sort();
+    for (key, value) in env {
+        let _ = writeln!(
+            unit,
+            "Environment={}",
+            quote(&escape_specifiers(&format!("{key}={value}")))
+        );
+    }
+
+    let _ = writeln!(unit, "\n[Install]");
+    let _ = writeln!(unit, "WantedBy=multi-user.target");
+
+    unit
+}
+
+fn unit_names(config: &Config, names: &[String]) -> String {
+    names
+        .iter()
+        .map(|name| unit_name(config, name))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Converts a command into a systemd command line, quoting every
+/// argument and converting `{{VAR}}` expressions into `${VAR}` (and
+/// argument-splitting `{{@VAR}}` expressions into `$VAR`, which systemd
+/// splits on whitespace).
+fn exec_line(command: &CommandConfig) -> String {
+    static TEMPLATE_VAR_REGEX: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"\{\{ *([A-Za-z0-9_]+) *\}\}").expect("regex should be valid"));
+    static SPLIT_VAR_REGEX: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^\{\{ *@([A-Za-z0-9_]+) *\}\}$").expect("regex should be valid"));
+
+    std::iter::once(&command.program)
+        .chain(&command.args)
+        .map(|arg| match SPLIT_VAR_REGEX.captures(arg) {
+            Some(caps) => format!("${}", &caps[1]),
+            None => {
+                let arg = escape_specifiers(arg).replace('$', "$$");
+                quote(&TEMPLATE_VAR_REGEX.replace_all(&arg, "$${${1}}"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Escapes systemd's `%` specifiers.
+fn escape_specifiers(s: &str) -> String {
+    s.replace('%', "%%")
+}
+
+/// Quotes a value using systemd's (C-style) quoting rules.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,67 @@
+//! Limits the file descriptors that commands inherit from Ground
+//! Control.
+//!
+//! Every file that Ground Control opens itself is already closed when a
+//! command is executed (the standard library opens everything with
+//! `O_CLOEXEC`), but file descriptors that Ground Control *inherited*
+//! are not, so every command would otherwise inherit them in turn
+//! (keeping sockets open long after Ground Control has exited, for
+//! example).
+
+use std::{fs, os::unix::io::RawFd};
+
+use color_eyre::eyre::{self, WrapErr};
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+
+/// Directory that lists the open file descriptors of Ground Control.
+const FD_DIR: &str = "/proc/self/fd";
+
+/// Marks every open file descriptor (other than stdin, stdout, and
+/// stderr) as close-on-exec, except for `pass_fds`, which are instead
+/// made inheritable. Every file descriptor that changes is logged,
+/// along with the file (or socket) that it refers to.
+pub(crate) fn restrict_inheritance(pass_fds: &[RawFd]) -> eyre::Result<()> {
+    let fds: Vec<RawFd> = fs::read_dir(FD_DIR)
+        .wrap_err("Failed to list the open file descriptors")?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .filter(|fd| *fd > 2)
+        .collect();
+
+    for fd in &fds {
+        // Skip file descriptors that are no longer open (such as the
+        // one that was used to list the file descriptors).
+        let flags = match fcntl(*fd, FcntlArg::F_GETFD) {
+            Ok(flags) => FdFlag::from_bits_truncate(flags),
+            Err(_) => continue,
+        };
+
+        let pass = pass_fds.contains(fd);
+        if pass == flags.contains(FdFlag::FD_CLOEXEC) {
+            let flags = flags ^ FdFlag::FD_CLOEXEC;
+            fcntl(*fd, FcntlArg::F_SETFD(flags))
+                .wrap_err_with(|| format!("Failed to change the flags of file descriptor {fd}"))?;
+        }
+
+        let target = target(*fd);
+        if pass {
+            tracing::info!(%fd, %target, "Passing file descriptor to commands");
+        } else if !flags.contains(FdFlag::FD_CLOEXEC) {
+            tracing::info!(%fd, %target, "Closing inherited file descriptor in commands");
+        }
+    }
+
+    for fd in pass_fds.iter().filter(|fd| !fds.contains(fd)) {
+        tracing::warn!(%fd, "File descriptor in `pass-fds` is not open");
+    }
+
+    Ok(())
+}
+
+/// Returns the file (or socket, pipe, etc.) to which a file descriptor
+/// refers, for logging purposes.
+fn target(fd: RawFd) -> String {
+    match fs::read_link(format!("{FD_DIR}/{fd}")) {
+        Ok(target) => target.display().to_string(),
+        Err(_) => String::from("<unknown>"),
+    }
+}
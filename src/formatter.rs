@@ -40,7 +40,7 @@ impl GroundControlFormatter {
     /// processes).
     pub fn from_config(config: &Config) -> Self {
         // Assign a style to every phase of every daemon process.
-        let styles = vec![
+        let styles = [
             Style::new().green().bold(),
             Style::new().blue().bold(),
             Style::new().yellow().bold(),
@@ -154,13 +154,15 @@ struct EventVisitor {
 
 impl Visit for EventVisitor {
     fn record_str(&mut self, field: &Field, value: &str) {
-        write!(self.fields, " {}={}", field.name(), value).unwrap();
+        write!(self.fields, " {}={}", field.name(), value)
+            .expect("writing to a String should not fail");
     }
 
     fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
         match field.name() {
             "message" => self.message = format!(" {value:?}"),
-            _ => write!(self.fields, " {}={:?}", field.name(), value).unwrap(),
+            _ => write!(self.fields, " {}={:?}", field.name(), value)
+                .expect("writing to a String should not fail"),
         }
     }
 }
@@ -177,14 +179,16 @@ impl Visit for ConsoleOutputVisitor {
         match field.name() {
             "process" => self.process = value.to_string(),
             "output" => self.message = format!(" {value}"),
-            _ => write!(self.fields, " {}={}", field.name(), value).unwrap(),
+            _ => write!(self.fields, " {}={}", field.name(), value)
+                .expect("writing to a String should not fail"),
         }
     }
 
     fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
         match field.name() {
             "message" => self.message = format!(" {value:?}"),
-            _ => write!(self.fields, " {}={:?}", field.name(), value).unwrap(),
+            _ => write!(self.fields, " {}={:?}", field.name(), value)
+                .expect("writing to a String should not fail"),
         }
     }
 }
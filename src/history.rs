@@ -0,0 +1,270 @@
+//! Rolling history of daemon exits, stored in the state directory so
+//! that failures can be investigated after the fact. Failed daemons
+//! also get a snapshot of their last-known resource usage and most
+//! recent output (a black-box recorder for post-mortems).
+
+use std::{
+    fmt,
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::SystemTime,
+};
+
+use color_eyre::eyre::{self, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::{usage::ResourceUsage, ShutdownReason};
+
+/// Name of the history file in the state directory.
+const HISTORY_FILE: &str = "exit-history.log";
+
+/// Maximum number of entries kept in the history file (older entries
+/// are discarded).
+const MAX_ENTRIES: usize = 1000;
+
+/// What Ground Control did after a daemon exited.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ExitDecision {
+    /// The daemon was restarted.
+    Restarted,
+
+    /// The daemon was replaced by its standby process.
+    StandbyStarted,
+
+    /// Ground Control shut down.
+    ShutDown,
+}
+
+impl fmt::Display for ExitDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExitDecision::Restarted => write!(f, "restarted"),
+            ExitDecision::StandbyStarted => write!(f, "standby started"),
+            ExitDecision::ShutDown => write!(f, "shut down"),
+        }
+    }
+}
+
+/// Entry in the exit history.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// Time of the exit (RFC 3339, UTC).
+    pub timestamp: String,
+
+    /// Name of the process whose daemon exited.
+    pub process: String,
+
+    /// Exit status of the daemon.
+    pub status: String,
+
+    /// What Ground Control did after the daemon exited.
+    pub decision: String,
+
+    /// Last-known state of the daemon, if the daemon failed.
+    pub snapshot: Option<ExitSnapshot>,
+}
+
+impl fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}",
+            self.timestamp, self.process, self.status, self.decision
+        )?;
+        if let Some(snapshot) = &self.snapshot {
+            // JSON escapes any tabs and newlines in the output, which
+            // keeps the entry on a single line.
+            let snapshot = serde_json::to_string(snapshot).map_err(|_| fmt::Error)?;
+            write!(f, "\t{snapshot}")?;
+        }
+        Ok(())
+    }
+}
+
+impl HistoryEntry {
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(5, '\t');
+        Some(HistoryEntry {
+            timestamp: fields.next()?.to_string(),
+            process: fields.next()?.to_string(),
+            status: fields.next()?.to_string(),
+            decision: fields.next()?.to_string(),
+            snapshot: fields
+                .next()
+                .and_then(|snapshot| serde_json::from_str(snapshot).ok()),
+        })
+    }
+}
+
+/// Last-known state of a daemon that failed: its resource usage (as of
+/// the last sample, which is taken every second) and its most recent
+/// output.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExitSnapshot {
+    /// Resident set size, in kibibytes (if the daemon was sampled).
+    pub rss_kib: Option<u64>,
+
+    /// Number of threads (if the daemon was sampled).
+    pub threads: Option<u64>,
+
+    /// Number of open file descriptors (if the daemon was sampled, and
+    /// Ground Control was allowed to list them).
+    pub open_fds: Option<u64>,
+
+    /// Most recent lines of output (stdout and stderr), oldest first.
+    pub output: Vec<String>,
+}
+
+/// Details of a daemon failure that are added to the entry that records
+/// the failure in the exit history.
+#[derive(Debug, Default)]
+pub(crate) struct FailureDetails {
+    /// Last-known state of the daemon.
+    pub(crate) snapshot: Option<ExitSnapshot>,
+
+    /// Panic message of the task that supervised the daemon, if one of
+    /// its tasks panicked.
+    pub(crate) panic: Option<String>,
+}
+
+/// Failure details of the daemon of a single process, which are noted
+/// by the tasks that supervise the daemon until they are recorded in the
+/// exit history (or discarded). Each process has its own notes, so that
+/// the failure details never end up with another process (or spec) that
+/// uses the same name.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FailureNotes(Arc<Mutex<FailureDetails>>);
+
+impl FailureNotes {
+    /// Notes the last-known state of the daemon, which failed.
+    pub(crate) fn note_snapshot(&self, usage: ResourceUsage, output: Vec<String>) {
+        // Sampled processes always have at least one thread.
+        let sampled = usage.threads > 0;
+        self.lock().snapshot = Some(ExitSnapshot {
+            rss_kib: Some(usage.rss_kib).filter(|_| sampled),
+            threads: Some(usage.threads).filter(|_| sampled),
+            open_fds: usage.open_fds.filter(|_| sampled),
+            output,
+        });
+    }
+
+    /// Notes the panic message of a task that supervised the daemon.
+    pub(crate) fn note_panic(&self, message: String) {
+        self.lock().panic = Some(message);
+    }
+
+    /// Removes (and returns) the details of the daemon's failure.
+    pub(crate) fn take(&self) -> FailureDetails {
+        std::mem::take(&mut *self.lock())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, FailureDetails> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Adds the daemon exit (and the decision that was made because of the
+/// exit, along with the details of the daemon's failure) to the history
+/// in the state directory, if there is one. Errors are logged, but
+/// otherwise ignored, since the history is only informational.
+pub(crate) async fn record(
+    state_dir: Option<&Path>,
+    shutdown_reason: &ShutdownReason,
+    decision: ExitDecision,
+    failure: FailureDetails,
+) {
+    let state_dir = match state_dir {
+        Some(state_dir) => state_dir,
+        None => return,
+    };
+
+    let (process, status) = match shutdown_reason {
+        ShutdownReason::GracefulShutdown | ShutdownReason::ControlShutdown => return,
+        ShutdownReason::DaemonExited { process } => (process, String::from("exited cleanly")),
+        ShutdownReason::DaemonFailed {
+            process,
+            exit_code: Some(exit_code),
+            ..
+        } => (process, format!("exit code {exit_code}")),
+        ShutdownReason::DaemonFailed {
+            process,
+            signal: Some(signal),
+            ..
+        } => (process, format!("killed by signal {signal}")),
+        ShutdownReason::DaemonFailed { process, .. } => (process, String::from("killed")),
+    };
+    let status = match failure.panic {
+        Some(panic) => format!("{status}; supervision task panicked: {panic}"),
+        None => status,
+    };
+
+    let entry = HistoryEntry {
+        timestamp: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        process: process.clone(),
+        status,
+        decision: decision.to_string(),
+        snapshot: failure.snapshot,
+    };
+
+    if let Err(err) = append(state_dir, &entry).await {
+        tracing::warn!(?err, "Failed to record exit history");
+    }
+}
+
+/// Appends the entry to the history file, discarding the oldest entries
+/// if the history has grown too large.
+async fn append(state_dir: &Path, entry: &HistoryEntry) -> eyre::Result<()> {
+    tokio::fs::create_dir_all(state_dir)
+        .await
+        .wrap_err_with(|| {
+            format!(
+                "Failed to create state directory \"{}\"",
+                state_dir.display()
+            )
+        })?;
+
+    let path = state_dir.join(HISTORY_FILE);
+    let history = match tokio::fs::read_to_string(&path).await {
+        Ok(history) => history,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err).wrap_err("Failed to read exit history"),
+    };
+
+    let entry = entry.to_string();
+    let mut lines: Vec<&str> = history.lines().collect();
+    lines.push(&entry);
+    let first = lines.len().saturating_sub(MAX_ENTRIES);
+
+    // Write the new history to a temporary file and then rename it, so
+    // that the history is never left half-written.
+    let temp_path = state_dir.join(format!("{HISTORY_FILE}.tmp"));
+    tokio::fs::write(&temp_path, lines[first..].join("\n") + "\n")
+        .await
+        .wrap_err("Failed to write exit history")?;
+    tokio::fs::rename(&temp_path, &path)
+        .await
+        .wrap_err("Failed to replace exit history")?;
+
+    Ok(())
+}
+
+/// Reads the exit history for the given process (oldest entries first)
+/// from the state directory.
+pub fn read(state_dir: &Path, process: &str) -> eyre::Result<Vec<HistoryEntry>> {
+    let path = state_dir.join(HISTORY_FILE);
+    let history = match std::fs::read_to_string(&path) {
+        Ok(history) => history,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(err)
+                .wrap_err_with(|| format!("Failed to read exit history \"{}\"", path.display()))
+        }
+    };
+
+    Ok(history
+        .lines()
+        .filter_map(HistoryEntry::parse)
+        .filter(|entry| entry.process == process)
+        .collect())
+}
@@ -0,0 +1,109 @@
+//! Holds the startup of a process until it is released by an external
+//! approval: a release file appears, a `release.<name>` file is dropped
+//! into the control directory, or the `Supervisor` releases the hold.
+
+use std::{path::Path, time::Duration};
+
+use color_eyre::eyre::{self, eyre};
+use tokio::sync::mpsc;
+
+use crate::{config::HoldConfig, control, control::ControlRequest};
+
+/// Time between checks for the release files.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Outcome of waiting for a hold to be released.
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) enum Release {
+    /// The hold was released; the process can be started.
+    Released,
+
+    /// A shutdown was requested while waiting for the hold to be
+    /// released.
+    ShutdownRequested,
+}
+
+/// Waits for the hold on the process to be released, returning an error
+/// if the hold is still in place when its timeout expires. Requests that
+/// arrive through `control_requests` while waiting (other than the one
+/// that releases this hold) are added to `deferred`, where a release of
+/// this process's hold that arrived earlier is also found.
+pub(crate) async fn wait_for_release(
+    process_name: &str,
+    config: &HoldConfig,
+    control_dir: Option<&Path>,
+    shutdown: &mut mpsc::UnboundedReceiver<()>,
+    control_requests: &mut mpsc::UnboundedReceiver<ControlRequest>,
+    deferred: &mut Vec<ControlRequest>,
+) -> eyre::Result<Release> {
+    if take_release(process_name, deferred) {
+        tracing::info!(process = %process_name, "Hold already released");
+        return Ok(Release::Released);
+    }
+
+    tracing::warn!(process = %process_name, file = ?config.file, "Holding startup until the process is released");
+
+    let wait = async {
+        loop {
+            if is_released(process_name, config, control_dir).await {
+                return Release::Released;
+            }
+
+            tokio::select! {
+                _ = shutdown.recv() => return Release::ShutdownRequested,
+                Some(request) = control_requests.recv() => {
+                    deferred.push(request);
+                    if take_release(process_name, deferred) {
+                        return Release::Released;
+                    }
+                }
+                () = tokio::time::sleep(POLL_INTERVAL) => {}
+            }
+        }
+    };
+
+    let release = match config.timeout {
+        Some(timeout) => tokio::time::timeout(timeout, wait).await.map_err(|_| {
+            eyre!(
+                "Timed out after {} waiting for process \"{process_name}\" to be released",
+                humantime::format_duration(timeout)
+            )
+        })?,
+        None => wait.await,
+    };
+
+    if release == Release::Released {
+        tracing::info!(process = %process_name, "Hold released");
+    }
+    Ok(release)
+}
+
+/// Removes the request that releases the hold on the process from the
+/// deferred requests, returning true if there was one.
+fn take_release(process_name: &str, deferred: &mut Vec<ControlRequest>) -> bool {
+    match deferred.iter().position(
+        |request| matches!(request, ControlRequest::Release(name) if name == process_name),
+    ) {
+        Some(index) => {
+            deferred.remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns true if the hold's release file exists, or if a release file
+/// for the process has been dropped into the control directory (which is
+/// removed, since the control directory is not watched during startup).
+async fn is_released(process_name: &str, config: &HoldConfig, control_dir: Option<&Path>) -> bool {
+    if let Some(file) = &config.file {
+        if tokio::fs::metadata(file).await.is_ok() {
+            return true;
+        }
+    }
+
+    match control_dir {
+        Some(control_dir) => control::take_release_file(control_dir, process_name).await,
+        None => false,
+    }
+}
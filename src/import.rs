@@ -0,0 +1,570 @@
+//! Imports process definitions from other process managers, producing
+//! Ground Control specifications (as TOML text).
+
+use std::collections::BTreeMap;
+
+use color_eyre::eyre::{self, eyre, WrapErr};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
+
+/// Process converted from another process manager.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct ImportedProcess {
+    name: String,
+    disabled: bool,
+    depends_on: Vec<String>,
+    user: Option<String>,
+    run: Option<Vec<String>>,
+    stop: Option<String>,
+    restart: Option<&'static str>,
+    restart_except_exit_codes: Option<Vec<i32>>,
+
+    /// Settings that could not be converted (written as comments above
+    /// the process, so that they can be reviewed by hand).
+    notes: Vec<String>,
+}
+
+/// Signals that Ground Control can use to stop a process.
+const STOP_SIGNALS: [&str; 3] = ["SIGINT", "SIGQUIT", "SIGTERM"];
+
+/// Converts the services in a Docker Compose file into a Ground Control
+/// specification.
+///
+/// `command` and `entrypoint` become the `run` command, `depends_on`
+/// becomes `depends-on`, `environment` is added to the (global) `env`,
+/// and `stop_signal`, `user`, and `restart` are converted where Ground
+/// Control has an equivalent. Everything else -- including
+/// `healthcheck`, which Ground Control does not support -- is listed in
+/// a comment above the process.
+pub fn compose(yaml: &str) -> eyre::Result<String> {
+    let compose: ComposeFile = serde_yaml::from_str(yaml).wrap_err("Invalid Compose file")?;
+
+    let mut env = BTreeMap::new();
+    let mut notes = Vec::new();
+    let mut processes = Vec::with_capacity(compose.services.len());
+    for (name, service) in compose.services {
+        let name = match name {
+            serde_yaml::Value::String(name) => name,
+            name => return Err(eyre!("Invalid service name {name:?}")),
+        };
+        let service: ComposeService = serde_yaml::from_value(service)
+            .wrap_err_with(|| format!("Invalid service \"{name}\""))?;
+
+        let mut process = ImportedProcess {
+            name: name.clone(),
+            user: service.user,
+            ..Default::default()
+        };
+
+        let mut run = Vec::new();
+        for (key, command) in [
+            ("entrypoint", service.entrypoint),
+            ("command", service.command),
+        ] {
+            match command {
+                Some(StringOrList::List(args)) => run.extend(args),
+                Some(StringOrList::String(command)) => run.extend(
+                    shell_words::split(&command)
+                        .wrap_err_with(|| format!("Invalid `{key}` in service \"{name}\""))?,
+                ),
+                None => {}
+            }
+        }
+        if run.is_empty() {
+            process.notes.push(String::from(
+                "no `command` (the image's default command must be added by hand)",
+            ));
+        } else {
+            process.run = Some(run);
+        }
+
+        process.depends_on = match service.depends_on {
+            Some(DependsOn::List(names)) => names,
+            Some(DependsOn::Map(names)) => names.into_keys().collect(),
+            None => Vec::new(),
+        };
+
+        for (key, value) in service
+            .environment
+            .map(Environment::into_vars)
+            .unwrap_or_default()
+        {
+            match value {
+                // Ground Control passes its own environment through to
+                // every process, so there is nothing to convert.
+                None => {}
+                Some(value) => match env.get(&key) {
+                    Some(existing) if *existing != value => notes.push(format!(
+                        "`{key}` has different values in different services (using the value from the first service)"
+                    )),
+                    Some(_) => {}
+                    None => {
+                        env.insert(key, value);
+                    }
+                },
+            }
+        }
+
+        if let Some(stop_signal) = service.stop_signal {
+            let stop_signal = if stop_signal.starts_with("SIG") {
+                stop_signal
+            } else {
+                format!("SIG{stop_signal}")
+            };
+            if STOP_SIGNALS.contains(&stop_signal.as_str()) {
+                process.stop = Some(stop_signal);
+            } else {
+                process
+                    .notes
+                    .push(format!("unsupported `stop_signal` {stop_signal}"));
+            }
+        }
+
+        match service.restart.as_deref() {
+            None | Some("no") => {}
+            Some("on-failure") => process.restart_except_exit_codes = Some(Vec::new()),
+            Some(_) => process.restart = Some("always"),
+        }
+
+        if service.healthcheck.is_some() {
+            process
+                .notes
+                .push(String::from("`healthcheck` is not supported"));
+        }
+        for key in service.other.keys() {
+            process.notes.push(format!("`{key}` is not supported"));
+        }
+
+        processes.push(process);
+    }
+
+    Ok(to_toml(&notes, &env, &processes))
+}
+
+/// Converts the process types in a Heroku-style Procfile (`web: ...`,
+/// `worker: ...`) into a Ground Control specification.
+///
+/// Commands that use shell features (variables, pipes, redirection,
+/// etc.) are run with `/bin/sh -c`, as they would be on Heroku; all
+/// other commands are split into arguments and run directly.
+pub fn procfile(procfile: &str) -> eyre::Result<String> {
+    static PROCESS_REGEX: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^([A-Za-z0-9_-]+)\s*:\s*(.+)$").expect("regex should be valid"));
+
+    let mut processes = Vec::new();
+    for (index, line) in procfile.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let caps = PROCESS_REGEX
+            .captures(line)
+            .ok_or_else(|| eyre!("Invalid Procfile entry on line {}: \"{line}\"", index + 1))?;
+
+        let command = caps[2].trim();
+        let run = if command.contains(|c| "$`|&;<>(){}*?~\\".contains(c)) {
+            vec![
+                String::from("/bin/sh"),
+                String::from("-c"),
+                command.to_string(),
+            ]
+        } else {
+            shell_words::split(command)
+                .wrap_err_with(|| format!("Invalid command on line {}", index + 1))?
+        };
+
+        processes.push(ImportedProcess {
+            name: caps[1].to_string(),
+            run: Some(run),
+            ..Default::default()
+        });
+    }
+
+    Ok(to_toml(&[], &BTreeMap::new(), &processes))
+}
+
+/// Converts the `[program:x]` sections of a `supervisord.conf` file into
+/// a Ground Control specification.
+///
+/// `command`, `user`, `stopsignal`, `environment`, `autostart`, and
+/// `autorestart` (along with `exitcodes`) are converted, and programs
+/// are ordered by their `priority`. `%(ENV_X)s` expressions become
+/// `{{X}}` expressions. Other settings, and other types of sections,
+/// are listed in comments.
+pub fn supervisord(conf: &str) -> eyre::Result<String> {
+    let mut env = BTreeMap::new();
+    let mut notes = Vec::new();
+    let mut programs = Vec::new();
+    for (section, settings) in parse_ini(conf)? {
+        match section.strip_prefix("program:") {
+            Some(name) => programs.push(supervisord_program(
+                name.trim(),
+                settings,
+                &mut env,
+                &mut notes,
+            )?),
+            None if section == "supervisord" || section == "supervisorctl" => {}
+            None => notes.push(format!("section `[{section}]` is not supported")),
+        }
+    }
+
+    // Lower priorities start first (and the sort is stable, so programs
+    // with the same priority remain in file order).
+    programs.sort_by_key(|(priority, _)| *priority);
+    let processes: Vec<ImportedProcess> =
+        programs.into_iter().map(|(_, process)| process).collect();
+
+    Ok(to_toml(&notes, &env, &processes))
+}
+
+/// Converts a single `[program:x]` section, returning the program's
+/// priority and process.
+fn supervisord_program(
+    name: &str,
+    settings: IniSettings,
+    env: &mut BTreeMap<String, String>,
+    notes: &mut Vec<String>,
+) -> eyre::Result<(i64, ImportedProcess)> {
+    let mut process = ImportedProcess {
+        name: name.to_string(),
+        ..Default::default()
+    };
+    let mut priority = 999;
+    let mut autorestart = String::from("unexpected");
+    let mut exitcodes = vec![0];
+
+    for (key, value) in settings {
+        let value = supervisord_expand(name, &value);
+        match key.as_str() {
+            "command" => {
+                process.run = Some(
+                    shell_words::split(&value)
+                        .wrap_err_with(|| format!("Invalid `command` in program \"{name}\""))?,
+                )
+            }
+            "user" => process.user = Some(value),
+            "priority" => {
+                priority = value
+                    .parse()
+                    .wrap_err_with(|| format!("Invalid `priority` in program \"{name}\""))?
+            }
+            "autostart" => process.disabled = !parse_bool(&value),
+            "autorestart" => autorestart = value.to_lowercase(),
+            "exitcodes" => {
+                exitcodes = value
+                    .split(',')
+                    .map(|code| code.trim().parse())
+                    .collect::<Result<_, _>>()
+                    .wrap_err_with(|| format!("Invalid `exitcodes` in program \"{name}\""))?
+            }
+            "stopsignal" => {
+                let signal = format!("SIG{}", value.to_uppercase().trim_start_matches("SIG"));
+                if STOP_SIGNALS.contains(&signal.as_str()) {
+                    process.stop = Some(signal);
+                } else {
+                    process
+                        .notes
+                        .push(format!("unsupported `stopsignal` {value}"));
+                }
+            }
+            "environment" => {
+                for (key, value) in parse_supervisord_env(&value) {
+                    match env.get(&key) {
+                        Some(existing) if *existing != value => notes.push(format!(
+                            "`{key}` has different values in different programs (using the value from the first program)"
+                        )),
+                        Some(_) => {}
+                        None => {
+                            env.insert(key, value);
+                        }
+                    }
+                }
+            }
+            key => process.notes.push(format!("`{key}` is not supported")),
+        }
+    }
+
+    // Ground Control only restarts daemons by exit code after failures
+    // (non-zero exit codes), so the "expected" exit codes become the
+    // exceptions.
+    match autorestart.as_str() {
+        "unexpected" => process.restart_except_exit_codes = Some(exitcodes),
+        "false" => {}
+        _ => process.restart = Some("always"),
+    }
+
+    if process.run.is_none() {
+        process.notes.push(String::from(
+            "no `command` (the command must be added by hand)",
+        ));
+    }
+
+    Ok((priority, process))
+}
+
+/// Replaces supervisord's `%(ENV_X)s` expressions with Ground Control's
+/// `{{X}}` expressions, and `%(program_name)s` with the program's name.
+fn supervisord_expand(name: &str, value: &str) -> String {
+    static ENV_REGEX: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"%\(ENV_([A-Za-z0-9_]+)\)s").expect("regex should be valid"));
+
+    ENV_REGEX
+        .replace_all(value, "{{$1}}")
+        .replace("%(program_name)s", name)
+}
+
+/// Parses supervisord's `KEY="value",KEY2=value2` environment syntax.
+fn parse_supervisord_env(value: &str) -> Vec<(String, String)> {
+    static VAR_REGEX: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r#"([A-Za-z0-9_]+)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^,]*))"#)
+            .expect("regex should be valid")
+    });
+
+    VAR_REGEX
+        .captures_iter(value)
+        .map(|caps| {
+            let value = caps
+                .get(2)
+                .or_else(|| caps.get(3))
+                .or_else(|| caps.get(4))
+                .map_or("", |value| value.as_str());
+            (caps[1].to_string(), value.trim().to_string())
+        })
+        .collect()
+}
+
+fn parse_bool(value: &str) -> bool {
+    matches!(value.to_lowercase().as_str(), "true" | "yes" | "on" | "1")
+}
+
+/// Settings (key and value) in an INI section, in file order.
+type IniSettings = Vec<(String, String)>;
+
+/// Parses an INI file into its sections (in file order), each with its
+/// settings (in file order). Indented lines continue the previous value.
+fn parse_ini(text: &str) -> eyre::Result<Vec<(String, IniSettings)>> {
+    let mut sections: Vec<(String, IniSettings)> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with(';') || trimmed.starts_with('#') {
+            continue;
+        }
+
+        if let Some(section) = trimmed
+            .strip_prefix('[')
+            .and_then(|section| section.strip_suffix(']'))
+        {
+            sections.push((section.trim().to_string(), Vec::new()));
+            continue;
+        }
+
+        let settings = match sections.last_mut() {
+            Some((_, settings)) => settings,
+            None => return Err(eyre!("Setting outside of a section on line {}", index + 1)),
+        };
+
+        // Continuation of the previous value.
+        if line.starts_with(char::is_whitespace) {
+            if let Some((_, value)) = settings.last_mut() {
+                value.push(' ');
+                value.push_str(trimmed);
+                continue;
+            }
+        }
+
+        let (key, value) = trimmed
+            .split_once('=')
+            .ok_or_else(|| eyre!("Invalid setting on line {}: \"{trimmed}\"", index + 1))?;
+
+        // Strip inline comments (which must be preceded by whitespace).
+        let value = match value.find(" ;") {
+            Some(comment) => &value[..comment],
+            None => value,
+        };
+
+        settings.push((key.trim().to_lowercase(), value.trim().to_string()));
+    }
+
+    Ok(sections)
+}
+
+/// Docker Compose file (only the parts that are converted).
+#[derive(Deserialize)]
+struct ComposeFile {
+    /// Services, kept as a mapping so that the services remain in the
+    /// order in which they were defined.
+    #[serde(default)]
+    services: serde_yaml::Mapping,
+}
+
+#[derive(Deserialize)]
+struct ComposeService {
+    #[serde(default)]
+    command: Option<StringOrList>,
+
+    #[serde(default)]
+    entrypoint: Option<StringOrList>,
+
+    #[serde(default)]
+    depends_on: Option<DependsOn>,
+
+    #[serde(default)]
+    environment: Option<Environment>,
+
+    #[serde(default)]
+    stop_signal: Option<String>,
+
+    #[serde(default)]
+    user: Option<String>,
+
+    #[serde(default)]
+    restart: Option<String>,
+
+    #[serde(default)]
+    healthcheck: Option<serde_yaml::Value>,
+
+    /// Settings that are not converted (`image`, `ports`, etc.).
+    #[serde(flatten)]
+    other: BTreeMap<String, serde_yaml::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringOrList {
+    String(String),
+    List(Vec<String>),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DependsOn {
+    List(Vec<String>),
+    Map(BTreeMap<String, serde_yaml::Value>),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Environment {
+    List(Vec<String>),
+    Map(BTreeMap<String, Option<serde_yaml::Value>>),
+}
+
+impl Environment {
+    /// Returns the variables (`None` values are passed through from the
+    /// host environment).
+    fn into_vars(self) -> Vec<(String, Option<String>)> {
+        match self {
+            Environment::List(vars) => vars
+                .into_iter()
+                .map(|var| match var.split_once('=') {
+                    Some((key, value)) => (key.to_string(), Some(value.to_string())),
+                    None => (var, None),
+                })
+                .collect(),
+            Environment::Map(vars) => vars
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = value.map(|value| match value {
+                        serde_yaml::Value::String(value) => value,
+                        serde_yaml::Value::Bool(value) => value.to_string(),
+                        serde_yaml::Value::Number(value) => value.to_string(),
+                        value => serde_yaml::to_string(&value)
+                            .unwrap_or_default()
+                            .trim()
+                            .to_string(),
+                    });
+                    (key, value)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Writes the processes (and global notes and environment variables) as
+/// a Ground Control specification.
+fn to_toml(
+    notes: &[String],
+    env: &BTreeMap<String, String>,
+    processes: &[ImportedProcess],
+) -> String {
+    let mut toml = String::new();
+
+    for note in notes {
+        toml.push_str(&format!("# NOTE: {note}\n"));
+    }
+
+    if !env.is_empty() {
+        toml.push_str("[env]\n");
+        for (key, value) in env {
+            toml.push_str(&format!("{} = {}\n", toml_key(key), toml_string(value)));
+        }
+    }
+
+    for process in processes {
+        if !toml.is_empty() {
+            toml.push('\n');
+        }
+
+        for note in &process.notes {
+            toml.push_str(&format!("# NOTE: {note}\n"));
+        }
+
+        toml.push_str("[[processes]]\n");
+        toml.push_str(&format!("name = {}\n", toml_string(&process.name)));
+        if process.disabled {
+            toml.push_str("disabled = true\n");
+        }
+        if !process.depends_on.is_empty() {
+            toml.push_str(&format!(
+                "depends-on = {}\n",
+                toml_array(process.depends_on.iter().map(|name| toml_string(name)))
+            ));
+        }
+        if let Some(run) = &process.run {
+            let command = toml_array(run.iter().map(|arg| toml_string(arg)));
+            match &process.user {
+                Some(user) => toml.push_str(&format!(
+                    "run = {{ user = {}, command = {command} }}\n",
+                    toml_string(user)
+                )),
+                None => toml.push_str(&format!("run = {command}\n")),
+            }
+        }
+        if let Some(stop) = &process.stop {
+            toml.push_str(&format!("stop = {}\n", toml_string(stop)));
+        }
+        if let Some(restart) = process.restart {
+            toml.push_str(&format!("restart = {}\n", toml_string(restart)));
+        }
+        if let Some(codes) = &process.restart_except_exit_codes {
+            toml.push_str(&format!(
+                "restart-except-exit-codes = {}\n",
+                toml_array(codes.iter().map(|code| code.to_string()))
+            ));
+        }
+    }
+
+    toml
+}
+
+fn toml_string(s: &str) -> String {
+    toml::Value::String(s.to_string()).to_string()
+}
+
+fn toml_key(key: &str) -> String {
+    if !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        key.to_string()
+    } else {
+        toml_string(key)
+    }
+}
+
+fn toml_array(values: impl Iterator<Item = String>) -> String {
+    format!("[{}]", values.collect::<Vec<_>>().join(", "))
+}
@@ -14,16 +14,76 @@
     clippy::unwrap_used
 )]
 
-use color_eyre::eyre;
-use config::Config;
-use tokio::sync::mpsc;
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
 
-use crate::process::Process;
+use color_eyre::eyre::{self, WrapErr};
+use config::{Config, ProcessConfig, ShutdownClass};
+use nix::{sys::signal::Signal, unistd::Pid};
+use serde::Serialize;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::{mpsc, mpsc::error::TryRecvError, Semaphore},
+};
 
+use crate::{
+    api::{ApiServer, ApiStatus, ProcessStatus},
+    command::{CommandEnv, ExitStatus},
+    control::ControlRequest,
+    events::EventLog,
+    history::{ExitDecision, FailureDetails},
+    hold::Release,
+    process::{CommandFailed, Process, ProcessStartFailed, ShutdownAborted},
+    profile::StartupProfile,
+    socket::{SocketCommand, SocketRequest, SocketServer},
+};
+
+pub use crate::containment::install_panic_hook;
+pub use crate::supervisor::Supervisor;
+
+mod api;
 mod command;
 pub mod config;
+#[cfg(feature = "consul")]
+mod consul;
+mod containment;
+mod control;
+#[cfg(feature = "cli")]
+pub mod doctor;
+mod events;
+pub mod exit_code;
+#[cfg(feature = "cli")]
+pub mod export;
+mod fds;
+#[cfg(feature = "cli")]
 pub mod formatter;
+pub mod history;
+mod hold;
+#[cfg(feature = "cli")]
+pub mod import;
+mod locations;
+#[cfg(feature = "cli")]
+pub mod log_filter;
+mod pressure;
+mod probe;
 mod process;
+mod profile;
+mod quiesce;
+mod ready;
+mod reaper;
+mod schedule;
+mod setup;
+pub mod socket;
+mod supervisor;
+mod usage;
+mod wait;
+mod watchdog;
 
 /// Errors generated by Ground Control.
 #[derive(Debug, thiserror::Error)]
@@ -32,28 +92,275 @@ pub enum Error {
     #[error("Startup aborted")]
     StartupAborted(#[from] eyre::Report),
 
-    /// A long-running daemon exited with a non-zero exit code.
-    #[error("Daemon process exited with a non-zero exit code")]
-    AbnormalShutdown,
+    /// One or more long-running daemons exited with a non-zero exit
+    /// code (contains the `DaemonFailed` reason for every failed
+    /// daemon). If there is a `main` process, its exit alone decides
+    /// whether the shutdown is abnormal (even if the process was
+    /// stopped by Ground Control), and its reason comes first.
+    #[error("Daemon process exited with a non-zero exit code: {}", process_names(.0))]
+    AbnormalShutdown(Vec<ShutdownReason>),
+
+    /// A daemon's `stop` failed and its `on-stop-failure` is `abort`, so
+    /// the remaining processes were not stopped, or the shutdown took
+    /// longer than the `shutdown-timeout`, so the remaining daemons were
+    /// killed.
+    #[error("Shutdown aborted")]
+    ShutdownAborted(eyre::Report),
+}
+
+impl Error {
+    /// Returns the exit code (see `exit_code`) that describes this
+    /// error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::StartupAborted(_) => exit_code::STARTUP_ABORTED,
+            Error::AbnormalShutdown(_) => exit_code::ABNORMAL_SHUTDOWN,
+            Error::ShutdownAborted(report)
+                if report.downcast_ref::<ShutdownTimedOut>().is_some() =>
+            {
+                exit_code::SHUTDOWN_TIMEOUT
+            }
+            Error::ShutdownAborted(_) => exit_code::SHUTDOWN_ABORTED,
+        }
+    }
+
+    /// Returns a structured description of the failure that aborted
+    /// startup, or `None` if this error is not a `StartupAborted` error.
+    pub fn startup_failure(&self) -> Option<StartupFailure> {
+        let report = match self {
+            Error::StartupAborted(report) => report,
+            Error::AbnormalShutdown(_) | Error::ShutdownAborted(_) => return None,
+        };
+
+        let start_failed = report.downcast_ref::<ProcessStartFailed>();
+        let status = start_failed
+            .and_then(|start_failed| start_failed.error.downcast_ref::<CommandFailed>())
+            .and_then(|command_failed| command_failed.status);
+        Some(StartupFailure {
+            process: start_failed.map(|start_failed| start_failed.process.clone()),
+            owner: start_failed.and_then(|start_failed| start_failed.owner.clone()),
+            description: start_failed.and_then(|start_failed| start_failed.description.clone()),
+            phase: start_failed.map(|start_failed| start_failed.phase.clone()),
+            exit_code: match status {
+                Some(ExitStatus::Exited(exit_code)) => Some(exit_code),
+                Some(ExitStatus::Killed(_)) | None => None,
+            },
+            signal: match status {
+                Some(ExitStatus::Killed(signal)) => signal,
+                Some(ExitStatus::Exited(_)) | None => None,
+            },
+            message: report
+                .chain()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(": "),
+        })
+    }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-enum ShutdownReason {
+/// Structured description of the failure that aborted startup, which
+/// the Ground Control CLI writes to stderr as a single line of JSON.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename = "startup-failed", rename_all = "kebab-case")]
+pub struct StartupFailure {
+    /// Name of the process that failed to start, if the failure was the
+    /// failure of a process.
+    pub process: Option<String>,
+
+    /// Owner of the process that failed to start, if the process has an
+    /// `owner`.
+    pub owner: Option<String>,
+
+    /// Description of the process that failed to start, if the process
+    /// has a `description`.
+    pub description: Option<String>,
+
+    /// Phase of the startup of the process that failed (`wait-for`,
+    /// `hold`, `runtime-dir`, `pre`, `run`, `ready`, `post-start`, and
+    /// so on), if the failure was the failure of a process.
+    pub phase: Option<String>,
+
+    /// Exit code of the failed command, if the command exited.
+    pub exit_code: Option<i32>,
+
+    /// Signal that killed the failed command, if the command was killed
+    /// by a signal.
+    pub signal: Option<i32>,
+
+    /// Error message (the full chain of errors, separated by colons).
+    pub message: String,
+}
+
+/// Error reported (as a `ShutdownAborted` error) when the shutdown takes
+/// longer than the `shutdown-timeout`.
+#[derive(Debug, thiserror::Error)]
+#[error("Timed out after {} stopping processes", humantime::format_duration(*.0))]
+struct ShutdownTimedOut(Duration);
+
+/// Reason that Ground Control shut down.
+///
+/// The signal that triggered a graceful shutdown is not part of the
+/// reason, since the external shutdown channel (see `run`) does not
+/// carry one. The enum is non-exhaustive, so that reasons (or details,
+/// such as that signal) can be added without breaking callers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ShutdownReason {
     /// Graceful shutdown was triggered by an external signal.
     GracefulShutdown,
 
+    /// Graceful shutdown was requested through the control directory
+    /// (with the `shutdown` file).
+    ControlShutdown,
+
     /// Daemon exited cleanly.
-    DaemonExited,
+    DaemonExited {
+        /// Name of the daemon process.
+        process: String,
+    },
+
+    /// Daemon failed (non-zero exit code, or was killed).
+    DaemonFailed {
+        /// Name of the daemon process.
+        process: String,
+
+        /// Exit code of the daemon, or `None` if the daemon was
+        /// killed.
+        exit_code: Option<i32>,
+
+        /// Signal that killed the daemon, if the daemon was killed by a
+        /// signal.
+        signal: Option<i32>,
+    },
+}
+
+impl ShutdownReason {
+    /// Returns the exit code (see `exit_code`) of a clean shutdown for
+    /// this reason.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ShutdownReason::ControlShutdown => exit_code::CONTROL_SHUTDOWN,
+            ShutdownReason::GracefulShutdown
+            | ShutdownReason::DaemonExited { .. }
+            | ShutdownReason::DaemonFailed { .. } => exit_code::SUCCESS,
+        }
+    }
+}
+
+/// Metadata about the Ground Control build (which is also written to
+/// the status file in the `control-dir`).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BuildInfo {
+    /// Version of Ground Control.
+    pub version: &'static str,
+
+    /// Git commit from which Ground Control was built (`unknown` if the
+    /// build was not performed in a Git checkout).
+    pub git_sha: &'static str,
+
+    /// Time at which Ground Control was built, as an RFC 3339 timestamp
+    /// (in UTC).
+    pub build_timestamp: &'static str,
+
+    /// Optional features that were enabled in the build.
+    pub features: Vec<&'static str>,
+}
+
+/// Returns the metadata of the current Ground Control build.
+pub fn build_info() -> BuildInfo {
+    let features = [
+        ("cli", cfg!(feature = "cli")),
+        ("consul", cfg!(feature = "consul")),
+        ("tokio-console", cfg!(feature = "tokio-console")),
+    ];
+
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("GROUNDCONTROL_GIT_SHA"),
+        build_timestamp: env!("GROUNDCONTROL_BUILD_TIMESTAMP"),
+        features: features
+            .into_iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(feature, _)| feature)
+            .collect(),
+    }
+}
+
+fn process_names(shutdown_reasons: &[ShutdownReason]) -> String {
+    shutdown_reasons
+        .iter()
+        .filter_map(exited_process)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Removes (and returns) the details of the failure of the daemon that
+/// exited (if the shutdown reason is the exit of a daemon) from its
+/// process.
+fn take_failure(running: &[Process], shutdown_reason: &ShutdownReason) -> FailureDetails {
+    exited_process(shutdown_reason)
+        .and_then(|process| {
+            running
+                .iter()
+                .find(|running_process| running_process.name() == process)
+        })
+        .map(Process::take_failure)
+        .unwrap_or_default()
+}
 
-    /// Daemon failed (non-zero exit code).
-    DaemonFailed,
+/// Returns the name of the daemon that exited, if the shutdown reason is
+/// the exit of a daemon.
+fn exited_process(shutdown_reason: &ShutdownReason) -> Option<&str> {
+    match shutdown_reason {
+        ShutdownReason::GracefulShutdown | ShutdownReason::ControlShutdown => None,
+        ShutdownReason::DaemonExited { process } | ShutdownReason::DaemonFailed { process, .. } => {
+            Some(process.as_str())
+        }
+    }
 }
 
 /// Runs a Ground Control specification, returning only when all of the
 /// processes have stopped (either because one process triggered a
-/// shutdown, or because the `shutdown` signal was triggered).
-pub async fn run(config: Config, mut shutdown: mpsc::UnboundedReceiver<()>) -> Result<(), Error> {
-    tracing::info!("Ground Control starting.");
+/// shutdown, or because the `shutdown` signal was triggered). Returns
+/// the reason for the shutdown if the shutdown was clean.
+pub async fn run(
+    config: Config,
+    shutdown: mpsc::UnboundedReceiver<()>,
+) -> Result<ShutdownReason, Error> {
+    let (request_sender, request_receiver) = mpsc::unbounded_channel();
+
+    // The state of a running spec is large, so keep it on the heap
+    // instead of in (every) future that awaits `run`.
+    Box::pin(run_spec(config, shutdown, request_sender, request_receiver)).await
+}
+
+/// Runs a Ground Control specification (see `run`), handling the pause
+/// and resume requests sent to `control_requests` (in addition to those
+/// made through the control directory, which are sent with
+/// `request_sender`).
+pub(crate) async fn run_spec(
+    config: Config,
+    mut shutdown: mpsc::UnboundedReceiver<()>,
+    request_sender: mpsc::UnboundedSender<ControlRequest>,
+    mut control_requests: mpsc::UnboundedReceiver<ControlRequest>,
+) -> Result<ShutdownReason, Error> {
+    // Log the startup banner, which identifies the exact spec that is
+    // being run.
+    let mut profile = StartupProfile::new();
+    let fingerprint = config.fingerprint()?;
+    let config_path = config
+        .path
+        .as_ref()
+        .map(|path| path.display().to_string())
+        .unwrap_or_default();
+    tracing::info!(
+        version = env!("CARGO_PKG_VERSION"),
+        config = %config_path,
+        %fingerprint,
+        processes = config.processes.len(),
+        "Ground Control starting."
+    );
 
     // Create the shutdown channel, which will be used to initiate the
     // shutdown process, regardless of if this is a graceful shutdown
@@ -62,67 +369,587 @@ pub async fn run(config: Config, mut shutdown: mpsc::UnboundedReceiver<()>) -> R
     // daemon process.
     let (shutdown_sender, mut shutdown_receiver) = mpsc::unbounded_channel::<ShutdownReason>();
 
-    // Set extra environment variables.
-    for (key, value) in &config.env {
-        std::env::set_var(key, value);
+    // Validate the config (and check that the environment supports
+    // it), then determine the order in which the processes will be
+    // started.
+    config.validate()?;
+    probe::check(&config)?;
+    let processes = config.startup_order()?;
+    let profiled_processes = config
+        .startup_profile
+        .as_ref()
+        .map(|_| processes.clone())
+        .unwrap_or_default();
+    let mut standbys = config.standby_processes()?;
+
+    // Keep the file descriptors that Ground Control inherited (sockets
+    // and log files of whatever started Ground Control, for example)
+    // from leaking into every command, unless they are meant to be
+    // passed on.
+    fds::restrict_inheritance(&config.pass_fds)?;
+
+    // Reap orphaned processes if we are PID 1 (since nothing else will).
+    reaper::start();
+
+    // Start listening for signals to forward to the daemon (or to the
+    // `main` process), if requested. Signals received during startup
+    // are forwarded once startup has completed.
+    let mut forwarded_signals = if config.forward_signals {
+        forward_signals(&FORWARDED_SIGNALS).wrap_err("Failed to register signal handlers")?
+    } else if processes.iter().any(|process| process.main) {
+        forward_signals(&MAIN_PROCESS_SIGNALS).wrap_err("Failed to register signal handlers")?
+    } else {
+        mpsc::unbounded_channel().1
+    };
+
+    // Prepare the control directory (if any) before starting anything,
+    // so that stale control files are not acted on.
+    if let Some(control_dir) = &config.control_dir {
+        control::prepare(control_dir).await?;
+        control::write_status(
+            control_dir,
+            &control::Status {
+                build: build_info(),
+                config: config_path,
+                fingerprint,
+                processes: config.processes.len(),
+            },
+        )
+        .await?;
     }
 
+    // Listen on the control socket (if any) before starting anything, so
+    // that a socket that cannot be bound aborts startup; requests made
+    // during startup are answered once startup has completed.
+    let (socket_sender, mut socket_requests) = mpsc::unbounded_channel();
+    let socket_server = config
+        .control_socket
+        .as_deref()
+        .map(|path| SocketServer::start(path, socket_sender))
+        .transpose()?;
+
+    // Likewise for the HTTP API (if any), which answers the requests
+    // made during startup without waiting for startup to complete.
+    let (api_sender, mut api_requests) = mpsc::unbounded_channel();
+    let api_server = config
+        .api
+        .as_ref()
+        .map(|api| ApiServer::start(api.listen, api_sender))
+        .transpose()?;
+
+    // Open the lifecycle event log (if any).
+    let events = match &config.event_log {
+        Some(path) => EventLog::open(path)?,
+        None => EventLog::default(),
+    };
+
+    // Perform the setup steps (before any process is started).
+    setup::run(
+        &config.setup,
+        &CommandEnv {
+            config_env: config.env.clone(),
+            ..CommandEnv::default()
+        },
+    )
+    .await?;
+
     // Start every process in the order they were found in the config
-    // file.
-    let mut running: Vec<Process> = Vec::with_capacity(config.processes.len());
-    for process_config in config.processes.into_iter() {
-        let process = match process::start_process(process_config, shutdown_sender.clone()).await {
-            Ok(process) => process,
-            Err(err) => {
-                tracing::error!(?err, "Failed to start process; aborting startup procedure");
-
-                // Stop all of the daemon processes that have already
-                // started (otherwise they will block Ground Control
-                // from exiting and thus the container from shutting
-                // down).
-                while let Some(process) = running.pop() {
-                    if let Err(err) = process.stop_process().await {
-                        tracing::error!(?err, "Error stopping process after aborted startup");
-                    }
+    // file (adjusted to ensure that dependencies are started first).
+    // Runs of independent one-shot processes are started concurrently
+    // if the config allows it.
+    let mut running: Vec<Process> = Vec::with_capacity(processes.len());
+    let mut pending = processes.into_iter().peekable();
+    let mut startup_interrupted = false;
+    let mut deferred_requests = Vec::new();
+    while let Some(process_config) = pending.next() {
+        // Stop launching processes as soon as a shutdown is requested
+        // (the processes that are being started when the request arrives
+        // finish starting, and are then stopped with everything else).
+        if shutdown_requested(&mut shutdown) {
+            tracing::info!(
+                "Shutdown requested during startup; not starting the remaining processes"
+            );
+            startup_interrupted = true;
+            break;
+        }
+
+        // Pause before a held process until its hold is released (which
+        // is not part of the process's startup time).
+        if let Some(hold) = &process_config.hold {
+            match hold::wait_for_release(
+                &process_config.name,
+                hold,
+                config.control_dir.as_deref(),
+                &mut shutdown,
+                &mut control_requests,
+                &mut deferred_requests,
+            )
+            .await
+            {
+                Ok(Release::Released) => {}
+                Ok(Release::ShutdownRequested) => {
+                    tracing::info!(
+                        "Shutdown requested during startup; not starting the remaining processes"
+                    );
+                    startup_interrupted = true;
+                    break;
+                }
+                Err(err) => {
+                    tracing::error!(?err, "Process was not released; aborting startup procedure");
+                    stop_after_aborted_startup(
+                        &mut running,
+                        shutdown_sender,
+                        &mut shutdown_receiver,
+                    )
+                    .await;
+                    return Err(Error::StartupAborted(ProcessStartFailed::wrap(
+                        &process_config,
+                        "hold",
+                        err,
+                    )));
                 }
+            }
+        }
 
-                // Manually drop `shutdown_sender` here, and then drain
-                // all of the receiver signals. If we let the channel
-                // auto-drop (which happens at the entrance to this
-                // match arm), then stopping the already-started
-                // processes will generate a bunch of spurious errors,
-                // since they will be unable to send their shutdown
-                // signals. That also generates out-of-order log lines,
-                // since the warnings about those signals may not show
-                // up until *after* Ground Control itself thinks it has
-                // stopped.
-                drop(shutdown_sender);
-                while shutdown_receiver.recv().await.is_some() {}
+        let mut batch = vec![process_config];
+        while config.startup_concurrency > 1 && batch[0].run.is_none() {
+            match pending.peek() {
+                Some(next)
+                    if next.run.is_none()
+                        && next.hold.is_none()
+                        && !batch.iter().any(|process| next.starts_after(process)) =>
+                {
+                    batch.extend(pending.next());
+                }
+                _ => break,
+            }
+        }
 
-                // Return the original error, now that everything has
-                // been stopped.
-                return Err(Error::StartupAborted(err));
+        let mut startup_error = None;
+        for (process_name, (started, finished), result) in start_processes(
+            batch,
+            config.startup_concurrency,
+            config.restrict_expansion,
+            &config.env,
+            &events,
+            &shutdown_sender,
+        )
+        .await
+        {
+            profile.record(&process_name, started, finished, result.is_ok());
+            match result {
+                Ok(process) => running.push(process),
+                Err(err) if config.is_optional(&process_name) => {
+                    // Optional processes are allowed to fail; log the
+                    // error (loudly, since something is missing) and
+                    // keep going.
+                    tracing::warn!(
+                        process = %process_name,
+                        ?err,
+                        "OPTIONAL PROCESS FAILED TO START; skipping it and continuing startup procedure"
+                    );
+                }
+                Err(err) if config.is_failure_tolerated(&process_name) => {
+                    // Processes that are only *wanted* by other
+                    // processes are allowed to fail; log the error and
+                    // keep going.
+                    tracing::warn!(
+                        process = %process_name,
+                        ?err,
+                        "Wanted process failed to start; continuing startup procedure"
+                    );
+                }
+                Err(err) => {
+                    // Keep the first error, but continue collecting
+                    // the rest of the processes in this batch so that
+                    // they can be stopped.
+                    if startup_error.is_none() {
+                        startup_error = Some(err);
+                    }
+                }
             }
-        };
+        }
+
+        if let Some(err) = startup_error {
+            tracing::error!(?err, "Failed to start process; aborting startup procedure");
+            stop_after_aborted_startup(&mut running, shutdown_sender, &mut shutdown_receiver).await;
 
-        running.push(process);
+            // Return the original error, now that everything has been
+            // stopped.
+            return Err(Error::StartupAborted(err));
+        }
     }
 
-    // Convert an external shutdown signal into a shutdown message.
-    let external_shutdown_sender = shutdown_sender.clone();
-    tokio::spawn(async move {
-        // Both sending the shutdown signal, *and dropping the sender,*
-        // trigger a shutdown.
-        let _ = shutdown.recv().await;
-        let _ = external_shutdown_sender.send(ShutdownReason::GracefulShutdown);
-    });
+    let state_dir = config.state_dir.as_deref();
 
-    tracing::info!("Startup phase completed; waiting for shutdown signal or any process to exit.");
+    let shutdown_reason = if startup_interrupted {
+        // The shutdown request was already received, so there is nothing
+        // to wait for.
+        ShutdownReason::GracefulShutdown
+    } else {
+        // Convert an external shutdown signal into a shutdown message.
+        let external_shutdown_sender = shutdown_sender.clone();
+        tokio::spawn(async move {
+            // Both sending the shutdown signal, *and dropping the sender,*
+            // trigger a shutdown.
+            let _ = shutdown.recv().await;
+            let _ = external_shutdown_sender.send(ShutdownReason::GracefulShutdown);
+        });
 
-    let shutdown_reason = shutdown_receiver
-        .recv()
-        .await
-        .expect("All shutdown senders closed without sending a shutdown signal.");
+        // Handle the requests that arrived while startup was held.
+        for request in deferred_requests {
+            handle_control_request(&mut running, request).await;
+        }
+
+        // Watch the control directory for shutdown (and other) requests.
+        if let Some(control_dir) = &config.control_dir {
+            control::watch(control_dir.clone(), shutdown_sender.clone(), request_sender);
+        }
+
+        tracing::info!(
+            "Startup phase completed; waiting for shutdown signal or any process to exit."
+        );
+        if let Some(api_server) = &api_server {
+            api_server.startup_completed();
+        }
+
+        // Write the startup profile, if requested (failing to do so is
+        // not a reason to stop the processes).
+        if let Some(profile_path) = &config.startup_profile {
+            if let Err(err) =
+                write_startup_profile(&profile, &profiled_processes, profile_path).await
+            {
+                tracing::warn!(?err, "Failed to write startup profile");
+            }
+        }
+
+        // Daemons that are waiting (for their restart delay) to be
+        // restarted.
+        let mut pending_restarts: Vec<PendingRestart> = Vec::new();
+
+        loop {
+            // Set if restarting a daemon failed, in which case the exit
+            // of the daemon is handled as if it was not to be restarted.
+            let mut restart_failed = false;
+
+            // Details of the failure of the daemon whose restart failed,
+            // which were taken from its process when the restart was
+            // scheduled.
+            let mut restart_failure = None;
+
+            let shutdown_reason = tokio::select! {
+                shutdown_reason = shutdown_receiver.recv() => shutdown_reason
+                    .expect("All shutdown senders closed without sending a shutdown signal."),
+                Some(signal) = forwarded_signals.recv() => {
+                    tracing::debug!(%signal, "Forwarding signal");
+                    for process in running
+                        .iter()
+                        .filter(|process| config.forward_signals || process.is_main())
+                    {
+                        if let Err(err) = process.send_signal(signal) {
+                            tracing::warn!(?err, "Failed to forward signal");
+                        }
+                    }
+                    continue;
+                }
+                Some(request) = control_requests.recv() => {
+                    handle_control_request(&mut running, request).await;
+                    continue;
+                }
+                Some(request) = socket_requests.recv() => {
+                    handle_socket_request(&mut running, request, &shutdown_sender).await;
+                    continue;
+                }
+                Some(request) = api_requests.recv() => {
+                    let _ = request.reply.send(api_status(&running));
+                    continue;
+                }
+                (process, timer) = daemon_timer_elapsed(&running) => {
+                    if let Some(index) = running
+                        .iter()
+                        .position(|running_process| running_process.name() == process)
+                    {
+                        match timer {
+                            // Stop the daemon (and the processes bound to
+                            // it), then restart it (if its restart policy
+                            // allows) or shut down, as if it had exited.
+                            DaemonTimer::MaxRuntime => {
+                                tracing::warn!(%process, "Daemon exceeded its `max-runtime`; stopping it");
+                                if let Err(err) = running[index].stop_daemon().await {
+                                    tracing::error!(%process, ?err, "Failed to stop daemon");
+                                } else if running[index].restarts_after_max_runtime() {
+                                    stop_bound_processes(&mut running, &process).await;
+                                    match running[index].restart_daemon(shutdown_sender.clone()).await {
+                                        Ok(()) => {
+                                            restart_bound_processes(&mut running, &process, &shutdown_sender).await;
+                                            continue;
+                                        }
+                                        Err(err) => tracing::error!(?err, "Failed to restart daemon"),
+                                    }
+                                }
+                            }
+
+                            // Restart the daemon (and then the processes
+                            // bound to it), shutting down (as if it had
+                            // exited) if that fails.
+                            DaemonTimer::RestartSchedule => {
+                                tracing::info!(%process, "Restarting daemon on its `restart-schedule`");
+                                match running[index]
+                                    .restart_on_schedule(shutdown_sender.clone())
+                                    .await
+                                {
+                                    Ok(()) => {
+                                        restart_bound_processes(&mut running, &process, &shutdown_sender).await;
+                                        continue;
+                                    }
+                                    Err(err) => tracing::error!(%process, ?err, "Failed to restart daemon"),
+                                }
+                            }
+                        }
+                    }
+                    ShutdownReason::DaemonExited { process }
+                }
+                process = daemon_unhealthy(&running) => {
+                    // Stop the daemon (and the processes bound to it),
+                    // then restart it (subject to its restart limit,
+                    // backoff, and throttle, as with any other failed
+                    // daemon) or shut down (as if it had failed),
+                    // depending on its `health-check` policy.
+                    let reason = ShutdownReason::DaemonFailed {
+                        process: process.clone(),
+                        exit_code: None,
+                        signal: None,
+                    };
+                    if let Some(index) = running
+                        .iter()
+                        .position(|running_process| running_process.name() == process)
+                    {
+                        tracing::error!(%process, "Daemon failed its health check; stopping it");
+                        if let Err(err) = running[index].stop_daemon().await {
+                            tracing::error!(%process, ?err, "Failed to stop daemon");
+                        } else if running[index].restarts_when_unhealthy()
+                            && within_restart_limit(&running[index])
+                        {
+                            stop_bound_processes(&mut running, &process).await;
+                            let delay = running[index].record_restart();
+                            tracing::debug!(%process, ?delay, "Waiting before restarting daemon");
+                            pending_restarts.push(PendingRestart {
+                                process,
+                                reason,
+                                failure: running[index].take_failure(),
+                                deadline: tokio::time::Instant::now() + delay,
+                                throttled_since: None,
+                            });
+                            continue;
+                        }
+                    }
+                    reason
+                }
+                index = restart_due(&pending_restarts) => {
+                    let PendingRestart {
+                        process,
+                        reason,
+                        failure,
+                        throttled_since,
+                        ..
+                    } = pending_restarts.swap_remove(index);
+                    let running_process = running
+                        .iter_mut()
+                        .find(|running_process| running_process.name() == process)
+                        .expect("Restarts are only scheduled for running processes.");
+
+                    // The daemon may have been started (through the
+                    // control socket, for example) in the meantime.
+                    if running_process.uptime().is_some() {
+                        continue;
+                    }
+
+                    // Keep delaying the restart while the system is under
+                    // memory pressure (if requested), checking again
+                    // after a short while (and handling everything else,
+                    // including a shutdown, which then decides the
+                    // outcome, in the meantime).
+                    if let Some(throttle) = running_process.restart_throttle() {
+                        if let Some(throttled_since) =
+                            pressure::delay_restart(&process, throttle, throttled_since).await
+                        {
+                            pending_restarts.push(PendingRestart {
+                                process,
+                                reason,
+                                failure,
+                                deadline: tokio::time::Instant::now() + pressure::POLL_INTERVAL,
+                                throttled_since: Some(throttled_since),
+                            });
+                            continue;
+                        }
+                    }
+
+                    match running_process
+                        .restart_daemon(shutdown_sender.clone())
+                        .await
+                    {
+                        Ok(()) => {
+                            restart_bound_processes(&mut running, &process, &shutdown_sender).await;
+                            history::record(state_dir, &reason, ExitDecision::Restarted, failure)
+                                .await;
+                            continue;
+                        }
+                        Err(err) => {
+                            tracing::error!(?err, "Failed to restart daemon");
+                            restart_failed = true;
+                            restart_failure = Some(failure);
+                            reason
+                        }
+                    }
+                }
+            };
+
+            // Take the details of the daemon's failure (if any) from its
+            // process right away, even if there is no exit history to
+            // record them in, so that they never end up with a later
+            // failure.
+            let failure =
+                restart_failure.unwrap_or_else(|| take_failure(&running, &shutdown_reason));
+
+            // Log every daemon failure along with the owner and
+            // description of the process, so that alerts on the logs
+            // identify who should look at the failure.
+            if let ShutdownReason::DaemonFailed {
+                process,
+                exit_code,
+                signal,
+            } = &shutdown_reason
+            {
+                if let Some(failed) = running
+                    .iter()
+                    .find(|running_process| running_process.name() == process)
+                {
+                    tracing::error!(
+                        %process,
+                        ?exit_code,
+                        ?signal,
+                        owner = failed.owner(),
+                        description = failed.description(),
+                        "Daemon failed"
+                    );
+                }
+            }
+
+            // Failed daemons are restarted if their exit code (or restart
+            // policy) asks for a restart, as are daemons that exited
+            // cleanly if their restart policy is `always` (after a short
+            // delay, so that a daemon that exits immediately does not
+            // spin), unless restarting the daemon already failed.
+            // The processes that are bound to a daemon that stopped are
+            // stopped along with it (and restarted if it is).
+            if let ShutdownReason::DaemonFailed { process, .. }
+            | ShutdownReason::DaemonExited { process } = &shutdown_reason
+            {
+                stop_bound_processes(&mut running, process).await;
+            }
+
+            let restart = match &shutdown_reason {
+                _ if restart_failed => None,
+                ShutdownReason::DaemonFailed {
+                    process,
+                    exit_code,
+                    signal,
+                } => {
+                    let exit_code = exit_code.or_else(|| signal.map(|signal| 128 + signal));
+                    running
+                        .iter_mut()
+                        .find(|running_process| {
+                            running_process.name() == process
+                                && exit_code.map_or(false, |exit_code| {
+                                    running_process.restarts_after(exit_code)
+                                })
+                        })
+                        .filter(|running_process| within_restart_limit(running_process))
+                        .map(|running_process| {
+                            tracing::warn!(%process, ?exit_code, "Daemon failed; restarting");
+                            (process, running_process)
+                        })
+                }
+                ShutdownReason::DaemonExited { process } => running
+                    .iter_mut()
+                    .find(|running_process| {
+                        running_process.name() == process
+                            && running_process.restarts_after_clean_exit()
+                    })
+                    .filter(|running_process| within_restart_limit(running_process))
+                    .map(|running_process| {
+                        tracing::info!(%process, "Daemon exited; restarting");
+                        (process, running_process)
+                    }),
+                _ => None,
+            };
+            if let Some((process, running_process)) = restart {
+                // Restart the daemon once its restart delay has elapsed,
+                // handling everything else (including shutdown requests)
+                // in the meantime.
+                let delay = running_process.record_restart();
+                tracing::debug!(%process, ?delay, "Waiting before restarting daemon");
+                pending_restarts.push(PendingRestart {
+                    process: process.clone(),
+                    reason: shutdown_reason.clone(),
+                    failure,
+                    deadline: tokio::time::Instant::now() + delay,
+                    throttled_since: None,
+                });
+                continue;
+            }
+
+            // Failed daemons with a standby process are replaced by that
+            // standby process instead of triggering a shutdown.
+            if let ShutdownReason::DaemonFailed { process, .. } = &shutdown_reason {
+                if let Some(standby) = standbys.remove(process) {
+                    tracing::warn!(%process, standby = %standby.name, "Daemon failed; starting standby process");
+                    match process::start_process(
+                        standby,
+                        config.restrict_expansion,
+                        config.env.clone(),
+                        events.clone(),
+                        shutdown_sender.clone(),
+                    )
+                    .await
+                    {
+                        Ok(standby) => {
+                            running.push(standby);
+                            history::record(
+                                state_dir,
+                                &shutdown_reason,
+                                ExitDecision::StandbyStarted,
+                                failure,
+                            )
+                            .await;
+                            continue;
+                        }
+                        Err(err) => tracing::error!(?err, "Failed to start standby process"),
+                    }
+                }
+            }
+
+            for pending in pending_restarts {
+                history::record(
+                    state_dir,
+                    &pending.reason,
+                    ExitDecision::ShutDown,
+                    pending.failure,
+                )
+                .await;
+            }
+            history::record(state_dir, &shutdown_reason, ExitDecision::ShutDown, failure).await;
+            break shutdown_reason;
+        }
+    };
+
+    // Stop serving the control socket and the HTTP API (answering the
+    // requests that have not been handled yet with an error), since the
+    // processes are about to be stopped.
+    drop(socket_server);
+    drop(socket_requests);
+    drop(api_server);
+    drop(api_requests);
 
     // Either one process exited or we received a stop signal; stop all
     // of the processes in the *reverse* order in which they were
@@ -130,19 +957,776 @@ pub async fn run(config: Config, mut shutdown: mpsc::UnboundedReceiver<()>) -> R
     // a daemon process that is still running) and `post`.
     tracing::info!("Completion signal triggered; shutting down all processes");
 
+    // Daemons that have already exited (the one that triggered the
+    // shutdown, and any that exit on their own while we are shutting
+    // down; daemons that we stopped do not report their exit) only need
+    // their `post` command, so collect their exits as we go.
+    let mut shutdown_reasons = vec![shutdown_reason.clone()];
+    let mut usage_monitors = Vec::with_capacity(running.len());
+    let mut main_exit_status = None;
+    let shutdown_deadline = config
+        .shutdown_timeout
+        .map(|shutdown_timeout| (shutdown_timeout, Instant::now() + shutdown_timeout));
     while let Some(process) = running.pop() {
-        if let Err(err) = process.stop_process().await {
+        if let Some(usage_monitor) = process.usage_monitor() {
+            usage_monitors.push((process.name().to_owned(), usage_monitor));
+        }
+        if process.is_main() {
+            main_exit_status = process
+                .exit_status()
+                .map(|exit_status| (process.name().to_owned(), exit_status));
+        }
+
+        while let Ok(shutdown_reason) = shutdown_receiver.try_recv() {
+            shutdown_reasons.push(shutdown_reason);
+        }
+        let exited = shutdown_reasons
+            .iter()
+            .any(|shutdown_reason| exited_process(shutdown_reason) == Some(process.name()));
+
+        // Kill the daemon being stopped, and every daemon that has not
+        // yet been stopped, if the shutdown runs out of time (a `stop`
+        // command that never finishes would otherwise hang the shutdown).
+        // Daemons are killed sooner if their `shutdown-class` gives them
+        // less time to stop, in which case the shutdown continues.
+        let result = match shutdown_deadline {
+            Some((shutdown_timeout, deadline)) => {
+                let daemon_killer = process.daemon_killer();
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                let critical_pending = running
+                    .iter()
+                    .any(|process| process.shutdown_class() == ShutdownClass::Critical);
+                let kill_at = class_kill_time(
+                    process.shutdown_class(),
+                    shutdown_timeout,
+                    deadline,
+                    critical_pending,
+                );
+                match tokio::time::timeout(remaining, stop_process_by(process, exited, kill_at))
+                    .await
+                {
+                    Ok(result) => result,
+                    Err(_) => {
+                        let err = eyre::Report::new(ShutdownTimedOut(shutdown_timeout));
+                        tracing::error!(?err, "Killing the remaining processes");
+                        for daemon_killer in daemon_killer.into_iter().chain(
+                            running
+                                .drain(..)
+                                .rev()
+                                .filter_map(|process| process.daemon_killer()),
+                        ) {
+                            daemon_killer.kill();
+                        }
+                        return Err(Error::ShutdownAborted(err));
+                    }
+                }
+            }
+            None => process.stop_process(exited).await,
+        };
+
+        if let Err(err) = result {
+            if err.downcast_ref::<ShutdownAborted>().is_some() {
+                tracing::error!(?err, "Aborting shutdown");
+                return Err(Error::ShutdownAborted(err));
+            }
+
             tracing::error!(?err, "Error stopping process");
         }
     }
 
+    // Report the peak resource usage of every daemon (in the order in
+    // which the daemons were started), which helps with right-sizing
+    // memory limits.
+    for (process, usage_monitor) in usage_monitors.iter().rev() {
+        let usage = usage_monitor.usage();
+        tracing::info!(
+            %process,
+            peak_rss_kib = usage.peak_rss_kib,
+            cpu_time = ?usage.cpu_time,
+            "Daemon resource usage"
+        );
+    }
+
+    // Collect the exits of daemons that exited while the last processes
+    // were being stopped as well, so that every failure is reported.
+    while let Ok(shutdown_reason) = shutdown_receiver.try_recv() {
+        shutdown_reasons.push(shutdown_reason);
+    }
+
     tracing::info!("All processes have exited; Ground Control shutting down.");
 
-    // Clean shutdowns (a daemon that exited with a non-error exit code,
+    // Clean shutdowns (daemons that exited with a non-error exit code,
     // or a graceful shutdown request) are success, abnormal shutdowns
     // are errors.
-    match shutdown_reason {
-        ShutdownReason::GracefulShutdown | ShutdownReason::DaemonExited => Ok(()),
-        ShutdownReason::DaemonFailed => Err(Error::AbnormalShutdown),
+    let mut failed: Vec<ShutdownReason> = shutdown_reasons
+        .into_iter()
+        .filter(|shutdown_reason| matches!(shutdown_reason, ShutdownReason::DaemonFailed { .. }))
+        .collect();
+    if !failed.is_empty() {
+        tracing::error!(processes = %process_names(&failed), "Daemon processes failed");
+    }
+
+    // The exit of the `main` process (whether it exited on its own, or
+    // was stopped) decides the outcome of the shutdown, if there is one,
+    // so that Ground Control's exit code mirrors that of the process.
+    let main_exit_status = main_exit_status
+        .and_then(|(process, exit_status)| Some((process, (*exit_status.borrow())?)));
+    if let Some((process, exit_status)) = main_exit_status {
+        tracing::info!(%process, ?exit_status, "Shutdown outcome follows the exit of the main process");
+        return match process::daemon_exit_reason(&process, exit_status) {
+            ShutdownReason::DaemonExited { .. } => Ok(shutdown_reason),
+            main_failed => {
+                failed.retain(|reason| exited_process(reason) != Some(process.as_str()));
+                failed.insert(0, main_failed);
+                Err(Error::AbnormalShutdown(failed))
+            }
+        };
+    }
+
+    if failed.is_empty() {
+        Ok(shutdown_reason)
+    } else {
+        Err(Error::AbnormalShutdown(failed))
+    }
+}
+
+/// Time that best-effort daemons are given to stop (see
+/// `ShutdownClass`).
+const BEST_EFFORT_STOP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Returns the time at which the daemon of a process with the given
+/// `shutdown-class` is killed if it has not yet stopped, if that is
+/// before the shutdown `deadline`: the last half of the
+/// `shutdown-timeout` is kept for the critical processes that have not
+/// yet been stopped (if `critical_pending`), and best-effort processes
+/// only get a short time to stop.
+fn class_kill_time(
+    shutdown_class: ShutdownClass,
+    shutdown_timeout: Duration,
+    deadline: Instant,
+    critical_pending: bool,
+) -> Option<Instant> {
+    let mut kill_at = deadline;
+    if critical_pending && shutdown_class != ShutdownClass::Critical {
+        kill_at -= shutdown_timeout / 2;
+    }
+    if shutdown_class == ShutdownClass::BestEffort {
+        kill_at = kill_at.min(Instant::now() + BEST_EFFORT_STOP_TIMEOUT);
+    }
+    (kill_at < deadline).then(|| kill_at)
+}
+
+/// Stops the process, killing its daemon (so that the process finishes
+/// stopping, including its `post` command) if the daemon has not stopped
+/// by the `kill_at` time (if any).
+async fn stop_process_by(
+    process: Process,
+    exited: bool,
+    kill_at: Option<Instant>,
+) -> eyre::Result<()> {
+    let process_name = process.name().to_owned();
+    let daemon_killer = process.daemon_killer();
+    let stop = process.stop_process(exited);
+    tokio::pin!(stop);
+
+    let (kill_at, daemon_killer) = match (kill_at, daemon_killer) {
+        (Some(kill_at), Some(daemon_killer)) => (kill_at, daemon_killer),
+        _ => return stop.await,
+    };
+    tokio::select! {
+        result = &mut stop => return result,
+        _ = tokio::time::sleep_until(kill_at.into()) => {}
+    }
+
+    tracing::warn!(process = %process_name, "Daemon did not stop in the time allowed by its `shutdown-class`; killing it");
+    daemon_killer.kill();
+    stop.await
+}
+
+/// Stops all of the daemon processes that have already started when
+/// startup is aborted (otherwise they will block Ground Control from
+/// exiting and thus the container from shutting down).
+async fn stop_after_aborted_startup(
+    running: &mut Vec<Process>,
+    shutdown_sender: mpsc::UnboundedSender<ShutdownReason>,
+    shutdown_receiver: &mut mpsc::UnboundedReceiver<ShutdownReason>,
+) {
+    while let Some(process) = running.pop() {
+        if let Err(err) = process.stop_process(false).await {
+            tracing::error!(?err, "Error stopping process after aborted startup");
+        }
+    }
+
+    // Manually drop `shutdown_sender` here, and then drain all of the
+    // receiver signals. If we let the channel auto-drop (which happens
+    // when we return), then stopping the already-started processes will
+    // generate a bunch of spurious errors, since they will be unable to
+    // send their shutdown signals. That also generates out-of-order log
+    // lines, since the warnings about those signals may not show up
+    // until *after* Ground Control itself thinks it has stopped.
+    drop(shutdown_sender);
+    while shutdown_receiver.recv().await.is_some() {}
+}
+
+/// Returns true if the external shutdown signal has been triggered
+/// (either sent, or its sender dropped) without waiting for it.
+fn shutdown_requested(shutdown: &mut mpsc::UnboundedReceiver<()>) -> bool {
+    !matches!(shutdown.try_recv(), Err(TryRecvError::Empty))
+}
+
+/// Pauses or resumes one (or every) running process, drains a process,
+/// or changes the log level (holds are only released during startup),
+/// as requested through the control directory or the `Supervisor`.
+async fn handle_control_request(running: &mut [Process], request: ControlRequest) {
+    match &request {
+        ControlRequest::Pause(name) | ControlRequest::Resume(name) => {
+            let pause = matches!(request, ControlRequest::Pause(_));
+            match running.iter_mut().find(|process| process.name() == *name) {
+                Some(process) => pause_or_resume(process, pause),
+                None => {
+                    tracing::warn!(process = %name, "Control request for unknown (or stopped) process");
+                }
+            }
+        }
+        ControlRequest::PauseAll => {
+            // Pause the dependents before their dependencies, so that
+            // nothing is left running against a paused dependency.
+            tracing::info!("Pausing all processes");
+            for process in running
+                .iter_mut()
+                .rev()
+                .filter(|process| process.is_daemon())
+            {
+                pause_or_resume(process, true);
+            }
+        }
+        ControlRequest::ResumeAll => {
+            tracing::info!("Resuming all processes");
+            for process in running.iter_mut().filter(|process| process.is_daemon()) {
+                pause_or_resume(process, false);
+            }
+        }
+        ControlRequest::Drain(name) => {
+            match running.iter_mut().find(|process| process.name() == *name) {
+                Some(process) => {
+                    tracing::info!(process = %name, "Draining process");
+                    if let Err(err) = process.drain().await {
+                        tracing::warn!(process = %name, ?err, "Failed to handle control request");
+                    }
+                }
+                None => {
+                    tracing::warn!(process = %name, "Control request for unknown (or stopped) process");
+                }
+            }
+        }
+        ControlRequest::Release(name) => {
+            tracing::warn!(process = %name, "Release request for process that is not held");
+        }
+        ControlRequest::LogLevel { process, level } => {
+            if let Some(name) = process {
+                if !running.iter().any(|process| process.name() == *name) {
+                    tracing::warn!(process = %name, "Control request for unknown (or stopped) process");
+                    return;
+                }
+            }
+
+            tracing::info!(process = process.as_deref().unwrap_or("*"), %level, "Changing log level");
+            if let Err(err) = set_log_level(process.as_deref(), level) {
+                tracing::warn!(?err, "Failed to handle control request");
+            }
+        }
+    }
+}
+
+/// Answers a request made through the control socket: lists every
+/// process, or starts, stops, or restarts the daemon of a process (along
+/// with the processes bound to it) without shutting down. A daemon that
+/// is stopped this way stays stopped (and is not restarted by its
+/// restart policy) until it is started again.
+async fn handle_socket_request(
+    running: &mut [Process],
+    request: SocketRequest,
+    shutdown_sender: &mpsc::UnboundedSender<ShutdownReason>,
+) {
+    let name = match &request.command {
+        SocketCommand::Status => {
+            let _ = request.reply.send(Ok(socket_status(running)));
+            return;
+        }
+        SocketCommand::Start(name) | SocketCommand::Stop(name) | SocketCommand::Restart(name) => {
+            name.clone()
+        }
+    };
+
+    let index = match running.iter().position(|process| process.name() == name) {
+        Some(index) if running[index].has_daemon() => index,
+
+        // Restarting a one-shot process runs its `pre` command again.
+        Some(index) if matches!(request.command, SocketCommand::Restart(_)) => {
+            let reply = match running[index].restart_one_shot().await {
+                Ok(()) => Ok(format!("Restarted process \"{name}\"\n")),
+                Err(err) => {
+                    tracing::error!(process = %name, ?err, "Failed to restart one-shot process");
+                    Err(format!("Failed to restart process \"{name}\": {err:#}"))
+                }
+            };
+            let _ = request.reply.send(reply);
+            return;
+        }
+        Some(_) => {
+            let _ = request
+                .reply
+                .send(Err(format!("Process \"{name}\" has no daemon")));
+            return;
+        }
+        None => {
+            let _ = request
+                .reply
+                .send(Err(format!("Unknown (or not started) process \"{name}\"")));
+            return;
+        }
+    };
+
+    let running_daemon = running[index].is_daemon();
+    let (stop, start) = match &request.command {
+        SocketCommand::Start(_) if running_daemon => {
+            let _ = request
+                .reply
+                .send(Err(format!("Process \"{name}\" is already running")));
+            return;
+        }
+        SocketCommand::Stop(_) if !running_daemon => {
+            let _ = request
+                .reply
+                .send(Err(format!("Process \"{name}\" is not running")));
+            return;
+        }
+        SocketCommand::Start(_) => (false, true),
+        SocketCommand::Stop(_) => (true, false),
+        _ => (running_daemon, true),
+    };
+
+    if stop {
+        stop_bound_processes(running, &name).await;
+        if let Err(err) = running[index].stop_daemon().await {
+            tracing::error!(process = %name, ?err, "Failed to stop daemon");
+            let _ = request
+                .reply
+                .send(Err(format!("Failed to stop process \"{name}\": {err:#}")));
+            return;
+        }
+    }
+
+    if start {
+        if let Err(err) = running[index].restart_daemon(shutdown_sender.clone()).await {
+            tracing::error!(process = %name, ?err, "Failed to start daemon");
+            let _ = request
+                .reply
+                .send(Err(format!("Failed to start process \"{name}\": {err:#}")));
+            return;
+        }
+        restart_bound_processes(running, &name, shutdown_sender).await;
+    }
+
+    let action = match (stop, start) {
+        (true, true) => "Restarted",
+        (true, false) => "Stopped",
+        _ => "Started",
+    };
+    let _ = request
+        .reply
+        .send(Ok(format!("{action} process \"{name}\"\n")));
+}
+
+/// Lists every process (in startup order) for the control socket's
+/// `status` request: one line per process, with the name, state, PID,
+/// uptime, owner, and description of the process separated by tabs
+/// (with `-` for values that are missing).
+fn socket_status(running: &[Process]) -> String {
+    running
+        .iter()
+        .map(|process| {
+            let pid = process
+                .pid()
+                .map_or_else(|| String::from("-"), |pid| pid.to_string());
+            let uptime = process.uptime().map_or_else(
+                || String::from("-"),
+                |uptime| {
+                    humantime::format_duration(Duration::from_secs(uptime.as_secs())).to_string()
+                },
+            );
+            format!(
+                "{}\t{}\t{pid}\t{uptime}\t{}\t{}\n",
+                process.name(),
+                process.state(),
+                process.owner().unwrap_or("-"),
+                process.description().unwrap_or("-"),
+            )
+        })
+        .collect()
+}
+
+/// Describes every process (in startup order) for the HTTP API: Ground
+/// Control is healthy if every daemon is running and has not failed its
+/// health check.
+fn api_status(running: &[Process]) -> ApiStatus {
+    let processes: Vec<ProcessStatus> = running
+        .iter()
+        .map(|process| ProcessStatus {
+            name: process.name().to_owned(),
+            state: process.state(),
+            healthy: !process.has_daemon()
+                || (process.state() == "running" && !process.is_unhealthy()),
+            pid: process.pid().map(Pid::as_raw),
+            uptime_secs: process.uptime().map(|uptime| uptime.as_secs()),
+            owner: process.owner().map(str::to_owned),
+            description: process.description().map(str::to_owned),
+            ports: process.ports().to_vec(),
+        })
+        .collect();
+    ApiStatus {
+        healthy: processes.iter().all(|process| process.healthy),
+        starting: false,
+        processes,
+    }
+}
+
+/// Returns true if the daemon of the process has not reached its
+/// `restart-limit` (logging that the daemon will not be restarted if it
+/// has).
+fn within_restart_limit(process: &Process) -> bool {
+    if process.restart_limit_reached() {
+        tracing::error!(process = %process.name(), "Daemon reached its `restart-limit`; not restarting it");
+        false
+    } else {
+        true
+    }
+}
+
+/// Stops the daemons of the processes that are bound to the given
+/// process (`bound-to`), since they cannot run without it.
+async fn stop_bound_processes(running: &mut [Process], target: &str) {
+    for process in running
+        .iter_mut()
+        .filter(|process| process.is_bound_to(target) && process.is_daemon())
+    {
+        tracing::info!(process = %process.name(), %target, "Stopping process bound to stopped daemon");
+        if let Err(err) = process.stop_daemon().await {
+            tracing::error!(process = %process.name(), ?err, "Failed to stop daemon");
+        }
+    }
+}
+
+/// Restarts the daemons of the processes that are bound to the given
+/// (restarted) process, stopping them first if they are still running.
+/// A daemon that cannot be restarted is reported as having failed.
+async fn restart_bound_processes(
+    running: &mut [Process],
+    target: &str,
+    shutdown_sender: &mpsc::UnboundedSender<ShutdownReason>,
+) {
+    stop_bound_processes(running, target).await;
+    for process in running
+        .iter_mut()
+        .filter(|process| process.is_bound_to(target))
+    {
+        if let Err(err) = process.restart_daemon(shutdown_sender.clone()).await {
+            tracing::error!(process = %process.name(), ?err, "Failed to restart daemon");
+            let _ = shutdown_sender.send(ShutdownReason::DaemonFailed {
+                process: process.name().to_owned(),
+                exit_code: None,
+                signal: None,
+            });
+        }
+    }
+}
+
+/// Timer that stops (and possibly restarts) a daemon once it elapses.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum DaemonTimer {
+    /// The daemon has exceeded its `max-runtime`.
+    MaxRuntime,
+
+    /// The daemon is due to be restarted on its `restart-schedule`.
+    RestartSchedule,
+}
+
+/// Waits until the daemon of one of the running processes exceeds its
+/// `max-runtime` or is due to be restarted on its `restart-schedule`,
+/// returning the name of the process and the timer that elapsed (or
+/// never returns, if none of the processes have either timer).
+async fn daemon_timer_elapsed(running: &[Process]) -> (String, DaemonTimer) {
+    let next = running
+        .iter()
+        .flat_map(|process| {
+            [
+                process
+                    .max_runtime_deadline()
+                    .map(|deadline| (deadline, process.name(), DaemonTimer::MaxRuntime)),
+                process
+                    .scheduled_restart()
+                    .map(|deadline| (deadline, process.name(), DaemonTimer::RestartSchedule)),
+            ]
+        })
+        .flatten()
+        .min_by_key(|(deadline, _, _)| *deadline);
+    match next {
+        Some((deadline, process, timer)) => {
+            tokio::time::sleep_until(deadline).await;
+            (process.to_owned(), timer)
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Daemon that is waiting for its restart delay to elapse before it is
+/// restarted.
+#[derive(Debug)]
+struct PendingRestart {
+    /// Name of the process.
+    process: String,
+
+    /// Exit (or failure) of the daemon that caused the restart.
+    reason: ShutdownReason,
+
+    /// Details of the daemon's failure, for the exit history.
+    failure: FailureDetails,
+
+    /// Time at which the daemon is to be restarted.
+    deadline: tokio::time::Instant,
+
+    /// Time at which the restart was first delayed because of memory
+    /// pressure, if it was (see `pressure::delay_restart`).
+    throttled_since: Option<tokio::time::Instant>,
+}
+
+/// Waits until the earliest of the pending restarts is due, returning
+/// its index (or never returns, if no restarts are pending).
+async fn restart_due(pending: &[PendingRestart]) -> usize {
+    match pending
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, restart)| restart.deadline)
+    {
+        Some((index, restart)) => {
+            tokio::time::sleep_until(restart.deadline).await;
+            index
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Waits for the daemon of any of the running processes to fail its
+/// health check, returning the name of the process (or never returns, if
+/// none of the processes have a health check).
+async fn daemon_unhealthy(running: &[Process]) -> String {
+    FirstReady(
+        running
+            .iter()
+            .map(|process| {
+                Box::pin(async move {
+                    process.health_check_failed().await;
+                    process.name().to_owned()
+                })
+            })
+            .collect(),
+    )
+    .await
+}
+
+/// Future that completes with the output of the first of its futures to
+/// complete (or never completes, if it has no futures).
+struct FirstReady<F>(Vec<Pin<Box<F>>>);
+
+impl<F: Future> Future for FirstReady<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.0
+            .iter_mut()
+            .find_map(|future| match future.as_mut().poll(cx) {
+                Poll::Ready(output) => Some(output),
+                Poll::Pending => None,
+            })
+            .map_or(Poll::Pending, Poll::Ready)
+    }
+}
+
+/// Writes the startup profile (see `Config::startup_profile`) to the
+/// given file.
+async fn write_startup_profile(
+    profile: &StartupProfile,
+    processes: &[ProcessConfig],
+    path: &std::path::Path,
+) -> eyre::Result<()> {
+    let json = profile.to_json(processes)?;
+    tokio::fs::write(path, json)
+        .await
+        .wrap_err_with(|| format!("Failed to write startup profile \"{}\"", path.display()))
+}
+
+/// Changes the log level (globally, or for a single process) of the
+/// installed `log_filter::LogFilter`.
+#[cfg(feature = "cli")]
+fn set_log_level(process: Option<&str>, level: &str) -> eyre::Result<()> {
+    log_filter::set_level(process, level)
+}
+
+/// Log level changes require the (`cli`-only) reloadable log filter.
+#[cfg(not(feature = "cli"))]
+fn set_log_level(_process: Option<&str>, _level: &str) -> eyre::Result<()> {
+    Err(eyre::eyre!("Log level changes require the `cli` feature"))
+}
+
+/// Pauses (or resumes) the daemon of the process, logging any errors.
+fn pause_or_resume(process: &mut Process, pause: bool) {
+    let result = if pause {
+        tracing::info!(process = %process.name(), "Pausing process");
+        process.pause()
+    } else {
+        tracing::info!(process = %process.name(), "Resuming process");
+        process.resume()
+    };
+    if let Err(err) = result {
+        tracing::warn!(process = %process.name(), ?err, "Failed to handle control request");
+    }
+}
+
+/// Signals that are forwarded to the daemon when `forward-signals` is
+/// enabled (everything that a supervisor might be sent, other than
+/// signals related to faults and job control).
+const FORWARDED_SIGNALS: [Signal; 9] = [
+    Signal::SIGHUP,
+    Signal::SIGINT,
+    Signal::SIGQUIT,
+    Signal::SIGTERM,
+    Signal::SIGUSR1,
+    Signal::SIGUSR2,
+    Signal::SIGWINCH,
+    Signal::SIGALRM,
+    Signal::SIGCONT,
+];
+
+/// Signals that are forwarded to the `main` process: the forwarded
+/// signals, other than the ones that trigger a graceful shutdown.
+const MAIN_PROCESS_SIGNALS: [Signal; 7] = [
+    Signal::SIGHUP,
+    Signal::SIGQUIT,
+    Signal::SIGUSR1,
+    Signal::SIGUSR2,
+    Signal::SIGWINCH,
+    Signal::SIGALRM,
+    Signal::SIGCONT,
+];
+
+/// Registers handlers for the given signals, and returns the channel on
+/// which the signals will be delivered.
+fn forward_signals(signals: &[Signal]) -> std::io::Result<mpsc::UnboundedReceiver<Signal>> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    for &forwarded_signal in signals {
+        let mut stream = signal(SignalKind::from_raw(forwarded_signal as i32))?;
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            while stream.recv().await.is_some() {
+                if sender.send(forwarded_signal).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    Ok(receiver)
+}
+
+/// Logs the fully-resolved form of every command in the config -- the
+/// final arguments, user, working directory, and environment (with the
+/// values of sensitive variables redacted) -- without running anything,
+/// so that the commands can be audited before they are executed.
+pub fn log_resolved_commands(config: &Config) -> eyre::Result<()> {
+    config.validate()?;
+
+    let mut processes = config.startup_order()?;
+    let mut standbys: Vec<ProcessConfig> = config.standby_processes()?.into_values().collect();
+    standbys.sort_by(|a, b| a.name.cmp(&b.name));
+    processes.extend(standbys);
+
+    for process in &processes {
+        process::log_resolved_commands(process, &config.env, config.restrict_expansion)?;
+    }
+
+    Ok(())
+}
+
+/// Times at which a process began and finished starting.
+type StartTiming = (Instant, Instant);
+
+/// Starts a batch of processes, running at most `concurrency` of them at
+/// the same time, and returns the name, start timing, and start result
+/// of each process (in the same order as the batch).
+async fn start_processes(
+    batch: Vec<ProcessConfig>,
+    concurrency: usize,
+    restrict_expansion: bool,
+    config_env: &HashMap<String, String>,
+    events: &EventLog,
+    shutdown_sender: &mpsc::UnboundedSender<ShutdownReason>,
+) -> Vec<(String, StartTiming, eyre::Result<Process>)> {
+    // Avoid spawning tasks for the common case of a single process.
+    if batch.len() == 1 {
+        let mut results = Vec::with_capacity(1);
+        for process_config in batch {
+            let process_name = process_config.name.clone();
+            let started = Instant::now();
+            let result = process::start_process(
+                process_config,
+                restrict_expansion,
+                config_env.clone(),
+                events.clone(),
+                shutdown_sender.clone(),
+            )
+            .await;
+            results.push((process_name, (started, Instant::now()), result));
+        }
+        return results;
+    }
+
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+    let handles: Vec<_> = batch
+        .into_iter()
+        .map(|process_config| {
+            let process_name = process_config.name.clone();
+            let semaphore = semaphore.clone();
+            let config_env = config_env.clone();
+            let events = events.clone();
+            let shutdown_sender = shutdown_sender.clone();
+            let handle = tokio::spawn(async move {
+                let _permit = semaphore
+                    .acquire_owned()
+                    .await
+                    .expect("startup semaphore should never be closed");
+                let started = Instant::now();
+                let result = process::start_process(
+                    process_config,
+                    restrict_expansion,
+                    config_env,
+                    events,
+                    shutdown_sender,
+                )
+                .await;
+                ((started, Instant::now()), result)
+            });
+            (process_name, handle)
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(handles.len());
+    for (process_name, handle) in handles {
+        let (timing, result) = handle.await.unwrap_or_else(|err| {
+            let now = Instant::now();
+            ((now, now), Err(eyre::eyre!("Startup task failed: {err}")))
+        });
+        results.push((process_name, timing, result));
     }
+    results
 }
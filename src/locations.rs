@@ -0,0 +1,278 @@
+//! Locations (line and column) of the keys in a TOML document, by key
+//! path (for example, `processes[2].stop`), so that config errors can
+//! point at the key that caused them.
+//!
+//! The document is scanned line by line: table headers (`[table]` and
+//! `[[array]]`) set the current table, and `key = value` lines add keys
+//! to it. Keys inside inline tables and arrays are not recorded; their
+//! errors are reported at the key that holds the inline value.
+
+use std::collections::HashMap;
+
+/// Locations of the keys (and tables) of a TOML document, as one-based
+/// line and column numbers.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct KeyLocations(HashMap<String, (usize, usize)>);
+
+impl KeyLocations {
+    /// Scans the TOML document for the locations of its keys. Documents
+    /// that are not valid TOML are scanned as well as possible.
+    pub(crate) fn scan(text: &str) -> Self {
+        let mut locations = HashMap::new();
+
+        // Number of elements in every array of tables, by the key path
+        // of the array.
+        let mut arrays: HashMap<String, usize> = HashMap::new();
+
+        let mut table = String::new();
+        let mut value = ValueState::default();
+        for (line_index, line) in text.lines().enumerate() {
+            if value.is_open() {
+                value.scan(line);
+                continue;
+            }
+
+            let trimmed = line.trim_start();
+            let location = (line_index + 1, line.len() - trimmed.len() + 1);
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            if let Some(header) = trimmed.strip_prefix("[[") {
+                let parts = split_key(header.split("]]").next().unwrap_or_default());
+                if let Some((last, parents)) = parts.split_last() {
+                    let array = join_path(&resolve(&arrays, parents), last);
+                    let count = arrays.entry(array.clone()).or_insert(0);
+                    table = format!("{array}[{count}]");
+                    *count += 1;
+                    locations.entry(table.clone()).or_insert(location);
+                }
+            } else if let Some(header) = trimmed.strip_prefix('[') {
+                let parts = split_key(header.split(']').next().unwrap_or_default());
+                table = resolve(&arrays, &parts);
+                locations.entry(table.clone()).or_insert(location);
+            } else if let Some((key, rest)) = split_assignment(trimmed) {
+                let mut path = table.clone();
+                for part in split_key(key) {
+                    path = join_path(&path, &part);
+                    locations.entry(path.clone()).or_insert(location);
+                }
+                value.scan(rest);
+            }
+        }
+
+        KeyLocations(locations)
+    }
+
+    /// Returns the location of the key with the given path, or the
+    /// location of the nearest key or table that contains it (such as
+    /// `processes[2].run` for `processes[2].run.user`, or `processes[2]`
+    /// for a key in an inline table), if any.
+    pub(crate) fn find(&self, path: &str) -> Option<(usize, usize)> {
+        let mut path = path;
+        loop {
+            if let Some(location) = self.0.get(path) {
+                return Some(*location);
+            }
+            path = &path[..path.rfind(['.', '['])?];
+        }
+    }
+
+    /// Returns the location of the key with the given path (and only
+    /// that key), if known.
+    pub(crate) fn get(&self, path: &str) -> Option<(usize, usize)> {
+        self.0.get(path).copied()
+    }
+}
+
+/// Locations only describe where settings were found, and so never make
+/// two configs unequal.
+impl PartialEq for KeyLocations {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// Resolves the parts of a header key into a key path, which refers to
+/// the last element of any array of tables along the way.
+fn resolve(arrays: &HashMap<String, usize>, parts: &[String]) -> String {
+    let mut path = String::new();
+    for part in parts {
+        path = join_path(&path, part);
+        if let Some(count) = arrays.get(&path) {
+            path = format!("{path}[{}]", count - 1);
+        }
+    }
+    path
+}
+
+/// Appends a key to a key path.
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+/// Splits a (possibly dotted) key into its parts, removing the quotes
+/// from quoted parts.
+fn split_key(key: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut quote = None;
+    for c in key.chars() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, '.') => parts.push(std::mem::take(&mut part).trim().to_owned()),
+            _ => part.push(c),
+        }
+    }
+    parts.push(part.trim().to_owned());
+    parts
+}
+
+/// Splits a `key = value` line into its key and value, if the line is an
+/// assignment.
+fn split_assignment(line: &str) -> Option<(&str, &str)> {
+    let mut quote = None;
+    for (index, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, '=') => return Some((&line[..index], &line[index + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// State of a value that may continue onto the following lines (an
+/// array, an inline table, or a multi-line string).
+#[derive(Debug, Default)]
+struct ValueState {
+    /// Number of unclosed brackets and braces.
+    depth: usize,
+
+    /// Delimiter of the unclosed multi-line string, if any.
+    multiline: Option<&'static str>,
+}
+
+impl ValueState {
+    /// Returns true if the value continues onto the next line.
+    fn is_open(&self) -> bool {
+        self.depth > 0 || self.multiline.is_some()
+    }
+
+    /// Scans (the rest of) a line of the value.
+    fn scan(&mut self, line: &str) {
+        let mut rest = line;
+        while !rest.is_empty() {
+            if let Some(delimiter) = self.multiline {
+                match rest.find(delimiter) {
+                    Some(end) => {
+                        rest = &rest[end + delimiter.len()..];
+                        self.multiline = None;
+                    }
+                    None => return,
+                }
+                continue;
+            }
+
+            if let Some(delimiter) = ["\"\"\"", "'''"]
+                .into_iter()
+                .find(|delimiter| rest.starts_with(delimiter))
+            {
+                self.multiline = Some(delimiter);
+                rest = &rest[delimiter.len()..];
+                continue;
+            }
+
+            let mut chars = rest.chars();
+            match chars.next() {
+                Some('#') => return,
+                Some('[' | '{') => self.depth += 1,
+                Some(']' | '}') => self.depth = self.depth.saturating_sub(1),
+                Some('"') => {
+                    // Skip the string (and its escaped characters).
+                    let mut escaped = false;
+                    for c in chars.by_ref() {
+                        match c {
+                            '\\' if !escaped => escaped = true,
+                            '"' if !escaped => break,
+                            _ => escaped = false,
+                        }
+                    }
+                }
+                Some('\'') => {
+                    for c in chars.by_ref() {
+                        if c == '\'' {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            rest = chars.as_str();
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn locates_keys_and_tables() {
+        let toml = indoc! {r#"
+            suppress-timestamps = true
+
+            [[processes]]
+            name = "a"
+            run = [
+                "/bin/sh", "-c", "x = 1",
+            ]
+
+            [[processes]]
+              name = "b"
+            script = """
+            key = value
+            """
+            env.FOO = "bar"
+
+            [processes.run]
+            command = "/app/run.sh"
+            "#};
+        let locations = KeyLocations::scan(toml);
+
+        assert_eq!(Some((1, 1)), locations.get("suppress-timestamps"));
+        assert_eq!(Some((3, 1)), locations.get("processes[0]"));
+        assert_eq!(Some((5, 1)), locations.get("processes[0].run"));
+        assert_eq!(None, locations.get("x"));
+        assert_eq!(Some((10, 3)), locations.get("processes[1].name"));
+        assert_eq!(None, locations.get("key"));
+        assert_eq!(Some((14, 1)), locations.get("processes[1].env.FOO"));
+        assert_eq!(Some((16, 1)), locations.get("processes[1].run"));
+        assert_eq!(Some((17, 1)), locations.get("processes[1].run.command"));
+    }
+
+    #[test]
+    fn finds_nearest_enclosing_key() {
+        let toml = indoc! {r#"
+            [[processes]]
+            name = "a"
+            depends-on = ["b"]
+            run = { command = "/app/run.sh", user = "app" }
+            "#};
+        let locations = KeyLocations::scan(toml);
+
+        assert_eq!(Some((3, 1)), locations.find("processes[0].depends-on[0]"));
+        assert_eq!(Some((4, 1)), locations.find("processes[0].run.user"));
+        assert_eq!(Some((1, 1)), locations.find("processes[0].ready"));
+        assert_eq!(None, locations.find("processes[1].ready"));
+    }
+}
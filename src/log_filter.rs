@@ -0,0 +1,301 @@
+//! Reloadable log filter, which allows the log level to be changed
+//! while Ground Control is running (globally, or for the logs of a
+//! single process) through the `log-level` control files.
+
+use std::collections::BTreeMap;
+
+use color_eyre::eyre::{self, eyre, WrapErr};
+use once_cell::sync::OnceCell;
+use tracing::{
+    field::{Field, Visit},
+    metadata::LevelFilter,
+    span,
+    subscriber::Interest,
+    Metadata, Subscriber,
+};
+use tracing_subscriber::{
+    layer::{Context, Filter},
+    registry::LookupSpan,
+    reload, EnvFilter,
+};
+
+/// Name of the span in which everything that Ground Control does for a
+/// process (starting it, monitoring its commands, restarting it, and
+/// stopping it) happens.
+const PROCESS_SPAN: &str = "process";
+
+/// Level of the process span (see `process::start_process`).
+const PROCESS_SPAN_LEVEL: LevelFilter = LevelFilter::INFO;
+
+/// Function that changes the level of the installed filter.
+type SetLevel = Box<dyn Fn(Option<&str>, &str) -> eyre::Result<()> + Send + Sync>;
+
+/// Installed filter, if any (see `install`).
+static SET_LEVEL: OnceCell<SetLevel> = OnceCell::new();
+
+/// Log filter that combines a global `RUST_LOG`-style filter with
+/// per-process levels, which enable more verbose logs for everything
+/// that happens in the span of the process.
+#[derive(Debug)]
+pub struct LogFilter {
+    /// Filter for all events.
+    global: EnvFilter,
+
+    /// Levels of the processes whose events are logged even if the
+    /// global filter would not log them.
+    processes: BTreeMap<String, LevelFilter>,
+}
+
+impl LogFilter {
+    /// Creates a log filter from the `RUST_LOG` environment variable,
+    /// without any per-process levels.
+    pub fn from_default_env() -> Self {
+        Self {
+            global: EnvFilter::from_default_env(),
+            processes: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the global filter (`level` is a level or `RUST_LOG`-style
+    /// directives, and restores the `RUST_LOG` environment variable if
+    /// empty) or the level of a single process (removing the process's
+    /// level if `level` is empty).
+    fn set_level(&mut self, process: Option<&str>, level: &str) -> eyre::Result<()> {
+        match process {
+            None if level.is_empty() => self.global = EnvFilter::from_default_env(),
+            None => {
+                self.global = EnvFilter::try_new(level)
+                    .wrap_err_with(|| format!("Invalid log level \"{level}\""))?;
+            }
+            Some(process) if level.is_empty() => {
+                self.processes.remove(process);
+            }
+            Some(process) => {
+                let level = level
+                    .parse()
+                    .map_err(|_| eyre!("Invalid log level \"{level}\""))?;
+                self.processes.insert(process.to_owned(), level);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the most verbose level of any process.
+    fn max_process_level(&self) -> Option<LevelFilter> {
+        self.processes.values().max().copied()
+    }
+}
+
+/// Name of the process to which a process span belongs (stored in the
+/// span's extensions when the span is created).
+#[derive(Debug)]
+struct ProcessName(String);
+
+/// Visitor that records the `name` field of a process span.
+#[derive(Default)]
+struct ProcessNameVisitor(Option<String>);
+
+impl Visit for ProcessNameVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "name" {
+            self.0 = Some(value.to_owned());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "name" {
+            self.0 = Some(format!("{value:?}"));
+        }
+    }
+}
+
+/// Returns true if the metadata belongs to a process span.
+fn is_process_span(metadata: &Metadata<'_>) -> bool {
+    metadata.is_span()
+        && metadata.name() == PROCESS_SPAN
+        && metadata.target().starts_with("groundcontrol")
+}
+
+impl<S> Filter<S> for LogFilter
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn enabled(&self, metadata: &Metadata<'_>, cx: &Context<'_, S>) -> bool {
+        if is_process_span(metadata) || Filter::enabled(&self.global, metadata, cx) {
+            return true;
+        }
+
+        // Otherwise, log the event if it happened in the span of a
+        // process whose level allows it.
+        if !metadata.is_event() || self.processes.is_empty() {
+            return false;
+        }
+        cx.lookup_current()
+            .and_then(|span| {
+                span.scope().find_map(|span| {
+                    let extensions = span.extensions();
+                    extensions
+                        .get::<ProcessName>()
+                        .and_then(|process| self.processes.get(&process.0).copied())
+                })
+            })
+            .map_or(false, |level| level >= *metadata.level())
+    }
+
+    fn callsite_enabled(&self, metadata: &'static Metadata<'static>) -> Interest {
+        if is_process_span(metadata) {
+            return Interest::always();
+        }
+
+        let interest = Filter::<S>::callsite_enabled(&self.global, metadata);
+        match self.max_process_level() {
+            Some(level) if !interest.is_always() && level >= *metadata.level() => {
+                Interest::sometimes()
+            }
+            _ => interest,
+        }
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        let global = Filter::<S>::max_level_hint(&self.global)?;
+        [global, PROCESS_SPAN_LEVEL]
+            .into_iter()
+            .chain(self.max_process_level())
+            .max()
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, cx: Context<'_, S>) {
+        if is_process_span(attrs.metadata()) {
+            let mut visitor = ProcessNameVisitor::default();
+            attrs.record(&mut visitor);
+            if let (Some(name), Some(span)) = (visitor.0, cx.span(id)) {
+                span.extensions_mut().replace(ProcessName(name));
+            }
+        }
+
+        Filter::on_new_span(&self.global, attrs, id, cx);
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, cx: Context<'_, S>) {
+        Filter::on_record(&self.global, id, values, cx);
+    }
+
+    fn on_enter(&self, id: &span::Id, cx: Context<'_, S>) {
+        Filter::on_enter(&self.global, id, cx);
+    }
+
+    fn on_exit(&self, id: &span::Id, cx: Context<'_, S>) {
+        Filter::on_exit(&self.global, id, cx);
+    }
+
+    fn on_close(&self, id: span::Id, cx: Context<'_, S>) {
+        Filter::on_close(&self.global, id, cx);
+    }
+}
+
+/// Installs the handle of a reloadable `LogFilter`, which allows the
+/// `log-level` control files to change the log level. Only the first
+/// handle is installed.
+pub fn install<S>(handle: reload::Handle<LogFilter, S>)
+where
+    S: 'static,
+{
+    let _ = SET_LEVEL.set(Box::new(move |process, level| {
+        let mut result = Ok(());
+        handle
+            .modify(|filter| result = filter.set_level(process, level))
+            .wrap_err("Failed to reload log filter")?;
+        result
+    }));
+}
+
+/// Changes the level of the installed filter (globally, or for a single
+/// process).
+pub(crate) fn set_level(process: Option<&str>, level: &str) -> eyre::Result<()> {
+    let set_level = SET_LEVEL
+        .get()
+        .ok_or_else(|| eyre!("No reloadable log filter is installed"))?;
+    set_level(process, level)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use pretty_assertions::assert_eq;
+    use tracing_subscriber::{layer::SubscriberExt, Layer, Registry};
+
+    use super::*;
+
+    /// Layer that records the messages of the events that it receives.
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl<S: Subscriber> Layer<S> for Recorder {
+        fn on_event(&self, event: &tracing::Event<'_>, _cx: Context<'_, S>) {
+            struct Message(String);
+            impl Visit for Message {
+                fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                    if field.name() == "message" {
+                        self.0 = format!("{value:?}");
+                    }
+                }
+            }
+
+            let mut message = Message(String::new());
+            event.record(&mut message);
+            #[allow(clippy::unwrap_used)]
+            self.0.lock().unwrap().push(message.0);
+        }
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[test]
+    fn changes_levels_at_runtime() {
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let (filter, handle) = reload::Layer::new(LogFilter {
+            global: EnvFilter::new("info"),
+            processes: BTreeMap::new(),
+        });
+        let subscriber = Registry::default().with(Recorder(messages.clone()).with_filter(filter));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let log = || {
+                tracing::debug!("outside");
+                let span = tracing::info_span!("process", name = "api");
+                let _entered = span.enter();
+                tracing::debug!("api");
+                tracing::trace!("api trace");
+            };
+
+            log();
+            handle
+                .modify(|filter| filter.set_level(Some("api"), "debug").unwrap())
+                .unwrap();
+            log();
+            handle
+                .modify(|filter| filter.set_level(None, "trace").unwrap())
+                .unwrap();
+            log();
+            handle
+                .modify(|filter| {
+                    filter.set_level(None, "info").unwrap();
+                    filter.set_level(Some("api"), "").unwrap();
+                })
+                .unwrap();
+            log();
+        });
+
+        assert_eq!(
+            vec!["api", "outside", "api", "api trace"],
+            *messages.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_levels() {
+        let mut filter = LogFilter::from_default_env();
+        assert!(filter.set_level(Some("api"), "loud").is_err());
+        assert!(filter.set_level(None, "=").is_err());
+    }
+}
@@ -14,25 +14,165 @@
     clippy::unwrap_used
 )]
 
-use clap::Parser;
+use clap::{builder::PossibleValuesParser, CommandFactory, Parser};
+use cli::{Cli, Command, ExportCommand, ImportCommand};
 use color_eyre::eyre::{self, WrapErr};
-use groundcontrol::config::Config;
+use groundcontrol::{
+    config::Config, doctor::Severity, exit_code, socket::SocketCommand, ShutdownReason,
+};
 use tokio::{
     signal::unix::{signal, SignalKind},
     sync::mpsc,
 };
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
+
+/// Command line arguments (in their own module, since the `Subcommand`
+/// derive generates fully-qualified paths).
+#[allow(unused_qualifications)]
+mod cli {
+    use std::path::PathBuf;
+
+    use clap::{Parser, Subcommand};
+
+    #[derive(Parser)]
+    #[clap(
+        about,
+        version,
+        long_about = None,
+        args_conflicts_with_subcommands = true,
+        subcommand_negates_reqs = true
+    )]
+    pub(crate) struct Cli {
+        #[clap(subcommand)]
+        pub(crate) command: Option<Command>,
+
+        /// Check the configuration file for errors, but do not start any
+        /// processes.
+        #[clap(long)]
+        pub(crate) check: bool,
+
+        /// Disable the given process (can be repeated); the process will
+        /// not be started, as if it had `disabled = true` in the config
+        /// file.
+        #[clap(long = "disable", value_name = "PROCESS")]
+        pub(crate) disable: Vec<String>,
+
+        /// Restrict template expansion in commands with an `only-env`
+        /// allow-list to the allowed variables, regardless of the config
+        /// file's `restrict-expansion` setting.
+        #[clap(long)]
+        pub(crate) restrict_expansion: bool,
+
+        /// Log the fully-resolved form of every command (arguments, user,
+        /// working directory, and environment, with sensitive values
+        /// redacted), but do not start any processes.
+        #[clap(long)]
+        pub(crate) print_resolved: bool,
+
+        /// Write the time that each process took to start (and the
+        /// critical path of dependencies) to the given file as JSON once
+        /// startup has completed.
+        #[clap(long, value_name = "FILE")]
+        pub(crate) startup_profile: Option<PathBuf>,
+
+        /// Read the config file as a `supervisord.conf` file (converting
+        /// its `[program:x]` sections into processes).
+        #[clap(long)]
+        pub(crate) supervisord: bool,
+
+        /// Print the version and build metadata (Git commit, build time,
+        /// and enabled features) as JSON, then exit.
+        #[clap(long, exclusive = true)]
+        pub(crate) version_json: bool,
+
+        #[clap(required = true)]
+        pub(crate) config_file: Option<String>,
+    }
+
+    #[derive(Subcommand)]
+    pub(crate) enum Command {
+        /// Print a shell completion script (which completes process names
+        /// from the config file, if provided) to standard output.
+        Completions {
+            shell: clap_complete::Shell,
+            config_file: Option<String>,
+        },
+
+        /// Inspect the runtime environment (and the config file, if
+        /// provided) for problems, and print what was found.
+        Doctor { config_file: Option<String> },
+
+        /// Export the processes in a config file to another process manager.
+        #[clap(subcommand)]
+        Export(ExportCommand),
+
+        /// List the processes of a running Ground Control (through the
+        /// `control-socket` in its config file), along with the state,
+        /// PID, and uptime of each process.
+        Status { config_file: String },
 
-#[derive(Parser)]
-#[clap(about, long_about = None)]
-struct Cli {
-    /// Check the configuration file for errors, but do not start any
-    /// processes.
-    #[clap(long)]
-    check: bool,
+        /// Start the stopped daemon of a process in a running Ground
+        /// Control (through the `control-socket` in its config file).
+        Start {
+            process: String,
+            config_file: String,
+        },
 
-    config_file: String,
+        /// Stop the daemon of a process in a running Ground Control
+        /// (through the `control-socket` in its config file), without
+        /// shutting down; the daemon stays stopped until it is started.
+        Stop {
+            process: String,
+            config_file: String,
+        },
+
+        /// Restart the daemon of a process in a running Ground Control
+        /// (through the `control-socket` in its config file).
+        Restart {
+            process: String,
+            config_file: String,
+        },
+
+        /// Print the exit history of a process (from the `state-dir` in
+        /// its config file).
+        History {
+            process: String,
+            config_file: String,
+        },
+
+        /// Convert another process manager's configuration into a config
+        /// file (which is written to standard output).
+        #[clap(subcommand)]
+        Import(ImportCommand),
+    }
+
+    #[derive(Subcommand)]
+    pub(crate) enum ExportCommand {
+        /// Convert every process into a systemd service unit.
+        Systemd {
+            config_file: String,
+
+            /// Directory into which the service units are written.
+            #[clap(short, long, value_name = "DIR")]
+            output: PathBuf,
+        },
+    }
+
+    #[derive(Subcommand)]
+    pub(crate) enum ImportCommand {
+        /// Convert the services in a Docker Compose file.
+        Compose { compose_file: String },
+
+        /// Convert the process types in a Heroku-style Procfile.
+        Procfile { procfile: String },
+
+        /// Convert the programs in a `supervisord.conf` file.
+        Supervisord { conf_file: String },
+    }
 }
 
+// `#[tokio::main]` expands to an `expect` on the runtime builder.
+#[allow(clippy::unwrap_in_result)]
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
     // Install color-eyre hooks.
@@ -40,20 +180,37 @@ async fn main() -> eyre::Result<()> {
 
     // Crash the process on a panic anywhere (including in a background
     // Tokio task, since we want panic to mean "something is very wrong;
-    // stop everything").
-    std::panic::set_hook(Box::new(|info| {
-        eprintln!("Process panicked: {info}");
-        std::process::abort();
-    }));
+    // stop everything"), except in the tasks that supervise a single
+    // daemon, whose panics only fail that daemon.
+    groundcontrol::install_panic_hook();
 
     // Parse the command line arguments.
     let cli = Cli::parse();
 
+    // Print the build metadata, if requested.
+    if cli.version_json {
+        println!("{}", serde_json::to_string(&groundcontrol::build_info())?);
+        return Ok(());
+    }
+
+    // Run the subcommand instead of starting the processes, if one was
+    // provided.
+    if let Some(command) = cli.command {
+        return run_command(command).await;
+    }
+
     // Read and parse the config file.
-    let config_file = tokio::fs::read_to_string(cli.config_file)
-        .await
-        .wrap_err("Failed to read config file")?;
-    let config: Config = toml::from_str(&config_file).wrap_err("Failed to parse config file")?;
+    let config_path = cli
+        .config_file
+        .clone()
+        .expect("config file is required without a subcommand");
+    let config = match load_config(&cli, &config_path).await {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            std::process::exit(exit_code::CONFIG_ERROR);
+        }
+    };
 
     // We're done if this was only a config file check.
     if cli.check {
@@ -66,35 +223,58 @@ async fn main() -> eyre::Result<()> {
     if std::env::var_os("RUST_LOG").is_none() {
         std::env::set_var("RUST_LOG", "info")
     }
-    tracing_subscriber::fmt()
+    // The log filter can be changed at runtime through the `log-level`
+    // control files.
+    let (filter, filter_handle) = tracing_subscriber::reload::Layer::new(
+        groundcontrol::log_filter::LogFilter::from_default_env(),
+    );
+    groundcontrol::log_filter::install(filter_handle);
+    let fmt_layer = tracing_subscriber::fmt::layer()
         .event_format(
             groundcontrol::formatter::GroundControlFormatter::from_config(&config)
                 .with_include_timestamp(!config.suppress_timestamps),
         )
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
+        .with_filter(filter);
+    let registry = tracing_subscriber::registry().with(fmt_layer);
+
+    // Expose the Tokio runtime to `tokio-console`, if enabled.
+    #[cfg(feature = "tokio-console")]
+    let registry = registry.with(console_subscriber::spawn());
+
+    registry.init();
+
+    // Log the resolved commands instead of starting the processes, if
+    // requested.
+    if cli.print_resolved {
+        return groundcontrol::log_resolved_commands(&config);
+    }
 
     // Create the external shutdown signal (used to shut down Ground
     // Control on UNIX signals).
     let (shutdown_sender, mut shutdown_receiver) = mpsc::unbounded_channel();
 
-    let sigint_shutdown_sender = shutdown_sender.clone();
-    tokio::spawn(async move {
-        signal(SignalKind::interrupt())
-            .expect("Failed to register SIGINT handler")
-            .recv()
-            .await;
-        let _ = sigint_shutdown_sender.send(());
-    });
-
-    let sigterm_shutdown_sender = shutdown_sender.clone();
-    tokio::spawn(async move {
-        signal(SignalKind::terminate())
-            .expect("Failed to register SIGTERM handler")
-            .recv()
-            .await;
-        let _ = sigterm_shutdown_sender.send(());
-    });
+    // SIGINT and SIGTERM trigger a graceful shutdown, unless signals are
+    // being forwarded to the daemon (in which case the daemon decides
+    // what to do with the signal, and its exit triggers the shutdown).
+    if !config.forward_signals {
+        let sigint_shutdown_sender = shutdown_sender.clone();
+        tokio::spawn(async move {
+            signal(SignalKind::interrupt())
+                .expect("Failed to register SIGINT handler")
+                .recv()
+                .await;
+            let _ = sigint_shutdown_sender.send(());
+        });
+
+        let sigterm_shutdown_sender = shutdown_sender.clone();
+        tokio::spawn(async move {
+            signal(SignalKind::terminate())
+                .expect("Failed to register SIGTERM handler")
+                .recv()
+                .await;
+            let _ = sigterm_shutdown_sender.send(());
+        });
+    }
 
     // Run the Ground Control specification, *unless* we are in
     // break-glass mode, in which case we freeze startup and just wait
@@ -102,14 +282,47 @@ async fn main() -> eyre::Result<()> {
     // into a machine that is in a startup-crash loop, perhaps due to an
     // issue on an attached, persistent storage volume)
     if std::env::var_os("BREAK_GLASS").is_none() {
-        groundcontrol::run(config, shutdown_receiver).await?;
+        let mirror_exit_code = config.forward_signals
+            || config
+                .processes
+                .iter()
+                .any(|process| process.main && !process.disabled);
+        match groundcontrol::run(config, shutdown_receiver).await {
+            // Mirror the exit code of the daemon (like tini) if we are
+            // forwarding signals to the daemon, or of the main process.
+            Err(groundcontrol::Error::AbnormalShutdown(reasons)) if mirror_exit_code => {
+                std::process::exit(daemon_exit_code(&reasons));
+            }
+            // Follow the (human-readable) error with a single line of
+            // JSON that orchestrators and log alerts can parse.
+            Err(err @ groundcontrol::Error::StartupAborted(_)) => {
+                let failure = err.startup_failure();
+                eprintln!("Error: {:?}", eyre::Report::new(err));
+                if let Some(failure) = failure {
+                    eprintln!("{}", serde_json::to_string(&failure)?);
+                }
+                std::process::exit(exit_code::STARTUP_ABORTED);
+            }
+            Err(err) => {
+                let code = err.exit_code();
+                eprintln!("Error: {:?}", eyre::Report::new(err));
+                std::process::exit(code);
+            }
+            // A shutdown requested through the control directory is
+            // reported as such, even when mirroring the exit code.
+            Ok(reason) if !mirror_exit_code || reason == ShutdownReason::ControlShutdown => {
+                std::process::exit(reason.exit_code());
+            }
+            Ok(_) => {}
+        }
     } else {
         tracing::info!("BREAK GLASS MODE: no processes will be started");
 
-        shutdown_receiver
-            .recv()
-            .await
-            .expect("All shutdown senders closed without sending a shutdown signal.");
+        if shutdown_receiver.recv().await.is_none() {
+            return Err(eyre::eyre!(
+                "All shutdown senders closed without sending a shutdown signal."
+            ));
+        }
 
         tracing::info!(
             "Shutdown signal triggered (make sure to clear the `BREAK_GLASS` environment variable)"
@@ -118,3 +331,219 @@ async fn main() -> eyre::Result<()> {
 
     Ok(())
 }
+
+/// Sends a request to the control socket of the running Ground Control
+/// with the given config file, returning the output of the request.
+async fn socket_request(config_file: &str, command: SocketCommand) -> eyre::Result<String> {
+    let config = read_config(config_file).await?;
+    let control_socket = config.control_socket.as_ref().ok_or_else(|| {
+        eyre::eyre!("Config file \"{config_file}\" does not have a `control-socket`")
+    })?;
+    groundcontrol::socket::request(control_socket, &command).await
+}
+
+/// Reads and parses the config file (including the processes in the
+/// config's `processes-dir`).
+async fn read_config(config_path: &str) -> eyre::Result<Config> {
+    let config_file = tokio::fs::read_to_string(config_path)
+        .await
+        .wrap_err_with(|| format!("Failed to read config file \"{config_path}\""))?;
+    let mut config = Config::from_toml(&config_file)
+        .wrap_err_with(|| format!("Failed to parse config file \"{config_path}\""))?;
+    config
+        .load_processes_dir()
+        .wrap_err("Failed to load processes directory")?;
+    config.path = Some(config_path.into());
+    Ok(config)
+}
+
+/// Reads a `supervisord.conf` file and converts it into a config.
+async fn read_supervisord_config(config_path: &str) -> eyre::Result<Config> {
+    let conf = tokio::fs::read_to_string(config_path)
+        .await
+        .wrap_err_with(|| format!("Failed to read supervisord config \"{config_path}\""))?;
+    let config_file = groundcontrol::import::supervisord(&conf)
+        .wrap_err_with(|| format!("Failed to convert supervisord config \"{config_path}\""))?;
+    let mut config = Config::from_toml(&config_file)
+        .wrap_err_with(|| format!("Failed to parse supervisord config \"{config_path}\""))?;
+    config.path = Some(config_path.into());
+    Ok(config)
+}
+
+/// Runs a subcommand.
+async fn run_command(command: Command) -> eyre::Result<()> {
+    match command {
+        Command::Completions { shell, config_file } => {
+            let mut command = Cli::command();
+
+            // Complete the process names in the config file (instead of
+            // arbitrary values) for the arguments that take a process.
+            if let Some(config_file) = config_file {
+                let config = read_config(&config_file).await?;
+                let names: Vec<String> = config
+                    .processes
+                    .iter()
+                    .map(|process| process.name.clone())
+                    .collect();
+                command = command.mut_arg("disable", |arg| {
+                    arg.value_parser(PossibleValuesParser::new(names.clone()))
+                });
+                for subcommand in ["start", "stop", "restart", "history"] {
+                    let names = names.clone();
+                    command = command.mut_subcommand(subcommand, |subcommand| {
+                        subcommand.mut_arg("process", |arg| {
+                            arg.value_parser(PossibleValuesParser::new(names))
+                        })
+                    });
+                }
+            }
+
+            let name = command.get_name().to_string();
+            clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+        }
+        Command::Export(ExportCommand::Systemd {
+            config_file,
+            output,
+        }) => {
+            let config = read_config(&config_file).await?;
+            config
+                .validate()
+                .wrap_err_with(|| format!("Invalid config file \"{config_file}\""))?;
+
+            for (file_name, unit) in groundcontrol::export::systemd_units(&config) {
+                let path = output.join(file_name);
+                tokio::fs::write(&path, unit).await.wrap_err_with(|| {
+                    format!("Failed to write unit file \"{}\"", path.display())
+                })?;
+                println!("{}", path.display());
+            }
+        }
+        Command::Doctor { config_file } => {
+            let config = match &config_file {
+                Some(config_file) => Some(read_config(config_file).await?),
+                None => None,
+            };
+
+            let findings = groundcontrol::doctor::diagnose(config.as_ref());
+            for finding in &findings {
+                println!("{finding}");
+            }
+
+            let errors = findings
+                .iter()
+                .filter(|finding| finding.severity == Severity::Error)
+                .count();
+            if errors > 0 {
+                return Err(eyre::eyre!("Found {errors} problem(s)"));
+            }
+        }
+        Command::Status { config_file } => {
+            print!(
+                "{}",
+                socket_request(&config_file, SocketCommand::Status).await?
+            );
+        }
+        Command::Start {
+            process,
+            config_file,
+        } => {
+            print!(
+                "{}",
+                socket_request(&config_file, SocketCommand::Start(process)).await?
+            );
+        }
+        Command::Stop {
+            process,
+            config_file,
+        } => {
+            print!(
+                "{}",
+                socket_request(&config_file, SocketCommand::Stop(process)).await?
+            );
+        }
+        Command::Restart {
+            process,
+            config_file,
+        } => {
+            print!(
+                "{}",
+                socket_request(&config_file, SocketCommand::Restart(process)).await?
+            );
+        }
+        Command::History {
+            process,
+            config_file,
+        } => {
+            let config = read_config(&config_file).await?;
+            let state_dir = config.state_dir.as_ref().ok_or_else(|| {
+                eyre::eyre!("Config file \"{config_file}\" does not have a `state-dir`")
+            })?;
+            for entry in groundcontrol::history::read(state_dir, &process)? {
+                println!("{entry}");
+            }
+        }
+        Command::Import(ImportCommand::Compose { compose_file }) => {
+            let compose = tokio::fs::read_to_string(&compose_file)
+                .await
+                .wrap_err_with(|| format!("Failed to read Compose file \"{compose_file}\""))?;
+            print!("{}", groundcontrol::import::compose(&compose)?);
+        }
+        Command::Import(ImportCommand::Procfile { procfile }) => {
+            let text = tokio::fs::read_to_string(&procfile)
+                .await
+                .wrap_err_with(|| format!("Failed to read Procfile \"{procfile}\""))?;
+            print!("{}", groundcontrol::import::procfile(&text)?);
+        }
+        Command::Import(ImportCommand::Supervisord { conf_file }) => {
+            let conf = tokio::fs::read_to_string(&conf_file)
+                .await
+                .wrap_err_with(|| format!("Failed to read supervisord config \"{conf_file}\""))?;
+            print!("{}", groundcontrol::import::supervisord(&conf)?);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads, parses, and validates the config file, applying the config
+/// overrides from the command line.
+async fn load_config(cli: &Cli, config_path: &str) -> eyre::Result<Config> {
+    let mut config = if cli.supervisord {
+        read_supervisord_config(config_path).await?
+    } else {
+        read_config(config_path).await?
+    };
+    if cli.restrict_expansion {
+        config.restrict_expansion = true;
+    }
+    config.startup_profile = cli.startup_profile.clone();
+    for name in &cli.disable {
+        config
+            .disable_process(name)
+            .wrap_err("Failed to disable process")?;
+    }
+    config
+        .validate()
+        .wrap_err_with(|| format!("Invalid config file \"{config_path}\""))?;
+    Ok(config)
+}
+
+/// Returns the exit code of the first failed daemon, using the shell
+/// convention of 128 plus the signal number for daemons that were
+/// killed by a signal.
+fn daemon_exit_code(reasons: &[ShutdownReason]) -> i32 {
+    reasons
+        .iter()
+        .find_map(|reason| match reason {
+            ShutdownReason::DaemonFailed {
+                exit_code: Some(exit_code),
+                ..
+            } => Some(*exit_code),
+            ShutdownReason::DaemonFailed {
+                signal: Some(signal),
+                ..
+            } => Some(128 + signal),
+            _ => None,
+        })
+        .unwrap_or(1)
+}
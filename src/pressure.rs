@@ -0,0 +1,149 @@
+//! Measures memory pressure, so that failed daemons are not restarted
+//! while the system is running out of memory.
+
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::config::RestartThrottleConfig;
+
+/// Time between checks of the memory pressure.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Checks whether the restart of a daemon should be delayed (for
+/// another `POLL_INTERVAL`) because the system is under memory pressure
+/// (as defined by the throttle config), returning the time at which the
+/// restart was first delayed if so. `delayed_since` is that time if the
+/// restart has already been delayed; restarts are not delayed for longer
+/// than the throttle's maximum delay (measured with Tokio's monotonic
+/// clock, so that changes to the system clock do not cut the delay short
+/// or extend it).
+pub(crate) async fn delay_restart(
+    process_name: &str,
+    config: &RestartThrottleConfig,
+    delayed_since: Option<Instant>,
+) -> Option<Instant> {
+    let reason = match memory_pressure(config).await {
+        Some(reason) => reason,
+        None => {
+            if delayed_since.is_some() {
+                tracing::info!(process = %process_name, "Memory pressure subsided; restarting daemon");
+            }
+            return None;
+        }
+    };
+
+    match delayed_since {
+        Some(delayed_since) if delayed_since.elapsed() >= config.max_delay => {
+            tracing::warn!(process = %process_name, %reason, "Memory pressure did not subside; restarting daemon anyway");
+            None
+        }
+        Some(delayed_since) => Some(delayed_since),
+        None => {
+            tracing::warn!(process = %process_name, %reason, "System is under memory pressure; delaying restart");
+            Some(Instant::now())
+        }
+    }
+}
+
+/// Returns a description of the memory pressure if the system is under
+/// more pressure than the throttle config allows. Measurements that are
+/// not available (for example, on kernels without PSI) are ignored.
+async fn memory_pressure(config: &RestartThrottleConfig) -> Option<String> {
+    if let Ok(psi) = tokio::fs::read_to_string("/proc/pressure/memory").await {
+        if let Some(pressure) = parse_pressure(&psi) {
+            if pressure > config.max_pressure {
+                return Some(format!(
+                    "memory pressure is {pressure:.1}% (maximum {}%)",
+                    config.max_pressure
+                ));
+            }
+        }
+    }
+
+    if let Ok(meminfo) = tokio::fs::read_to_string("/proc/meminfo").await {
+        if let Some(available) = parse_available(&meminfo) {
+            if available < config.min_available {
+                return Some(format!(
+                    "{available:.1}% of memory is available (minimum {}%)",
+                    config.min_available
+                ));
+            }
+        }
+    }
+
+    None
+}
+
+/// Parses the percentage of time that at least one task was stalled on
+/// memory over the last 10 seconds (`some avg10`) from the contents of
+/// `/proc/pressure/memory`.
+fn parse_pressure(psi: &str) -> Option<f64> {
+    psi.lines()
+        .find_map(|line| line.strip_prefix("some "))?
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("avg10="))?
+        .parse()
+        .ok()
+}
+
+/// Parses the percentage of memory that is available from the contents
+/// of `/proc/meminfo`.
+fn parse_available(meminfo: &str) -> Option<f64> {
+    let field = |name: &str| -> Option<f64> {
+        meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix(name))?
+            .trim_start_matches(':')
+            .split_whitespace()
+            .next()?
+            .parse()
+            .ok()
+    };
+
+    let total = field("MemTotal")?;
+    let available = field("MemAvailable")?;
+    (total > 0.0).then(|| available / total * 100.0)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn parses_proc_files() {
+        let psi = "some avg10=12.50 avg60=3.00 avg300=1.00 total=123456\n\
+                   full avg10=4.00 avg60=1.00 avg300=0.50 total=23456\n";
+        assert_eq!(Some(12.5), parse_pressure(psi));
+        assert_eq!(None, parse_pressure("full avg10=4.00\n"));
+
+        let meminfo = "MemTotal:        8000000 kB\n\
+                       MemFree:          100000 kB\n\
+                       MemAvailable:     400000 kB\n";
+        assert_eq!(Some(5.0), parse_available(meminfo));
+        assert_eq!(None, parse_available("MemTotal: 8000000 kB\n"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_after_max_delay() {
+        // More memory than exists is never available, so the system is
+        // always "under pressure".
+        let config = RestartThrottleConfig {
+            max_pressure: 100.0,
+            min_available: 101.0,
+            max_delay: Duration::from_secs(300),
+        };
+
+        let started = delay_restart("daemon", &config, None).await.unwrap();
+        assert_eq!(
+            Some(started),
+            delay_restart("daemon", &config, Some(started)).await
+        );
+
+        tokio::time::advance(config.max_delay).await;
+        assert_eq!(None, delay_restart("daemon", &config, Some(started)).await);
+    }
+}
@@ -0,0 +1,312 @@
+//! Probes the runtime environment for the kernel features (and
+//! privileges) that the configured options require, so that an option
+//! which cannot be honored is reported by name at startup, instead of
+//! failing obscurely once the process that uses it runs (or crashes).
+
+use std::path::Path;
+
+use color_eyre::eyre::{self, eyre};
+use nix::{
+    sys::resource::{getrlimit, Resource},
+    unistd::geteuid,
+};
+
+use crate::config::{Config, ProcessConfig};
+
+/// File that lists the mount points (used by `wait-for.mount`).
+const MOUNTINFO: &str = "/proc/self/mountinfo";
+
+/// File that reports memory pressure (used by `restart-throttle`).
+const MEMORY_PRESSURE: &str = "/proc/pressure/memory";
+
+/// File that reports the available memory (used by `restart-throttle`).
+const MEMINFO: &str = "/proc/meminfo";
+
+/// File that determines where the kernel writes core dumps.
+const CORE_PATTERN: &str = "/proc/sys/kernel/core_pattern";
+
+/// Features of the runtime environment that configured options rely on.
+#[derive(Clone, Debug)]
+pub(crate) struct Environment {
+    /// True if the mount points can be read from `/proc/self/mountinfo`.
+    mountinfo: bool,
+
+    /// True if the kernel reports memory pressure (PSI).
+    memory_pressure: bool,
+
+    /// True if the available memory can be read from `/proc/meminfo`.
+    meminfo: bool,
+
+    /// Hard limit on the size of core dumps (`RLIMIT_CORE`), which the
+    /// `core-dump.limit` cannot exceed.
+    core_hard_limit: u64,
+
+    /// The kernel's `core_pattern`, if it can be read.
+    core_pattern: Option<String>,
+
+    /// Effective user ID of Ground Control.
+    euid: u32,
+}
+
+impl Environment {
+    /// Probes the current environment.
+    pub(crate) fn current() -> Self {
+        Self {
+            mountinfo: Path::new(MOUNTINFO).exists(),
+            memory_pressure: Path::new(MEMORY_PRESSURE).exists(),
+            meminfo: Path::new(MEMINFO).exists(),
+            core_hard_limit: getrlimit(Resource::RLIMIT_CORE).map_or(u64::MAX, |(_, hard)| hard),
+            core_pattern: std::fs::read_to_string(CORE_PATTERN)
+                .ok()
+                .map(|pattern| pattern.trim().to_owned()),
+            euid: geteuid().as_raw(),
+        }
+    }
+}
+
+/// Option that cannot be honored in the runtime environment.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Unsupported {
+    /// Name of the process whose option cannot be honored.
+    pub(crate) process: String,
+
+    /// Description of the option and of what is missing.
+    pub(crate) message: String,
+
+    /// True if the process cannot work as configured (as opposed to
+    /// working with reduced functionality).
+    pub(crate) fatal: bool,
+}
+
+/// Returns the options of the (enabled) processes that cannot be honored
+/// in the environment.
+pub(crate) fn probe(config: &Config, environment: &Environment) -> Vec<Unsupported> {
+    let mut unsupported = Vec::new();
+    for process in config.processes.iter().filter(|process| !process.disabled) {
+        let mut report = |fatal: bool, message: String| {
+            unsupported.push(Unsupported {
+                process: process.name.clone(),
+                message,
+                fatal,
+            });
+        };
+
+        if let Some(mount) = process
+            .wait_for
+            .as_ref()
+            .and_then(|wait_for| wait_for.mount.as_ref())
+        {
+            if !environment.mountinfo {
+                report(
+                    true,
+                    format!(
+                        "Process \"{}\" waits for mount point \"{}\" (`wait-for.mount`), but {MOUNTINFO} cannot be read (is /proc mounted?)",
+                        process.name,
+                        mount.display()
+                    ),
+                );
+            }
+        }
+
+        if process.restart_throttle.is_some() {
+            if !environment.memory_pressure {
+                report(
+                    false,
+                    format!(
+                        "Process \"{}\" has a `restart-throttle.max-pressure`, but the kernel does not report memory pressure (PSI requires Linux 4.20 or later, built with CONFIG_PSI); only `min-available` is checked",
+                        process.name
+                    ),
+                );
+            }
+            if !environment.meminfo {
+                report(
+                    false,
+                    format!(
+                        "Process \"{}\" has a `restart-throttle.min-available`, but {MEMINFO} cannot be read (is /proc mounted?)",
+                        process.name
+                    ),
+                );
+            }
+        }
+
+        if let Some(core_dump) = &process.core_dump {
+            let limit = core_dump.limit.unwrap_or(u64::MAX);
+            if environment.core_hard_limit == 0 {
+                report(
+                    false,
+                    format!(
+                        "Process \"{}\" has a `core-dump`, but core dumps are disabled by the hard RLIMIT_CORE limit of Ground Control (`ulimit -Hc`)",
+                        process.name
+                    ),
+                );
+            } else if environment.core_hard_limit < limit {
+                report(
+                    false,
+                    format!(
+                        "Process \"{}\" has a `core-dump.limit` of {}, but the hard RLIMIT_CORE limit of Ground Control (`ulimit -Hc`) is {} bytes",
+                        process.name,
+                        core_dump
+                            .limit
+                            .map_or_else(|| String::from("unlimited"), |limit| format!("{limit} bytes")),
+                        environment.core_hard_limit
+                    ),
+                );
+            }
+
+            if let (Some(dir), Some(pattern)) = (&core_dump.dir, &environment.core_pattern) {
+                if pattern.starts_with('|') {
+                    report(
+                        false,
+                        format!(
+                            "Process \"{}\" looks for core dumps in `core-dump.dir` \"{}\", but the kernel's core_pattern pipes core dumps to a program (\"{pattern}\")",
+                            process.name,
+                            dir.display()
+                        ),
+                    );
+                }
+            }
+        }
+
+        for (phase, user) in other_users(process, environment.euid) {
+            report(
+                true,
+                format!(
+                    "Process \"{}\" runs its `{phase}` command as user \"{user}\" (`{phase}.user`), but Ground Control is not running as root (UID {})",
+                    process.name, environment.euid
+                ),
+            );
+        }
+    }
+
+    unsupported
+}
+
+/// Returns the phase and user of the commands of the process that run as
+/// a user other than Ground Control's (effective) user, unless Ground
+/// Control is running as root. Unknown users are ignored, since they are
+/// reported when the command runs.
+fn other_users(process: &ProcessConfig, euid: u32) -> Vec<(&'static str, &str)> {
+    if euid == 0 {
+        return Vec::new();
+    }
+
+    process
+        .commands()
+        .into_iter()
+        .filter_map(|(phase, command)| command.user.as_deref().map(|user| (phase, user)))
+        .filter(|(_, user)| users::get_user_by_name(user).map_or(false, |user| user.uid() != euid))
+        .collect()
+}
+
+/// Probes the current environment, logging the options that will only
+/// partially work, and returning an error that names every option that
+/// cannot be honored at all.
+pub(crate) fn check(config: &Config) -> eyre::Result<()> {
+    let mut fatal = Vec::new();
+    for unsupported in probe(config, &Environment::current()) {
+        if unsupported.fatal {
+            tracing::error!(process = %unsupported.process, "{}", unsupported.message);
+            fatal.push(unsupported.message);
+        } else {
+            tracing::warn!(process = %unsupported.process, "{}", unsupported.message);
+        }
+    }
+
+    if fatal.is_empty() {
+        Ok(())
+    } else {
+        Err(eyre!(
+            "The runtime environment does not support the configuration: {}",
+            fatal.join("; ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    /// Environment that supports everything.
+    fn capable() -> Environment {
+        Environment {
+            mountinfo: true,
+            memory_pressure: true,
+            meminfo: true,
+            core_hard_limit: u64::MAX,
+            core_pattern: Some(String::from("core")),
+            euid: 0,
+        }
+    }
+
+    #[allow(clippy::unwrap_used)]
+    fn config() -> Config {
+        toml::from_str(
+            r#"
+            [[processes]]
+            name = "app"
+            wait-for = { mount = "/data" }
+            restart-throttle = {}
+            core-dump = { limit = 1048576, dir = "/cores" }
+            run = { user = "root", command = "/app/server" }
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn accepts_capable_environments() {
+        assert_eq!(Vec::<Unsupported>::new(), probe(&config(), &capable()));
+    }
+
+    #[test]
+    fn names_the_options_that_cannot_be_honored() {
+        let environment = Environment {
+            mountinfo: false,
+            memory_pressure: false,
+            core_hard_limit: 0,
+            core_pattern: Some(String::from("|/usr/lib/systemd/systemd-coredump %P")),
+            euid: 1000,
+            ..capable()
+        };
+
+        let unsupported = probe(&config(), &environment);
+        let fatal: Vec<_> = unsupported
+            .iter()
+            .filter(|unsupported| unsupported.fatal)
+            .map(|unsupported| unsupported.message.as_str())
+            .collect();
+        assert_eq!(
+            vec![
+                "Process \"app\" waits for mount point \"/data\" (`wait-for.mount`), but /proc/self/mountinfo cannot be read (is /proc mounted?)",
+                "Process \"app\" runs its `run` command as user \"root\" (`run.user`), but Ground Control is not running as root (UID 1000)",
+            ],
+            fatal
+        );
+        assert_eq!(
+            3,
+            unsupported
+                .iter()
+                .filter(|unsupported| !unsupported.fatal)
+                .count()
+        );
+    }
+
+    #[test]
+    fn reports_core_dump_limits_above_the_hard_limit() {
+        let environment = Environment {
+            core_hard_limit: 4096,
+            ..capable()
+        };
+
+        assert_eq!(
+            vec![Unsupported {
+                process: String::from("app"),
+                message: String::from("Process \"app\" has a `core-dump.limit` of 1048576 bytes, but the hard RLIMIT_CORE limit of Ground Control (`ulimit -Hc`) is 4096 bytes"),
+                fatal: false,
+            }],
+            probe(&config(), &environment)
+        );
+    }
+}
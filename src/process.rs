@@ -1,189 +1,1833 @@
 //! Starts and stops processes.
 
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
 use color_eyre::eyre::{self, eyre, WrapErr};
-use tokio::sync::{mpsc, oneshot};
+use nix::{errno::Errno, sys::signal::Signal, unistd::Pid};
+use tokio::{
+    sync::{mpsc, oneshot, watch},
+    time::Instant,
+};
+use tracing::Instrument;
 
 use crate::{
-    command::{self, CommandControl, ExitStatus},
-    config::{CommandConfig, ProcessConfig, StopMechanism},
+    command::{self, CommandControl, CommandEnv, ExitStatus, RunInputs},
+    config::{
+        self, CommandConfig, CoreDumpConfig, HealthCheckConfig, HttpStopConfig,
+        OnHealthCheckFailure, OnStopFailure, ProcessConfig, RestartThrottleConfig, ShutdownClass,
+        StopMechanism,
+    },
+    containment::DaemonTasks,
+    events::{self, Event, EventLog},
+    history::{FailureDetails, FailureNotes},
+    quiesce, ready,
+    schedule::Schedule,
+    setup,
+    usage::UsageMonitor,
+    wait,
+    watchdog::{self, Watchdog},
     ShutdownReason,
 };
 
+#[cfg(feature = "consul")]
+use crate::consul;
+
 /// Process being managed by Ground Control.
 #[derive(Debug)]
 pub(crate) struct Process {
     config: ProcessConfig,
+    env: CommandEnv,
+    events: EventLog,
+    runtime_dir: Option<PathBuf>,
     handle: ProcessHandle,
+    paused: bool,
+    drained: bool,
+
+    /// When the daemon (if any) was started, which is used to enforce the
+    /// process's `max-runtime` (and to reset its `restart-backoff`).
+    daemon_started: Option<Instant>,
+
+    /// When the daemon (if any) is next due to be restarted on the
+    /// process's `restart-schedule`.
+    scheduled_restart: Option<Instant>,
+
+    /// When the daemon was restarted after failures (within the window
+    /// of the process's `restart-limit`).
+    restarts: VecDeque<Instant>,
+
+    /// Number of restarts since the daemon last ran for longer than the
+    /// maximum delay of the process's `restart-backoff`.
+    consecutive_restarts: u32,
+
+    /// Argv and environment with which the daemon (if any) was last
+    /// started, which are compared with those of the next run when the
+    /// daemon is restarted.
+    run_inputs: Option<RunInputs>,
+
+    /// Details of the daemon's last failure, for the exit history.
+    failure: FailureNotes,
 }
 
 #[derive(Debug)]
 enum ProcessHandle {
-    Daemon(CommandControl, oneshot::Receiver<ExitStatus>),
+    Daemon(DaemonHandle),
     OneShot,
 }
 
+/// Handle to the running daemon of a process.
+#[derive(Debug)]
+struct DaemonHandle {
+    /// Control handle for the daemon's command.
+    control: CommandControl,
+
+    /// Receives the exit status of the daemon once it exits.
+    exit: oneshot::Receiver<ExitStatus>,
+
+    /// Set once Ground Control asks the daemon to stop (see
+    /// `start_daemon`).
+    stopping: Arc<AtomicBool>,
+
+    /// Resource usage of the daemon.
+    usage: UsageMonitor,
+
+    /// Whether the daemon has failed its health check, if the process
+    /// has a `health-check`.
+    unhealthy: Option<watch::Receiver<bool>>,
+
+    /// Exit status of the daemon (`None` until the daemon exits).
+    exit_status: watch::Receiver<Option<ExitStatus>>,
+}
+
 /// Starts the process and returns a handle to the process.
+/// `restrict_expansion` limits template expansion in the process's
+/// commands to the variables that each command is allowed to see.
+/// `config_env` contains the variables in the config's `env` table. The
+/// process's lifecycle events are written to `events`.
+#[tracing::instrument(name = "process", skip_all, fields(name = %config.name))]
 pub(crate) async fn start_process(
     config: ProcessConfig,
+    restrict_expansion: bool,
+    config_env: HashMap<String, String>,
+    events: EventLog,
     process_stopped: mpsc::UnboundedSender<ShutdownReason>,
 ) -> eyre::Result<Process> {
     tracing::info!("Starting process {}", config.name);
 
-    // Perform the pre-run action, if provided.
-    if let Some(pre_run) = &config.pre {
-        run_process_command(&config.name, ProcessPhase::PreRun, pre_run).await?;
+    // Wait for the process's start condition (if any).
+    if let Some(wait_for) = &config.wait_for {
+        let env = CommandEnv {
+            config_env: config_env.clone(),
+            ..CommandEnv::default()
+        };
+        wait::wait_for(&config.name, wait_for, &env)
+            .await
+            .map_err(|err| ProcessStartFailed::wrap(&config, "wait-for", err))?;
     }
 
-    // Run the process itself (if this is a daemon process with a `run`
-    // command).
-    let handle = if let Some(run) = &config.run {
-        let (daemon_sender, daemon_receiver) = oneshot::channel();
-
-        let (control, monitor) = command::run(&config.name, run)
-            .wrap_err_with(|| format!("`run` command failed for process \"{}\"", config.name))?;
+    // Resolve the environment once, so that every one of the process's
+    // commands -- including `stop` and `post` -- see the same
+    // environment, unless the process asked for a fresh environment.
+    let mut env = CommandEnv {
+        snapshot: (!config.fresh_env).then(|| command::snapshot_env(&config_env)),
+        extra: HashMap::new(),
+        restrict_expansion,
+        preset: config.env_preset,
+        config_env,
+    };
+    insert_time_zone_env(&config, &mut env)
+        .map_err(|err| ProcessStartFailed::wrap(&config, "timezone", err))?;
 
-        // Spawn a task to wait for the command to exit, then notify
-        // both ourselves (to allow `stop` to return) and the shutdown
-        // listener that our daemon process has exited.
-        let process_name = config.name.clone();
-        tokio::spawn(async move {
-            let exit_status = monitor.wait().await;
+    // Create the runtime directory (if requested) and make its path
+    // available to every one of the process's commands.
+    let runtime_dir = config.runtime_dir_path();
+    if let Some(runtime_dir) = &runtime_dir {
+        create_runtime_dir(&config, runtime_dir)
+            .await
+            .map_err(|err| ProcessStartFailed::wrap(&config, "runtime-dir", err))?;
+        insert_runtime_dir_env(&config, runtime_dir, &mut env);
+    }
 
-            // TODO: Should this ever really happen? I would prefer to
-            // just `expect` here if it is not possible. *But,* we need
-            // to verify that, during some sort of startup/shutdown
-            // failure, that we do not drop things too early and then
-            // the receiver is gone.
-            if daemon_sender.send(exit_status).is_err() {
-                tracing::error!(process = %process_name, "Daemon receiver dropped before receiving exit signal.");
+    // Start the process, removing the runtime directory if the process
+    // fails to start (since it will never be stopped).
+    let failure = FailureNotes::default();
+    let process = match start_commands(&config, &env, &events, &failure, process_stopped).await {
+        Ok(handle) => Process {
+            run_inputs: matches!(handle, ProcessHandle::Daemon(..))
+                .then(|| resolve_run_inputs(&config, &env))
+                .flatten(),
+            daemon_started: matches!(handle, ProcessHandle::Daemon(..)).then(Instant::now),
+            scheduled_restart: matches!(handle, ProcessHandle::Daemon(..))
+                .then(|| next_scheduled_restart(&config))
+                .flatten(),
+            config,
+            env,
+            events,
+            runtime_dir,
+            handle,
+            paused: false,
+            drained: false,
+            restarts: VecDeque::new(),
+            consecutive_restarts: 0,
+            failure,
+        },
+        Err(err) => {
+            if let Some(runtime_dir) = &runtime_dir {
+                remove_runtime_dir(&config.name, runtime_dir).await;
             }
+            let phase = if config.run.is_some() { "run" } else { "pre" };
+            return Err(ProcessStartFailed::wrap(&config, phase, err));
+        }
+    };
 
-            let shutdown_reason = match exit_status {
-                ExitStatus::Exited(0) => ShutdownReason::DaemonExited,
-                ExitStatus::Exited(_) | ExitStatus::Killed => ShutdownReason::DaemonFailed,
-            };
+    // Wait for the daemon to become ready (if it has a readiness probe)
+    // and run its `post-start` command, stopping the process if either
+    // fails.
+    if let Err(err) = process.finish_daemon_start().await {
+        let err = ProcessStartFailed::wrap(&process.config, "ready", err);
+        if let Err(stop_err) = process.stop_process(false).await {
+            tracing::error!(?stop_err, "Error stopping process after failed startup");
+        }
+        return Err(err);
+    }
 
-            if let Err(err) = process_stopped.send(shutdown_reason) {
+    // Register the service now that the process has started, stopping
+    // the process if that fails.
+    #[cfg(feature = "consul")]
+    if let Some(service) = &process.config.service {
+        if let Err(err) = consul::register(&process.config.name, service, &process.env).await {
+            let err = ProcessStartFailed::wrap(&process.config, "service", err);
+            if let Err(stop_err) = process.stop_process(false).await {
                 tracing::error!(
-                    process = %process_name,
-                    ?err,
-                    "Shutdown receiver dropped before all processes have exited."
+                    ?stop_err,
+                    "Error stopping process after failed service registration"
                 );
             }
-        });
+            return Err(err);
+        }
+    }
+
+    Ok(process)
+}
+
+/// Returns the next time at which the daemon of the process is due to be
+/// restarted on its `restart-schedule` (if any).
+fn next_scheduled_restart(config: &ProcessConfig) -> Option<Instant> {
+    let schedule = Schedule::parse(config.restart_schedule.as_deref()?).ok()?;
+    let now = SystemTime::now();
+    let next = schedule.next_after(now)?;
+    Some(Instant::now() + next.duration_since(now).unwrap_or_default())
+}
+
+/// Resolves the inputs of the process's `run` command, returning `None`
+/// (after logging why) if they cannot be resolved.
+fn resolve_run_inputs(config: &ProcessConfig, env: &CommandEnv) -> Option<RunInputs> {
+    match RunInputs::resolve(config.run.as_ref()?, env) {
+        Ok(run_inputs) => Some(run_inputs),
+        Err(err) => {
+            tracing::debug!(process = %config.name, ?err, "Unable to resolve the inputs of the daemon");
+            None
+        }
+    }
+}
+
+/// Logs the resolved form of every one of the process's commands (see
+/// `command::resolve`) without running them. `env` contains the
+/// variables from the config's `env` table, which are added to Ground
+/// Control's environment.
+pub(crate) fn log_resolved_commands(
+    config: &ProcessConfig,
+    env: &HashMap<String, String>,
+    restrict_expansion: bool,
+) -> eyre::Result<()> {
+    let mut env = CommandEnv {
+        snapshot: Some(command::snapshot_env(env)),
+        extra: HashMap::new(),
+        restrict_expansion,
+        preset: config.env_preset,
+        config_env: env.clone(),
+    };
+    insert_time_zone_env(config, &mut env)?;
+    if let Some(runtime_dir) = config.runtime_dir_path() {
+        insert_runtime_dir_env(config, &runtime_dir, &mut env);
+    }
+
+    for (phase, command) in config.commands() {
+        let resolved = command::resolve(command, &env).wrap_err_with(|| {
+            format!(
+                "Failed to resolve `{phase}` command for process \"{}\"",
+                config.name
+            )
+        })?;
+        tracing::info!(
+            process = %config.name,
+            %phase,
+            argv = ?resolved.argv,
+            user = ?resolved.user,
+            cwd = %resolved.cwd.display(),
+            env = ?resolved.env,
+            "Resolved command"
+        );
+    }
 
-        ProcessHandle::Daemon(control, daemon_receiver)
-    } else {
-        ProcessHandle::OneShot
+    Ok(())
+}
+
+/// Makes the process's time zone (if any) available to every one of the
+/// process's commands as `TZ`, after verifying that the time zone exists
+/// in the tz database.
+fn insert_time_zone_env(config: &ProcessConfig, env: &mut CommandEnv) -> eyre::Result<()> {
+    if let Some(timezone) = &config.timezone {
+        let path = config::time_zone_path(timezone);
+        if !path.is_file() {
+            return Err(eyre!(
+                "Time zone \"{timezone}\" of process \"{}\" is not in the tz database (\"{}\" does not exist)",
+                config.name,
+                path.display()
+            ));
+        }
+        env.extra.insert(String::from("TZ"), timezone.clone());
+    }
+    Ok(())
+}
+
+/// Makes the path of the runtime directory (and of the watchdog file and
+/// socket in that directory, if the process has a watchdog) available to
+/// every one of the process's commands.
+fn insert_runtime_dir_env(config: &ProcessConfig, runtime_dir: &Path, env: &mut CommandEnv) {
+    env.extra.insert(
+        String::from("GC_RUNTIME_DIR"),
+        runtime_dir.to_string_lossy().into_owned(),
+    );
+
+    if config.watchdog.is_some() {
+        let (file, socket) = watchdog::paths(runtime_dir);
+        env.extra.insert(
+            String::from("GC_WATCHDOG_FILE"),
+            file.to_string_lossy().into_owned(),
+        );
+        env.extra.insert(
+            String::from("GC_WATCHDOG_SOCKET"),
+            socket.to_string_lossy().into_owned(),
+        );
+    }
+}
+
+/// Runs the `pre` and `run` commands of the process, returning the
+/// handle to the daemon (or a one-shot handle if there is no `run`
+/// command).
+async fn start_commands(
+    config: &ProcessConfig,
+    env: &CommandEnv,
+    events: &EventLog,
+    failure: &FailureNotes,
+    process_stopped: mpsc::UnboundedSender<ShutdownReason>,
+) -> eyre::Result<ProcessHandle> {
+    // Perform the pre-run action, if provided.
+    run_pre(config, env, events).await?;
+
+    // Run the process itself (if this is a daemon process with a `run`
+    // command). Standard input goes to the `run` command of daemon
+    // processes, or the `pre` command of one-shot processes.
+    let stdin = config.stdin.as_ref().map(|stdin| stdin.file.as_path());
+    match &config.run {
+        Some(run) => start_daemon(config, run, env, events, failure, stdin, process_stopped).await,
+        None => Ok(ProcessHandle::OneShot),
+    }
+}
+
+/// Runs the `pre` command of the process (if any), unless it has already
+/// succeeded (for one-shot processes with a success marker), in which
+/// case the command is skipped.
+async fn run_pre(config: &ProcessConfig, env: &CommandEnv, events: &EventLog) -> eyre::Result<()> {
+    let pre_run = match &config.pre {
+        Some(pre_run) => pre_run,
+        None => return Ok(()),
     };
 
-    Ok(Process { config, handle })
+    match &config.success_marker {
+        Some(marker) if marker.exists() => {
+            tracing::info!(process = %config.name, marker = %marker.display(), "Success marker exists; skipping `pre` command");
+        }
+        _ => {
+            let pre_stdin = match (&config.run, &config.stdin) {
+                (None, Some(stdin)) => Some(stdin.file.as_path()),
+                _ => None,
+            };
+            run_process_command(
+                &config.name,
+                ProcessPhase::PreRun,
+                pre_run,
+                env,
+                events,
+                pre_stdin,
+            )
+            .await?;
+
+            if let Some(marker) = &config.success_marker {
+                create_success_marker(marker).await.wrap_err_with(|| {
+                    format!(
+                        "Failed to create success marker \"{}\" for process \"{}\"",
+                        marker.display(),
+                        config.name
+                    )
+                })?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the shutdown reason that reports the exit of a daemon.
+pub(crate) fn daemon_exit_reason(process: &str, exit_status: ExitStatus) -> ShutdownReason {
+    match exit_status {
+        ExitStatus::Exited(0) => ShutdownReason::DaemonExited {
+            process: process.to_owned(),
+        },
+        ExitStatus::Exited(exit_code) => ShutdownReason::DaemonFailed {
+            process: process.to_owned(),
+            exit_code: Some(exit_code),
+            signal: None,
+        },
+        ExitStatus::Killed(signal) => ShutdownReason::DaemonFailed {
+            process: process.to_owned(),
+            exit_code: None,
+            signal,
+        },
+    }
+}
+
+/// Creates the success marker file (and its parent directories). The
+/// marker is written to a temporary file that is then renamed into
+/// place, so that the marker never exists in a partially-written state.
+async fn create_success_marker(marker: &Path) -> eyre::Result<()> {
+    let parent = marker
+        .parent()
+        .ok_or_else(|| eyre!("Success marker has no parent directory"))?;
+    tokio::fs::create_dir_all(parent).await?;
+
+    let file_name = marker
+        .file_name()
+        .ok_or_else(|| eyre!("Success marker has no file name"))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".tmp-{}", std::process::id()));
+    let temp_path = parent.join(temp_name);
+
+    let contents = format!("{}\n", humantime::format_rfc3339_seconds(SystemTime::now()));
+    tokio::fs::write(&temp_path, contents).await?;
+    tokio::fs::rename(&temp_path, marker).await?;
+    Ok(())
+}
+
+/// Delay before restarting a failed daemon (unless the process has a
+/// `restart-backoff`).
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Delay between attempts to start a `run` command that failed to spawn
+/// with a transient error.
+const SPAWN_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Returns true if the command failed to spawn with an error that is
+/// likely to go away if the spawn is retried.
+fn is_transient_spawn_error(err: &eyre::Report) -> bool {
+    err.chain()
+        .filter_map(|err| err.downcast_ref::<std::io::Error>())
+        .filter_map(std::io::Error::raw_os_error)
+        .any(|errno| matches!(Errno::from_i32(errno), Errno::ETXTBSY | Errno::EAGAIN))
+}
+
+/// Runs the `run` command of a daemon process, and spawns a task that
+/// notifies the shutdown listener when the daemon exits (unless Ground
+/// Control stopped the daemon).
+async fn start_daemon(
+    config: &ProcessConfig,
+    run: &CommandConfig,
+    env: &CommandEnv,
+    events: &EventLog,
+    failure: &FailureNotes,
+    stdin: Option<&Path>,
+    process_stopped: mpsc::UnboundedSender<ShutdownReason>,
+) -> eyre::Result<ProcessHandle> {
+    let (daemon_sender, daemon_receiver) = oneshot::channel();
+    let (exit_sender, exit_receiver) = watch::channel(None);
+
+    // Set once Ground Control asks the daemon to stop, at which
+    // point the daemon's exit is expected and is no longer reported
+    // to the shutdown listener.
+    let stopping = Arc::new(AtomicBool::new(false));
+
+    // Enable core dumps (up to the hard limit, unless a lower limit was
+    // requested) if the process asked for core dump handling.
+    let core_dump = config.core_dump.clone();
+    let core_limit = core_dump
+        .as_ref()
+        .map(|core_dump| core_dump.limit.unwrap_or(u64::MAX));
+
+    // Create the watchdog (if requested) before the daemon starts, so
+    // that the daemon can ping the watchdog as soon as it is running.
+    let watchdog = match (config.watchdog, config.runtime_dir_path()) {
+        (Some(timeout), Some(runtime_dir)) => Some(
+            Watchdog::create(&runtime_dir, timeout, run.user.as_deref()).wrap_err_with(|| {
+                format!("Failed to create watchdog for process \"{}\"", config.name)
+            })?,
+        ),
+        _ => None,
+    };
+
+    // Retry transient spawn failures (for example, `ETXTBSY` if a `pre`
+    // command was still writing the binary when it exited).
+    let mut attempt = 0;
+    let started = SystemTime::now();
+    let (control, monitor) = loop {
+        match command::run(&config.name, run, env, stdin, core_limit) {
+            Ok(handles) => break handles,
+            Err(err) if attempt < config.spawn_retries && is_transient_spawn_error(&err) => {
+                attempt += 1;
+                tracing::warn!(process = %config.name, ?err, attempt, "Transient error starting `run` command; retrying");
+                tokio::time::sleep(SPAWN_RETRY_DELAY).await;
+            }
+            Err(err) => {
+                return Err(err.wrap_err(CommandFailed {
+                    process: config.name.clone(),
+                    phase: String::from("run"),
+                    status: None,
+                }))
+            }
+        }
+    };
+
+    events.record(
+        &config.name,
+        Event::DaemonSpawned {
+            pid: control.pid().as_raw(),
+        },
+    );
+
+    // Every task that supervises the daemon fails the daemon (instead of
+    // aborting Ground Control) if the task panics.
+    let exited = Arc::new(AtomicBool::new(false));
+    let tasks = DaemonTasks::new(
+        config.name.clone(),
+        control.pid(),
+        exited.clone(),
+        stopping.clone(),
+        failure.clone(),
+    );
+
+    // Sample the daemon's resource usage until it exits.
+    let usage = UsageMonitor::start(&tasks, control.pid(), exited.clone());
+
+    // Kill the daemon if it stops pinging its watchdog.
+    if let Some(watchdog) = watchdog {
+        watchdog.start(
+            &tasks,
+            config.name.clone(),
+            control.pid(),
+            exited.clone(),
+            stopping.clone(),
+        );
+    }
+
+    // Check the health of the daemon periodically (if requested).
+    let unhealthy = start_health_check(
+        &tasks,
+        config,
+        env.clone(),
+        events.clone(),
+        control.pid(),
+        exited.clone(),
+        stopping.clone(),
+    );
+
+    // Spawn a task to wait for the command to exit, then notify
+    // both ourselves (to allow `stop` to return) and the shutdown
+    // listener that our daemon process has exited.
+    let process_name = config.name.clone();
+    let program = run.program.clone();
+    let crash_env = env.clone();
+    let daemon_stopping = stopping.clone();
+    let daemon_exited = exited.clone();
+    let panic_stopped = process_stopped.clone();
+    let daemon_usage = usage.clone();
+    let daemon_output = control.recent_output();
+    let daemon_events = events.clone();
+    let daemon_pid = control.pid();
+    let daemon_failure = failure.clone();
+    let wait_for_exit = async move {
+        let exit_status = monitor.wait().await;
+        daemon_exited.store(true, Ordering::SeqCst);
+        exit_sender.send_replace(Some(exit_status));
+
+        let (exit_code, signal) = events::exit_fields(exit_status);
+        daemon_events.record(
+            &process_name,
+            Event::DaemonExited {
+                pid: daemon_pid.as_raw(),
+                exit_code,
+                signal,
+                expected: daemon_stopping.load(Ordering::SeqCst),
+            },
+        );
+
+        // TODO: Should this ever really happen? I would prefer to
+        // just `expect` here if it is not possible. *But,* we need
+        // to verify that, during some sort of startup/shutdown
+        // failure, that we do not drop things too early and then
+        // the receiver is gone.
+        if daemon_sender.send(exit_status).is_err() {
+            tracing::error!(process = %process_name, "Daemon receiver dropped before receiving exit signal.");
+        }
+
+        if daemon_stopping.load(Ordering::SeqCst) {
+            return;
+        }
+
+        // Keep the last-known state of a failed daemon for the exit
+        // history.
+        if exit_status != ExitStatus::Exited(0) {
+            daemon_failure.note_snapshot(daemon_usage.usage(), daemon_output.lines().await);
+        }
+
+        // Run the crash hook (if any) before reporting the failure, so
+        // that the hook has finished by the time the daemon is restarted
+        // or Ground Control shuts down.
+        if let (ExitStatus::Killed(Some(signal)), Some(core_dump)) = (exit_status, &core_dump) {
+            handle_crash(
+                &process_name,
+                &program,
+                core_dump,
+                &crash_env,
+                &daemon_events,
+                signal,
+                started,
+            )
+            .await;
+        }
+
+        if let Err(err) = process_stopped.send(daemon_exit_reason(&process_name, exit_status)) {
+            tracing::error!(
+                process = %process_name,
+                ?err,
+                "Shutdown receiver dropped before all processes have exited."
+            );
+        }
+    };
+
+    // If waiting for the exit panics, the exit will never be reported,
+    // so kill the daemon (if it is still running) and report it as
+    // failed instead.
+    let panic_tasks = tasks.clone();
+    let process_name = config.name.clone();
+    tasks.spawn_with(wait_for_exit.in_current_span(), move || {
+        if panic_tasks.is_stopping() {
+            return;
+        }
+        if !exited.swap(true, Ordering::SeqCst) {
+            panic_tasks.kill_daemon();
+        }
+        let _ = panic_stopped.send(ShutdownReason::DaemonFailed {
+            process: process_name,
+            exit_code: None,
+            signal: None,
+        });
+    });
+
+    Ok(ProcessHandle::Daemon(DaemonHandle {
+        control,
+        exit: daemon_receiver,
+        stopping,
+        usage,
+        unhealthy,
+        exit_status: exit_receiver,
+    }))
+}
+
+/// Spawns a task that checks the health of a daemon every `interval`
+/// (if the process has a `health-check`) until the daemon exits (or is
+/// stopped), returning a receiver that becomes true once `failure-threshold` consecutive checks have failed.
+/// The task stops checking at that point, unless the daemon is only
+/// reported as unhealthy (`on-failure = "report"`), in which case the
+/// receiver becomes false again once `success-threshold` consecutive
+/// checks have passed. Checks are skipped while the daemon is paused.
+fn start_health_check(
+    tasks: &DaemonTasks,
+    process: &ProcessConfig,
+    env: CommandEnv,
+    events: EventLog,
+    pid: Pid,
+    exited: Arc<AtomicBool>,
+    stopping: Arc<AtomicBool>,
+) -> Option<watch::Receiver<bool>> {
+    let config = process.health_check.clone()?;
+    let (unhealthy_sender, unhealthy) = watch::channel(false);
+    let process_name = process.name.clone();
+    let default_port = process.ports.first().copied();
+    let check = async move {
+        let probe = ready::Probe::of_health_check(&config, default_port);
+        let mut healthy = true;
+        let mut failures = 0;
+        let mut successes = 0;
+        loop {
+            tokio::time::sleep(config.interval).await;
+            if exited.load(Ordering::SeqCst) || stopping.load(Ordering::SeqCst) {
+                return;
+            }
+            if watchdog::is_stopped(pid) {
+                tracing::debug!(process = %process_name, "Daemon is stopped; skipping its health check");
+                continue;
+            }
+
+            let result = match tokio::time::timeout(
+                config.timeout,
+                ready::check(&process_name, probe, &env),
+            )
+            .await
+            {
+                Ok(result) => result,
+                Err(_) => Err(eyre!(
+                    "Health check timed out after {}",
+                    humantime::format_duration(config.timeout)
+                )),
+            };
+            match result {
+                Ok(()) => {
+                    failures = 0;
+                    successes += 1;
+                    if healthy || successes < config.success_threshold {
+                        if !healthy {
+                            tracing::info!(
+                                process = %process_name,
+                                successes,
+                                threshold = config.success_threshold,
+                                "Unhealthy daemon passed its health check"
+                            );
+                        }
+                        continue;
+                    }
+
+                    healthy = true;
+                    let reason = format!("Passed {successes} consecutive health checks");
+                    tracing::info!(process = %process_name, %reason, "Daemon is healthy again");
+                    events.record(
+                        &process_name,
+                        Event::HealthChanged {
+                            pid: pid.as_raw(),
+                            healthy,
+                            reason: &reason,
+                        },
+                    );
+                    let _ = unhealthy_sender.send(false);
+                }
+                Err(err) => {
+                    successes = 0;
+                    failures += 1;
+                    tracing::warn!(
+                        process = %process_name,
+                        ?err,
+                        failures,
+                        threshold = config.failure_threshold,
+                        "Daemon failed its health check"
+                    );
+                    if !healthy || failures < config.failure_threshold {
+                        continue;
+                    }
+                    if exited.load(Ordering::SeqCst) || stopping.load(Ordering::SeqCst) {
+                        return;
+                    }
+
+                    healthy = false;
+                    let reason = format!("Failed {failures} consecutive health checks: {err}");
+                    tracing::warn!(process = %process_name, %reason, "Daemon is unhealthy");
+                    events.record(
+                        &process_name,
+                        Event::HealthChanged {
+                            pid: pid.as_raw(),
+                            healthy,
+                            reason: &reason,
+                        },
+                    );
+                    let _ = unhealthy_sender.send(true);
+                    if config.on_failure != OnHealthCheckFailure::Report {
+                        return;
+                    }
+                }
+            }
+        }
+    };
+    tasks.spawn(check.in_current_span());
+    Some(unhealthy)
 }
 
 impl Process {
+    /// Returns the name of the process.
+    pub(crate) fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    /// Returns true if this is a daemon process.
+    pub(crate) fn is_daemon(&self) -> bool {
+        matches!(self.handle, ProcessHandle::Daemon(..))
+    }
+
+    /// Returns true if this process has a daemon (a `run` command),
+    /// whether or not the daemon is currently running.
+    pub(crate) fn has_daemon(&self) -> bool {
+        self.config.run.is_some()
+    }
+
+    /// Returns the state of the process (for the control socket's
+    /// `status` request): `running`, `paused`, `exited` (for a daemon
+    /// that has exited, but whose exit has not yet been handled),
+    /// `stopped` (for a daemon that was stopped through the control
+    /// socket), or `active` (for one-shot processes).
+    pub(crate) fn state(&self) -> &'static str {
+        match &self.handle {
+            ProcessHandle::Daemon(daemon) if daemon.exit_status.borrow().is_some() => "exited",
+            ProcessHandle::Daemon(..) if self.paused => "paused",
+            ProcessHandle::Daemon(..) => "running",
+            ProcessHandle::OneShot if self.has_daemon() => "stopped",
+            ProcessHandle::OneShot => "active",
+        }
+    }
+
+    /// Returns true if the daemon of this process has failed its health
+    /// check (false for processes without a running daemon, or without a
+    /// `health-check`).
+    pub(crate) fn is_unhealthy(&self) -> bool {
+        match &self.handle {
+            ProcessHandle::Daemon(DaemonHandle {
+                unhealthy: Some(unhealthy),
+                ..
+            }) => *unhealthy.borrow(),
+            ProcessHandle::Daemon(..) | ProcessHandle::OneShot => false,
+        }
+    }
+
+    /// Returns the PID of the daemon of this process, if the daemon is
+    /// running.
+    pub(crate) fn pid(&self) -> Option<Pid> {
+        match &self.handle {
+            ProcessHandle::Daemon(daemon) => Some(daemon.control.pid()),
+            ProcessHandle::OneShot => None,
+        }
+    }
+
+    /// Returns how long the daemon of this process has been running, if
+    /// the daemon is running.
+    pub(crate) fn uptime(&self) -> Option<Duration> {
+        self.daemon_started.map(|started| started.elapsed())
+    }
+
+    /// Returns the owner of the process, if the config names one.
+    pub(crate) fn owner(&self) -> Option<&str> {
+        self.config.owner.as_deref()
+    }
+
+    /// Returns the description of the process, if the config has one.
+    pub(crate) fn description(&self) -> Option<&str> {
+        self.config.description.as_deref()
+    }
+
+    /// Returns the TCP ports that the process declares.
+    pub(crate) fn ports(&self) -> &[u16] {
+        &self.config.ports
+    }
+
+    /// Returns true if this is the `main` process (to which signals are
+    /// forwarded).
+    pub(crate) fn is_main(&self) -> bool {
+        self.config.main
+    }
+
+    /// Returns the `shutdown-class` of this process.
+    pub(crate) fn shutdown_class(&self) -> ShutdownClass {
+        self.config.shutdown_class
+    }
+
+    /// Returns true if this process is bound to the process with the
+    /// given name (`bound-to`).
+    pub(crate) fn is_bound_to(&self, target: &str) -> bool {
+        self.config.bound_to.as_deref() == Some(target)
+    }
+
+    /// Waits for the daemon of this process to fail its health check
+    /// (never returns if the process has no running daemon, no
+    /// `health-check`, or a health check that only reports the daemon as
+    /// unhealthy).
+    pub(crate) async fn health_check_failed(&self) {
+        let mut unhealthy = match &self.handle {
+            ProcessHandle::Daemon(DaemonHandle {
+                unhealthy: Some(unhealthy),
+                ..
+            }) if !self.reports_when_unhealthy() => unhealthy.clone(),
+            ProcessHandle::Daemon(..) | ProcessHandle::OneShot => {
+                return std::future::pending().await
+            }
+        };
+        while !*unhealthy.borrow_and_update() {
+            if unhealthy.changed().await.is_err() {
+                // The health check stopped without the daemon becoming
+                // unhealthy (because the daemon exited, or was stopped).
+                return std::future::pending().await;
+            }
+        }
+    }
+
+    /// Returns true if the daemon of this process should be restarted
+    /// (instead of shutting down Ground Control) once it fails its
+    /// health check.
+    pub(crate) fn restarts_when_unhealthy(&self) -> bool {
+        matches!(
+            self.config.health_check,
+            Some(HealthCheckConfig {
+                on_failure: OnHealthCheckFailure::Restart,
+                ..
+            })
+        )
+    }
+
+    /// Returns true if the daemon of this process should only be
+    /// reported as unhealthy (and left running) when it fails its health
+    /// check.
+    fn reports_when_unhealthy(&self) -> bool {
+        matches!(
+            self.config.health_check,
+            Some(HealthCheckConfig {
+                on_failure: OnHealthCheckFailure::Report,
+                ..
+            })
+        )
+    }
+
+    /// Returns true if the daemon of this process should be restarted
+    /// after failing with the given exit code.
+    pub(crate) fn restarts_after(&self, exit_code: i32) -> bool {
+        self.is_daemon() && self.config.restarts_after(exit_code)
+    }
+
+    /// Returns true if the daemon of this process should be restarted
+    /// after exiting cleanly.
+    pub(crate) fn restarts_after_clean_exit(&self) -> bool {
+        self.is_daemon() && self.config.restarts_after_clean_exit()
+    }
+
+    /// Returns true if the daemon of this process should be restarted
+    /// after it was stopped for exceeding its `max-runtime`.
+    pub(crate) fn restarts_after_max_runtime(&self) -> bool {
+        self.config.restarts_after_max_runtime()
+    }
+
+    /// Returns the time at which the daemon of this process will have
+    /// exceeded its `max-runtime`, if the process has a running daemon
+    /// and a `max-runtime`.
+    pub(crate) fn max_runtime_deadline(&self) -> Option<Instant> {
+        self.daemon_started
+            .zip(self.config.max_runtime)
+            .map(|(started, max_runtime)| started + max_runtime)
+    }
+
+    /// Returns true if the daemon of this process has already been
+    /// restarted as many times as its `restart-limit` allows (within the
+    /// limit's window).
+    pub(crate) fn restart_limit_reached(&self) -> bool {
+        self.config.restart_limit.map_or(false, |limit| {
+            let restarts = self
+                .restarts
+                .iter()
+                .filter(|restarted| restarted.elapsed() < limit.window)
+                .count();
+            restarts >= limit.restarts as usize
+        })
+    }
+
+    /// Records a restart of the failed daemon of this process, returning
+    /// how long to wait before restarting it: the fixed restart delay, or
+    /// the next delay of the process's `restart-backoff`. The exited
+    /// daemon is no longer running, so its `max-runtime` and
+    /// `restart-schedule` timers are cancelled until it is restarted.
+    pub(crate) fn record_restart(&mut self) -> Duration {
+        let now = Instant::now();
+        let daemon_started = self.daemon_started.take();
+        self.scheduled_restart = None;
+        if let Some(limit) = &self.config.restart_limit {
+            self.restarts
+                .retain(|restarted| now.duration_since(*restarted) < limit.window);
+            self.restarts.push_back(now);
+        }
+
+        let backoff = match &self.config.restart_backoff {
+            Some(backoff) => backoff,
+            None => return RESTART_DELAY,
+        };
+
+        // A daemon that ran for at least the maximum delay is considered
+        // to have recovered, which resets the backoff.
+        if daemon_started.map_or(false, |started| now.duration_since(started) >= backoff.max) {
+            self.consecutive_restarts = 0;
+        }
+
+        let delay = backoff
+            .initial
+            .saturating_mul(2u32.saturating_pow(self.consecutive_restarts))
+            .min(backoff.max);
+        self.consecutive_restarts = self.consecutive_restarts.saturating_add(1);
+        delay
+    }
+
+    /// Returns the restart throttle of this process (if any).
+    pub(crate) fn restart_throttle(&self) -> Option<&RestartThrottleConfig> {
+        self.config.restart_throttle.as_ref()
+    }
+
+    /// Removes (and returns) the details of the last failure of the
+    /// daemon of this process, which are recorded in the exit history.
+    pub(crate) fn take_failure(&self) -> FailureDetails {
+        self.failure.take()
+    }
+
+    /// Returns the resource usage monitor of the daemon of this process
+    /// (if this is a daemon process).
+    pub(crate) fn usage_monitor(&self) -> Option<UsageMonitor> {
+        match &self.handle {
+            ProcessHandle::Daemon(daemon) => Some(daemon.usage.clone()),
+            ProcessHandle::OneShot => None,
+        }
+    }
+
+    /// Returns a handle to the exit status of the daemon of this process
+    /// (which is `None` until the daemon exits), or `None` if this is a
+    /// one-shot process. The handle outlives the process, so that the
+    /// exit status of a daemon that is being stopped can be read once
+    /// the process has stopped.
+    pub(crate) fn exit_status(&self) -> Option<watch::Receiver<Option<ExitStatus>>> {
+        match &self.handle {
+            ProcessHandle::Daemon(daemon) => Some(daemon.exit_status.clone()),
+            ProcessHandle::OneShot => None,
+        }
+    }
+
+    /// Restarts this one-shot process by running its `pre` command again
+    /// (unless its success marker exists, in which case nothing is
+    /// run). The process remains active whether or not the command
+    /// succeeds.
+    #[tracing::instrument(name = "process", skip_all, fields(name = %self.config.name))]
+    pub(crate) async fn restart_one_shot(&self) -> eyre::Result<()> {
+        tracing::info!("Restarting one-shot process {}", self.config.name);
+        run_pre(&self.config, &self.env, &self.events).await
+    }
+
+    /// Restarts the daemon of this process (which must have already
+    /// exited) by running its `run` command again; the `pre` and `post`
+    /// commands are not run, and the runtime directory is kept.
+    #[tracing::instrument(name = "process", skip_all, fields(name = %self.config.name))]
+    pub(crate) async fn restart_daemon(
+        &mut self,
+        process_stopped: mpsc::UnboundedSender<ShutdownReason>,
+    ) -> eyre::Result<()> {
+        tracing::info!("Restarting process {}", self.config.name);
+
+        let run =
+            self.config.run.as_ref().ok_or_else(|| {
+                eyre!("Process \"{}\" has no daemon to restart", self.config.name)
+            })?;
+
+        // Log what (if anything) changed in the daemon's inputs since it
+        // was last started, to show whether the restart is running the
+        // daemon any differently than before (the environment can only
+        // change with `fresh-env`, since the environment of every other
+        // process is captured once, when the process starts).
+        let run_inputs = resolve_run_inputs(&self.config, &self.env);
+        if let (Some(previous), Some(current)) = (&self.run_inputs, &run_inputs) {
+            let changes = current.changes_since(previous);
+            if changes.is_empty() {
+                tracing::info!("Inputs unchanged since the previous run");
+            } else {
+                tracing::info!(?changes, "Inputs changed since the previous run");
+            }
+        }
+        self.run_inputs = run_inputs;
+
+        let stdin = self.config.stdin.as_ref().map(|stdin| stdin.file.as_path());
+        self.handle = start_daemon(
+            &self.config,
+            run,
+            &self.env,
+            &self.events,
+            &self.failure,
+            stdin,
+            process_stopped,
+        )
+        .await?;
+        self.daemon_started = Some(Instant::now());
+        self.scheduled_restart = next_scheduled_restart(&self.config);
+        self.paused = false;
+
+        self.finish_daemon_start().await
+    }
+
+    /// Waits for the (just started) daemon of this process to become
+    /// ready, if the process has a `ready` probe, then runs the
+    /// `post-start` command (if present). One-shot processes have no
+    /// daemon, and return immediately.
+    async fn finish_daemon_start(&self) -> eyre::Result<()> {
+        if !self.is_daemon() {
+            return Ok(());
+        }
+
+        if let Some(ready) = &self.config.ready {
+            ready::wait_until_ready(
+                &self.config.name,
+                ready,
+                &self.env,
+                self.config.ports.first().copied(),
+            )
+            .await?;
+        }
+
+        match &self.config.post_start {
+            Some(post_start) => {
+                run_process_command(
+                    &self.config.name,
+                    ProcessPhase::PostStart,
+                    post_start,
+                    &self.env,
+                    &self.events,
+                    None,
+                )
+                .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the time at which the daemon of this process is next due
+    /// to be restarted on its `restart-schedule`, if the process has a
+    /// running daemon and a `restart-schedule`.
+    pub(crate) fn scheduled_restart(&self) -> Option<Instant> {
+        self.scheduled_restart
+    }
+
+    /// Restarts the daemon of this process on its `restart-schedule`:
+    /// the daemon's service (if any) is deregistered before the daemon is
+    /// stopped, and registered again once the daemon has been restarted.
+    /// Drained daemons are restarted, but stay drained.
+    pub(crate) async fn restart_on_schedule(
+        &mut self,
+        process_stopped: mpsc::UnboundedSender<ShutdownReason>,
+    ) -> eyre::Result<()> {
+        #[cfg(feature = "consul")]
+        if self.config.service.is_some() && !self.drained {
+            if let Err(err) = consul::deregister(&self.config.name, &self.env).await {
+                tracing::warn!(process = %self.config.name, ?err, "Error deregistering service.");
+            }
+        }
+
+        self.stop_daemon().await?;
+        self.restart_daemon(process_stopped).await?;
+
+        #[cfg(feature = "consul")]
+        if let Some(service) = &self.config.service {
+            if !self.drained {
+                consul::register(&self.config.name, service, &self.env).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pauses the daemon of this process (along with everything else in
+    /// its process group) with `SIGSTOP`, until `resume` is called.
+    pub(crate) fn pause(&mut self) -> eyre::Result<()> {
+        self.signal_daemon_group(Signal::SIGSTOP)?;
+        self.paused = true;
+        Ok(())
+    }
+
+    /// Resumes the (paused) daemon of this process with `SIGCONT`.
+    pub(crate) fn resume(&mut self) -> eyre::Result<()> {
+        self.signal_daemon_group(Signal::SIGCONT)?;
+        self.paused = false;
+        Ok(())
+    }
+
+    /// Marks the daemon of this process as not ready (deregistering its
+    /// service, if any) without stopping it, so that it can be drained
+    /// before maintenance. The daemon stays drained until it is stopped.
+    pub(crate) async fn drain(&mut self) -> eyre::Result<()> {
+        if !self.is_daemon() {
+            return Err(eyre!(
+                "Process \"{}\" does not have a daemon",
+                self.config.name
+            ));
+        }
+        if self.drained {
+            return Ok(());
+        }
+
+        #[cfg(feature = "consul")]
+        if self.config.service.is_some() {
+            consul::deregister(&self.config.name, &self.env).await?;
+        }
+
+        self.drained = true;
+        Ok(())
+    }
+
+    /// Sends a signal to the process group of the daemon of this process
+    /// (one-shot processes have no daemon to signal, which is an error).
+    fn signal_daemon_group(&self, signal: Signal) -> eyre::Result<()> {
+        match &self.handle {
+            ProcessHandle::Daemon(daemon) => daemon.control.kill_group(signal),
+            ProcessHandle::OneShot => Err(eyre!(
+                "Process \"{}\" does not have a daemon",
+                self.config.name
+            )),
+        }
+    }
+
+    /// Returns a handle that kills the daemon of this process (if this
+    /// is a daemon process), which remains usable while the process is
+    /// being stopped.
+    pub(crate) fn daemon_killer(&self) -> Option<DaemonKiller> {
+        match &self.handle {
+            ProcessHandle::Daemon(daemon) => Some(DaemonKiller {
+                control: daemon.control.clone(),
+                stopping: daemon.stopping.clone(),
+            }),
+            ProcessHandle::OneShot => None,
+        }
+    }
+
+    /// Sends a signal to the daemon of this process (one-shot processes
+    /// have nothing to signal, and ignore the signal).
+    pub(crate) fn send_signal(&self, signal: Signal) -> eyre::Result<()> {
+        match &self.handle {
+            ProcessHandle::Daemon(daemon) => daemon.control.kill(signal),
+            ProcessHandle::OneShot => Ok(()),
+        }
+    }
+
     /// Stops the process: executes the `stop` command/signal if this is
     /// a daemon process; waits for the process to exit; runs the `post`
-    /// command (if present).
-    pub(crate) async fn stop_process(self) -> eyre::Result<()> {
+    /// command (if present). `exited` skips straight to the `post`
+    /// command for daemons that are known to have already exited (which
+    /// would otherwise fail to be stopped).
+    #[tracing::instrument(name = "process", skip_all, fields(name = %self.config.name))]
+    pub(crate) async fn stop_process(mut self, exited: bool) -> eyre::Result<()> {
         tracing::info!("Stopping process {}", self.config.name);
 
+        // Deregister the service first, so that nothing new is sent to
+        // the process while it is stopping.
+        #[cfg(feature = "consul")]
+        if self.config.service.is_some() && !self.drained {
+            if let Err(err) = consul::deregister(&self.config.name, &self.env).await {
+                tracing::warn!(process = %self.config.name, ?err, "Error deregistering service.");
+            }
+        }
+
         // Stop the process (which is only required for daemon
         // processes; one-shot processes never "started").
-        match self.handle {
-            ProcessHandle::Daemon(control, mut daemon_receiver) => {
-                // Has the daemon already shut down? If so, we do not
-                // need to stop it (we just need to run the `post`
-                // command, if any). Note that, if the `stop` operation
-                // fails, we will *not* wait for the daemon to exit,
-                // since it probably did not get our stop signal.
-                if daemon_receiver.try_recv().is_ok() {
-                    tracing::debug!(process = %self.config.name, "Process already exited; no need to `stop` it.");
-                } else if let Err(err) = match self.config.stop {
-                    StopMechanism::Signal(signal) => control.kill(signal.into()),
-                    StopMechanism::Command(command) => {
-                        run_process_command(&self.config.name, ProcessPhase::Stop, &command).await
-                    }
-                } {
-                    tracing::warn!(process = %self.config.name, ?err, "Error stopping process.");
-                } else {
-                    // Wait for the daemon to stop.
-                    match daemon_receiver.await {
-                        Ok(ExitStatus::Exited(0)) => {
-                            tracing::debug!(process = %self.config.name, "Process exited cleanly");
-                        }
-                        Ok(ExitStatus::Exited(exit_code)) => {
-                            tracing::warn!(process = %self.config.name, %exit_code, "Process exited with non-zero exit code");
-                        }
-                        Ok(ExitStatus::Killed) => {
-                            tracing::warn!(process = %self.config.name, "Process was killed");
-                        }
-                        Err(_) => {
-                            // TODO: Should this ever really happen? I
-                            // would prefer to just `expect` here if it
-                            // is not possible. *But,* we need to verify
-                            // that, during some sort of
-                            // startup/shutdown failure, that we do not
-                            // drop things too early and then receiver
-                            // is gone.
-                            tracing::error!("Daemon sender dropped before delivering exit signal.")
-                        }
-                    }
-                }
+        let handle = std::mem::replace(&mut self.handle, ProcessHandle::OneShot);
+        self.stop_daemon_handle(handle, exited).await?;
+
+        // Execute the `post`(-run) command.
+        let result = match &self.config.post {
+            Some(post_run) => {
+                run_process_command(
+                    &self.config.name,
+                    ProcessPhase::PostRun,
+                    post_run,
+                    &self.env,
+                    &self.events,
+                    None,
+                )
+                .await
             }
-            ProcessHandle::OneShot => {}
+            None => Ok(()),
         };
 
-        // Execute the `post`(-run) command.
-        if let Some(post_run) = &self.config.post {
-            run_process_command(&self.config.name, ProcessPhase::PostRun, post_run).await?;
+        // Clean up the runtime directory now that nothing is using it.
+        if let Some(runtime_dir) = &self.runtime_dir {
+            remove_runtime_dir(&self.config.name, runtime_dir).await;
         }
 
         // The process has been stopped.
+        result
+    }
+
+    /// Stops the daemon of this process without stopping the process
+    /// (because it exceeded its `max-runtime`, failed its health check,
+    /// is due to be restarted on its `restart-schedule`, is bound to a
+    /// daemon that stopped, or was stopped or restarted through the
+    /// control socket). The daemon is stopped as it would be at
+    /// shutdown, but the `post` command is not run, and its timers and
+    /// paused state are cleared, so that it can be started again with
+    /// `restart_daemon`.
+    #[tracing::instrument(name = "process", skip_all, fields(name = %self.config.name))]
+    pub(crate) async fn stop_daemon(&mut self) -> eyre::Result<()> {
+        tracing::info!("Stopping daemon of process {}", self.config.name);
+
+        let handle = std::mem::replace(&mut self.handle, ProcessHandle::OneShot);
+        self.daemon_started = None;
+        self.scheduled_restart = None;
+        let result = self.stop_daemon_handle(handle, false).await;
+        self.paused = false;
+        result
+    }
+
+    /// Stops a daemon with the process's `stop` command/signal and waits
+    /// for the daemon to exit (see `stop_process`). Only returns an error
+    /// if stopping the daemon failed and the process's `on-stop-failure`
+    /// is `abort`; one-shot processes have nothing to stop.
+    async fn stop_daemon_handle(&self, handle: ProcessHandle, exited: bool) -> eyre::Result<()> {
+        let DaemonHandle {
+            control,
+            exit: mut daemon_receiver,
+            stopping,
+            ..
+        } = match handle {
+            ProcessHandle::Daemon(daemon) => daemon,
+            ProcessHandle::OneShot => return Ok(()),
+        };
+
+        // Any exit from this point forward is the result of us stopping
+        // the daemon, and not a reason to shut down.
+        stopping.store(true, Ordering::SeqCst);
+
+        // Has the daemon already shut down? If so, we do not need to stop
+        // it (we just need to run the `post` command, if any). Note that,
+        // if the `stop` operation fails, we will *not* wait for the
+        // daemon to exit (unless we kill it), since it probably did not
+        // get our stop signal.
+        let mut exited = exited || daemon_receiver.try_recv().is_ok();
+
+        // Give load balancers time to take the daemon out of rotation
+        // (after its service was deregistered by `stop_process`) before
+        // it is stopped, unless the daemon has already exited.
+        if let Some(delay) = self.config.pre_stop_delay {
+            if !exited && !self.paused {
+                tracing::info!(process = %self.config.name, ?delay, "Waiting before stopping process");
+                tokio::time::sleep(delay).await;
+            }
+        }
+
+        // A paused daemon cannot respond to being stopped, so resume it
+        // first.
+        if self.paused && !exited {
+            if let Err(err) = control.kill_group(Signal::SIGCONT) {
+                tracing::warn!(process = %self.config.name, ?err, "Error resuming paused process.");
+            }
+        }
+
+        // The daemon may also have exited during the delay.
+        exited = exited || daemon_receiver.try_recv().is_ok();
+
+        // Run the `pre-stop` command right before the daemon is stopped;
+        // the daemon is stopped even if the command fails.
+        if let (Some(pre_stop), false) = (&self.config.pre_stop, exited) {
+            if let Err(err) = run_process_command(
+                &self.config.name,
+                ProcessPhase::PreStop,
+                pre_stop,
+                &self.env,
+                &self.events,
+                None,
+            )
+            .await
+            {
+                tracing::warn!(process = %self.config.name, ?err, "Error running `pre-stop` command.");
+            }
+        }
+
+        if !exited {
+            let stop = match &self.config.stop {
+                StopMechanism::Signal(signal) => Signal::from(*signal).as_str(),
+                StopMechanism::Http(_) => "http",
+                StopMechanism::Command(_) => "command",
+            };
+            self.events.record(
+                &self.config.name,
+                Event::StopIssued {
+                    pid: control.pid().as_raw(),
+                    stop,
+                },
+            );
+        }
+
+        let mut exit_status = None;
+        if exited {
+            tracing::debug!(process = %self.config.name, "Process already exited; no need to `stop` it.");
+        } else if let Err(err) = match &self.config.stop {
+            StopMechanism::Signal(signal) => control.kill((*signal).into()),
+            StopMechanism::Http(http) => {
+                quiesce_daemon(&self.config.name, http, &control, &mut daemon_receiver)
+                    .await
+                    .map(|status| exit_status = status)
+            }
+            StopMechanism::Command(command) => {
+                run_process_command(
+                    &self.config.name,
+                    ProcessPhase::Stop,
+                    command,
+                    &self.env,
+                    &self.events,
+                    None,
+                )
+                .await
+            }
+        } {
+            tracing::warn!(process = %self.config.name, ?err, "Error stopping process.");
+
+            match self.config.on_stop_failure {
+                OnStopFailure::Continue => {}
+                OnStopFailure::KillGroup => {
+                    tracing::warn!(process = %self.config.name, "Killing the process group of the process.");
+                    self.events.record(
+                        &self.config.name,
+                        Event::StopIssued {
+                            pid: control.pid().as_raw(),
+                            stop: Signal::SIGKILL.as_str(),
+                        },
+                    );
+                    match control.kill_group(Signal::SIGKILL) {
+                        Ok(()) => wait_for_daemon(&self.config.name, daemon_receiver).await,
+                        Err(err) => {
+                            tracing::error!(process = %self.config.name, ?err, "Error killing process group.");
+                        }
+                    }
+                }
+                OnStopFailure::Abort => {
+                    return Err(err.wrap_err(ShutdownAborted {
+                        process: self.config.name.clone(),
+                    }));
+                }
+            }
+        } else if let Some(exit_status) = exit_status {
+            log_daemon_exit(&self.config.name, Ok(exit_status));
+        } else {
+            wait_for_daemon(&self.config.name, daemon_receiver).await;
+        }
+
         Ok(())
     }
 }
 
+/// Kills a daemon (see `Process::daemon_killer`), which is used when the
+/// shutdown of Ground Control runs out of time.
+#[derive(Debug)]
+pub(crate) struct DaemonKiller {
+    control: CommandControl,
+    stopping: Arc<AtomicBool>,
+}
+
+impl DaemonKiller {
+    /// Kills the daemon, along with everything else in its process
+    /// group, with SIGKILL (without waiting for the daemon to exit). The
+    /// daemon's exit is not a reason to shut down.
+    pub(crate) fn kill(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        if let Err(err) = self.control.kill_group(Signal::SIGKILL) {
+            tracing::debug!(
+                ?err,
+                "Error killing process group (it may have already exited)"
+            );
+        }
+    }
+}
+
+/// Asks a daemon to quiesce with its `stop` HTTP request, then waits (up
+/// to the `timeout`) for the daemon to exit, returning its exit status.
+/// The `then-signal` is sent instead if the request fails, or if the
+/// daemon has not exited in time (in which case no exit status is
+/// returned).
+async fn quiesce_daemon(
+    process: &str,
+    http: &HttpStopConfig,
+    control: &CommandControl,
+    daemon_receiver: &mut oneshot::Receiver<ExitStatus>,
+) -> eyre::Result<Option<ExitStatus>> {
+    match quiesce::request(&http.http).await {
+        Ok(()) => {
+            tracing::info!(%process, url = %http.http, "Requested quiesce; waiting for process to exit");
+            match tokio::time::timeout(http.timeout, &mut *daemon_receiver).await {
+                Ok(Ok(exit_status)) => return Ok(Some(exit_status)),
+                Ok(Err(_)) => return Ok(None),
+                Err(_) => {
+                    tracing::warn!(%process, timeout = ?http.timeout, "Process did not exit after quiesce request; sending signal");
+                }
+            }
+        }
+        Err(err) => {
+            tracing::warn!(%process, url = %http.http, ?err, "Quiesce request failed; sending signal");
+        }
+    }
+
+    control.kill(http.then_signal.into()).map(|()| None)
+}
+
+/// Waits for a daemon that is being stopped to exit, logging how it
+/// exited.
+async fn wait_for_daemon(process: &str, daemon_receiver: oneshot::Receiver<ExitStatus>) {
+    log_daemon_exit(process, daemon_receiver.await);
+}
+
+/// Logs how a daemon that was being stopped exited.
+fn log_daemon_exit(process: &str, exit_status: Result<ExitStatus, oneshot::error::RecvError>) {
+    match exit_status {
+        Ok(ExitStatus::Exited(0)) => {
+            tracing::debug!(%process, "Process exited cleanly");
+        }
+        Ok(ExitStatus::Exited(exit_code)) => {
+            tracing::warn!(%process, %exit_code, "Process exited with non-zero exit code");
+        }
+        Ok(ExitStatus::Killed(_)) => {
+            tracing::warn!(%process, "Process was killed");
+        }
+        Err(_) => {
+            // TODO: Should this ever really happen? I would prefer to
+            // just `expect` here if it is not possible. *But,* we need
+            // to verify that, during some sort of startup/shutdown
+            // failure, that we do not drop things too early and then
+            // receiver is gone.
+            tracing::error!("Daemon sender dropped before delivering exit signal.")
+        }
+    }
+}
+
+/// Error context added to the `stop` failure of a process whose
+/// `on-stop-failure` is `abort`, which tells Ground Control to stop
+/// shutting down the remaining processes.
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to stop process \"{process}\"; aborting shutdown")]
+pub(crate) struct ShutdownAborted {
+    process: String,
+}
+
+/// Runs the `on-crash` command of a daemon that was killed by a signal.
+/// The command is given the signal, the daemon's program, and the newest
+/// core dump written since the daemon started (if any) in environment
+/// variables. Errors are logged, since the daemon has already failed.
+async fn handle_crash(
+    process_name: &str,
+    program: &str,
+    core_dump: &CoreDumpConfig,
+    env: &CommandEnv,
+    events: &EventLog,
+    signal: i32,
+    started: SystemTime,
+) {
+    tracing::error!(process = %process_name, %signal, "Daemon crashed");
+
+    let on_crash = match &core_dump.on_crash {
+        Some(on_crash) => on_crash,
+        None => return,
+    };
+
+    let mut env = env.clone();
+    env.extra
+        .insert(String::from("GC_CRASH_SIGNAL"), signal.to_string());
+    env.extra
+        .insert(String::from("GC_CRASH_PROGRAM"), program.to_string());
+
+    if let Some(dir) = &core_dump.dir {
+        match newest_core_dump(dir, started).await {
+            Ok(Some(core_file)) => {
+                env.extra.insert(
+                    String::from("GC_CORE_FILE"),
+                    core_file.to_string_lossy().into_owned(),
+                );
+            }
+            Ok(None) => {
+                tracing::warn!(process = %process_name, dir = %dir.display(), "No core dump found");
+            }
+            Err(err) => {
+                tracing::warn!(process = %process_name, ?err, "Failed to search for core dump");
+            }
+        }
+    }
+
+    if let Err(err) = run_process_command(
+        process_name,
+        ProcessPhase::OnCrash,
+        on_crash,
+        &env,
+        events,
+        None,
+    )
+    .await
+    {
+        tracing::error!(?err, "Error running crash hook");
+    }
+}
+
+/// Returns the most recently modified file in the core dump directory,
+/// ignoring files that were last modified before `since`. File
+/// timestamps come from a coarser clock than `SystemTime::now`, and so
+/// `since` is moved back by a second to allow for that imprecision.
+async fn newest_core_dump(dir: &Path, since: SystemTime) -> std::io::Result<Option<PathBuf>> {
+    let since = since
+        .checked_sub(Duration::from_secs(1))
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    let mut newest: Option<(SystemTime, PathBuf)> = None;
+
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        let modified = metadata.modified()?;
+        if metadata.is_file()
+            && modified >= since
+            && newest
+                .as_ref()
+                .map_or(true, |(newest_modified, _)| modified > *newest_modified)
+        {
+            newest = Some((modified, entry.path()));
+        }
+    }
+
+    Ok(newest.map(|(_, path)| path))
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 enum ProcessPhase {
     PreRun,
+    PostStart,
+    PreStop,
     Stop,
     PostRun,
+    OnCrash,
 }
 
 impl std::fmt::Display for ProcessPhase {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ProcessPhase::PreRun => write!(f, "pre"),
+            ProcessPhase::PostStart => write!(f, "post-start"),
+            ProcessPhase::PreStop => write!(f, "pre-stop"),
             ProcessPhase::Stop => write!(f, "stop"),
             ProcessPhase::PostRun => write!(f, "post"),
+            ProcessPhase::OnCrash => write!(f, "on-crash"),
         }
     }
 }
 
-/// Runs one of a process's "phase" commands -- `pre`, `stop`, or
-/// `post`, but crucially, not `run` -- and returns the success or
-/// failure of the command.
+/// Runs one of a process's "phase" commands -- `pre`, `post-start`,
+/// `pre-stop`, `stop`, `post`, or `on-crash`, but crucially, not `run`
+/// -- and returns the success or failure of the command.
 async fn run_process_command(
     process_name: &str,
     process_phase: ProcessPhase,
     command: &CommandConfig,
+    env: &CommandEnv,
+    events: &EventLog,
+    stdin: Option<&Path>,
 ) -> eyre::Result<()> {
-    let (_control, monitor) = command::run(&format!("{process_name}[{process_phase}]"), command)
-        .wrap_err_with(|| {
-            format!("`{process_phase}` command failed for process \"{process_name}\"")
-        })?;
+    let (control, monitor) = command::run(
+        &format!("{process_name}[{process_phase}]"),
+        command,
+        env,
+        stdin,
+        None,
+    )
+    .wrap_err_with(|| CommandFailed {
+        process: process_name.to_owned(),
+        phase: process_phase.to_string(),
+        status: None,
+    })?;
+
+    let phase = process_phase.to_string();
+    let pid = control.pid().as_raw();
+    events.record(process_name, Event::CommandStarted { phase: &phase, pid });
+
+    // Kill the command if we stop waiting for it (because the shutdown
+    // ran out of time, for example), instead of leaving it running.
+    let mut guard = KillOnDrop(Some(control));
+    let exit_status = monitor.wait().await;
+    guard.0 = None;
+
+    let (exit_code, signal) = events::exit_fields(exit_status);
+    events.record(
+        process_name,
+        Event::CommandExited {
+            phase: &phase,
+            pid,
+            exit_code,
+            signal,
+        },
+    );
 
-    match monitor.wait().await {
+    match exit_status {
         ExitStatus::Exited(0) => Ok(()),
-        ExitStatus::Exited(exit_code) => {
-            Err(eyre!(
-                "`{process_phase}` command failed for process \"{process_name}\" (exit code {exit_code})",
-            ))
+        status => Err(eyre::Report::new(CommandFailed {
+            process: process_name.to_owned(),
+            phase: process_phase.to_string(),
+            status: Some(status),
+        })),
+    }
+}
+
+/// Kills the process group of a command when dropped, unless the
+/// command's control handle has been taken back out of the guard (once
+/// the command has exited).
+struct KillOnDrop(Option<CommandControl>);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        if let Some(control) = &self.0 {
+            if let Err(err) = control.kill_group(Signal::SIGKILL) {
+                tracing::debug!(?err, "Error killing abandoned command");
+            }
+        }
+    }
+}
+
+/// Failure of one of a process's commands (either because the command
+/// could not be started, or because it exited with a non-zero exit code
+/// or was killed), which is kept in the error chain so that a failed
+/// startup can be described in a structured form.
+#[derive(Debug)]
+pub(crate) struct CommandFailed {
+    /// Name of the process.
+    pub(crate) process: String,
+
+    /// Phase of the command (`pre`, `run`, `stop`, and so on).
+    pub(crate) phase: String,
+
+    /// Exit status of the command, or `None` if the command could not
+    /// be started.
+    pub(crate) status: Option<ExitStatus>,
+}
+
+impl std::fmt::Display for CommandFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let CommandFailed {
+            process,
+            phase,
+            status,
+        } = self;
+
+        match status {
+            Some(ExitStatus::Exited(exit_code)) => write!(
+                f,
+                "`{phase}` command failed for process \"{process}\" (exit code {exit_code})"
+            ),
+            Some(ExitStatus::Killed(_)) => {
+                write!(f, "`{phase}` command was killed for process \"{process}\"")
+            }
+            None => write!(f, "`{phase}` command failed for process \"{process}\""),
+        }
+    }
+}
+
+impl std::error::Error for CommandFailed {}
+
+/// Failure to start a process, which identifies the process (along with
+/// its owner) and the phase of its startup that failed, so that a failed
+/// startup can be described in a structured form whatever the cause of
+/// the failure. The failure is transparent: it is displayed as (and has
+/// the same sources as) the error that it wraps, so that the error chain
+/// is unchanged.
+#[derive(Debug)]
+pub(crate) struct ProcessStartFailed {
+    /// Name of the process.
+    pub(crate) process: String,
+
+    /// Owner of the process.
+    pub(crate) owner: Option<String>,
+
+    /// Description of the process.
+    pub(crate) description: Option<String>,
+
+    /// Phase of the startup (`wait-for`, `runtime-dir`, `pre`, `run`,
+    /// `ready`, and so on).
+    pub(crate) phase: String,
+
+    /// Error that stopped the process from starting.
+    pub(crate) error: eyre::Report,
+}
+
+impl ProcessStartFailed {
+    /// Wraps the error that stopped the process from starting in the
+    /// given phase, or in the phase of the failed command (if the error
+    /// is a failed command).
+    pub(crate) fn wrap(config: &ProcessConfig, phase: &str, error: eyre::Report) -> eyre::Report {
+        let phase = error
+            .downcast_ref::<CommandFailed>()
+            .map_or_else(|| phase.to_owned(), |failed| failed.phase.clone());
+        eyre::Report::new(ProcessStartFailed {
+            process: config.name.clone(),
+            owner: config.owner.clone(),
+            description: config.description.clone(),
+            phase,
+            error,
+        })
+    }
+}
+
+impl std::fmt::Display for ProcessStartFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for ProcessStartFailed {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.chain().nth(1)
+    }
+}
+
+/// Creates the runtime directory for the process, owned by the user
+/// that runs the process's `run` command (or `pre` command, for one-shot
+/// processes). The directory is removed when the process stops, so a
+/// directory that already exists (and which Ground Control therefore did
+/// not create) is refused instead of being reused.
+async fn create_runtime_dir(config: &ProcessConfig, runtime_dir: &Path) -> eyre::Result<()> {
+    let create = async {
+        if let Some(parent) = runtime_dir.parent() {
+            tokio::fs::create_dir_all(parent).await?;
         }
-        ExitStatus::Killed => {
-            Err(eyre!(
-                "`{process_phase}` command was killed for process \"{process_name}\"",
-            ))
+        tokio::fs::create_dir(runtime_dir).await
+    };
+    create.await.map_err(|err| {
+        let reason = if err.kind() == std::io::ErrorKind::AlreadyExists {
+            eyre!("Directory already exists")
+        } else {
+            eyre::Report::new(err)
+        };
+        reason.wrap_err(format!(
+            "Failed to create runtime directory \"{}\" for process \"{}\"",
+            runtime_dir.display(),
+            config.name
+        ))
+    })?;
+
+    let username = config
+        .run
+        .as_ref()
+        .or(config.pre.as_ref())
+        .and_then(|command| command.user.as_deref());
+    if username.is_some() {
+        if let Err(err) = setup::chown(runtime_dir, username, None) {
+            remove_runtime_dir(&config.name, runtime_dir).await;
+            return Err(err);
         }
     }
+
+    Ok(())
+}
+
+/// Removes the runtime directory of a process (logging, but otherwise
+/// ignoring, any errors).
+async fn remove_runtime_dir(process_name: &str, runtime_dir: &Path) {
+    if let Err(err) = tokio::fs::remove_dir_all(runtime_dir).await {
+        tracing::warn!(process = %process_name, ?err, "Failed to remove runtime directory");
+    }
 }
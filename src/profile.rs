@@ -0,0 +1,214 @@
+//! Startup profile, which records how long each process took to start
+//! and the chain of dependencies that determined how long startup took
+//! (so that CI can track container boot times across builds).
+
+use std::time::{Duration, Instant};
+
+use color_eyre::eyre;
+use serde::Serialize;
+
+use crate::config::ProcessConfig;
+
+/// Timings of a startup phase, collected while the processes start.
+#[derive(Debug)]
+pub(crate) struct StartupProfile {
+    /// When the startup phase began.
+    started: Instant,
+
+    /// Timings of the processes, in the order in which they were started.
+    processes: Vec<ProcessTiming>,
+}
+
+/// Time at which a process started (and finished) starting, relative to
+/// the beginning of the startup phase.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ProcessTiming {
+    /// Name of the process.
+    name: String,
+
+    /// Milliseconds after the beginning of startup at which the process
+    /// began starting.
+    started_ms: u64,
+
+    /// Milliseconds after the beginning of startup at which the process
+    /// had started (its `pre` command completed and its daemon was
+    /// spawned and ready).
+    finished_ms: u64,
+
+    /// Milliseconds that it took to start the process.
+    duration_ms: u64,
+
+    /// True if the process started successfully.
+    succeeded: bool,
+}
+
+/// Startup profile, as written to the profile file.
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Report<'a> {
+    /// Milliseconds from the beginning of startup (including the setup
+    /// steps) until every process had started.
+    total_ms: u64,
+
+    /// Timings of the processes, in the order in which they were started.
+    processes: &'a [ProcessTiming],
+
+    /// Names of the processes on the critical path, from the first
+    /// process to start to the last process to finish starting.
+    critical_path: Vec<String>,
+}
+
+impl StartupProfile {
+    /// Begins profiling a startup phase.
+    pub(crate) fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            processes: Vec::new(),
+        }
+    }
+
+    /// Records the time that it took to start a process.
+    pub(crate) fn record(
+        &mut self,
+        name: &str,
+        started: Instant,
+        finished: Instant,
+        succeeded: bool,
+    ) {
+        let started_ms = millis(started.saturating_duration_since(self.started));
+        let finished_ms = millis(finished.saturating_duration_since(self.started));
+        self.processes.push(ProcessTiming {
+            name: name.to_owned(),
+            started_ms,
+            finished_ms,
+            duration_ms: finished_ms.saturating_sub(started_ms),
+            succeeded,
+        });
+    }
+
+    /// Returns the critical path: the process that finished starting
+    /// last, preceded by the dependency that it waited for (the process
+    /// that it starts after that finished starting last), and so on.
+    #[cfg_attr(not(feature = "cli"), allow(dead_code))]
+    fn critical_path(&self, configs: &[ProcessConfig]) -> Vec<String> {
+        let config = |name: &str| configs.iter().find(|config| config.name == name);
+
+        let mut path = Vec::new();
+        let mut current = last_finished(self.processes.iter());
+        while let Some(timing) = current {
+            path.push(timing.name.clone());
+            current = config(&timing.name).and_then(|process| {
+                last_finished(self.processes.iter().filter(|dependency| {
+                    !path.contains(&dependency.name)
+                        && config(&dependency.name)
+                            .map_or(false, |dependency| process.starts_after(dependency))
+                }))
+            });
+        }
+
+        path.reverse();
+        path
+    }
+
+    /// Serializes the profile as JSON, using the configs of the processes
+    /// to determine the critical path.
+    #[cfg(feature = "cli")]
+    pub(crate) fn to_json(&self, configs: &[ProcessConfig]) -> eyre::Result<String> {
+        let report = Report {
+            total_ms: millis(self.started.elapsed()),
+            processes: &self.processes,
+            critical_path: self.critical_path(configs),
+        };
+        Ok(serde_json::to_string_pretty(&report)?)
+    }
+
+    /// Startup profiles are serialized with the (`cli`-only) JSON
+    /// serializer.
+    #[cfg(not(feature = "cli"))]
+    pub(crate) fn to_json(&self, _configs: &[ProcessConfig]) -> eyre::Result<String> {
+        Err(eyre::eyre!("Startup profiles require the `cli` feature"))
+    }
+}
+
+/// Returns the timing that finished last (the last to be started, if
+/// several finished at the same time).
+fn last_finished<'a>(
+    timings: impl Iterator<Item = &'a ProcessTiming>,
+) -> Option<&'a ProcessTiming> {
+    timings.fold(None, |last, timing| match last {
+        Some(last) if last.finished_ms > timing.finished_ms => Some(last),
+        _ => Some(timing),
+    })
+}
+
+/// Converts a duration to whole milliseconds.
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::config::Config;
+
+    #[allow(clippy::unwrap_used)]
+    #[test]
+    fn follows_the_slowest_dependencies() {
+        let config: Config = toml::from_str(
+            r#"
+            [[processes]]
+            name = "certs"
+            pre = "/bin/true"
+
+            [[processes]]
+            name = "migrate"
+            pre = "/bin/true"
+
+            [[processes]]
+            name = "cache"
+            pre = "/bin/true"
+
+            [[processes]]
+            name = "app"
+            depends-on = ["certs", "migrate"]
+            run = "/bin/true"
+
+            [[processes]]
+            name = "sidecar"
+            run = "/bin/true"
+            "#,
+        )
+        .unwrap();
+
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut profile = StartupProfile {
+            started: start,
+            processes: Vec::new(),
+        };
+        profile.record("certs", at(0), at(50), true);
+        profile.record("migrate", at(0), at(300), true);
+        profile.record("cache", at(0), at(400), true);
+        profile.record("app", at(400), at(900), true);
+        profile.record("sidecar", at(400), at(850), true);
+
+        assert_eq!(
+            vec!["migrate", "app"],
+            profile.critical_path(&config.processes)
+        );
+        assert_eq!(
+            ProcessTiming {
+                name: String::from("app"),
+                started_ms: 400,
+                finished_ms: 900,
+                duration_ms: 500,
+                succeeded: true,
+            },
+            profile.processes[3]
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! Sends the HTTP requests that ask daemons to quiesce (drain their work
+//! and exit) through an admin endpoint, such as Envoy's `/quitquitquit`,
+//! as well as the requests of `http` readiness probes.
+
+use std::time::Duration;
+
+use color_eyre::eyre::{self, eyre, WrapErr};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+use url::{Host, Position, Url};
+
+/// Longest amount of time to wait for the response to a request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Verifies that a quiesce (or readiness probe) URL is a valid `http://`
+/// URL.
+pub(crate) fn validate_url(url: &str) -> eyre::Result<()> {
+    parse_url(url).map(|_| ())
+}
+
+/// Parses a quiesce (or readiness probe) URL, which must be an
+/// `http://` URL with a host.
+fn parse_url(url: &str) -> eyre::Result<Url> {
+    let parsed = Url::parse(url).wrap_err_with(|| format!("Invalid `http` URL \"{url}\""))?;
+    if parsed.scheme() != "http" {
+        return Err(eyre!(
+            "Unsupported `http` URL scheme \"{}\" (expected http)",
+            parsed.scheme()
+        ));
+    }
+    if parsed.host_str().is_none() {
+        return Err(eyre!("`http` URL \"{url}\" has no host"));
+    }
+
+    Ok(parsed)
+}
+
+/// Sends a `POST` request to the URL, returning an error if the request
+/// fails, times out, or is answered with anything other than a `2xx`
+/// status.
+pub(crate) async fn request(url: &str) -> eyre::Result<()> {
+    send("POST", url).await
+}
+
+/// Sends a `GET` request to the URL (for a readiness probe), returning
+/// an error if the request fails, times out, or is answered with
+/// anything other than a `2xx` status.
+pub(crate) async fn get(url: &str) -> eyre::Result<()> {
+    send("GET", url).await
+}
+
+/// Sends a request with the given method to the URL, with a timeout.
+async fn send(method: &str, url: &str) -> eyre::Result<()> {
+    let url = parse_url(url)?;
+    tokio::time::timeout(REQUEST_TIMEOUT, send_request(method, &url))
+        .await
+        .map_err(|_| eyre!("Timed out waiting for a response"))?
+}
+
+/// Sends an (empty) HTTP/1.1 request and checks the status of the
+/// response.
+async fn send_request(method: &str, url: &Url) -> eyre::Result<()> {
+    let host = host_to_connect(url).ok_or_else(|| eyre!("`http` URL has no host"))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    let mut stream = BufReader::new(TcpStream::connect((host.as_str(), port)).await?);
+    let request = format!(
+        "{method} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        &url[Position::BeforePath..Position::AfterQuery],
+        &url[Position::BeforeHost..Position::AfterPort],
+    );
+    stream.get_mut().write_all(request.as_bytes()).await?;
+
+    let mut status_line = String::new();
+    stream.read_line(&mut status_line).await?;
+    match parse_status(&status_line) {
+        Some(status) if (200..300).contains(&status) => Ok(()),
+        Some(status) => Err(eyre!("Request failed with HTTP status {status}")),
+        None => Err(eyre!(
+            "Invalid HTTP response \"{}\"",
+            status_line.trim_end()
+        )),
+    }
+}
+
+/// Returns the host of the URL in the form used to connect to it: IPv6
+/// addresses are enclosed in brackets in URLs (`http://[::1]:8080/`),
+/// but must not be when they are parsed as addresses. Host names are
+/// resolved when connecting, using every address (IPv4 or IPv6) that
+/// they resolve to.
+pub(crate) fn host_to_connect(url: &Url) -> Option<String> {
+    match url.host()? {
+        Host::Ipv6(address) => Some(address.to_string()),
+        host => Some(host.to_string()),
+    }
+}
+
+/// Parses the status code out of an HTTP status line (`HTTP/1.1 200 OK`).
+fn parse_status(status_line: &str) -> Option<u16> {
+    let mut parts = status_line.split_whitespace();
+    if !parts.next()?.starts_with("HTTP/") {
+        return None;
+    }
+    parts.next()?.parse().ok()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn strips_brackets_from_ipv6_hosts() {
+        let host = |url| host_to_connect(&Url::parse(url).unwrap());
+        assert_eq!(Some(String::from("::1")), host("http://[::1]:9901/ready"));
+        assert_eq!(
+            Some(String::from("127.0.0.1")),
+            host("http://127.0.0.1/ready")
+        );
+        assert_eq!(
+            Some(String::from("envoy.local")),
+            host("http://envoy.local/")
+        );
+    }
+
+    #[test]
+    fn parses_status_lines() {
+        assert_eq!(Some(200), parse_status("HTTP/1.1 200 OK\r\n"));
+        assert_eq!(Some(503), parse_status("HTTP/1.0 503 Service Unavailable"));
+        assert_eq!(None, parse_status("SSH-2.0-OpenSSH_9.0"));
+        assert_eq!(None, parse_status(""));
+    }
+
+    #[test]
+    fn validates_urls() {
+        assert!(validate_url("http://127.0.0.1:9000/quitquitquit").is_ok());
+        assert!(validate_url("https://127.0.0.1:9000/quitquitquit").is_err());
+        assert!(validate_url("127.0.0.1:9000").is_err());
+    }
+}
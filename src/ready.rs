@@ -0,0 +1,203 @@
+//! Waits for daemons to become ready (according to their `ready` probe)
+//! before the processes that are started after them are started, and
+//! runs the same probes for the periodic health checks of daemons.
+
+use std::{net::Ipv6Addr, path::Path, time::Duration};
+
+use color_eyre::eyre::{self, eyre};
+use tokio::net::TcpStream;
+
+use crate::{
+    command::{self, CommandEnv, ExitStatus},
+    config::{CommandConfig, HealthCheckConfig, ReadyConfig},
+    quiesce,
+};
+
+/// Time between attempts of a probe that has not yet succeeded.
+const RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Probe of a `ready` (or `health-check`) configuration.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Probe<'a> {
+    /// Address that must accept TCP connections.
+    Tcp(&'a str),
+
+    /// `http://` URL that must respond with a `2xx` status.
+    Http(&'a str),
+
+    /// Command that must exit successfully.
+    Exec(&'a CommandConfig),
+
+    /// File that must exist.
+    File(&'a Path),
+
+    /// Local port (the first of the process's `ports`) that must accept
+    /// TCP connections.
+    Port(u16),
+}
+
+impl<'a> Probe<'a> {
+    /// Returns the probe of a `ready` configuration, which checks the
+    /// `default_port` if the configuration does not have a probe.
+    fn of_ready(config: &'a ReadyConfig, default_port: Option<u16>) -> Self {
+        if let Some(address) = &config.tcp {
+            Probe::Tcp(address)
+        } else if let Some(url) = &config.http {
+            Probe::Http(url)
+        } else if let Some(path) = &config.file {
+            Probe::File(path)
+        } else if let Some(exec) = &config.exec {
+            Probe::Exec(exec)
+        } else {
+            Probe::Port(
+                default_port.expect("`ready` probes are validated when the config is loaded"),
+            )
+        }
+    }
+
+    /// Returns the probe of a `health-check` configuration, which checks
+    /// the `default_port` if the configuration does not have a probe.
+    pub(crate) fn of_health_check(
+        config: &'a HealthCheckConfig,
+        default_port: Option<u16>,
+    ) -> Self {
+        if let Some(address) = &config.tcp {
+            Probe::Tcp(address)
+        } else if let Some(url) = &config.http {
+            Probe::Http(url)
+        } else if let Some(exec) = &config.exec {
+            Probe::Exec(exec)
+        } else {
+            Probe::Port(
+                default_port
+                    .expect("`health-check` probes are validated when the config is loaded"),
+            )
+        }
+    }
+}
+
+impl std::fmt::Display for Probe<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Probe::Tcp(address) => write!(f, "{address} to accept connections"),
+            Probe::Http(url) => write!(f, "{url} to respond successfully"),
+            Probe::Exec(_) => write!(f, "probe command to succeed"),
+            Probe::File(path) => write!(f, "\"{}\" to exist", path.display()),
+            Probe::Port(port) => write!(f, "port {port} to accept connections"),
+        }
+    }
+}
+
+/// Verifies that a `tcp` probe address is of the form `host:port`, where
+/// IPv6 addresses are enclosed in brackets (`[::1]:8080`). Host names
+/// are resolved when connecting, using every address (IPv4 or IPv6) that
+/// they resolve to.
+pub(crate) fn validate_tcp_address(address: &str) -> eyre::Result<()> {
+    let valid_host = |host: &str| match host.strip_prefix('[') {
+        Some(host) => host
+            .strip_suffix(']')
+            .map_or(false, |address| address.parse::<Ipv6Addr>().is_ok()),
+        None => !host.is_empty() && !host.contains(':'),
+    };
+    match address.rsplit_once(':') {
+        Some((host, port)) if valid_host(host) && port.parse::<u16>().is_ok() => Ok(()),
+        _ => Err(eyre!(
+            "`tcp` address \"{address}\" must be of the form \"host:port\" (or \"[ipv6-address]:port\")"
+        )),
+    }
+}
+
+/// Waits for the probe to succeed, returning an error if it has still
+/// not succeeded when the timeout expires. `env` is the environment of
+/// the process, in which `exec` probes are run, and `default_port` is
+/// the first of the process's `ports` (if any).
+pub(crate) async fn wait_until_ready(
+    process_name: &str,
+    config: &ReadyConfig,
+    env: &CommandEnv,
+    default_port: Option<u16>,
+) -> eyre::Result<()> {
+    let probe = Probe::of_ready(config, default_port);
+    tracing::info!(process = %process_name, "Waiting for {probe}");
+
+    let mut last_error = None;
+    let result = tokio::time::timeout(config.timeout, async {
+        loop {
+            match check(process_name, probe, env).await {
+                Ok(()) => return,
+                Err(err) => {
+                    tracing::debug!(process = %process_name, ?err, "Process not yet ready");
+                    last_error = Some(err);
+                }
+            }
+
+            tokio::time::sleep(RETRY_INTERVAL).await;
+        }
+    })
+    .await;
+
+    if result.is_err() {
+        if let Some(err) = last_error {
+            tracing::warn!(process = %process_name, ?err, "Readiness probe did not succeed");
+        }
+
+        return Err(eyre!(
+            "Timed out after {} waiting for {probe} (process \"{process_name}\")",
+            humantime::format_duration(config.timeout)
+        ));
+    }
+
+    tracing::info!(process = %process_name, "Process is ready");
+    Ok(())
+}
+
+/// Runs the probe once.
+pub(crate) async fn check(
+    process_name: &str,
+    probe: Probe<'_>,
+    env: &CommandEnv,
+) -> eyre::Result<()> {
+    match probe {
+        Probe::Tcp(address) => {
+            TcpStream::connect(address).await?;
+            Ok(())
+        }
+        Probe::Port(port) => {
+            TcpStream::connect(("localhost", port)).await?;
+            Ok(())
+        }
+        Probe::Http(url) => quiesce::get(url).await,
+        Probe::Exec(exec) => {
+            let (_control, monitor) =
+                command::run(&format!("{process_name}[probe]"), exec, env, None, None)?;
+            match monitor.wait().await {
+                ExitStatus::Exited(0) => Ok(()),
+                status => Err(eyre!("Probe command failed ({status:?})")),
+            }
+        }
+        Probe::File(path) => {
+            if tokio::fs::try_exists(path).await? {
+                Ok(())
+            } else {
+                Err(eyre!("\"{}\" does not exist", path.display()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_tcp_addresses() {
+        assert!(validate_tcp_address("127.0.0.1:5432").is_ok());
+        assert!(validate_tcp_address("db.internal:6379").is_ok());
+        assert!(validate_tcp_address("[::1]:8080").is_ok());
+        assert!(validate_tcp_address("127.0.0.1").is_err());
+        assert!(validate_tcp_address(":5432").is_err());
+        assert!(validate_tcp_address("127.0.0.1:postgres").is_err());
+        assert!(validate_tcp_address("::1:8080").is_err());
+        assert!(validate_tcp_address("[]:8080").is_err());
+    }
+}
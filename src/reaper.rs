@@ -0,0 +1,126 @@
+//! Reaps orphaned processes when Ground Control runs as PID 1.
+//!
+//! Processes whose parent exits (the children of a daemon that
+//! double-forks, for example) are re-parented to PID 1, which must wait
+//! for them once they exit; otherwise they remain zombies for as long as
+//! the container runs. Ground Control's own commands are reaped by their
+//! monitors (see `command::monitor_process`), so the reaper only waits
+//! for the zombies that are not commands.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use nix::{
+    sys::wait::{waitpid, WaitPidFlag},
+    unistd::Pid,
+};
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::command;
+
+/// Starts reaping orphaned processes every time a child process exits,
+/// if Ground Control is running as PID 1 (and the reaper has not already
+/// been started).
+pub(crate) fn start() {
+    static STARTED: AtomicBool = AtomicBool::new(false);
+
+    if std::process::id() != 1 || STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let mut sigchld = match signal(SignalKind::child()) {
+        Ok(sigchld) => sigchld,
+        Err(err) => {
+            tracing::warn!(
+                ?err,
+                "Unable to listen for SIGCHLD; orphaned processes will not be reaped"
+            );
+            return;
+        }
+    };
+
+    tracing::debug!("Running as PID 1; reaping orphaned processes");
+    tokio::spawn(async move {
+        while sigchld.recv().await.is_some() {
+            reap_orphans();
+        }
+    });
+}
+
+/// Waits for every zombie child process that is not one of Ground
+/// Control's (monitored) commands.
+fn reap_orphans() {
+    let zombies = match zombie_children() {
+        Ok(zombies) => zombies,
+        Err(err) => {
+            tracing::warn!(?err, "Unable to list zombie processes");
+            return;
+        }
+    };
+
+    command::with_monitored(|monitored| {
+        for pid in zombies.iter().filter(|pid| !monitored.contains(pid)) {
+            match waitpid(*pid, Some(WaitPidFlag::WNOHANG)) {
+                Ok(status) => tracing::debug!(%pid, ?status, "Reaped orphaned process"),
+                Err(err) => tracing::debug!(%pid, ?err, "Error reaping orphaned process"),
+            }
+        }
+    });
+}
+
+/// Returns the PIDs of the child processes of Ground Control that have
+/// exited, but have not yet been waited for.
+fn zombie_children() -> std::io::Result<Vec<Pid>> {
+    let own_pid = std::process::id() as i32;
+    let mut zombies = Vec::new();
+    for entry in std::fs::read_dir("/proc")? {
+        let pid = match entry?
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            Some(pid) => pid,
+            None => continue,
+        };
+
+        // Processes may exit (and disappear) while we are looking.
+        let stat = match std::fs::read_to_string(format!("/proc/{pid}/stat")) {
+            Ok(stat) => stat,
+            Err(_) => continue,
+        };
+        if parse_state_and_parent(&stat) == Some(('Z', own_pid)) {
+            zombies.push(Pid::from_raw(pid));
+        }
+    }
+
+    Ok(zombies)
+}
+
+/// Parses the process state and the PID of the parent process out of
+/// the contents of `/proc/<pid>/stat`. Both follow the command name,
+/// which is in parentheses (and may itself contain spaces and
+/// parentheses).
+fn parse_state_and_parent(stat: &str) -> Option<(char, i32)> {
+    let (_, rest) = stat.rsplit_once(')')?;
+    let mut fields = rest.split_whitespace();
+    let state = fields.next()?.chars().next()?;
+    let parent = fields.next()?.parse().ok()?;
+    Some((state, parent))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_state_and_parent() {
+        assert_eq!(
+            Some(('Z', 1)),
+            parse_state_and_parent("42 (sleep) Z 1 42 42 0 -1 4194564")
+        );
+        assert_eq!(
+            Some(('S', 7)),
+            parse_state_and_parent("43 (my (odd) daemon) S 7 43 43 0 -1 4194560")
+        );
+        assert_eq!(None, parse_state_and_parent("44 (truncated"));
+    }
+}
@@ -0,0 +1,239 @@
+//! Cron schedules (`minute hour day-of-month month day-of-week`, in UTC)
+//! for the scheduled restarts of daemons (`restart-schedule`).
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use color_eyre::eyre::{self, eyre, WrapErr};
+
+/// Number of days to search for the next time that matches a schedule
+/// (long enough to find February 29th on a given day of the week).
+const SEARCH_DAYS: u64 = 366 * 28;
+
+/// Parsed cron schedule: the values that each field matches, as bit
+/// sets.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+
+    /// True if the day of the month is restricted (not `*`), in which
+    /// case (as in cron) a day matches if either the day of the month or
+    /// the day of the week matches.
+    days_restricted: bool,
+
+    /// True if the day of the week is restricted (not `*`).
+    weekdays_restricted: bool,
+}
+
+impl Schedule {
+    /// Parses a five-field cron expression. Each field is `*`, a number,
+    /// a range (`1-5`), or a step (`*/15`, `0-30/10`), or a
+    /// comma-separated list of those. Days of the week are 0-7 (where 0
+    /// and 7 are both Sunday).
+    pub(crate) fn parse(expression: &str) -> eyre::Result<Self> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let (minutes, hours, days, months, weekdays) = match fields.as_slice() {
+            [minutes, hours, days, months, weekdays] => (minutes, hours, days, months, weekdays),
+            _ => {
+                return Err(eyre!(
+                    "Invalid schedule \"{expression}\" (expected five fields: minute, hour, day of month, month, and day of week)"
+                ))
+            }
+        };
+
+        let parse = |field: &str, name: &str, min: u32, max: u32| {
+            parse_field(field, min, max).wrap_err_with(|| {
+                format!("Invalid {name} \"{field}\" in schedule \"{expression}\"")
+            })
+        };
+        let mut weekdays_set = parse(weekdays, "day of week", 0, 7)?;
+        if weekdays_set & (1 << 7) != 0 {
+            weekdays_set = (weekdays_set | 1) & !(1 << 7);
+        }
+
+        Ok(Self {
+            minutes: parse(minutes, "minute", 0, 59)?,
+            hours: parse(hours, "hour", 0, 23)?,
+            days: parse(days, "day of month", 1, 31)?,
+            months: parse(months, "month", 1, 12)?,
+            weekdays: weekdays_set,
+            days_restricted: *days != "*",
+            weekdays_restricted: *weekdays != "*",
+        })
+    }
+
+    /// Returns the first time after the given time that matches the
+    /// schedule (to the minute), or `None` if nothing matches (for
+    /// example, February 30th).
+    pub(crate) fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let seconds = time.duration_since(UNIX_EPOCH).ok()?.as_secs();
+
+        // Start with the minute after the given time.
+        let start = seconds / 60 + 1;
+        let start_day = start / (24 * 60);
+        for day in start_day..start_day + SEARCH_DAYS {
+            if !self.matches_day(day) {
+                continue;
+            }
+
+            let first_minute = if day == start_day {
+                start % (24 * 60)
+            } else {
+                0
+            };
+            if let Some(minute_of_day) = (first_minute..24 * 60).find(|minute_of_day| {
+                is_set(self.hours, minute_of_day / 60) && is_set(self.minutes, minute_of_day % 60)
+            }) {
+                let minutes = day * 24 * 60 + minute_of_day;
+                return Some(UNIX_EPOCH + Duration::from_secs(minutes * 60));
+            }
+        }
+
+        None
+    }
+
+    /// Returns true if the day (counted from the UNIX epoch) matches the
+    /// month, day of the month, and day of the week of the schedule.
+    fn matches_day(&self, day: u64) -> bool {
+        let (month, day_of_month) = month_and_day(day);
+        let weekday = (day + 4) % 7; // 1970-01-01 was a Thursday.
+        if !is_set(self.months, month) {
+            return false;
+        }
+
+        let day_matches = is_set(self.days, day_of_month);
+        let weekday_matches = is_set(self.weekdays, weekday);
+        match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day_matches || weekday_matches,
+            _ => day_matches && weekday_matches,
+        }
+    }
+}
+
+/// Returns true if the value is in the bit set.
+fn is_set(set: u64, value: u64) -> bool {
+    set & (1 << value) != 0
+}
+
+/// Parses one field of a cron expression into the set of values that it
+/// matches.
+fn parse_field(field: &str, min: u32, max: u32) -> eyre::Result<u64> {
+    let parse_value = |value: &str| -> eyre::Result<u32> {
+        let value: u32 = value
+            .parse()
+            .map_err(|_| eyre!("\"{value}\" is not a number"))?;
+        if value < min || value > max {
+            return Err(eyre!("{value} is not between {min} and {max}"));
+        }
+        Ok(value)
+    };
+
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| eyre!("\"{step}\" is not a valid step"))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (first, last) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((first, last)) => (parse_value(first)?, parse_value(last)?),
+                None => {
+                    let value = parse_value(range)?;
+                    (value, value)
+                }
+            },
+        };
+        if first > last {
+            return Err(eyre!("{first}-{last} is not a valid range"));
+        }
+
+        for value in (first..=last).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+
+    Ok(set)
+}
+
+/// Returns the month (1-12) and day of the month (1-31) of the day
+/// (counted from the UNIX epoch), using the proleptic Gregorian
+/// calendar.
+fn month_and_day(day: u64) -> (u64, u64) {
+    let z = day + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day_of_month = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (month, day_of_month)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    /// Returns the time at the given UNIX timestamp.
+    fn at(timestamp: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(timestamp)
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[test]
+    fn finds_next_times() {
+        // 2026-10-17T10:30:15Z (a Saturday).
+        let now = at(1_792_233_015);
+
+        let next = |expression| Schedule::parse(expression).unwrap().next_after(now);
+        // 2026-10-18T04:00:00Z
+        assert_eq!(Some(at(1_792_296_000)), next("0 4 * * *"));
+        // 2026-10-17T10:45:00Z
+        assert_eq!(Some(at(1_792_233_900)), next("*/15 * * * *"));
+        // 2026-10-19T04:00:00Z (Monday)
+        assert_eq!(Some(at(1_792_382_400)), next("0 4 * * 1-5"));
+        // 2026-10-18T04:00:00Z (Sunday, as 7)
+        assert_eq!(Some(at(1_792_296_000)), next("0 4 * * 7"));
+        // 2026-10-19T00:00:00Z (the 1st of the month or a Monday; the
+        // Monday comes first)
+        assert_eq!(Some(at(1_792_368_000)), next("0 0 1 * 1"));
+        // 2026-11-01T00:00:00Z
+        assert_eq!(Some(at(1_793_491_200)), next("0 0 1 * *"));
+        // 2028-02-29T00:00:00Z
+        assert_eq!(Some(at(1_835_395_200)), next("0 0 29 2 *"));
+        assert_eq!(None, next("0 0 30 2 *"));
+    }
+
+    #[test]
+    fn rejects_invalid_schedules() {
+        for expression in [
+            "0 4 * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "daily",
+        ] {
+            assert!(
+                Schedule::parse(expression).is_err(),
+                "{expression} should be invalid"
+            );
+        }
+    }
+}
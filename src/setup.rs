@@ -0,0 +1,161 @@
+//! Performs the filesystem setup steps that run before any process is
+//! started.
+
+use std::{os::unix::fs::PermissionsExt, path::Path};
+
+use color_eyre::eyre::{self, eyre, WrapErr};
+
+use crate::{
+    command::{substitute_env_var, CommandEnv},
+    config::{FileAttributes, SetupConfig},
+};
+
+/// Performs every setup step, in order, stopping at the first failure.
+/// Environment variables in templates are expanded using `env`.
+pub(crate) async fn run(steps: &[SetupConfig], env: &CommandEnv) -> eyre::Result<()> {
+    for step in steps {
+        tracing::debug!(?step, "Performing setup step");
+        run_step(step, env).await?;
+    }
+
+    Ok(())
+}
+
+async fn run_step(step: &SetupConfig, env: &CommandEnv) -> eyre::Result<()> {
+    match step {
+        SetupConfig::Directory { path, attributes } => {
+            tokio::fs::create_dir_all(path)
+                .await
+                .wrap_err_with(|| format!("Failed to create directory \"{}\"", path.display()))?;
+            apply_attributes(path, attributes).await
+        }
+
+        SetupConfig::Chown { path, user, group } => chown(path, user.as_deref(), group.as_deref()),
+
+        SetupConfig::Chmod { path, mode } => chmod(path, *mode).await,
+
+        SetupConfig::Symlink { path, target } => {
+            // Replace existing symlinks, so that the step can be
+            // performed every time that Ground Control starts.
+            if let Ok(metadata) = tokio::fs::symlink_metadata(path).await {
+                if metadata.file_type().is_symlink() {
+                    tokio::fs::remove_file(path).await.wrap_err_with(|| {
+                        format!("Failed to remove existing symlink \"{}\"", path.display())
+                    })?;
+                }
+            }
+
+            tokio::fs::symlink(target, path).await.wrap_err_with(|| {
+                format!(
+                    "Failed to create symlink \"{}\" -> \"{}\"",
+                    path.display(),
+                    target.display()
+                )
+            })
+        }
+
+        SetupConfig::Copy {
+            source,
+            path,
+            attributes,
+        } => {
+            tokio::fs::copy(source, path).await.wrap_err_with(|| {
+                format!(
+                    "Failed to copy \"{}\" to \"{}\"",
+                    source.display(),
+                    path.display()
+                )
+            })?;
+            apply_attributes(path, attributes).await
+        }
+
+        SetupConfig::Write {
+            path,
+            contents,
+            attributes,
+        } => write_template(path, contents, attributes, env).await,
+
+        SetupConfig::Render {
+            template,
+            path,
+            attributes,
+        } => {
+            let contents = tokio::fs::read_to_string(template)
+                .await
+                .wrap_err_with(|| format!("Failed to read template \"{}\"", template.display()))?;
+            write_template(path, &contents, attributes, env).await
+        }
+    }
+}
+
+/// Performs environment variable expansion on the template and writes
+/// the result to the given path.
+async fn write_template(
+    path: &Path,
+    template: &str,
+    attributes: &FileAttributes,
+    env: &CommandEnv,
+) -> eyre::Result<()> {
+    let contents = substitute_env_var(template, env).wrap_err_with(|| {
+        format!(
+            "Environment variable expansion failed for file \"{}\"",
+            path.display()
+        )
+    })?;
+    tokio::fs::write(path, contents)
+        .await
+        .wrap_err_with(|| format!("Failed to write file \"{}\"", path.display()))?;
+    apply_attributes(path, attributes).await
+}
+
+/// Sets the ownership and permissions of a file or directory.
+async fn apply_attributes(path: &Path, attributes: &FileAttributes) -> eyre::Result<()> {
+    if attributes.user.is_some() || attributes.group.is_some() {
+        chown(
+            path,
+            attributes.user.as_deref(),
+            attributes.group.as_deref(),
+        )?;
+    }
+
+    if let Some(mode) = attributes.mode {
+        chmod(path, mode).await?;
+    }
+
+    Ok(())
+}
+
+/// Changes the owner and/or group of a file or directory. The group
+/// defaults to the primary group of the user if only the user is
+/// provided.
+pub(crate) fn chown(path: &Path, user: Option<&str>, group: Option<&str>) -> eyre::Result<()> {
+    let user = user
+        .map(|username| {
+            users::get_user_by_name(username)
+                .ok_or_else(|| eyre!("Unknown username \"{username}\""))
+        })
+        .transpose()?;
+
+    let gid = match group {
+        Some(groupname) => Some(
+            users::get_group_by_name(groupname)
+                .ok_or_else(|| eyre!("Unknown group \"{groupname}\""))?
+                .gid(),
+        ),
+        None => user.as_ref().map(|user| user.primary_group_id()),
+    };
+
+    nix::unistd::chown(
+        path,
+        user.map(|user| user.uid().into()),
+        gid.map(Into::into),
+    )
+    .wrap_err_with(|| format!("Failed to change the owner of \"{}\"", path.display()))
+}
+
+/// Changes the permissions of a file or directory.
+async fn chmod(path: &Path, mode: u32) -> eyre::Result<()> {
+    tokio::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+        .await
+        .wrap_err_with(|| format!("Failed to change the permissions of \"{}\"", path.display()))
+}
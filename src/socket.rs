@@ -0,0 +1,233 @@
+//! Control socket: a Unix domain socket served by a running Ground
+//! Control, through which the `status`, `start`, `stop`, and `restart`
+//! subcommands inspect and control its processes (which is handy for
+//! interactive debugging inside a container).
+//!
+//! The protocol is line-based: the client sends a single request line
+//! (`status`, or `start`, `stop`, or `restart` followed by the name of a
+//! process), and Ground Control answers with `ok` or `error` on the
+//! first line, followed by the output (or error message) of the request,
+//! then closes the connection.
+
+use std::{
+    fmt,
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use color_eyre::eyre::{self, eyre, WrapErr};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+
+/// Longest request line that is accepted (process names are short).
+const MAX_REQUEST_LEN: u64 = 1024;
+
+/// Longest amount of time to wait for a client to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Request made through the control socket.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SocketCommand {
+    /// List every process, along with its state.
+    Status,
+
+    /// Start the (stopped) daemon of the named process.
+    Start(String),
+
+    /// Stop the daemon of the named process, without shutting down.
+    Stop(String),
+
+    /// Stop, then start, the daemon of the named process.
+    Restart(String),
+}
+
+impl fmt::Display for SocketCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SocketCommand::Status => write!(f, "status"),
+            SocketCommand::Start(process) => write!(f, "start {process}"),
+            SocketCommand::Stop(process) => write!(f, "stop {process}"),
+            SocketCommand::Restart(process) => write!(f, "restart {process}"),
+        }
+    }
+}
+
+impl FromStr for SocketCommand {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let process = words.next().map(str::to_owned);
+        let command = match (command, process) {
+            ("status", None) => SocketCommand::Status,
+            ("start", Some(process)) => SocketCommand::Start(process),
+            ("stop", Some(process)) => SocketCommand::Stop(process),
+            ("restart", Some(process)) => SocketCommand::Restart(process),
+            _ => return Err(eyre!("Invalid request \"{}\"", s.trim())),
+        };
+        if words.next().is_some() {
+            return Err(eyre!("Invalid request \"{}\"", s.trim()));
+        }
+        Ok(command)
+    }
+}
+
+/// Request received through the control socket, which is answered (with
+/// the output of the request, or an error message) through `reply`.
+#[derive(Debug)]
+pub(crate) struct SocketRequest {
+    pub(crate) command: SocketCommand,
+    pub(crate) reply: oneshot::Sender<Result<String, String>>,
+}
+
+/// Serves the control socket, sending every request to `requests`.
+/// Dropping the returned server stops serving requests and removes the
+/// socket.
+#[derive(Debug)]
+pub(crate) struct SocketServer {
+    path: PathBuf,
+    task: JoinHandle<()>,
+}
+
+impl SocketServer {
+    /// Starts listening on the socket at the given path (replacing the
+    /// socket of a previous run, if any).
+    pub(crate) fn start(
+        path: &Path,
+        requests: mpsc::UnboundedSender<SocketRequest>,
+    ) -> eyre::Result<Self> {
+        let stale = std::fs::symlink_metadata(path)
+            .map_or(false, |metadata| metadata.file_type().is_socket());
+        if stale {
+            std::fs::remove_file(path).wrap_err_with(|| {
+                format!(
+                    "Failed to remove stale control socket \"{}\"",
+                    path.display()
+                )
+            })?;
+        }
+        let listener = UnixListener::bind(path)
+            .wrap_err_with(|| format!("Failed to bind control socket \"{}\"", path.display()))?;
+        tracing::info!(path = %path.display(), "Listening for requests on the control socket");
+
+        let task = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(serve_connection(stream, requests.clone()));
+                    }
+                    Err(err) => {
+                        tracing::warn!(?err, "Failed to accept control socket connection");
+                    }
+                }
+            }
+        });
+
+        Ok(SocketServer {
+            path: path.to_owned(),
+            task,
+        })
+    }
+}
+
+impl Drop for SocketServer {
+    fn drop(&mut self) {
+        self.task.abort();
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            tracing::debug!(?err, "Failed to remove control socket");
+        }
+    }
+}
+
+/// Reads the request of a single connection, waits for the request to be
+/// handled, and writes the response.
+async fn serve_connection(stream: UnixStream, requests: mpsc::UnboundedSender<SocketRequest>) {
+    let mut stream = BufReader::new(stream);
+    let mut line = String::new();
+    let mut request = (&mut stream).take(MAX_REQUEST_LEN);
+    let read = request.read_line(&mut line);
+    let response = match tokio::time::timeout(REQUEST_TIMEOUT, read).await {
+        Ok(Ok(_)) => match line.parse::<SocketCommand>() {
+            Ok(command) => {
+                tracing::info!(%command, "Request made through the control socket");
+                let (reply, response) = oneshot::channel();
+                let _ = requests.send(SocketRequest { command, reply });
+                response
+                    .await
+                    .unwrap_or_else(|_| Err(String::from("Ground Control is shutting down")))
+            }
+            Err(err) => Err(err.to_string()),
+        },
+        Ok(Err(err)) => Err(format!("Failed to read request: {err}")),
+        Err(_) => Err(String::from("Timed out waiting for the request")),
+    };
+
+    let response = match response {
+        Ok(output) => format!("ok\n{output}"),
+        Err(message) => format!("error\n{message}\n"),
+    };
+    if let Err(err) = stream.get_mut().write_all(response.as_bytes()).await {
+        tracing::debug!(?err, "Failed to write control socket response");
+    }
+}
+
+/// Sends a request to the control socket of a running Ground Control,
+/// returning the output of the request (or an error with the message of
+/// a failed request).
+pub async fn request(path: &Path, command: &SocketCommand) -> eyre::Result<String> {
+    let mut stream = UnixStream::connect(path).await.wrap_err_with(|| {
+        format!(
+            "Failed to connect to control socket \"{}\" (is Ground Control running?)",
+            path.display()
+        )
+    })?;
+    stream
+        .write_all(format!("{command}\n").as_bytes())
+        .await
+        .wrap_err("Failed to send request")?;
+
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .await
+        .wrap_err("Failed to read response")?;
+    match response.split_once('\n') {
+        Some(("ok", output)) => Ok(output.to_owned()),
+        Some(("error", message)) => Err(eyre!("{}", message.trim_end())),
+        _ => Err(eyre!("Invalid response from control socket")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        for command in [
+            SocketCommand::Status,
+            SocketCommand::Start(String::from("worker")),
+            SocketCommand::Stop(String::from("worker")),
+            SocketCommand::Restart(String::from("worker")),
+        ] {
+            assert_eq!(
+                Some(command.clone()),
+                format!("{command}\n").parse::<SocketCommand>().ok()
+            );
+        }
+
+        assert!("status worker".parse::<SocketCommand>().is_err());
+        assert!("stop".parse::<SocketCommand>().is_err());
+        assert!("stop a b".parse::<SocketCommand>().is_err());
+        assert!("kill worker".parse::<SocketCommand>().is_err());
+    }
+}
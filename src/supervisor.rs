@@ -0,0 +1,181 @@
+//! Runs several independent specifications in the same Tokio runtime.
+
+use std::collections::HashMap;
+
+use color_eyre::eyre::eyre;
+use tokio::{sync::mpsc, task::JoinHandle};
+use tracing::Instrument;
+
+use crate::{config::Config, control::ControlRequest, Error, ShutdownReason};
+
+/// Supervisor for several named specifications (one per tenant, for
+/// example), each of which runs concurrently and is shut down
+/// independently of the others.
+///
+/// Signals are delivered to the whole process, so specifications that
+/// `forward-signals` cannot be run by a supervisor. The `env` table of
+/// each specification only applies to that specification's processes,
+/// so specifications may set different values for the same variable.
+///
+/// Dropping the supervisor triggers a shutdown of every specification,
+/// but does not wait for the shutdowns to complete (see `shutdown`).
+#[derive(Debug, Default)]
+pub struct Supervisor {
+    specs: HashMap<String, Spec>,
+}
+
+/// Running specification.
+#[derive(Debug)]
+struct Spec {
+    shutdown: mpsc::UnboundedSender<()>,
+    requests: mpsc::UnboundedSender<ControlRequest>,
+    task: JoinHandle<Result<ShutdownReason, Error>>,
+}
+
+impl Supervisor {
+    /// Creates a supervisor that is not running any specifications.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts running the specification under the given name (which
+    /// must not be in use by another specification), returning as soon
+    /// as the specification has been validated. Use `wait_spec` to wait
+    /// for the specification to stop.
+    pub fn spawn_spec(&mut self, name: impl Into<String>, config: Config) -> Result<(), Error> {
+        let name = name.into();
+        if self.specs.contains_key(&name) {
+            return Err(eyre!("Spec \"{name}\" is already running").into());
+        }
+        if config.forward_signals {
+            return Err(eyre!(
+                "Spec \"{name}\" uses `forward-signals`, which is not supported when running multiple specs"
+            )
+            .into());
+        }
+        config.validate()?;
+
+        let (shutdown, shutdown_receiver) = mpsc::unbounded_channel();
+        let (requests, request_receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn(
+            crate::run_spec(
+                config,
+                shutdown_receiver,
+                requests.clone(),
+                request_receiver,
+            )
+            .instrument(tracing::info_span!("spec", spec = %name)),
+        );
+        self.specs.insert(
+            name,
+            Spec {
+                shutdown,
+                requests,
+                task,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Returns the names of the specifications (in sorted order) that
+    /// have not yet been waited for.
+    pub fn spec_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.specs.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Triggers a graceful shutdown of the specification, returning
+    /// false if there is no specification with the given name. Use
+    /// `wait_spec` to wait for the shutdown to complete.
+    pub fn shutdown_spec(&self, name: &str) -> bool {
+        match self.specs.get(name) {
+            Some(spec) => {
+                // The specification may have already stopped on its
+                // own, in which case there is nothing to shut down.
+                let _ = spec.shutdown.send(());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Pauses every daemon in the specification with `SIGSTOP` (which
+    /// quiesces the specification without stopping it, for a snapshot or
+    /// backup, for example), returning false if there is no
+    /// specification with the given name. Requests made during startup
+    /// are handled once startup has completed.
+    pub fn pause_spec(&self, name: &str) -> bool {
+        self.send_request(name, ControlRequest::PauseAll)
+    }
+
+    /// Resumes every (paused) daemon in the specification with
+    /// `SIGCONT`, returning false if there is no specification with the
+    /// given name.
+    pub fn resume_spec(&self, name: &str) -> bool {
+        self.send_request(name, ControlRequest::ResumeAll)
+    }
+
+    /// Releases the startup hold (`hold`) of a process in the
+    /// specification, returning false if there is no specification with
+    /// the given name. Releasing a process that is not (yet) held
+    /// releases its hold as soon as it is reached during startup.
+    pub fn release_hold(&self, name: &str, process: &str) -> bool {
+        self.send_request(name, ControlRequest::Release(process.to_owned()))
+    }
+
+    /// Sends a request to the specification, returning false if there is
+    /// no specification with the given name.
+    fn send_request(&self, name: &str, request: ControlRequest) -> bool {
+        match self.specs.get(name) {
+            Some(spec) => {
+                // The specification may have already stopped on its
+                // own, in which case there is nothing to pause or resume.
+                let _ = spec.requests.send(request);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Waits for the specification to stop (on its own, or after
+    /// `shutdown_spec`), then removes it from the supervisor (freeing
+    /// its name) and returns its result. Returns `None` if there is no
+    /// specification with the given name.
+    pub async fn wait_spec(&mut self, name: &str) -> Option<Result<ShutdownReason, Error>> {
+        let spec = self.specs.remove(name)?;
+        Some(join(spec).await)
+    }
+
+    /// Shuts down every specification and waits for all of them to stop,
+    /// returning the name and result of each specification (sorted by
+    /// name).
+    pub async fn shutdown(self) -> Vec<(String, Result<ShutdownReason, Error>)> {
+        for spec in self.specs.values() {
+            let _ = spec.shutdown.send(());
+        }
+
+        let mut specs: Vec<(String, Spec)> = self.specs.into_iter().collect();
+        specs.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut results = Vec::with_capacity(specs.len());
+        for (name, spec) in specs {
+            results.push((name, join(spec).await));
+        }
+        results
+    }
+}
+
+/// Waits for the specification's task to complete and returns its
+/// result.
+async fn join(spec: Spec) -> Result<ShutdownReason, Error> {
+    // Keep the shutdown sender alive until the task completes, since
+    // dropping the sender triggers a shutdown.
+    let result = spec
+        .task
+        .await
+        .unwrap_or_else(|err| Err(eyre!("Spec task failed: {err}").into()));
+    drop(spec.shutdown);
+    result
+}
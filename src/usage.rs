@@ -0,0 +1,164 @@
+//! Tracks the resource usage of daemon processes by sampling `/proc`
+//! (resource usage is not available on systems without `/proc`).
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::Duration,
+};
+
+use nix::unistd::{sysconf, Pid, SysconfVar};
+
+use crate::containment::DaemonTasks;
+
+/// Time between samples of a daemon's resource usage.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Resource usage of a daemon process.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ResourceUsage {
+    /// Peak resident set size, in kibibytes.
+    pub(crate) peak_rss_kib: u64,
+
+    /// Total (user and system) CPU time.
+    pub(crate) cpu_time: Duration,
+
+    /// Resident set size (as of the most recent sample), in kibibytes.
+    pub(crate) rss_kib: u64,
+
+    /// Number of threads (as of the most recent sample).
+    pub(crate) threads: u64,
+
+    /// Number of open file descriptors (as of the most recent sample),
+    /// if Ground Control is allowed to list them.
+    pub(crate) open_fds: Option<u64>,
+}
+
+/// Handle to the most recent sample of a daemon's resource usage.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct UsageMonitor {
+    usage: Arc<Mutex<ResourceUsage>>,
+}
+
+impl UsageMonitor {
+    /// Starts sampling the resource usage of the daemon, stopping once
+    /// the `exited` flag is set (after which the PID could be reused).
+    pub(crate) fn start(tasks: &DaemonTasks, pid: Pid, exited: Arc<AtomicBool>) -> Self {
+        let monitor = UsageMonitor::default();
+
+        let usage = monitor.usage.clone();
+        tasks.spawn(async move {
+            while !exited.load(Ordering::SeqCst) {
+                if let Some(sample) = sample(pid).await {
+                    let mut usage = usage.lock().unwrap_or_else(PoisonError::into_inner);
+                    usage.peak_rss_kib = usage.peak_rss_kib.max(sample.peak_rss_kib);
+                    usage.cpu_time = usage.cpu_time.max(sample.cpu_time);
+                    usage.rss_kib = sample.rss_kib;
+                    usage.threads = sample.threads;
+                    usage.open_fds = sample.open_fds;
+                }
+
+                tokio::time::sleep(SAMPLE_INTERVAL).await;
+            }
+        });
+
+        monitor
+    }
+
+    /// Returns the most recent resource usage of the daemon.
+    pub(crate) fn usage(&self) -> ResourceUsage {
+        *self.usage.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Reads the current resource usage of the process from `/proc`,
+/// returning `None` if the usage could not be read.
+async fn sample(pid: Pid) -> Option<ResourceUsage> {
+    let status = tokio::fs::read_to_string(format!("/proc/{pid}/status"))
+        .await
+        .ok()?;
+    let stat = tokio::fs::read_to_string(format!("/proc/{pid}/stat"))
+        .await
+        .ok()?;
+
+    Some(ResourceUsage {
+        peak_rss_kib: parse_status_field(&status, "VmHWM")?,
+        cpu_time: parse_cpu_time(&stat)?,
+        rss_kib: parse_status_field(&status, "VmRSS")?,
+        threads: parse_status_field(&status, "Threads")?,
+        open_fds: count_open_fds(pid).await,
+    })
+}
+
+/// Parses a numeric field (such as `VmHWM`, in kibibytes, or `Threads`)
+/// from the contents of `/proc/<pid>/status`.
+fn parse_status_field(status: &str, field: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+}
+
+/// Counts the open file descriptors of the process, returning `None`
+/// if they could not be listed (for a daemon that runs as another user,
+/// for example).
+async fn count_open_fds(pid: Pid) -> Option<u64> {
+    let mut entries = tokio::fs::read_dir(format!("/proc/{pid}/fd")).await.ok()?;
+    let mut count = 0;
+    while entries.next_entry().await.ok()?.is_some() {
+        count += 1;
+    }
+    Some(count)
+}
+
+/// Parses the total CPU time (`utime` plus `stime`) from the contents of
+/// `/proc/<pid>/stat`.
+fn parse_cpu_time(stat: &str) -> Option<Duration> {
+    // The second field (the command name) can contain spaces, so skip
+    // past the closing parenthesis; `utime` and `stime` are then the
+    // 12th and 13th fields.
+    let mut fields = stat
+        .get(stat.rfind(')')? + 1..)?
+        .split_whitespace()
+        .skip(11);
+    let utime: u64 = fields.next()?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+
+    let ticks_per_second = u64::try_from(sysconf(SysconfVar::CLK_TCK).ok()??).ok()?;
+    if ticks_per_second == 0 {
+        return None;
+    }
+
+    let ticks = utime + stime;
+    Some(
+        Duration::from_secs(ticks / ticks_per_second)
+            + Duration::from_nanos((ticks % ticks_per_second) * 1_000_000_000 / ticks_per_second),
+    )
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn parses_proc_files() {
+        let status = "Name:\tserver\nVmPeak:\t  20000 kB\nVmHWM:\t    5120 kB\nVmRSS:\t    4096 kB\nThreads:\t3\n";
+        assert_eq!(Some(5120), parse_status_field(status, "VmHWM"));
+        assert_eq!(Some(4096), parse_status_field(status, "VmRSS"));
+        assert_eq!(Some(3), parse_status_field(status, "Threads"));
+        assert_eq!(None, parse_status_field(status, "VmSwap"));
+
+        let ticks_per_second = sysconf(SysconfVar::CLK_TCK).unwrap().unwrap() as u32;
+        let stat = format!(
+            "1234 (my server) S 1 1234 1234 0 -1 4194560 100 0 0 0 {} {} 0 0 20 0 1 0",
+            ticks_per_second * 2,
+            ticks_per_second / 2
+        );
+        assert_eq!(Some(Duration::from_millis(2500)), parse_cpu_time(&stat));
+    }
+}
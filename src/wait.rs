@@ -0,0 +1,363 @@
+//! Waits for the conditions that must be true before a process can be
+//! started.
+
+use std::{path::Path, time::Duration};
+
+use color_eyre::eyre::{self, eyre, WrapErr};
+use percent_encoding::percent_decode_str;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+use url::Url;
+
+use crate::{
+    command::{substitute_env_var, CommandEnv},
+    config::WaitForConfig,
+    quiesce,
+};
+
+/// Time between checks of a condition that is not yet true.
+const RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Services that can be checked with a `connect` condition.
+const CONNECT_SCHEMES: [&str; 3] = ["postgres", "mysql", "redis"];
+
+/// Waits for the condition to become true, returning an error if the
+/// condition is still false when the timeout expires. Environment
+/// variables in the condition are expanded using `env`.
+pub(crate) async fn wait_for(
+    process_name: &str,
+    config: &WaitForConfig,
+    env: &CommandEnv,
+) -> eyre::Result<()> {
+    let condition = describe(config, env)?;
+    tracing::info!(process = %process_name, "Waiting for {condition}");
+
+    let mut last_error = None;
+    let result = tokio::time::timeout(config.timeout, async {
+        loop {
+            match check(config, env).await {
+                Ok(()) => return,
+                Err(err) => {
+                    tracing::debug!(process = %process_name, ?err, "Condition not yet met");
+                    last_error = Some(err);
+                }
+            }
+
+            tokio::time::sleep(RETRY_INTERVAL).await;
+        }
+    })
+    .await;
+
+    if result.is_err() {
+        if let Some(err) = last_error {
+            tracing::warn!(process = %process_name, ?err, "Condition was not met");
+        }
+
+        return Err(eyre!(
+            "Timed out after {} waiting for {condition} (process \"{process_name}\")",
+            humantime::format_duration(config.timeout)
+        ));
+    }
+
+    Ok(())
+}
+
+/// Describes the condition (without including any credentials).
+fn describe(config: &WaitForConfig, env: &CommandEnv) -> eyre::Result<String> {
+    if let Some(hostname) = &config.dns {
+        Ok(format!("DNS name \"{hostname}\" to resolve"))
+    } else if let Some(url) = &config.connect {
+        let url = parse_connect_url(url, env)?;
+        let (scheme, host, port) = connect_address(&url)?;
+        Ok(format!("{scheme}://{host}:{port} to accept connections"))
+    } else if let Some(path) = &config.mount {
+        Ok(format!("\"{}\" to be mounted", path.display()))
+    } else {
+        Err(eyre!("`wait-for` does not specify a condition"))
+    }
+}
+
+/// Checks the condition once.
+async fn check(config: &WaitForConfig, env: &CommandEnv) -> eyre::Result<()> {
+    if let Some(hostname) = &config.dns {
+        let mut addrs = tokio::net::lookup_host((hostname.as_str(), 0)).await?;
+        return match addrs.next() {
+            Some(_) => Ok(()),
+            None => Err(eyre!("DNS name resolved to no addresses")),
+        };
+    }
+
+    if let Some(url) = &config.connect {
+        let url = parse_connect_url(url, env)?;
+        let (scheme, _, port) = connect_address(&url)?;
+        let host = quiesce::host_to_connect(&url)
+            .ok_or_else(|| eyre!("`connect` URL does not include a host"))?;
+        let stream = TcpStream::connect((host.as_str(), port)).await?;
+        return match scheme {
+            "postgres" => check_postgres(stream, &url).await,
+            "mysql" => check_mysql(stream).await,
+            "redis" => check_redis(stream, &url).await,
+            _ => unreachable!("connect URL schemes are validated when parsed"),
+        };
+    }
+
+    if let Some(path) = &config.mount {
+        if !is_mount_point(path).await? {
+            return Err(eyre!("\"{}\" is not a mount point", path.display()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns true if the path is a mount point, according to the kernel's
+/// list of mounts in the current mount namespace.
+async fn is_mount_point(path: &Path) -> eyre::Result<bool> {
+    let path = tokio::fs::canonicalize(path)
+        .await
+        .wrap_err_with(|| format!("Failed to resolve \"{}\"", path.display()))?;
+    let mountinfo = tokio::fs::read_to_string("/proc/self/mountinfo")
+        .await
+        .wrap_err("Failed to read the list of mounts")?;
+
+    // The mount point is the fifth field of each line, with spaces (and
+    // other special characters) escaped as octal sequences.
+    Ok(mountinfo
+        .lines()
+        .filter_map(|line| line.split(' ').nth(4))
+        .any(|mount_point| Path::new(&unescape_mount_point(mount_point)) == path))
+}
+
+/// Replaces the octal escape sequences (such as `\040` for a space) in a
+/// mount point from `/proc/self/mountinfo`.
+fn unescape_mount_point(mount_point: &str) -> String {
+    let mut unescaped = Vec::with_capacity(mount_point.len());
+    let mut bytes = mount_point.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            unescaped.push(byte);
+            continue;
+        }
+
+        let octal: Vec<u8> = bytes.clone().take(3).collect();
+        match std::str::from_utf8(&octal)
+            .ok()
+            .and_then(|octal| u8::from_str_radix(octal, 8).ok())
+        {
+            Some(value) if octal.len() == 3 => {
+                unescaped.push(value);
+                bytes.nth(2);
+            }
+            _ => unescaped.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+/// Verifies that a `connect` URL is for a supported service. (the rest
+/// of the URL cannot be validated until environment variables have been
+/// expanded)
+pub(crate) fn validate_connect_url(url: &str) -> eyre::Result<()> {
+    let scheme = url.split_once("://").map(|(scheme, _)| scheme);
+    match scheme {
+        Some(scheme) if CONNECT_SCHEMES.contains(&scheme) => Ok(()),
+        Some(scheme) => Err(eyre!(
+            "Unsupported `connect` URL scheme \"{scheme}\" (expected one of {})",
+            CONNECT_SCHEMES.join(", ")
+        )),
+        None => Err(eyre!("Invalid `connect` URL \"{url}\"")),
+    }
+}
+
+/// Expands environment variables in the `connect` URL and parses the
+/// URL.
+fn parse_connect_url(url: &str, env: &CommandEnv) -> eyre::Result<Url> {
+    validate_connect_url(url)?;
+    let url = substitute_env_var(url, env)
+        .wrap_err("Environment variable expansion failed for `connect` URL")?;
+    Url::parse(&url).wrap_err("Invalid `connect` URL")
+}
+
+/// Returns the scheme, host, and port (using the default port for the
+/// service if the URL does not include a port) of a `connect` URL.
+fn connect_address(url: &Url) -> eyre::Result<(&str, &str, u16)> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| eyre!("`connect` URL does not include a host"))?;
+    let port = url.port().unwrap_or(match url.scheme() {
+        "postgres" => 5432,
+        "mysql" => 3306,
+        _ => 6379,
+    });
+    Ok((url.scheme(), host, port))
+}
+
+/// Returns the percent-decoded username and password of the URL.
+fn credentials(url: &Url) -> eyre::Result<(Option<String>, Option<String>)> {
+    let decode = |value: &str| -> eyre::Result<String> {
+        Ok(percent_decode_str(value)
+            .decode_utf8()
+            .wrap_err("Invalid credentials in `connect` URL")?
+            .into_owned())
+    };
+
+    let username = match url.username() {
+        "" => None,
+        username => Some(decode(username)?),
+    };
+    let password = url.password().map(decode).transpose()?;
+    Ok((username, password))
+}
+
+/// Sends a PostgreSQL startup message and checks the response, which is
+/// either an authentication request (the server is accepting
+/// connections) or an error. As with `pg_isready`, every error other
+/// than "the database system is starting up" means that the server is
+/// accepting connections, since the server had to be running in order
+/// to reject the user or database.
+async fn check_postgres(mut stream: TcpStream, url: &Url) -> eyre::Result<()> {
+    let (username, _) = credentials(url)?;
+    let username = username.unwrap_or_else(|| String::from("postgres"));
+    let database = match url.path().trim_start_matches('/') {
+        "" => username.clone(),
+        database => database.to_string(),
+    };
+
+    let mut body = Vec::new();
+    body.extend_from_slice(&196_608_i32.to_be_bytes()); // Protocol 3.0
+    for (key, value) in [("user", &username), ("database", &database)] {
+        body.extend_from_slice(key.as_bytes());
+        body.push(0);
+        body.extend_from_slice(value.as_bytes());
+        body.push(0);
+    }
+    body.push(0);
+
+    let mut message = Vec::with_capacity(body.len() + 4);
+    message.extend_from_slice(&(body.len() as i32 + 4).to_be_bytes());
+    message.extend_from_slice(&body);
+    stream.write_all(&message).await?;
+
+    match stream.read_u8().await? {
+        b'R' => Ok(()),
+        b'E' => {
+            let length = stream.read_i32().await?;
+            let mut fields = vec![0; usize::try_from(length - 4).unwrap_or_default()];
+            stream.read_exact(&mut fields).await?;
+
+            // Fields are a type byte followed by a null-terminated
+            // string; `C` is the SQLSTATE code.
+            let code = fields
+                .split(|byte| *byte == 0)
+                .find_map(|field| field.strip_prefix(b"C"))
+                .map(String::from_utf8_lossy);
+            match code.as_deref() {
+                Some("57P03") => Err(eyre!("PostgreSQL server is starting up")),
+                _ => Ok(()),
+            }
+        }
+        other => Err(eyre!(
+            "Unexpected PostgreSQL response message type {:?}",
+            char::from(other)
+        )),
+    }
+}
+
+/// Reads the MySQL server's initial handshake packet, which the server
+/// sends as soon as it is ready to accept a connection (otherwise the
+/// server sends an error packet).
+async fn check_mysql(mut stream: TcpStream) -> eyre::Result<()> {
+    // Packets start with a three-byte length and a sequence number.
+    let mut header = [0; 4];
+    stream.read_exact(&mut header).await?;
+    let length = u32::from_le_bytes([header[0], header[1], header[2], 0]) as usize;
+    let mut payload = vec![0; length];
+    stream.read_exact(&mut payload).await?;
+
+    match payload.first() {
+        Some(0x0a) => Ok(()),
+        Some(0xff) if payload.len() >= 3 => Err(eyre!(
+            "MySQL server returned error {}: {}",
+            u16::from_le_bytes([payload[1], payload[2]]),
+            String::from_utf8_lossy(&payload[3..])
+        )),
+        _ => Err(eyre!("Unexpected MySQL handshake packet")),
+    }
+}
+
+/// Authenticates (if the URL includes a password) and sends a `PING` to
+/// the Redis server, which must respond with `PONG`.
+async fn check_redis(stream: TcpStream, url: &Url) -> eyre::Result<()> {
+    let mut stream = BufReader::new(stream);
+
+    let (username, password) = credentials(url)?;
+    if let Some(password) = password {
+        let auth = match username {
+            Some(username) => vec![String::from("AUTH"), username, password],
+            None => vec![String::from("AUTH"), password],
+        };
+        redis_command(&mut stream, &auth).await?;
+    }
+
+    match redis_command(&mut stream, &[String::from("PING")])
+        .await?
+        .as_str()
+    {
+        "+PONG" => Ok(()),
+        reply => Err(eyre!("Unexpected Redis reply to PING: {reply}")),
+    }
+}
+
+/// Sends a Redis command and returns the (single-line) reply, returning
+/// an error if the reply is an error.
+async fn redis_command(stream: &mut BufReader<TcpStream>, args: &[String]) -> eyre::Result<String> {
+    let mut command = format!("*{}\r\n", args.len());
+    for arg in args {
+        command.push_str(&format!("${}\r\n{arg}\r\n", arg.len()));
+    }
+    stream.get_mut().write_all(command.as_bytes()).await?;
+
+    let mut reply = String::new();
+    stream.read_line(&mut reply).await?;
+    let reply = reply.trim_end().to_string();
+    if let Some(err) = reply.strip_prefix('-') {
+        return Err(eyre!("Redis server returned an error: {err}"));
+    }
+    Ok(reply)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn unescapes_mount_points() {
+        assert_eq!("/data", unescape_mount_point("/data"));
+        assert_eq!("/mnt/my data", unescape_mount_point("/mnt/my\\040data"));
+        assert_eq!("/mnt/a\\b", unescape_mount_point("/mnt/a\\134b"));
+        assert_eq!("/mnt/odd\\9", unescape_mount_point("/mnt/odd\\9"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn times_out_on_monotonic_clock() {
+        // A new directory is never a mount point.
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = WaitForConfig {
+            dns: None,
+            connect: None,
+            mount: Some(dir.path().to_path_buf()),
+            timeout: Duration::from_secs(3_600),
+        };
+        let err = wait_for("app", &config, &CommandEnv::default())
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Timed out after 1h waiting for"));
+    }
+}
@@ -0,0 +1,271 @@
+//! Kills daemons that stop pinging their watchdog, which catches daemons
+//! that have deadlocked (and thus are still running, but are no longer
+//! doing anything useful).
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use color_eyre::eyre::{self, WrapErr};
+use nix::{
+    sys::signal::{kill, Signal},
+    unistd::Pid,
+};
+use tokio::{net::UnixDatagram, time::Instant};
+use tracing::Instrument;
+
+use crate::containment::DaemonTasks;
+
+use crate::setup;
+
+/// Name of the file (in the process's runtime directory) that the daemon
+/// touches to ping the watchdog.
+const WATCHDOG_FILE: &str = "watchdog";
+
+/// Name of the datagram socket (in the process's runtime directory) to
+/// which the daemon sends datagrams to ping the watchdog.
+const WATCHDOG_SOCKET: &str = "watchdog.sock";
+
+/// Returns the paths of the watchdog file and socket of a process with
+/// the given runtime directory.
+pub(crate) fn paths(runtime_dir: &Path) -> (PathBuf, PathBuf) {
+    (
+        runtime_dir.join(WATCHDOG_FILE),
+        runtime_dir.join(WATCHDOG_SOCKET),
+    )
+}
+
+/// Watchdog of a single run of a daemon.
+#[derive(Debug)]
+pub(crate) struct Watchdog {
+    file: PathBuf,
+    socket: UnixDatagram,
+    timeout: Duration,
+    modified: Option<SystemTime>,
+}
+
+impl Watchdog {
+    /// Creates the watchdog file and binds the watchdog socket in the
+    /// runtime directory (replacing those of a previous run of the
+    /// daemon), giving both to the daemon's user.
+    pub(crate) fn create(
+        runtime_dir: &Path,
+        timeout: Duration,
+        user: Option<&str>,
+    ) -> eyre::Result<Self> {
+        let (file, socket_path) = paths(runtime_dir);
+
+        // Remove the old file (instead of truncating it), so that its
+        // modification time is always the start of this run.
+        for path in [&file, &socket_path] {
+            if path.exists() {
+                std::fs::remove_file(path).wrap_err_with(|| {
+                    format!("Failed to remove old watchdog file \"{}\"", path.display())
+                })?;
+            }
+        }
+
+        std::fs::File::create(&file)
+            .wrap_err_with(|| format!("Failed to create watchdog file \"{}\"", file.display()))?;
+        let socket = UnixDatagram::bind(&socket_path).wrap_err_with(|| {
+            format!(
+                "Failed to bind watchdog socket \"{}\"",
+                socket_path.display()
+            )
+        })?;
+
+        if user.is_some() {
+            setup::chown(&file, user, None)?;
+            setup::chown(&socket_path, user, None)?;
+        }
+
+        let modified = modified_time(&file);
+        Ok(Self {
+            file,
+            socket,
+            timeout,
+            modified,
+        })
+    }
+
+    /// Watches the daemon with the given PID, killing the daemon (with
+    /// `SIGKILL`, since a deadlocked daemon may not respond to anything
+    /// else) if neither the file nor the socket is pinged within the
+    /// timeout. The daemon's exit then triggers a restart or shutdown,
+    /// as with any other failure. Stops watching once the daemon exits
+    /// or is being stopped.
+    ///
+    /// Deadlines are tracked with Tokio's monotonic clock, and the
+    /// watchdog file only counts as pinged if its modification time has
+    /// changed, so jumps in the system clock (from NTP, or a VM that was
+    /// suspended) do not cause the daemon to be killed.
+    pub(crate) fn start(
+        mut self,
+        tasks: &DaemonTasks,
+        process_name: String,
+        pid: Pid,
+        exited: Arc<AtomicBool>,
+        stopping: Arc<AtomicBool>,
+    ) {
+        let watch = async move {
+            let mut buf = [0; 64];
+            let mut deadline = Instant::now() + self.timeout;
+            loop {
+                tokio::select! {
+                    Ok(_) = self.socket.recv(&mut buf) => {
+                        deadline = Instant::now() + self.timeout;
+                        continue;
+                    }
+                    () = tokio::time::sleep_until(deadline) => {}
+                }
+
+                if exited.load(Ordering::SeqCst) || stopping.load(Ordering::SeqCst) {
+                    return;
+                }
+
+                // The datagram deadline expired, but the file may have
+                // been touched in the meantime.
+                let modified = modified_time(&self.file);
+                match remaining_time(self.timeout, self.modified, modified, SystemTime::now()) {
+                    Some(remaining) => {
+                        self.modified = modified;
+                        deadline = Instant::now() + remaining;
+                    }
+                    _ if is_stopped(pid) => {
+                        // A stopped (paused, or frozen) daemon cannot ping
+                        // its watchdog; give it a full timeout once it is
+                        // running again.
+                        tracing::debug!(process = %process_name, "Daemon is stopped; not enforcing its watchdog deadline");
+                        deadline = Instant::now() + self.timeout;
+                    }
+                    _ => {
+                        tracing::error!(
+                            process = %process_name,
+                            timeout = %humantime::format_duration(self.timeout),
+                            "Daemon missed its watchdog deadline; killing it"
+                        );
+                        if let Err(err) = kill(pid, Signal::SIGKILL) {
+                            tracing::warn!(process = %process_name, ?err, "Failed to kill daemon");
+                        }
+                        return;
+                    }
+                }
+            }
+        };
+        tasks.spawn(watch.in_current_span());
+    }
+}
+
+/// Returns true if the process is stopped (by `SIGSTOP`, for example),
+/// according to the state in `/proc/<pid>/stat`.
+pub(crate) fn is_stopped(pid: Pid) -> bool {
+    std::fs::read_to_string(format!("/proc/{pid}/stat"))
+        .ok()
+        .as_deref()
+        .and_then(parse_state)
+        .map_or(false, |state| state == 'T' || state == 't')
+}
+
+/// Parses the process state out of the contents of `/proc/<pid>/stat`.
+/// The state follows the command name, which is in parentheses (and
+/// may itself contain spaces and parentheses).
+fn parse_state(stat: &str) -> Option<char> {
+    let (_, rest) = stat.rsplit_once(')')?;
+    rest.trim_start().chars().next()
+}
+
+/// Returns the modification time of the file, or `None` if the file no
+/// longer exists.
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).ok()?.modified().ok()
+}
+
+/// Returns the time remaining until the next watchdog deadline if the
+/// watchdog file was touched since the previous check (its modification
+/// time changed from `previous` to `current`), or `None` if the daemon
+/// missed its deadline. The remaining time is normally the timeout minus
+/// the age of the file, but the file's age is measured with the system
+/// clock, so a full timeout is used if the age is implausible (because
+/// the system clock jumped).
+fn remaining_time(
+    timeout: Duration,
+    previous: Option<SystemTime>,
+    current: Option<SystemTime>,
+    now: SystemTime,
+) -> Option<Duration> {
+    let current = current?;
+    if previous == Some(current) {
+        return None;
+    }
+
+    match now.duration_since(current) {
+        Ok(age) if age < timeout => Some(timeout - age),
+        _ => Some(timeout),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn tolerates_clock_jumps() {
+        let timeout = Duration::from_secs(10);
+        let touched = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let before = Some(touched - Duration::from_secs(5));
+
+        // Touched three seconds ago.
+        assert_eq!(
+            Some(Duration::from_secs(7)),
+            remaining_time(
+                timeout,
+                before,
+                Some(touched),
+                touched + Duration::from_secs(3)
+            )
+        );
+
+        // The clock jumped forward (or backward) after the file was
+        // touched.
+        assert_eq!(
+            Some(timeout),
+            remaining_time(
+                timeout,
+                before,
+                Some(touched),
+                touched + Duration::from_secs(3_600)
+            )
+        );
+        assert_eq!(
+            Some(timeout),
+            remaining_time(
+                timeout,
+                before,
+                Some(touched),
+                touched - Duration::from_secs(3_600)
+            )
+        );
+
+        // Not touched since the last check (however recent the file
+        // appears to be), or removed.
+        assert_eq!(
+            None,
+            remaining_time(timeout, Some(touched), Some(touched), touched)
+        );
+        assert_eq!(None, remaining_time(timeout, before, None, touched));
+    }
+
+    #[test]
+    fn parses_state() {
+        assert_eq!(Some('T'), parse_state("42 (sleep) T 1 42 42 0 -1"));
+        assert_eq!(Some('S'), parse_state("42 (my (odd) name) S 1 42 42"));
+        assert_eq!(None, parse_state("garbage"));
+    }
+}
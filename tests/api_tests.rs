@@ -0,0 +1,108 @@
+//! Tests that verify the HTTP API.
+
+use std::{net::SocketAddr, time::Duration};
+
+use pretty_assertions::assert_eq;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::common::{spawn_daemon_waiter, start, stop};
+
+mod common;
+
+/// Sends a `GET` request for the path to the API, returning the status
+/// code and body of the response.
+async fn get(addr: SocketAddr, path: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes())
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (status, body.to_owned())
+}
+
+/// `/healthz` reports whether every daemon is running, and `/status`
+/// describes every process.
+#[test_log::test(tokio::test)]
+async fn api_reports_health_and_status() {
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let config = format!(
+        r##"
+        control-dir = "{{temp_path}}/control"
+        api = {{ listen = "{addr}" }}
+
+        [[processes]]
+        name = "setup"
+        pre = [ "/bin/sh", "-c", "echo setup >> {{result_path}}" ]
+
+        [[processes]]
+        name = "worker"
+        owner = "team-jobs"
+        ports = [ 8080, 9090 ]
+        run = [ "/bin/sh", "{{test-daemon.sh}}", "worker", "{{result_path}}", "{{temp_path}}" ]
+        "##
+    );
+
+    let (gc, tx, dir) = start(&config).await;
+
+    let control_dir = dir.path().join("control");
+    let worker_waiter = spawn_daemon_waiter(&dir, "worker");
+    let client = tokio::task::spawn(async move {
+        let pid = worker_waiter.await.unwrap();
+
+        assert_eq!((200, String::from("ok\n")), get(addr, "/healthz").await);
+
+        let (status, body) = get(addr, "/status").await;
+        assert_eq!(200, status);
+        let status: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(true, status["healthy"]);
+        assert_eq!(false, status["starting"]);
+        assert_eq!("setup", status["processes"][0]["name"]);
+        assert_eq!("active", status["processes"][0]["state"]);
+        assert_eq!("worker", status["processes"][1]["name"]);
+        assert_eq!("running", status["processes"][1]["state"]);
+        assert_eq!(pid.as_raw(), status["processes"][1]["pid"]);
+        assert_eq!("team-jobs", status["processes"][1]["owner"]);
+        assert_eq!(
+            serde_json::json!([8080, 9090]),
+            status["processes"][1]["ports"]
+        );
+
+        assert_eq!(404, get(addr, "/metrics").await.0);
+
+        // Paused daemons are not running, so the bundle is unhealthy.
+        std::fs::write(control_dir.join("pause.worker"), "").unwrap();
+        let mut healthz = get(addr, "/healthz").await;
+        for _ in 0..50 {
+            if healthz.0 != 200 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            healthz = get(addr, "/healthz").await;
+        }
+        assert_eq!((503, String::from("unhealthy\n")), healthz);
+
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+    client.await.unwrap();
+
+    assert!(result.is_ok());
+    assert_eq!(
+        "setup\nworker:started\nworker:shutdown-requested\nworker:stopped\n",
+        output
+    );
+}
@@ -42,13 +42,27 @@ use tokio::sync::{
 ///   the next process. (without that, test results would be
 ///   inconsistent depending on which process got to run first, and for
 ///   how long).
+#[allow(dead_code)]
 pub async fn start(
     config: &str,
 ) -> (
-    impl Future<Output = Result<(), groundcontrol::Error>>,
+    impl Future<Output = Result<groundcontrol::ShutdownReason, groundcontrol::Error>>,
     UnboundedSender<()>,
     TempDir,
 ) {
+    let (config, dir) = prepare(config).await;
+
+    // Start Ground Control and return the handles.
+    let (tx, rx) = mpsc::unbounded_channel();
+    let gc = groundcontrol::run(config, rx);
+    (gc, tx, dir)
+}
+
+/// Prepares the test directory and test scripts, and performs template
+/// replacement in the provided configuration (see `start`), but does
+/// not run Ground Control. Returns the parsed config and the temp
+/// directory.
+pub async fn prepare(config: &str) -> (Config, TempDir) {
     // Create a temp directory into which we can write output from the
     // commands, as a simple way of verifying that the commands are in
     // fact run in the proper order.
@@ -90,18 +104,18 @@ pub async fn start(
     )
     .unwrap();
 
-    // Start Ground Control and return the handles.
-    let (tx, rx) = mpsc::unbounded_channel();
-    let gc = groundcontrol::run(config, rx);
-    (gc, tx, dir)
+    (config, dir)
 }
 
 /// Waits for Ground Control to stop, then collects the contents of the
 /// result file.
 pub async fn stop(
-    gc: impl Future<Output = Result<(), groundcontrol::Error>>,
+    gc: impl Future<Output = Result<groundcontrol::ShutdownReason, groundcontrol::Error>>,
     dir: TempDir,
-) -> (Result<(), groundcontrol::Error>, String) {
+) -> (
+    Result<groundcontrol::ShutdownReason, groundcontrol::Error>,
+    String,
+) {
     // Wait for Ground Control to stop.
     let result = gc.await;
 
@@ -132,11 +146,17 @@ pub fn spawn_daemon_waiter(dir: &TempDir, daemon_name: &str) -> oneshot::Receive
     tokio::task::spawn(async move {
         loop {
             match tokio::fs::read_to_string(&daemon_pid_path).await {
-                Ok(text) => {
+                // The PID file is created before the PID is written to
+                // it, so keep waiting until the PID can be parsed.
+                Ok(text) if text.ends_with('\n') => {
                     let pid = Pid::from_raw(text.trim().parse::<i32>().unwrap());
                     tx.send(pid).unwrap();
                     break;
                 }
+                Ok(_) => {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    continue;
+                }
                 Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
                     tokio::time::sleep(Duration::from_millis(10)).await;
                     continue;
@@ -149,10 +169,22 @@ pub fn spawn_daemon_waiter(dir: &TempDir, daemon_name: &str) -> oneshot::Receive
     rx
 }
 
+/// Returns the state of the process with the given PID (from
+/// `/proc/<pid>/stat`; `T` for a stopped process, for example).
+#[allow(dead_code)]
+pub fn process_state(pid: Pid) -> char {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).unwrap();
+    let (_, rest) = stat.rsplit_once(')').unwrap();
+    rest.trim_start().chars().next().unwrap()
+}
+
 /// Asserts that the Ground Control result is the `StartupAborted` error
 /// and that the error report matches the expected text.
 #[allow(dead_code)]
-pub fn assert_startup_aborted(expected: &str, result: Result<(), groundcontrol::Error>) {
+pub fn assert_startup_aborted(
+    expected: &str,
+    result: Result<groundcontrol::ShutdownReason, groundcontrol::Error>,
+) {
     match result {
         Err(groundcontrol::Error::StartupAborted(report)) => {
             let report_text: String = report.chain().map(|r| format!("{r}\n")).collect();
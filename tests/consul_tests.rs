@@ -0,0 +1,153 @@
+//! Tests that verify service registration with the Consul agent.
+
+#![cfg(feature = "consul")]
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use pretty_assertions::assert_eq;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+
+use crate::common::{spawn_daemon_waiter, start, stop};
+
+mod common;
+
+/// Serializes the tests, since each test points Ground Control at its
+/// own fake agent through the (process-wide) `CONSUL_HTTP_ADDR`.
+static AGENT_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+/// Starts a fake Consul agent that records every request (as the
+/// request line followed by the body) and responds with an empty
+/// success response.
+async fn fake_consul_agent() -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let agent_requests = requests.clone();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).await.unwrap();
+
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).await.unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).await.unwrap();
+
+            agent_requests.lock().unwrap().push(format!(
+                "{} {}",
+                request_line.trim(),
+                String::from_utf8(body).unwrap()
+            ));
+
+            reader
+                .into_inner()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+        }
+    });
+
+    (addr, requests)
+}
+
+/// Processes with a `service` are registered once they have started,
+/// and deregistered before they are stopped.
+#[test_log::test(tokio::test)]
+async fn service_registered_while_running() {
+    let _lock = AGENT_LOCK.lock().await;
+    let (addr, requests) = fake_consul_agent().await;
+    std::env::set_var("CONSUL_HTTP_ADDR", addr);
+
+    let config = r##"
+        [[processes]]
+        name = "web"
+        service = { name = "web-svc", port = 8080, tags = ["primary"], health = "http://127.0.0.1:8080/health" }
+        run = [ "/bin/sh", "-c", "echo web >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!("web\n", output);
+
+    assert_eq!(
+        vec![
+            r#"PUT /v1/agent/service/register HTTP/1.1 {"Check":{"DeregisterCriticalServiceAfter":"1m","HTTP":"http://127.0.0.1:8080/health","Interval":"10s"},"ID":"web","Name":"web-svc","Port":8080,"Tags":["primary"]}"#,
+            "PUT /v1/agent/service/deregister/web HTTP/1.1 ",
+        ],
+        *requests.lock().unwrap()
+    );
+}
+
+/// Draining a process (with a `drain.<name>` control file) deregisters
+/// its service without stopping it, and the service is not deregistered
+/// again when the process is stopped.
+#[test_log::test(tokio::test)]
+async fn drained_service_is_deregistered() {
+    let _lock = AGENT_LOCK.lock().await;
+    let (addr, requests) = fake_consul_agent().await;
+    std::env::set_var("CONSUL_HTTP_ADDR", addr);
+
+    let config = r##"
+        control-dir = "{temp_path}/control"
+
+        [[processes]]
+        name = "web"
+        service = { port = 8080 }
+        run = [ "/bin/sh", "{test-daemon.sh}", "web", "{result_path}", "{temp_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let control_dir = dir.path().join("control");
+    let daemon_waiter = spawn_daemon_waiter(&dir, "web");
+    let drain_requests = requests.clone();
+    let drained = tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+
+        std::fs::write(control_dir.join("drain.web"), "").unwrap();
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        let drained = drain_requests.lock().unwrap().len();
+
+        tx.send(()).unwrap();
+        drained
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!("web:started\nweb:shutdown-requested\nweb:stopped\n", output);
+
+    assert_eq!(2, drained.await.unwrap());
+    assert_eq!(
+        vec![
+            r#"PUT /v1/agent/service/register HTTP/1.1 {"ID":"web","Name":"web","Port":8080,"Tags":[]}"#,
+            "PUT /v1/agent/service/deregister/web HTTP/1.1 ",
+        ],
+        *requests.lock().unwrap()
+    );
+}
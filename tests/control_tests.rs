@@ -0,0 +1,152 @@
+//! Tests that verify the file-based control channel.
+
+use std::time::Duration;
+
+use groundcontrol::ShutdownReason;
+use pretty_assertions::assert_eq;
+
+use crate::common::{prepare, process_state, spawn_daemon_waiter, start, stop};
+
+mod common;
+
+/// Creating the `shutdown` file in the control directory triggers a
+/// graceful shutdown.
+#[test_log::test(tokio::test)]
+async fn shutdown_file_triggers_shutdown() {
+    let config = r##"
+        control-dir = "{temp_path}/control"
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo $$ > {temp_path}/daemon.pid; while true; do sleep 0.1; done" ]
+        post = [ "/bin/sh", "-c", "echo post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+
+    let shutdown_file = dir.path().join("control").join("shutdown");
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        std::fs::write(shutdown_file, "").unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(result, Ok(ShutdownReason::ControlShutdown)));
+    assert_eq!("post\n", output);
+}
+
+/// The control directory contains a status file that identifies the
+/// running spec.
+#[test_log::test(tokio::test)]
+async fn status_file_describes_running_spec() {
+    let config = r##"
+        control-dir = "{temp_path}/control"
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo $$ > {temp_path}/daemon.pid; while true; do sleep 0.1; done" ]
+        "##;
+
+    let (mut config, dir) = prepare(config).await;
+    config.path = Some("/etc/groundcontrol.toml".into());
+    let fingerprint = config.fingerprint().unwrap();
+
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let gc = groundcontrol::run(config, rx);
+
+    let status_file = dir.path().join("control").join("status");
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    let status = tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        let status = std::fs::read_to_string(status_file).unwrap();
+        tx.send(()).unwrap();
+        status
+    });
+
+    let (result, _output) = stop(gc, dir).await;
+
+    assert!(matches!(result, Ok(ShutdownReason::GracefulShutdown)));
+    assert_eq!(
+        format!(
+            "{}config = \"/etc/groundcontrol.toml\"\nfingerprint = \"{fingerprint}\"\nprocesses = 1\n",
+            toml::to_string(&groundcontrol::build_info()).unwrap()
+        ),
+        status.await.unwrap()
+    );
+}
+
+/// `pause.<name>` and `resume.<name>` files stop and continue the
+/// daemon of the named process.
+#[test_log::test(tokio::test)]
+async fn pause_and_resume_files_stop_and_continue_daemon() {
+    let config = r##"
+        control-dir = "{temp_path}/control"
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo $$ > {temp_path}/daemon.pid; while true; do sleep 0.1; done" ]
+        post = [ "/bin/sh", "-c", "echo post >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let control_dir = dir.path().join("control");
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    let states = tokio::task::spawn(async move {
+        let pid = daemon_waiter.await.unwrap();
+
+        std::fs::write(control_dir.join("pause.daemon"), "").unwrap();
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        let paused = process_state(pid);
+
+        std::fs::write(control_dir.join("resume.daemon"), "").unwrap();
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        let resumed = process_state(pid);
+
+        tx.send(()).unwrap();
+        (paused, resumed)
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(result, Ok(ShutdownReason::GracefulShutdown)));
+    assert_eq!("post\n", output);
+
+    let (paused, resumed) = states.await.unwrap();
+    assert_eq!('T', paused);
+    assert_ne!('T', resumed);
+}
+
+/// Paused daemons are resumed (so that they can respond to being
+/// stopped) during shutdown.
+#[test_log::test(tokio::test)]
+async fn paused_daemon_is_stopped_on_shutdown() {
+    let config = r##"
+        control-dir = "{temp_path}/control"
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo $$ > {temp_path}/daemon.pid; while true; do sleep 0.1; done" ]
+        post = [ "/bin/sh", "-c", "echo post >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let control_dir = dir.path().join("control");
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        let pid = daemon_waiter.await.unwrap();
+        std::fs::write(control_dir.join("pause.daemon"), "").unwrap();
+        while process_state(pid) != 'T' {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(result, Ok(ShutdownReason::GracefulShutdown)));
+    assert_eq!("post\n", output);
+}
@@ -0,0 +1,35 @@
+//! Tests that verify core dump handling.
+
+use groundcontrol::ShutdownReason;
+use pretty_assertions::assert_eq;
+
+use crate::common::{start, stop};
+
+mod common;
+
+/// The `on-crash` command runs when the daemon is killed by a signal,
+/// and receives the signal, the daemon's program, and the newest core
+/// dump.
+#[test_log::test(tokio::test)]
+async fn on_crash_receives_core_dump() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "mkdir -p {temp_path}/cores; echo core > {temp_path}/cores/core.fake; kill -USR1 $$" ]
+        post = [ "/bin/sh", "-c", "echo post >> {result_path}" ]
+        core-dump = { dir = "{temp_path}/cores", on-crash = [ "/bin/sh", "-c", "echo $GC_CRASH_SIGNAL $GC_CRASH_PROGRAM ${GC_CORE_FILE##*/} >> {result_path}" ] }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown(reasons)) if reasons == [ShutdownReason::DaemonFailed {
+            process: "daemon".into(),
+            exit_code: None,
+            signal: Some(10),
+        }]
+    ));
+    assert_eq!("10 /bin/sh core.fake\npost\n", output);
+}
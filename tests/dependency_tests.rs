@@ -0,0 +1,237 @@
+//! Tests that verify the `depends-on` and `wants` dependency
+//! relationships between processes.
+
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+use crate::common::{assert_startup_aborted, start, stop};
+
+mod common;
+
+/// Dependencies are started before the processes that depend on them,
+/// even if they are found later in the config file; shutdown happens
+/// in the reverse order.
+#[test_log::test(tokio::test)]
+async fn dependencies_start_first() {
+    let config = r##"
+        [[processes]]
+        name = "app"
+        depends-on = [ "db" ]
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo app-post >> {result_path}" ]
+
+        [[processes]]
+        name = "db"
+        pre = [ "/bin/sh", "-c", "echo db-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo db-post >> {result_path}" ]
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            db-pre
+            app-pre
+            daemon
+            app-post
+            db-post
+        "#},
+        output
+    );
+}
+
+/// A *wanted* process that fails to start does not abort startup.
+#[test_log::test(tokio::test)]
+async fn failed_wanted_process_continues_startup() {
+    let config = r##"
+        [[processes]]
+        name = "metrics"
+        pre = [ "/bin/sh", "-c", "exit 1" ]
+        post = [ "/bin/sh", "-c", "echo metrics-post >> {result_path}" ]
+
+        [[processes]]
+        name = "app"
+        wants = [ "metrics" ]
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+        run = [ "/bin/sh", "-c", "echo app >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo app-post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            app-pre
+            app
+            app-post
+        "#},
+        output
+    );
+}
+
+/// An optional process that fails to start is skipped, and startup
+/// continues with the remaining processes.
+#[test_log::test(tokio::test)]
+async fn failed_optional_process_continues_startup() {
+    let config = r##"
+        [[processes]]
+        name = "debug-proxy"
+        optional = true
+        pre = [ "/bin/sh", "-c", "exit 1" ]
+        run = [ "/bin/sh", "-c", "echo debug-proxy >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo debug-proxy-post >> {result_path}" ]
+
+        [[processes]]
+        name = "app"
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+        run = [ "/bin/sh", "-c", "echo app >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo app-post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            app-pre
+            app
+            app-post
+        "#},
+        output
+    );
+}
+
+/// A process that is wanted by one process, but required by another,
+/// aborts startup if it fails.
+#[test_log::test(tokio::test)]
+async fn failed_required_process_aborts_startup() {
+    let config = r##"
+        [[processes]]
+        name = "cache"
+        pre = [ "/bin/sh", "-c", "exit 1" ]
+
+        [[processes]]
+        name = "worker"
+        wants = [ "cache" ]
+        pre = [ "/bin/sh", "-c", "echo worker-pre >> {result_path}" ]
+
+        [[processes]]
+        name = "app"
+        depends-on = [ "cache" ]
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        indoc! {r#"
+            `pre` command failed for process "cache" (exit code 1)
+        "#},
+        result,
+    );
+
+    assert_eq!("", output);
+}
+
+/// Independent one-shot processes can run their `pre` commands
+/// concurrently. Each of these processes waits for the other to start,
+/// which means that this test would never complete if the processes
+/// were started in sequence.
+#[test_log::test(tokio::test)]
+async fn concurrent_oneshot_startup() {
+    let config = r##"
+        startup-concurrency = 2
+
+        [[processes]]
+        name = "a"
+        pre = [ "/bin/sh", "-c", "touch {temp_path}/a && while [ ! -f {temp_path}/b ]; do sleep 0; done" ]
+        post = [ "/bin/sh", "-c", "echo a-post >> {result_path}" ]
+
+        [[processes]]
+        name = "b"
+        pre = [ "/bin/sh", "-c", "touch {temp_path}/b && while [ ! -f {temp_path}/a ]; do sleep 0; done" ]
+        post = [ "/bin/sh", "-c", "echo b-post >> {result_path}" ]
+
+        [[processes]]
+        name = "c"
+        depends-on = [ "a" ]
+        pre = [ "/bin/sh", "-c", "echo c-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo c-post >> {result_path}" ]
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            c-pre
+            daemon
+            c-post
+            b-post
+            a-post
+        "#},
+        output
+    );
+}
+
+/// Processes that reference a barrier wait for every process that joins
+/// the barrier, even when the joiners start concurrently.
+#[test_log::test(tokio::test)]
+async fn barrier_waits_for_every_joiner() {
+    let config = r##"
+        startup-concurrency = 3
+
+        [[processes]]
+        name = "migrate-users"
+        joins = "migrations-done"
+        pre = [ "/bin/sh", "-c", "sleep 0.2; echo migrate-users >> {result_path}" ]
+
+        [[processes]]
+        name = "migrate-billing"
+        joins = "migrations-done"
+        pre = [ "/bin/sh", "-c", "echo migrate-billing >> {result_path}" ]
+
+        [[processes]]
+        name = "api"
+        depends-on = [ "migrations-done" ]
+        pre = [ "/bin/sh", "-c", "echo api >> {result_path}" ]
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            migrate-billing
+            migrate-users
+            api
+            daemon
+        "#},
+        output
+    );
+}
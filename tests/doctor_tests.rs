@@ -0,0 +1,57 @@
+//! Tests that verify the `doctor` diagnostics.
+
+#![cfg(feature = "cli")]
+
+use groundcontrol::{
+    config::Config,
+    doctor::{self, Finding, Severity},
+};
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+/// Users that do not exist and programs that cannot be executed are
+/// reported as errors; everything that is fine is reported as such.
+#[test]
+fn reports_config_problems() {
+    let config: Config = toml::from_str(indoc! {r#"
+        [[processes]]
+        name = "app"
+        pre = { user = "root", command = "/bin/sh -c true" }
+        run = { user = "groundcontrol-no-such-user", command = "/groundcontrol/no/such/program" }
+        timezone = "Mars/Olympus_Mons"
+
+        [[processes]]
+        name = "disabled"
+        disabled = true
+        run = "/groundcontrol/disabled/program"
+        "#})
+    .unwrap();
+
+    let findings = doctor::diagnose(Some(&config));
+
+    assert_eq!(
+        vec![
+            Finding {
+                severity: Severity::Error,
+                message: String::from("User \"groundcontrol-no-such-user\" does not exist (check /etc/passwd in the image)"),
+            },
+            Finding {
+                severity: Severity::Error,
+                message: String::from("Program \"/groundcontrol/no/such/program\" (`run` command of process \"app\") does not exist"),
+            },
+            Finding {
+                severity: Severity::Error,
+                message: String::from("Time zone \"Mars/Olympus_Mons\" does not exist (install the tz database in the image, or check `TZDIR`)"),
+            },
+        ],
+        findings
+            .iter()
+            .filter(|finding| finding.severity == Severity::Error)
+            .cloned()
+            .collect::<Vec<_>>()
+    );
+    assert!(findings.contains(&Finding {
+        severity: Severity::Ok,
+        message: String::from("User \"root\" exists"),
+    }));
+}
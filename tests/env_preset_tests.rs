@@ -0,0 +1,107 @@
+//! Tests that verify the environment presets, and the variables that
+//! describe the user that runs a command.
+
+use pretty_assertions::assert_eq;
+use users::os::unix::UserExt;
+
+use crate::common::{start, stop};
+
+mod common;
+
+/// The `minimal` preset provides the identity variables of the user,
+/// even if every other environment variable has been filtered out.
+#[test_log::test(tokio::test)]
+async fn minimal_preset_sets_user_variables() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        env-preset = "minimal"
+        run = { only-env = [], command = [ "/bin/sh", "-c", "echo $HOME $USER $LOGNAME [$LANG] >> {result_path}" ] }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    let user = users::get_user_by_uid(users::get_current_uid()).unwrap();
+    let username = user.name().to_str().unwrap();
+    assert_eq!(
+        format!("{} {username} {username} []\n", user.home_dir().display()),
+        output
+    );
+}
+
+/// The `full` preset adds the locale, temporary directory, and shell,
+/// keeping Ground Control's own values where it has them.
+#[test_log::test(tokio::test)]
+async fn full_preset_sets_locale_and_tmpdir() {
+    let config = r##"
+        env = { LANG = "en_US.UTF-8" }
+
+        [[processes]]
+        name = "daemon"
+        env-preset = "full"
+        run = { only-env = [], command = [ "/bin/sh", "-c", "echo $LANG $TMPDIR $SHELL >> {result_path}" ] }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    let user = users::get_user_by_uid(users::get_current_uid()).unwrap();
+    let tmpdir = std::env::var("TMPDIR").unwrap_or_else(|_| String::from("/tmp"));
+    assert_eq!(
+        format!("en_US.UTF-8 {tmpdir} {}\n", user.shell().display()),
+        output
+    );
+}
+
+/// Commands that run as a `user` get the identity variables of that
+/// user, even without a preset.
+#[test_log::test(tokio::test)]
+async fn user_sets_user_variables() {
+    let user = users::get_user_by_uid(users::get_current_uid()).unwrap();
+    let username = user.name().to_str().unwrap();
+
+    let config = format!(
+        r##"
+        [[processes]]
+        name = "daemon"
+        run = {{ user = "{username}", only-env = [], command = [ "/bin/sh", "-c", "echo $HOME $USER $LOGNAME >> {{result_path}}" ] }}
+        "##
+    );
+
+    let (gc, _tx, dir) = start(&config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!(
+        format!("{} {username} {username}\n", user.home_dir().display()),
+        output
+    );
+}
+
+/// Variables in the config's `env` table override the user variables.
+#[test_log::test(tokio::test)]
+async fn env_table_overrides_user_variables() {
+    let user = users::get_user_by_uid(users::get_current_uid()).unwrap();
+    let username = user.name().to_str().unwrap();
+
+    let config = format!(
+        r##"
+        env = {{ HOME = "/srv/app" }}
+
+        [[processes]]
+        name = "daemon"
+        run = {{ user = "{username}", only-env = [], command = [ "/bin/sh", "-c", "echo $HOME $USER >> {{result_path}}" ] }}
+        "##
+    );
+
+    let (gc, _tx, dir) = start(&config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!(format!("/srv/app {username}\n"), output);
+}
@@ -0,0 +1,191 @@
+//! Tests that verify that each process's environment is captured once,
+//! when the process starts (unless the process opts out of that
+//! behavior).
+//!
+//! These tests are kept separate from the other environment variable
+//! tests, since those tests modify `PATH`, which the test daemon needs
+//! in order to find its commands.
+
+use std::sync::{Arc, Mutex};
+
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+use crate::common::{spawn_daemon_waiter, start, stop};
+
+mod common;
+
+/// The environment is captured when the process starts, so changes to
+/// Ground Control's environment after that point are not seen by the
+/// process's `stop` and `post` commands.
+#[test_log::test(tokio::test)]
+async fn env_snapshot_used_for_all_commands() {
+    std::env::set_var("SNAPSHOTVAR1", "before");
+
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        post = [ "/bin/sh", "-c", "echo post $SNAPSHOTVAR1 {{SNAPSHOTVAR1}} >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        std::env::set_var("SNAPSHOTVAR1", "after");
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon:shutdown-requested
+            daemon:stopped
+            post before before
+        "#},
+        output
+    );
+}
+
+/// Processes with `fresh-env` see the environment as it is when each
+/// command is run.
+#[test_log::test(tokio::test)]
+async fn fresh_env_for_every_command() {
+    std::env::set_var("SNAPSHOTVAR2", "before");
+
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        fresh-env = true
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        post = [ "/bin/sh", "-c", "echo post $SNAPSHOTVAR2 {{SNAPSHOTVAR2}} >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        std::env::set_var("SNAPSHOTVAR2", "after");
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon:shutdown-requested
+            daemon:stopped
+            post after after
+        "#},
+        output
+    );
+}
+
+/// Restarting a daemon with `fresh-env` runs it with Ground Control's
+/// current environment, and logs how that changed the daemon's inputs.
+#[tokio::test]
+async fn fresh_env_restart_logs_changed_inputs() {
+    let (logs, _guard) = capture_logs();
+    std::env::set_var("SNAPSHOTVAR3", "before");
+
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        fresh-env = true
+        run = [ "/bin/sh", "-c", "echo run $SNAPSHOTVAR3 >> {result_path}; if [ -f {temp_path}/restarted ]; then exit 2; fi; touch {temp_path}/restarted; echo $$ > {temp_path}/first.pid; while [ ! -f {temp_path}/rotated ]; do sleep 0.1; done; exit 1" ]
+        restart-on-exit-codes = [1]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "first");
+    let rotated_path = dir.path().join("rotated");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        std::env::set_var("SNAPSHOTVAR3", "after");
+        tokio::fs::write(rotated_path, "").await.unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_err());
+    assert_eq!("run before\nrun after\n", output);
+
+    let logs = logs.lock().unwrap().clone();
+    let logs = String::from_utf8(logs).unwrap();
+    assert!(logs.contains(
+        r#"Inputs changed since the previous run changes=["SNAPSHOTVAR3: \"before\" -> \"after\""]"#
+    ));
+}
+
+/// Restarting a daemon without `fresh-env` runs it with the environment
+/// that was captured when the process started, so its inputs are
+/// unchanged.
+#[tokio::test]
+async fn snapshot_restart_logs_unchanged_inputs() {
+    let (logs, _guard) = capture_logs();
+    std::env::set_var("SNAPSHOTVAR4", "before");
+
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo run $SNAPSHOTVAR4 >> {result_path}; if [ -f {temp_path}/restarted ]; then exit 2; fi; touch {temp_path}/restarted; echo $$ > {temp_path}/first.pid; while [ ! -f {temp_path}/rotated ]; do sleep 0.1; done; exit 1" ]
+        restart-on-exit-codes = [1]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "first");
+    let rotated_path = dir.path().join("rotated");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        std::env::set_var("SNAPSHOTVAR4", "after");
+        tokio::fs::write(rotated_path, "").await.unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_err());
+    assert_eq!("run before\nrun before\n", output);
+
+    let logs = logs.lock().unwrap().clone();
+    let logs = String::from_utf8(logs).unwrap();
+    assert!(logs.contains("Inputs unchanged since the previous run"));
+}
+
+/// Captures the logs of the current thread (which runs every task of a
+/// single-threaded test) until the guard is dropped.
+fn capture_logs() -> (Arc<Mutex<Vec<u8>>>, tracing::subscriber::DefaultGuard) {
+    let logs = Arc::new(Mutex::new(Vec::new()));
+    let writer = logs.clone();
+    let guard = tracing::subscriber::set_default(
+        tracing_subscriber::fmt()
+            .with_writer(move || LogWriter(writer.clone()))
+            .finish(),
+    );
+    (logs, guard)
+}
+
+/// Writer that appends the logs to a shared buffer.
+struct LogWriter(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -287,6 +287,54 @@ async fn template_expansion_and_allowed_vars() {
     );
 }
 
+/// With `restrict-expansion`, template expansion in commands with an
+/// `only-env` allow-list can only see the allowed variables (and
+/// `PATH`); commands without an allow-list are unaffected.
+#[test_log::test(tokio::test)]
+async fn restricted_template_expansion() {
+    // Create some environment variables, including an override of the
+    // PATH variable so that it contains a predictable value.
+    std::env::set_var("PATH", "im_the_path");
+    std::env::set_var("TESTVAR1", "one");
+    std::env::set_var("TESTVAR2", "two");
+
+    let config = r##"
+        restrict-expansion = true
+
+        [[processes]]
+        name = "unrestricted"
+        pre = { command = [ "/bin/sh", "-c", "echo unrestricted: {{TESTVAR1}} >> {result_path}" ] }
+
+        [[processes]]
+        name = "allowed"
+        pre = { only-env = ["TESTVAR2"], command = [ "/bin/sh", "-c", "echo allowed: {{PATH}} {{TESTVAR2}} >> {result_path}" ] }
+
+        [[processes]]
+        name = "daemon"
+        run = { only-env = ["TESTVAR2"], command = [ "/bin/sh", "-c", "echo {{TESTVAR1}} >> {result_path}" ] }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        indoc! {r#"
+            `run` command failed for process "daemon"
+            Environment variable expansion failed for command "/bin/sh"
+            Unknown environment variable "TESTVAR1"
+        "#},
+        result,
+    );
+
+    assert_eq!(
+        indoc! {r#"
+            unrestricted: one
+            allowed: im_the_path two
+        "#},
+        output
+    );
+}
+
 /// Template expansion fails if the environment variable cannot be
 /// found.
 #[test_log::test(tokio::test)]
@@ -309,3 +357,88 @@ async fn template_expansion_requires_variable_to_exist() {
         result,
     );
 }
+
+/// Arguments that consist of a `{{@VAR}}` expression are split into zero
+/// or more arguments using shell quoting rules.
+#[test_log::test(tokio::test)]
+async fn argument_splitting() {
+    let config = r##"
+        [env]
+        EXTRA_ARGS = "--verbose '--name=hello world'"
+
+        [[processes]]
+        name = "split"
+        pre = [ "/bin/sh", "-c", "echo split $# \"$@\" >> {result_path}", "sh", "first", "{{@EXTRA_ARGS}}", "last" ]
+
+        [[processes]]
+        name = "unset"
+        run = [ "/bin/sh", "-c", "echo unset $# \"$@\" >> {result_path}", "sh", "{{ @MISSING_EXTRA_ARGS }}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            split 4 first --verbose --name=hello world last
+            unset 0
+        "#},
+        output
+    );
+}
+
+/// Argument splitting expressions cannot be combined with other text in
+/// the same argument.
+#[test_log::test(tokio::test)]
+async fn argument_splitting_requires_entire_argument() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo $@ >> {result_path}", "sh", "--flags={{@EXTRA_ARGS}}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, _output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        indoc! {r#"
+            `run` command failed for process "daemon"
+            Environment variable expansion failed for command "/bin/sh"
+            Argument splitting expression must be the entire argument: "--flags={{@EXTRA_ARGS}}"
+        "#},
+        result,
+    );
+}
+
+/// Programs that cannot be found in the `PATH` report the `PATH` that
+/// was searched (and, for `only-env` commands, where that `PATH` came
+/// from).
+#[test_log::test(tokio::test)]
+async fn not_found_program_reports_path() {
+    // Override the PATH variable so that it contains a predictable
+    // value.
+    std::env::set_var("PATH", "im_the_path");
+
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = { only-env = [], command = "not-a-real-program" }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        indoc! {r#"
+            `run` command failed for process "daemon"
+            Error starting command "not-a-real-program"
+            "not-a-real-program" was not found in `PATH` "im_the_path" (`only-env` clears the environment of this command, but passes through Ground Control's `PATH`)
+            No such file or directory (os error 2)
+        "#},
+        result,
+    );
+
+    assert_eq!("", output);
+}
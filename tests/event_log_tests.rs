@@ -0,0 +1,157 @@
+//! Tests that verify the lifecycle event log.
+
+use std::path::Path;
+
+use pretty_assertions::assert_eq;
+use tempfile::TempDir;
+
+use crate::common::{spawn_daemon_waiter, start, stop};
+
+mod common;
+
+/// Every command, daemon spawn, stop, and exit is written to the event
+/// log, in order.
+#[test_log::test(tokio::test)]
+async fn lifecycle_events_logged() {
+    let config = r##"
+        event-log = "{log_dir}/events.jsonl"
+
+        [[processes]]
+        name = "setup"
+        pre = [ "/bin/sh", "-c", "exit 0" ]
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        post = [ "/bin/sh", "-c", "exit 0" ]
+        "##;
+
+    let log_dir = TempDir::new().unwrap();
+    let config = config.replace("{log_dir}", log_dir.path().to_str().unwrap());
+
+    let (gc, tx, dir) = start(&config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    let pid = tokio::task::spawn(async move {
+        let pid = daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+        pid
+    });
+
+    let (result, _) = stop(gc, dir).await;
+    let pid = pid.await.unwrap().as_raw();
+    assert!(result.is_ok());
+
+    let events: Vec<serde_json::Value> =
+        std::fs::read_to_string(log_dir.path().join("events.jsonl"))
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+    assert!(events
+        .iter()
+        .all(|event| event["timestamp"].as_str().unwrap().ends_with('Z')));
+
+    let summary: Vec<(&str, &str, &str)> = events
+        .iter()
+        .map(|event| {
+            (
+                event["process"].as_str().unwrap(),
+                event["event"].as_str().unwrap(),
+                event["phase"]
+                    .as_str()
+                    .or_else(|| event["stop"].as_str())
+                    .unwrap_or_default(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            ("setup", "command-started", "pre"),
+            ("setup", "command-exited", "pre"),
+            ("daemon", "daemon-spawned", ""),
+            ("daemon", "stop-issued", "SIGTERM"),
+            ("daemon", "daemon-exited", ""),
+            ("daemon", "command-started", "post"),
+            ("daemon", "command-exited", "post"),
+        ],
+        summary
+    );
+
+    assert_eq!(pid, events[2]["pid"]);
+    assert_eq!(0, events[1]["exit-code"]);
+    assert_eq!(true, events[4]["expected"]);
+}
+
+/// Daemons whose health check only reports them as unhealthy are left
+/// running, and every change in their health (after enough consecutive
+/// failed, or passed, checks) is written to the event log.
+#[test_log::test(tokio::test)]
+async fn health_changes_logged() {
+    let config = r##"
+        event-log = "{log_dir}/events.jsonl"
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        health-check = { exec = [ "/bin/sh", "-c", "test -f {temp_path}/healthy" ], interval = "100ms", failure-threshold = 2, success-threshold = 2, on-failure = "report" }
+        "##;
+
+    let log_dir = TempDir::new().unwrap();
+    let config = config.replace("{log_dir}", log_dir.path().to_str().unwrap());
+    let events_path = log_dir.path().join("events.jsonl");
+
+    let (gc, tx, dir) = start(&config).await;
+
+    // Make the daemon healthy once it has become unhealthy, then shut
+    // down once it is healthy again.
+    let healthy_path = dir.path().join("healthy");
+    tokio::task::spawn(async move {
+        wait_for_health_change(&events_path, false).await;
+        std::fs::write(&healthy_path, "").unwrap();
+        wait_for_health_change(&events_path, true).await;
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+    assert!(result.is_ok());
+    assert_eq!(
+        "daemon:started\ndaemon:shutdown-requested\ndaemon:stopped\n",
+        output
+    );
+
+    let events: Vec<serde_json::Value> =
+        std::fs::read_to_string(log_dir.path().join("events.jsonl"))
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .filter(|event: &serde_json::Value| event["event"] == "health-changed")
+            .collect();
+    assert_eq!(2, events.len());
+    assert_eq!(false, events[0]["healthy"]);
+    assert!(events[0]["reason"]
+        .as_str()
+        .unwrap()
+        .starts_with("Failed 2 consecutive health checks: "));
+    assert_eq!(true, events[1]["healthy"]);
+    assert_eq!("Passed 2 consecutive health checks", events[1]["reason"]);
+}
+
+/// Waits for the event log to contain a `health-changed` event with the
+/// given health.
+async fn wait_for_health_change(events_path: &Path, healthy: bool) {
+    let needle = r#""event":"health-changed","pid":"#;
+    let healthy = format!(r#""healthy":{healthy}"#);
+    loop {
+        let events = tokio::fs::read_to_string(events_path)
+            .await
+            .unwrap_or_default();
+        if events
+            .lines()
+            .any(|line| line.contains(needle) && line.contains(&healthy))
+        {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+}
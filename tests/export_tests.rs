@@ -0,0 +1,95 @@
+//! Tests that verify exporting specifications to other process managers.
+
+#![cfg(feature = "cli")]
+
+use groundcontrol::config::Config;
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+/// Processes are exported as systemd units, including their
+/// dependencies, commands, stop mechanism, and environment.
+#[test]
+fn exports_systemd_units() {
+    let config: Config = toml::from_str(indoc! {r#"
+        env = { DB_URL = "postgres://db/%x" }
+
+        [[processes]]
+        name = "migrate"
+        before = ["api"]
+        joins = "migrations-done"
+        pre = { user = "app", command = "/app/migrate --url {{DB_URL}}" }
+
+        [[processes]]
+        name = "api"
+        description = "Serves the public API"
+        owner = "team-api"
+        depends-on = ["migrate"]
+        wants = ["migrations-done"]
+        run = ["/app/api", "{{@API_ARGS}}", "--price=$5"]
+        stop = "SIGINT"
+        post = "/app/cleanup"
+        restart-on-exit-codes = [1]
+
+        [[processes]]
+        name = "disabled"
+        disabled = true
+        run = "/app/disabled"
+        "#})
+    .unwrap();
+
+    let units = groundcontrol::export::systemd_units(&config);
+
+    assert_eq!(
+        vec![
+            (
+                String::from("migrate.service"),
+                String::from(indoc! {r#"
+                    [Unit]
+                    Description=migrate (exported from Ground Control)
+                    Before=api.service
+
+                    [Service]
+                    Type=oneshot
+                    RemainAfterExit=yes
+                    User=app
+                    ExecStart="/app/migrate" "--url" "${DB_URL}"
+                    Environment="DB_URL=postgres://db/%%x"
+
+                    [Install]
+                    WantedBy=multi-user.target
+                    "#})
+            ),
+            (
+                String::from("api.service"),
+                String::from(indoc! {r#"
+                    # Not exported (no systemd equivalent): owner, restart-on-exit-codes
+                    [Unit]
+                    Description=api: Serves the public API (exported from Ground Control)
+                    Requires=migrate.service
+                    Wants=migrations-done.target
+                    After=migrate.service migrations-done.target
+
+                    [Service]
+                    Type=simple
+                    ExecStart="/app/api" $API_ARGS "--price=$$5"
+                    KillSignal=SIGINT
+                    ExecStopPost="/app/cleanup"
+                    Environment="DB_URL=postgres://db/%%x"
+
+                    [Install]
+                    WantedBy=multi-user.target
+                    "#})
+            ),
+            (
+                String::from("migrations-done.target"),
+                String::from(indoc! {r#"
+                    [Unit]
+                    Description=migrations-done (barrier exported from Ground Control)
+                    Requires=migrate.service
+                    After=migrate.service
+                    "#})
+            ),
+        ],
+        units
+    );
+}
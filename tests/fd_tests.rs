@@ -0,0 +1,49 @@
+//! Tests that verify which of Ground Control's file descriptors are
+//! inherited by commands.
+
+use std::{fs::File, os::unix::io::AsRawFd};
+
+use indoc::indoc;
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+use pretty_assertions::assert_eq;
+
+use crate::common::{start, stop};
+
+mod common;
+
+/// Inherited file descriptors are closed in commands, unless they are
+/// listed in `pass-fds`. (both cases are covered by a single test, since
+/// every test in this file shares Ground Control's file descriptors)
+#[test_log::test(tokio::test)]
+async fn only_passed_fds_are_inherited() {
+    // Open two files without `O_CLOEXEC`, as if Ground Control had
+    // inherited them.
+    let inherited = File::open("/dev/null").unwrap();
+    let passed = File::open("/dev/null").unwrap();
+    for file in [&inherited, &passed] {
+        fcntl(file.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::empty())).unwrap();
+    }
+
+    let config = r##"
+        pass-fds = [ {passed} ]
+
+        [[processes]]
+        name = "fds"
+        run = [ "/bin/sh", "-c", "for fd in {inherited} {passed}; do if [ -e /proc/self/fd/$fd ]; then echo open >> {result_path}; else echo closed >> {result_path}; fi; done" ]
+        "##
+    .replace("{inherited}", &inherited.as_raw_fd().to_string())
+    .replace("{passed}", &passed.as_raw_fd().to_string());
+
+    let (gc, _tx, dir) = start(&config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            closed
+            open
+        "#},
+        output
+    );
+}
@@ -0,0 +1,43 @@
+//! Tests that verify signal forwarding (single-process mode).
+
+use groundcontrol::ShutdownReason;
+use nix::{sys::signal::Signal, unistd::Pid};
+use pretty_assertions::assert_eq;
+
+use crate::common::{spawn_daemon_waiter, start, stop};
+
+mod common;
+
+/// Signals received by Ground Control are forwarded to the daemon, and
+/// the daemon's exit (with its exit code) shuts down Ground Control.
+#[test_log::test(tokio::test)]
+async fn signals_forwarded_to_daemon() {
+    let config = r##"
+        forward-signals = true
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "trap 'echo usr1 >> {result_path}; exit 3' USR1; echo $$ > {temp_path}/daemon.pid; while true; do sleep 0.1; done" ]
+        post = [ "/bin/sh", "-c", "echo post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        nix::sys::signal::kill(Pid::this(), Signal::SIGUSR1).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown(reasons)) if reasons == [ShutdownReason::DaemonFailed {
+            process: "daemon".into(),
+            exit_code: Some(3),
+            signal: None,
+        }]
+    ));
+    assert_eq!("usr1\npost\n", output);
+}
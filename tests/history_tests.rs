@@ -0,0 +1,60 @@
+//! Tests that verify the exit history.
+
+use pretty_assertions::assert_eq;
+use tempfile::TempDir;
+
+use crate::common::{start, stop};
+
+mod common;
+
+/// Every daemon exit is recorded in the exit history, along with the
+/// decision that was made because of the exit and a snapshot of the
+/// daemon's most recent output.
+#[test_log::test(tokio::test)]
+async fn exits_recorded_in_history() {
+    let config = r##"
+        state-dir = "{state_dir}"
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "if [ -f {temp_path}/restarted ]; then echo 'second run' >&2; exit 2; fi; echo > {temp_path}/restarted; echo 'first run'; exit 1" ]
+        restart-on-exit-codes = [1]
+
+        [[processes]]
+        name = "other"
+        run = [ "/bin/sh", "-c", "while true; do sleep 0.1; done" ]
+        "##;
+
+    let state_dir = TempDir::new().unwrap();
+    let config = config.replace("{state_dir}", state_dir.path().to_str().unwrap());
+
+    let (gc, _tx, dir) = start(&config).await;
+    let (result, _) = stop(gc, dir).await;
+    assert!(result.is_err());
+
+    let history: Vec<(String, String, Vec<String>)> =
+        groundcontrol::history::read(state_dir.path(), "daemon")
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.status, entry.decision, entry.snapshot.unwrap().output))
+            .collect();
+    assert_eq!(
+        vec![
+            (
+                "exit code 1".to_string(),
+                "restarted".to_string(),
+                vec!["first run".to_string()]
+            ),
+            (
+                "exit code 2".to_string(),
+                "shut down".to_string(),
+                vec!["second run".to_string()]
+            ),
+        ],
+        history
+    );
+
+    assert!(groundcontrol::history::read(state_dir.path(), "other")
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,94 @@
+//! Tests that verify holding the startup of a process until it is
+//! released.
+
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+use crate::common::{assert_startup_aborted, start, stop};
+
+mod common;
+
+/// Startup pauses before a held process until its release file appears.
+#[test_log::test(tokio::test)]
+async fn hold_released_by_file() {
+    let config = r##"
+        [[processes]]
+        name = "approver"
+        run = [ "/bin/sh", "-c", "sleep 1; echo approved >> {result_path}; echo > {temp_path}/release; exec sleep 30" ]
+
+        [[processes]]
+        name = "app"
+        hold = { file = "{temp_path}/release" }
+        run = [ "/bin/sh", "-c", "echo app >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!(
+        indoc! {r#"
+            approved
+            app
+        "#},
+        output
+    );
+}
+
+/// A `release.<name>` file dropped into the control directory releases
+/// the hold on the process.
+#[test_log::test(tokio::test)]
+async fn hold_released_by_control_file() {
+    let config = r##"
+        control-dir = "{temp_path}/control"
+
+        [[processes]]
+        name = "approver"
+        run = [ "/bin/sh", "-c", "sleep 1; echo approved >> {result_path}; echo > {temp_path}/control/release.app; exec sleep 30" ]
+
+        [[processes]]
+        name = "app"
+        hold = {}
+        run = [ "/bin/sh", "-c", "echo app >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!(
+        indoc! {r#"
+            approved
+            app
+        "#},
+        output
+    );
+}
+
+/// Startup is aborted (and the processes that were already started are
+/// stopped) if the hold is not released before its timeout expires.
+#[test_log::test(tokio::test)]
+async fn hold_times_out() {
+    let config = r##"
+        [[processes]]
+        name = "setup"
+        pre = [ "/bin/sh", "-c", "echo setup-pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo setup-post >> {result_path}" ]
+
+        [[processes]]
+        name = "app"
+        hold = { file = "{temp_path}/release", timeout = "1s" }
+        run = [ "/bin/sh", "-c", "echo app >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        indoc! {r#"
+            Timed out after 1s waiting for process "app" to be released
+        "#},
+        result,
+    );
+    assert_eq!("setup-pre\nsetup-post\n", output);
+}
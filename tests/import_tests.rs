@@ -0,0 +1,169 @@
+//! Tests that verify importing other process managers' configurations.
+
+#![cfg(feature = "cli")]
+
+use groundcontrol::config::Config;
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+/// Docker Compose services are converted into processes, with notes for
+/// the settings that could not be converted.
+#[test]
+fn imports_compose_file() {
+    let toml = groundcontrol::import::compose(indoc! {r#"
+        services:
+          db:
+            image: postgres:15
+            environment:
+              POSTGRES_PASSWORD: secret
+              PGPORT: 5432
+            stop_signal: SIGINT
+            healthcheck:
+              test: ["CMD", "pg_isready"]
+          web:
+            image: app
+            entrypoint: /app/web
+            command: --port 8080 "--name=my app"
+            user: app
+            depends_on:
+              db:
+                condition: service_healthy
+            environment:
+              - PGPORT=5433
+              - API_KEY
+            restart: on-failure
+        "#})
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            # NOTE: `PGPORT` has different values in different services (using the value from the first service)
+            [env]
+            PGPORT = "5432"
+            POSTGRES_PASSWORD = "secret"
+
+            # NOTE: no `command` (the image's default command must be added by hand)
+            # NOTE: `healthcheck` is not supported
+            # NOTE: `image` is not supported
+            [[processes]]
+            name = "db"
+            stop = "SIGINT"
+
+            # NOTE: `image` is not supported
+            [[processes]]
+            name = "web"
+            depends-on = ["db"]
+            run = { user = "app", command = ["/app/web", "--port", "8080", "--name=my app"] }
+            restart-except-exit-codes = []
+            "#},
+        toml
+    );
+
+    let config = Config::from_toml(&toml).unwrap();
+    config.validate().unwrap();
+}
+
+/// Procfile process types are converted into processes, running
+/// commands that use shell features through the shell.
+#[test]
+fn imports_procfile() {
+    let toml = groundcontrol::import::procfile(indoc! {r#"
+        # Heroku processes
+        web: bundle exec puma -C config/puma.rb
+        worker:   bundle exec sidekiq -q "default queue"
+
+        release: ./bin/migrate && ./bin/seed --port $PORT
+        "#})
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            [[processes]]
+            name = "web"
+            run = ["bundle", "exec", "puma", "-C", "config/puma.rb"]
+
+            [[processes]]
+            name = "worker"
+            run = ["bundle", "exec", "sidekiq", "-q", "default queue"]
+
+            [[processes]]
+            name = "release"
+            run = ["/bin/sh", "-c", "./bin/migrate && ./bin/seed --port $PORT"]
+            "#},
+        toml
+    );
+
+    let config = Config::from_toml(&toml).unwrap();
+    config.validate().unwrap();
+
+    assert_eq!(
+        "Invalid Procfile entry on line 1: \"not a process\"",
+        groundcontrol::import::procfile("not a process")
+            .unwrap_err()
+            .to_string()
+    );
+}
+
+/// supervisord programs are converted into processes (in priority
+/// order), with notes for the settings that could not be converted.
+#[test]
+fn imports_supervisord_conf() {
+    let toml = groundcontrol::import::supervisord(indoc! {r#"
+        [supervisord]
+        nodaemon=true
+
+        [program:worker]
+        command=/app/worker --name "%(program_name)s" ; the worker
+        priority=20
+        environment=QUEUE="default queue",LEVEL=info
+        exitcodes=0,2
+        stdout_logfile=/dev/stdout
+
+        [program:web]
+        command=/app/web
+          --home %(ENV_HOME)s
+        priority=10
+        user=app
+        autorestart=false
+        stopsignal=INT
+
+        [program:off]
+        command=/app/off
+        autostart=false
+        autorestart=true
+
+        [group:all]
+        programs=web,worker
+        "#})
+    .unwrap();
+
+    assert_eq!(
+        indoc! {r#"
+            # NOTE: section `[group:all]` is not supported
+            [env]
+            LEVEL = "info"
+            QUEUE = "default queue"
+
+            [[processes]]
+            name = "web"
+            run = { user = "app", command = ["/app/web", "--home", "{{HOME}}"] }
+            stop = "SIGINT"
+
+            # NOTE: `stdout_logfile` is not supported
+            [[processes]]
+            name = "worker"
+            run = ["/app/worker", "--name", "worker"]
+            restart-except-exit-codes = [0, 2]
+
+            [[processes]]
+            name = "off"
+            disabled = true
+            run = ["/app/off"]
+            restart = "always"
+            "#},
+        toml
+    );
+
+    let config = Config::from_toml(&toml).unwrap();
+    config.validate().unwrap();
+}
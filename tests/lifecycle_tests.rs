@@ -168,3 +168,56 @@ async fn multiple_daemons_first_one_exits() {
         output
     );
 }
+
+/// Daemon process with `post-start` and `pre-stop` hooks, which run once
+/// the daemon is ready, and right before the daemon is stopped.
+#[test_log::test(tokio::test)]
+async fn daemon_lifecycle_hooks() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        ready = { exec = [ "/bin/sh", "{wait-daemon-start.sh}", "daemon", "{temp_path}" ] }
+        post-start = [ "/bin/sh", "-c", "echo daemon-post-start >> {result_path}" ]
+        pre-stop = [ "/bin/sh", "-c", "echo daemon-pre-stop >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {result_path}" ]
+
+        [[processes]]
+        name = "app"
+        pre = [ "/bin/sh", "-c", "echo app-pre >> {result_path}" ]
+        "##;
+
+    // Shut down once startup has completed.
+    let (gc, tx, dir) = start(config).await;
+
+    let result_path = dir.path().join("results.txt");
+    tokio::task::spawn(async move {
+        loop {
+            let output = tokio::fs::read_to_string(&result_path)
+                .await
+                .unwrap_or_default();
+            if output.contains("app-pre") {
+                tx.send(()).unwrap();
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon-post-start
+            app-pre
+            daemon-pre-stop
+            daemon:shutdown-requested
+            daemon:stopped
+            daemon-post
+        "#},
+        output
+    );
+}
@@ -0,0 +1,31 @@
+//! Tests that verify commands run through the user's login shell.
+
+use pretty_assertions::assert_eq;
+use users::os::unix::UserExt;
+
+use crate::common::{start, stop};
+
+mod common;
+
+/// Login shell commands get the identity variables of the user, even if
+/// every other environment variable has been filtered out.
+#[test_log::test(tokio::test)]
+async fn login_shell_sets_user_environment() {
+    let config = r##"
+        [[processes]]
+        name = "login"
+        run = { login-shell = true, only-env = [], command = [ "/bin/sh", "-c", "echo $HOME $LOGNAME $USER >> {result_path}" ] }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    let user = users::get_user_by_uid(users::get_current_uid()).unwrap();
+    let username = user.name().to_str().unwrap();
+    assert_eq!(
+        format!("{} {username} {username}\n", user.home_dir().display()),
+        output
+    );
+}
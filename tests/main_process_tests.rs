@@ -0,0 +1,107 @@
+//! Tests that verify signal forwarding to, and exit code propagation
+//! from, the `main` process.
+
+use groundcontrol::ShutdownReason;
+use indoc::indoc;
+use nix::{sys::signal::Signal, unistd::Pid};
+use pretty_assertions::assert_eq;
+
+use crate::common::{spawn_daemon_waiter, start, stop};
+
+mod common;
+
+/// Signals that do not trigger a graceful shutdown are forwarded to the
+/// `main` process (and only to that process).
+#[test_log::test(tokio::test)]
+async fn signals_forwarded_to_main_process() {
+    let config = r##"
+        [[processes]]
+        name = "sidecar"
+        run = [ "/bin/sh", "{test-daemon.sh}", "sidecar", "{result_path}", "{temp_path}" ]
+
+        [[processes]]
+        name = "app"
+        main = true
+        run = [ "/bin/sh", "-c", "trap 'echo hup >> {result_path}; exit 0' HUP; echo $$ > {temp_path}/app.pid; while true; do sleep 0.1; done" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+
+    let sidecar_waiter = spawn_daemon_waiter(&dir, "sidecar");
+    let app_waiter = spawn_daemon_waiter(&dir, "app");
+    tokio::task::spawn(async move {
+        sidecar_waiter.await.unwrap();
+        app_waiter.await.unwrap();
+        nix::sys::signal::kill(Pid::this(), Signal::SIGHUP).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    // The app exited cleanly after receiving the signal, which shut down
+    // the sidecar (which never saw the signal) as usual.
+    assert!(result.is_ok());
+    assert_eq!(
+        indoc! {r#"
+            sidecar:started
+            hup
+            sidecar:shutdown-requested
+            sidecar:stopped
+        "#},
+        output
+    );
+}
+
+/// The exit code of the `main` process decides the outcome of the
+/// shutdown, even if Ground Control stopped the process.
+#[test_log::test(tokio::test)]
+async fn main_process_exit_code_propagated() {
+    let config = r##"
+        [[processes]]
+        name = "app"
+        main = true
+        run = [ "/bin/sh", "-c", "trap 'echo app:stopped >> {result_path}; exit 7' TERM; echo $$ > {temp_path}/app.pid; while true; do sleep 0.1; done" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let app_waiter = spawn_daemon_waiter(&dir, "app");
+    tokio::task::spawn(async move {
+        app_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown(reasons)) if reasons == [ShutdownReason::DaemonFailed {
+            process: "app".into(),
+            exit_code: Some(7),
+            signal: None,
+        }]
+    ));
+    assert_eq!("app:stopped\n", output);
+}
+
+/// A clean exit of the `main` process is a clean shutdown, even if
+/// another daemon failed (and triggered the shutdown).
+#[test_log::test(tokio::test)]
+async fn main_process_clean_exit_overrides_failures() {
+    let config = r##"
+        [[processes]]
+        name = "app"
+        main = true
+        run = [ "/bin/sh", "-c", "trap 'echo app:stopped >> {result_path}; exit 0' TERM; echo $$ > {temp_path}/app.pid; while true; do sleep 0.1; done" ]
+
+        [[processes]]
+        name = "sidecar"
+        pre = [ "/bin/sh", "{wait-daemon-start.sh}", "app", "{temp_path}" ]
+        run = [ "/bin/sh", "-c", "sleep 0.2; echo sidecar:failed >> {result_path}; exit 2" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!("sidecar:failed\napp:stopped\n", output);
+}
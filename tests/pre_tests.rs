@@ -146,6 +146,7 @@ async fn not_found_pre_aborts_startup() {
         indoc! {r#"
             `pre` command failed for process "b"
             Error starting command "/user/binary/nope"
+            "/user/binary/nope" does not exist
             No such file or directory (os error 2)
         "#},
         result,
@@ -160,6 +161,77 @@ async fn not_found_pre_aborts_startup() {
     );
 }
 
+/// A `pre` command that exists, but is not executable, is reported as
+/// such (instead of just "Permission denied").
+#[test_log::test(tokio::test)]
+async fn not_executable_pre_aborts_startup() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        pre = [ "/bin/sh", "-c", "touch {temp_path}/script" ]
+
+        [[processes]]
+        name = "b"
+        pre = "{temp_path}/script"
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let script = dir.path().join("script").display().to_string();
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        &format!(
+            indoc! {r#"
+                `pre` command failed for process "b"
+                Error starting command "{script}"
+                "{script}" exists, but is not executable
+                Permission denied (os error 13)
+            "#},
+            script = script
+        ),
+        result,
+    );
+
+    assert_eq!("", output);
+}
+
+/// A `pre` command with an expected SHA-256 hash only runs if the hash of
+/// the program matches; a mismatch aborts startup.
+#[test_log::test(tokio::test)]
+async fn sha256_mismatch_aborts_startup() {
+    let config = r##"
+        [[processes]]
+        name = "write"
+        pre = [ "/bin/sh", "-c", "printf '#!/bin/sh\\necho verified >> \"$1\"\\n' > {temp_path}/script && chmod +x {temp_path}/script" ]
+
+        [[processes]]
+        name = "good"
+        pre = { sha256 = "ce6deae998540e350bad276a776876550a950acf9c8dff0d190b829147cbc636", command = [ "{temp_path}/script", "{result_path}" ] }
+
+        [[processes]]
+        name = "bad"
+        pre = { sha256 = "0000000000000000000000000000000000000000000000000000000000000000", command = [ "{temp_path}/script", "{result_path}" ] }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let script = dir.path().join("script").display().to_string();
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        &format!(
+            indoc! {r#"
+                `pre` command failed for process "bad"
+                Integrity check failed for command "{script}"
+                SHA-256 hash of "{script}" is ce6deae998540e350bad276a776876550a950acf9c8dff0d190b829147cbc636, not the expected 0000000000000000000000000000000000000000000000000000000000000000
+            "#},
+            script = script
+        ),
+        result,
+    );
+
+    assert_eq!("verified\n", output);
+}
+
 /// Verifies that a failed `pre` execution shuts down all
 /// previously-started long-running processes.
 #[test_log::test(tokio::test)]
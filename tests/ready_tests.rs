@@ -0,0 +1,165 @@
+//! Tests that verify the `ready` probes of daemons.
+
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+use tokio::net::TcpListener;
+
+use crate::common::{assert_startup_aborted, start, stop};
+
+mod common;
+
+/// Processes that are started after a daemon wait for the daemon's
+/// `file` probe to succeed.
+#[test_log::test(tokio::test)]
+async fn file_probe_delays_next_process() {
+    let config = r##"
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "-c", "sleep 1; echo db-ready >> {result_path}; echo > {temp_path}/db.ready; exec sleep 30" ]
+        ready = { file = "{temp_path}/db.ready" }
+
+        [[processes]]
+        name = "app"
+        depends-on = [ "db" ]
+        run = [ "/bin/sh", "-c", "echo app >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            db-ready
+            app
+        "#},
+        output
+    );
+}
+
+/// Processes that are started after a daemon wait for the daemon's
+/// `exec` probe to succeed.
+#[test_log::test(tokio::test)]
+async fn exec_probe_delays_next_process() {
+    let config = r##"
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "-c", "sleep 1; echo db-ready >> {result_path}; echo > {temp_path}/db.ready; exec sleep 30" ]
+        ready = { exec = [ "/bin/sh", "-c", "test -f {temp_path}/db.ready" ] }
+
+        [[processes]]
+        name = "app"
+        depends-on = [ "db" ]
+        run = [ "/bin/sh", "-c", "echo app >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            db-ready
+            app
+        "#},
+        output
+    );
+}
+
+/// `tcp` probes can connect to IPv6 addresses.
+#[test_log::test(tokio::test)]
+async fn tcp_probe_over_ipv6() {
+    let listener = TcpListener::bind("[::1]:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let config = format!(
+        r##"
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "-c", "echo db >> {{result_path}}; exec sleep 30" ]
+        ready = {{ tcp = "{addr}", timeout = "5s" }}
+
+        [[processes]]
+        name = "app"
+        run = [ "/bin/sh", "-c", "echo app >> {{result_path}}" ]
+        "##
+    );
+
+    let (gc, _tx, dir) = start(&config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            db
+            app
+        "#},
+        output
+    );
+}
+
+/// `ready` probes without a probe of their own wait for the first of
+/// the process's `ports` to accept connections.
+#[test_log::test(tokio::test)]
+async fn probe_defaults_to_first_port() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let config = format!(
+        r##"
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "-c", "echo db >> {{result_path}}; exec sleep 30" ]
+        ports = [ {port} ]
+        ready = {{ timeout = "5s" }}
+
+        [[processes]]
+        name = "app"
+        run = [ "/bin/sh", "-c", "echo app >> {{result_path}}" ]
+        "##
+    );
+
+    let (gc, _tx, dir) = start(&config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            db
+            app
+        "#},
+        output
+    );
+}
+
+/// Startup is aborted (and the daemon stopped) if the daemon is still
+/// not ready when the timeout expires.
+#[test_log::test(tokio::test)]
+async fn probe_times_out() {
+    let config = r##"
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "-c", "exec sleep 30" ]
+        post = [ "/bin/sh", "-c", "echo db-post >> {result_path}" ]
+        ready = { tcp = "127.0.0.1:1", timeout = "1s" }
+
+        [[processes]]
+        name = "app"
+        run = [ "/bin/sh", "-c", "echo app >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        indoc! {r#"
+            Timed out after 1s waiting for 127.0.0.1:1 to accept connections (process "db")
+        "#},
+        result,
+    );
+    assert_eq!("db-post\n", output);
+}
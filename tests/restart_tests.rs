@@ -0,0 +1,412 @@
+//! Tests that verify restarting failed daemons.
+
+use groundcontrol::{
+    socket::{self, SocketCommand},
+    ShutdownReason,
+};
+use pretty_assertions::assert_eq;
+
+use crate::common::{start, stop};
+
+mod common;
+
+/// Daemons are restarted after exiting with one of their
+/// `restart-on-exit-codes`, but other exit codes still shut down Ground
+/// Control.
+#[test_log::test(tokio::test)]
+async fn restart_on_exit_codes() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        pre = [ "/bin/sh", "-c", "echo pre >> {result_path}" ]
+        run = [ "/bin/sh", "-c", "echo run >> {result_path}; if [ -f {temp_path}/restarted ]; then exit 2; fi; echo > {temp_path}/restarted; exit 1" ]
+        post = [ "/bin/sh", "-c", "echo post >> {result_path}" ]
+        restart-on-exit-codes = [1]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown(reasons)) if reasons == [ShutdownReason::DaemonFailed {
+            process: "daemon".into(),
+            exit_code: Some(2),
+            signal: None,
+        }]
+    ));
+    assert_eq!("pre\nrun\nrun\npost\n", output);
+}
+
+/// Restarts are delayed while the system is under memory pressure, but
+/// only up to the throttle's `max-delay` (here, the system is always
+/// under pressure, since less than 100% of memory is available).
+#[test_log::test(tokio::test)]
+async fn restart_throttle_max_delay() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo run >> {result_path}; if [ -f {temp_path}/restarted ]; then exit 2; fi; echo > {temp_path}/restarted; exit 1" ]
+        restart-on-exit-codes = [1]
+        restart-throttle = { min-available = 100, max-delay = "1s" }
+        "##;
+
+    let started = std::time::Instant::now();
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown(reasons)) if reasons == [ShutdownReason::DaemonFailed {
+            process: "daemon".into(),
+            exit_code: Some(2),
+            signal: None,
+        }]
+    ));
+    assert_eq!("run\nrun\n", output);
+    assert!(started.elapsed() >= std::time::Duration::from_secs(2));
+}
+
+/// A shutdown that is requested while a restart is delayed by memory
+/// pressure decides the outcome of the shutdown (instead of the exit of
+/// the daemon that was waiting to be restarted).
+#[test_log::test(tokio::test)]
+async fn restart_throttle_interrupted_by_shutdown() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo run >> {result_path}; exit 1" ]
+        restart-on-exit-codes = [1]
+        restart-throttle = { min-available = 100, max-delay = "30s" }
+        "##;
+
+    let started = std::time::Instant::now();
+    let (gc, tx, dir) = start(config).await;
+
+    // Shut down while the restart is being delayed.
+    tokio::task::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert_eq!(ShutdownReason::GracefulShutdown, result.unwrap());
+    assert_eq!("run\n", output);
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+}
+
+/// Control requests are answered while a restart is delayed by memory
+/// pressure.
+#[test_log::test(tokio::test)]
+async fn restart_throttle_does_not_block_requests() {
+    let config = r##"
+        control-socket = "{temp_path}/gc.sock"
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo run >> {result_path}; exit 1" ]
+        restart-on-exit-codes = [1]
+        restart-throttle = { min-available = 100, max-delay = "30s" }
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let socket_path = dir.path().join("gc.sock");
+    let client = tokio::task::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(2500)).await;
+        let status = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            socket::request(&socket_path, &SocketCommand::Status),
+        )
+        .await
+        .expect("status request was not answered while the restart was delayed")
+        .unwrap();
+        assert!(status.starts_with("daemon\texited\t"));
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+    client.await.unwrap();
+
+    assert_eq!(ShutdownReason::GracefulShutdown, result.unwrap());
+    assert_eq!("run\n", output);
+}
+
+/// A shutdown that is requested while a daemon is waiting for its
+/// restart delay is handled right away, without restarting the daemon.
+#[test_log::test(tokio::test)]
+async fn restart_delay_interrupted_by_shutdown() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo run >> {result_path}; exit 1" ]
+        restart-on-exit-codes = [1]
+        restart-backoff = { initial = "30s", max = "60s" }
+        "##;
+
+    let started = std::time::Instant::now();
+    let (gc, tx, dir) = start(config).await;
+
+    // Shut down while the restart is being delayed.
+    tokio::task::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert_eq!(ShutdownReason::GracefulShutdown, result.unwrap());
+    assert_eq!("run\n", output);
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+}
+
+/// Daemons with the `always` restart policy are restarted after every
+/// exit, including clean exits.
+#[test_log::test(tokio::test)]
+async fn restart_always() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo run >> {result_path}; runs=$(wc -l < {result_path}); if [ $runs -eq 1 ]; then exit 0; elif [ $runs -eq 2 ]; then exit 5; fi; exec sleep 30" ]
+        restart = "always"
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    // Shut down once the daemon has been started for the third time.
+    let result_path = dir.path().join("results.txt");
+    tokio::task::spawn(async move {
+        loop {
+            let output = tokio::fs::read_to_string(&result_path)
+                .await
+                .unwrap_or_default();
+            if output.lines().count() >= 3 {
+                tx.send(()).unwrap();
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert_eq!(ShutdownReason::GracefulShutdown, result.unwrap());
+    assert_eq!("run\nrun\nrun\n", output);
+}
+
+/// Daemons that exceed their `max-runtime` are stopped, then restarted
+/// if their restart policy allows it.
+#[test_log::test(tokio::test)]
+async fn max_runtime_restarts_daemon() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo run >> {result_path}; exec sleep 30" ]
+        post = [ "/bin/sh", "-c", "echo post >> {result_path}" ]
+        restart = "on-failure"
+        max-runtime = "500ms"
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    // Shut down once the daemon has been restarted.
+    let result_path = dir.path().join("results.txt");
+    tokio::task::spawn(async move {
+        loop {
+            let output = tokio::fs::read_to_string(&result_path)
+                .await
+                .unwrap_or_default();
+            if output.lines().count() >= 2 {
+                tx.send(()).unwrap();
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert_eq!(ShutdownReason::GracefulShutdown, result.unwrap());
+    assert_eq!("run\nrun\npost\n", output);
+}
+
+/// Daemons that exceed their `max-runtime` without a restart policy are
+/// stopped, which shuts down Ground Control.
+#[test_log::test(tokio::test)]
+async fn max_runtime_shuts_down() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo run >> {result_path}; exec sleep 30" ]
+        post = [ "/bin/sh", "-c", "echo post >> {result_path}" ]
+        max-runtime = "500ms"
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_eq!(
+        ShutdownReason::DaemonExited {
+            process: "daemon".into()
+        },
+        result.unwrap()
+    );
+    assert_eq!("run\npost\n", output);
+}
+
+/// Daemons are no longer restarted once they reach their
+/// `restart-limit`, and the next failure shuts down Ground Control.
+#[test_log::test(tokio::test)]
+async fn restart_limit_shuts_down() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo run >> {result_path}; exit 1" ]
+        restart = "on-failure"
+        restart-backoff = { initial = "50ms", max = "200ms" }
+        restart-limit = { restarts = 3, window = "1m" }
+        "##;
+
+    let started = std::time::Instant::now();
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown(reasons)) if reasons == [ShutdownReason::DaemonFailed {
+            process: "daemon".into(),
+            exit_code: Some(1),
+            signal: None,
+        }]
+    ));
+    assert_eq!("run\nrun\nrun\nrun\n", output);
+
+    // The backoff (50ms, 100ms, then 200ms) replaces the one-second delay.
+    let elapsed = started.elapsed();
+    assert!(elapsed >= std::time::Duration::from_millis(350));
+    assert!(elapsed < std::time::Duration::from_secs(3));
+}
+
+/// Processes that are bound to a daemon are started after it, stopped
+/// as soon as it fails, and restarted along with it.
+#[test_log::test(tokio::test)]
+async fn bound_process_follows_target() {
+    let config = r##"
+        [[processes]]
+        name = "main"
+        run = [ "/bin/sh", "-c", "echo main >> {result_path}; sleep 1; if [ -f {temp_path}/restarted ]; then exit 2; fi; echo > {temp_path}/restarted; exit 1" ]
+        restart-on-exit-codes = [1]
+
+        [[processes]]
+        name = "sidecar"
+        bound-to = "main"
+        run = [ "/bin/sh", "-c", "sleep 0.2; echo sidecar >> {result_path}; exec sleep 30" ]
+        post = [ "/bin/sh", "-c", "echo sidecar-post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown(reasons)) if reasons == [ShutdownReason::DaemonFailed {
+            process: "main".into(),
+            exit_code: Some(2),
+            signal: None,
+        }]
+    ));
+    assert_eq!("main\nsidecar\nmain\nsidecar\nsidecar-post\n", output);
+}
+
+/// Daemons that fail their health check too many times in a row are
+/// restarted.
+#[test_log::test(tokio::test)]
+async fn health_check_restarts_daemon() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo run >> {result_path}; if [ -f {temp_path}/ran ]; then echo > {temp_path}/healthy; fi; echo > {temp_path}/ran; exec sleep 30" ]
+        post = [ "/bin/sh", "-c", "echo post >> {result_path}" ]
+        health-check = { exec = [ "/bin/sh", "-c", "test -f {temp_path}/healthy" ], interval = "100ms", failure-threshold = 2 }
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    // Shut down once the daemon has been restarted (and has passed a few
+    // of its health checks).
+    let result_path = dir.path().join("results.txt");
+    tokio::task::spawn(async move {
+        loop {
+            let output = tokio::fs::read_to_string(&result_path)
+                .await
+                .unwrap_or_default();
+            if output.lines().count() >= 2 {
+                tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+                tx.send(()).unwrap();
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert_eq!(ShutdownReason::GracefulShutdown, result.unwrap());
+    assert_eq!("run\nrun\npost\n", output);
+}
+
+/// Daemons that are restarted because they failed their health check
+/// are subject to their restart limit (and backoff), like any other
+/// failed daemon.
+#[test_log::test(tokio::test)]
+async fn health_check_respects_restart_limit() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo run >> {result_path}; exec sleep 30" ]
+        post = [ "/bin/sh", "-c", "echo post >> {result_path}" ]
+        health-check = { tcp = "127.0.0.1:1", interval = "100ms", failure-threshold = 1 }
+        restart-backoff = { initial = "50ms", max = "200ms" }
+        restart-limit = { restarts = 2, window = "1m" }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown(reasons)) if reasons == [ShutdownReason::DaemonFailed {
+            process: "daemon".into(),
+            exit_code: None,
+            signal: None,
+        }]
+    ));
+    assert_eq!("run\nrun\nrun\npost\n", output);
+}
+
+/// Daemons whose health check policy is `shutdown` shut down Ground
+/// Control once they are unhealthy.
+#[test_log::test(tokio::test)]
+async fn health_check_shuts_down() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo run >> {result_path}; exec sleep 30" ]
+        post = [ "/bin/sh", "-c", "echo post >> {result_path}" ]
+        health-check = { tcp = "127.0.0.1:1", interval = "100ms", failure-threshold = 3, on-failure = "shutdown" }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown(reasons)) if reasons == [ShutdownReason::DaemonFailed {
+            process: "daemon".into(),
+            exit_code: None,
+            signal: None,
+        }]
+    ));
+    assert_eq!("run\npost\n", output);
+}
@@ -0,0 +1,76 @@
+//! Tests that verify the per-process runtime directory.
+
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+use crate::common::{assert_startup_aborted, start, stop};
+
+mod common;
+
+/// The runtime directory is created before the process starts, is
+/// available to every command, and is removed after the process stops.
+#[test_log::test(tokio::test)]
+async fn runtime_dir_lifecycle() {
+    let config = r##"
+        [[processes]]
+        name = "checker"
+        post = [ "/bin/sh", "-c", "test -d {temp_path}/app-run || echo removed >> {result_path}" ]
+
+        [[processes]]
+        name = "app"
+        runtime-dir = "{temp_path}/app-run"
+        pre = [ "/bin/sh", "-c", "test -d $GC_RUNTIME_DIR && echo pre $GC_RUNTIME_DIR >> {result_path}" ]
+        run = [ "/bin/sh", "-c", "touch {{GC_RUNTIME_DIR}}/app.pid && echo run >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "test -f $GC_RUNTIME_DIR/app.pid && echo post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let runtime_dir = dir.path().join("app-run");
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        format!(
+            indoc! {r#"
+                pre {}
+                run
+                post
+                removed
+            "#},
+            runtime_dir.display()
+        ),
+        output
+    );
+}
+
+/// A runtime directory that already exists is refused (and left alone),
+/// since Ground Control removes the runtime directory when the process
+/// stops.
+#[test_log::test(tokio::test)]
+async fn existing_runtime_dir_is_refused() {
+    let config = r##"
+        [[processes]]
+        name = "app"
+        runtime-dir = "{temp_path}/app-run"
+        run = [ "/bin/sh", "-c", "echo run >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let runtime_dir = dir.path().join("app-run");
+    std::fs::create_dir(&runtime_dir).unwrap();
+    std::fs::write(runtime_dir.join("data"), "keep").unwrap();
+
+    let result = gc.await;
+    assert_startup_aborted(
+        &format!(
+            "Failed to create runtime directory \"{}\" for process \"app\"\nDirectory already exists\n",
+            runtime_dir.display()
+        ),
+        result,
+    );
+    assert_eq!(
+        "keep",
+        std::fs::read_to_string(runtime_dir.join("data")).unwrap()
+    );
+}
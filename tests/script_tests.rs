@@ -0,0 +1,58 @@
+//! Tests that verify inline scripts.
+
+use std::path::Path;
+
+use pretty_assertions::assert_eq;
+
+use crate::common::{start, stop};
+
+mod common;
+
+/// Inline scripts are written to a temporary file, executed with their
+/// interpreter, and removed once they exit.
+#[test_log::test(tokio::test)]
+async fn runs_inline_script() {
+    let config = r##"
+        env = { GREETING = "hello" }
+
+        [[processes]]
+        name = "script"
+        run = { script = """
+            #!/bin/sh
+            set -e
+            echo "$GREETING" >> {result_path}
+            echo "$0" >> {result_path}
+            """ }
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    let mut lines = output.lines();
+    assert_eq!(Some("hello"), lines.next());
+    let script_path = lines.next().unwrap();
+    assert!(!Path::new(script_path).exists());
+}
+
+/// Scripts are executable, and work with the other execution properties
+/// of detailed commands.
+#[test_log::test(tokio::test)]
+async fn inline_script_is_executable() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = { only-env = [], script = """
+            #!/bin/sh
+            test -x "$0" && echo executable >> {result_path}
+            """ }
+        post = [ "/bin/sh", "-c", "echo post >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!("executable\npost\n", output);
+}
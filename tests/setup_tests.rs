@@ -0,0 +1,124 @@
+//! Tests that verify the `[[setup]]` steps that run before any process
+//! is started.
+
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+use crate::common::{assert_startup_aborted, start, stop};
+
+mod common;
+
+/// Setup steps are performed in order before the first process starts.
+#[test_log::test(tokio::test)]
+async fn setup_steps_run_before_processes() {
+    std::env::set_var("SETUP_GREETING", "hello");
+
+    let config = r##"
+        [[setup]]
+        action = "directory"
+        path = "{temp_path}/data/app"
+        mode = "0750"
+
+        [[setup]]
+        action = "write"
+        path = "{temp_path}/data/app/greeting.txt"
+        contents = "{{SETUP_GREETING}} world\n"
+        mode = "0640"
+
+        [[setup]]
+        action = "copy"
+        source = "{temp_path}/data/app/greeting.txt"
+        path = "{temp_path}/data/app/copy.txt"
+
+        [[setup]]
+        action = "symlink"
+        path = "{temp_path}/current"
+        target = "{temp_path}/data/app"
+
+        [[processes]]
+        name = "check"
+        pre = [ "/bin/sh", "-c", "stat -c %a {temp_path}/data/app {temp_path}/current/greeting.txt >> {result_path} && cat {temp_path}/current/copy.txt >> {result_path}" ]
+        run = [ "/bin/sh", "-c", "echo daemon >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            750
+            640
+            hello world
+            daemon
+        "#},
+        output
+    );
+}
+
+/// A failed setup step aborts startup before any process is started.
+#[test_log::test(tokio::test)]
+async fn failed_setup_step_aborts_startup() {
+    let config = r##"
+        [[setup]]
+        action = "copy"
+        source = "{temp_path}/does-not-exist"
+        path = "{temp_path}/copy"
+
+        [[processes]]
+        name = "daemon"
+        pre = [ "/bin/sh", "-c", "echo pre >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let temp_path = dir.path().to_str().unwrap().to_string();
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        &format!(
+            indoc! {r#"
+                Failed to copy "{0}/does-not-exist" to "{0}/copy"
+                No such file or directory (os error 2)
+            "#},
+            temp_path
+        ),
+        result,
+    );
+
+    assert_eq!("", output);
+}
+
+/// The `render` action renders a template file using the environment
+/// variables (including those provided in the `env` table).
+#[test_log::test(tokio::test)]
+async fn render_template_file() {
+    let config = r##"
+        [env]
+        LISTEN_PORT = "8080"
+
+        [[setup]]
+        action = "render"
+        template = "{temp_path}/nginx.conf.template"
+        path = "{temp_path}/nginx.conf"
+
+        [[processes]]
+        name = "check"
+        run = [ "/bin/sh", "-c", "cat {temp_path}/nginx.conf >> {result_path}" ]
+        "##;
+
+    // Ground Control does not start until we wait for it to stop, so
+    // we can write the template file after creating the test directory.
+    let (gc, _tx, dir) = start(config).await;
+    tokio::fs::write(
+        dir.path().join("nginx.conf.template"),
+        "listen {{ LISTEN_PORT }};\n",
+    )
+    .await
+    .unwrap();
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!("listen 8080;\n", output);
+}
@@ -0,0 +1,154 @@
+//! Tests that verify the control socket.
+
+use std::{path::PathBuf, time::Duration};
+
+use groundcontrol::socket::{self, SocketCommand};
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+use crate::common::{spawn_daemon_waiter, start, stop};
+
+mod common;
+
+/// Daemons can be listed, stopped, started, and restarted through the
+/// control socket without shutting down Ground Control.
+#[test_log::test(tokio::test)]
+async fn daemons_controlled_through_socket() {
+    let config = r##"
+        control-socket = "{temp_path}/gc.sock"
+
+        [[processes]]
+        name = "setup"
+        pre = [ "/bin/sh", "-c", "echo setup >> {result_path}" ]
+
+        [[processes]]
+        name = "worker"
+        description = "Processes the job queue"
+        owner = "team-jobs"
+        run = [ "/bin/sh", "{test-daemon.sh}", "worker", "{result_path}", "{temp_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let socket_path: PathBuf = dir.path().join("gc.sock");
+    let worker_waiter = spawn_daemon_waiter(&dir, "worker");
+    let pid_path = dir.path().join("worker.pid");
+    let client = tokio::task::spawn(async move {
+        let pid = worker_waiter.await.unwrap();
+
+        let status = socket::request(&socket_path, &SocketCommand::Status)
+            .await
+            .unwrap();
+        let lines: Vec<Vec<&str>> = status
+            .lines()
+            .map(|line| line.split('\t').collect())
+            .collect();
+        assert_eq!(2, lines.len());
+        assert_eq!(["setup", "active", "-", "-", "-", "-"], lines[0].as_slice());
+        assert_eq!(["worker", "running"], lines[1][..2]);
+        assert_eq!(pid.to_string(), lines[1][2]);
+        assert_eq!(["team-jobs", "Processes the job queue"], lines[1][4..]);
+
+        let stopped = socket::request(&socket_path, &SocketCommand::Stop("worker".into()))
+            .await
+            .unwrap();
+        assert_eq!("Stopped process \"worker\"\n", stopped);
+        let status = socket::request(&socket_path, &SocketCommand::Status)
+            .await
+            .unwrap();
+        assert!(status.contains("worker\tstopped\t-\t-\tteam-jobs\t"));
+
+        let err = socket::request(&socket_path, &SocketCommand::Stop("setup".into()))
+            .await
+            .unwrap_err();
+        assert_eq!("Process \"setup\" has no daemon", err.to_string());
+
+        // Wait for the daemon to write its PID file (and thus install
+        // its signal handlers) after every start.
+        for command in [
+            SocketCommand::Start("worker".into()),
+            SocketCommand::Restart("worker".into()),
+        ] {
+            tokio::fs::remove_file(&pid_path).await.unwrap();
+            socket::request(&socket_path, &command).await.unwrap();
+            while !pid_path.exists() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+    client.await.unwrap();
+
+    assert!(result.is_ok());
+    assert_eq!(
+        indoc! {r#"
+            setup
+            worker:started
+            worker:shutdown-requested
+            worker:stopped
+            worker:started
+            worker:shutdown-requested
+            worker:stopped
+            worker:started
+            worker:shutdown-requested
+            worker:stopped
+        "#},
+        output
+    );
+}
+
+/// Restarting a one-shot process runs its `pre` command again (unless
+/// its success marker exists), but one-shot processes cannot be stopped
+/// or started.
+#[test_log::test(tokio::test)]
+async fn one_shot_restarted_through_socket() {
+    let config = r##"
+        control-socket = "{temp_path}/gc.sock"
+
+        [[processes]]
+        name = "setup"
+        pre = [ "/bin/sh", "-c", "echo setup >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo setup-post >> {result_path}" ]
+
+        [[processes]]
+        name = "migrate"
+        pre = [ "/bin/sh", "-c", "echo migrate >> {result_path}" ]
+        success-marker = "{temp_path}/migrated"
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let socket_path: PathBuf = dir.path().join("gc.sock");
+    let client = tokio::task::spawn(async move {
+        for name in ["setup", "migrate"] {
+            let restarted = socket::request(&socket_path, &SocketCommand::Restart(name.into()))
+                .await
+                .unwrap();
+            assert_eq!(format!("Restarted process \"{name}\"\n"), restarted);
+        }
+
+        let err = socket::request(&socket_path, &SocketCommand::Stop("setup".into()))
+            .await
+            .unwrap_err();
+        assert_eq!("Process \"setup\" has no daemon", err.to_string());
+
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+    client.await.unwrap();
+
+    assert!(result.is_ok());
+    assert_eq!(
+        indoc! {r#"
+            setup
+            migrate
+            setup
+            setup-post
+        "#},
+        output
+    );
+}
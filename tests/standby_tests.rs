@@ -0,0 +1,80 @@
+//! Tests that verify standby processes, which are started when their
+//! primary daemon fails.
+
+use groundcontrol::ShutdownReason;
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+use crate::common::{spawn_daemon_waiter, start, stop};
+
+mod common;
+
+/// The standby process is not started until the primary fails, at
+/// which point the standby takes over (instead of Ground Control
+/// shutting down).
+#[test_log::test(tokio::test)]
+async fn standby_replaces_failed_primary() {
+    let config = r##"
+        [[processes]]
+        name = "primary"
+        run = [ "/bin/sh", "-c", "echo primary >> {result_path} && exit 1" ]
+        post = [ "/bin/sh", "-c", "echo primary-post >> {result_path}" ]
+
+        [[processes]]
+        name = "standby"
+        standby-for = "primary"
+        pre = [ "/bin/sh", "-c", "echo standby-pre >> {result_path}" ]
+        run = [ "/bin/sh", "{test-daemon.sh}", "standby", "{result_path}", "{temp_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "standby");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert_eq!(ShutdownReason::GracefulShutdown, result.unwrap());
+
+    assert_eq!(
+        indoc! {r#"
+            primary
+            standby-pre
+            standby:started
+            standby:shutdown-requested
+            standby:stopped
+            primary-post
+        "#},
+        output
+    );
+}
+
+/// Standby processes are only started when the primary *fails*; a
+/// clean exit of the primary shuts down Ground Control as usual.
+#[test_log::test(tokio::test)]
+async fn standby_not_started_on_clean_exit() {
+    let config = r##"
+        [[processes]]
+        name = "primary"
+        run = [ "/bin/sh", "-c", "echo primary >> {result_path}" ]
+
+        [[processes]]
+        name = "standby"
+        standby-for = "primary"
+        run = [ "/bin/sh", "-c", "echo standby >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_eq!(
+        ShutdownReason::DaemonExited {
+            process: "primary".into()
+        },
+        result.unwrap()
+    );
+    assert_eq!("primary\n", output);
+}
@@ -2,6 +2,8 @@
 //! "startup" is defined as the process of getting all long-running
 //! processes into their started state).
 
+use groundcontrol::{ShutdownReason, StartupFailure};
+
 use crate::common::{spawn_daemon_waiter, start, stop};
 
 mod common;
@@ -28,7 +30,12 @@ async fn single_daemon_success() {
 
     let (gc, _tx, dir) = start(config).await;
     let (result, output) = stop(gc, dir).await;
-    assert!(result.is_ok());
+    assert_eq!(
+        ShutdownReason::DaemonExited {
+            process: "daemon".into()
+        },
+        result.unwrap()
+    );
     assert_eq!("daemon\n", output);
 }
 
@@ -55,13 +62,53 @@ async fn single_daemon_graceful_shutdown() {
     let (result, output) = stop(gc, dir).await;
 
     // This should result in a controlled shutdown.
-    assert!(result.is_ok());
+    assert_eq!(ShutdownReason::GracefulShutdown, result.unwrap());
     assert_eq!(
         "daemon:started\ndaemon:shutdown-requested\ndaemon:stopped\n",
         output
     );
 }
 
+/// A shutdown that is requested during startup stops Ground Control from
+/// starting any more processes: the process that is being started when
+/// the request arrives finishes starting, and then every process that
+/// was started is stopped (in reverse order).
+#[test_log::test(tokio::test)]
+async fn shutdown_during_startup() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+
+        [[processes]]
+        name = "slow"
+        pre = [ "/bin/sh", "-c", "/bin/sh {wait-daemon-start.sh} daemon {temp_path} && sleep 1 && echo slow:pre >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo slow:post >> {result_path}" ]
+
+        [[processes]]
+        name = "never"
+        pre = [ "/bin/sh", "-c", "echo never:pre >> {result_path}" ]
+        "##;
+
+    // Request the shutdown as soon as the daemon has started, which is
+    // while the "slow" process is still starting.
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert_eq!(ShutdownReason::GracefulShutdown, result.unwrap());
+    assert_eq!(
+        "daemon:started\nslow:pre\nslow:post\ndaemon:shutdown-requested\ndaemon:stopped\n",
+        output
+    );
+}
+
 /// Basic daemon failure test: starts a single daemon and expects it to
 /// fail during startup (which happens because we do *not* provide any
 /// arguments to the `test-daemon.sh` script).
@@ -84,7 +131,217 @@ async fn single_daemon_failure() {
     let (result, output) = stop(gc, dir).await;
     assert!(matches!(
         result,
-        Err(groundcontrol::Error::AbnormalShutdown)
+        Err(groundcontrol::Error::AbnormalShutdown(reasons)) if reasons == [ShutdownReason::DaemonFailed {
+            process: "daemon".into(),
+            exit_code: Some(2),
+            signal: None,
+        }]
     ));
     assert_eq!("", output);
 }
+
+/// Multiple daemons that fail at (nearly) the same time are all
+/// reported in the shutdown error, not just the first one to exit.
+#[test_log::test(tokio::test)]
+async fn multiple_daemon_failures() {
+    let config = r##"
+        [[processes]]
+        name = "daemon1"
+        run = [ "/bin/sh", "-c", "touch {temp_path}/daemon1 && exit 1" ]
+
+        [[processes]]
+        name = "daemon2"
+        run = [ "/bin/sh", "-c", "touch {temp_path}/daemon2 && exit 2" ]
+
+        # Wait for both daemons to fail before completing startup (the
+        # short sleep gives Ground Control time to observe both exits).
+        [[processes]]
+        name = "wait-daemons"
+        pre = [ "/bin/sh", "-c", "while [ ! -f {temp_path}/daemon1 ] || [ ! -f {temp_path}/daemon2 ]; do sleep 0; done; sleep 0.5" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+    match result {
+        Err(groundcontrol::Error::AbnormalShutdown(mut reasons)) => {
+            reasons.sort_by_key(|reason| format!("{reason:?}"));
+            assert_eq!(
+                vec![
+                    ShutdownReason::DaemonFailed {
+                        process: "daemon1".into(),
+                        exit_code: Some(1),
+                        signal: None,
+                    },
+                    ShutdownReason::DaemonFailed {
+                        process: "daemon2".into(),
+                        exit_code: Some(2),
+                        signal: None,
+                    },
+                ],
+                reasons
+            );
+        }
+        Ok(_) | Err(_) => panic!("Expected AbnormalShutdown error."),
+    };
+    assert_eq!("", output);
+}
+
+/// A `run` command that fails to start with a transient error (here,
+/// `ETXTBSY`, because the binary is still open for writing) is retried
+/// instead of aborting startup.
+#[test_log::test(tokio::test)]
+async fn transient_spawn_failure_is_retried() {
+    let config = r##"
+        [[processes]]
+        name = "app"
+        pre = [ "/bin/sh", "-c", "cp /bin/sh {temp_path}/app-sh && (exec 3>>{temp_path}/app-sh; touch {temp_path}/writing; sleep 0.15) >/dev/null 2>&1 & while [ ! -f {temp_path}/writing ]; do sleep 0; done" ]
+        run = [ "{temp_path}/app-sh", "-c", "echo app >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!("app\n", output);
+}
+
+/// A failed startup can be described in a structured form, which
+/// identifies the process (and its owner), phase, and exit code of the
+/// failed command.
+#[test_log::test(tokio::test)]
+async fn startup_failure_describes_failed_command() {
+    let config = r##"
+        [[processes]]
+        name = "a"
+        run = [ "/bin/sh", "{test-daemon.sh}", "a", "{result_path}", "{temp_path}" ]
+
+        # Wait for the daemon to start (so that it has trapped SIGTERM
+        # before it is stopped), which makes the output predictable.
+        [[processes]]
+        name = "wait-daemon-start"
+        pre = [ "/bin/sh", "{wait-daemon-start.sh}", "a", "{temp_path}" ]
+
+        [[processes]]
+        name = "b"
+        description = "Serves the public API"
+        owner = "team-api"
+        pre = [ "/bin/sh", "-c", "exit 3" ]
+        run = [ "/bin/sh", "-c", "echo b >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_eq!(
+        Some(StartupFailure {
+            process: Some("b".into()),
+            owner: Some("team-api".into()),
+            description: Some("Serves the public API".into()),
+            phase: Some("pre".into()),
+            exit_code: Some(3),
+            signal: None,
+            message: r#"`pre` command failed for process "b" (exit code 3)"#.into(),
+        }),
+        result.unwrap_err().startup_failure()
+    );
+    assert_eq!("a:started\na:shutdown-requested\na:stopped\n", output);
+}
+
+/// A `run` command that cannot be started is described as a failure of
+/// the `run` phase (without an exit code).
+#[test_log::test(tokio::test)]
+async fn startup_failure_describes_unstartable_run_command() {
+    let config = r##"
+        [[processes]]
+        name = "app"
+        run = [ "{temp_path}/does-not-exist" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, _output) = stop(gc, dir).await;
+
+    let failure = result.unwrap_err().startup_failure().unwrap();
+    assert_eq!(Some("app"), failure.process.as_deref());
+    assert_eq!(Some("run"), failure.phase.as_deref());
+    assert_eq!(None, failure.exit_code);
+    assert!(failure
+        .message
+        .starts_with(r#"`run` command failed for process "app": "#));
+}
+
+/// Failures that are not failed commands (such as a `wait-for` condition
+/// that times out) still identify the process and the step of its
+/// startup that failed.
+#[test_log::test(tokio::test)]
+async fn startup_failure_describes_failed_wait_for() {
+    let config = r##"
+        [[processes]]
+        name = "mount"
+        owner = "team-storage"
+        wait-for = { mount = "{temp_path}", timeout = "1s" }
+        pre = [ "/bin/sh", "-c", "echo mount >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let temp_path = dir.path().display().to_string();
+    let (result, output) = stop(gc, dir).await;
+
+    assert_eq!(
+        Some(StartupFailure {
+            process: Some("mount".into()),
+            owner: Some("team-storage".into()),
+            description: None,
+            phase: Some("wait-for".into()),
+            exit_code: None,
+            signal: None,
+            message: format!(
+                r#"Timed out after 1s waiting for "{temp_path}" to be mounted (process "mount")"#
+            ),
+        }),
+        result.unwrap_err().startup_failure()
+    );
+    assert_eq!("", output);
+}
+
+/// `--startup-profile` writes the startup time of every process, along
+/// with the critical path of dependencies, once startup has completed.
+#[cfg(feature = "cli")]
+#[test_log::test(tokio::test)]
+async fn startup_profile_records_critical_path() {
+    let config = r##"
+        [[processes]]
+        name = "certs"
+        pre = "/bin/true"
+
+        [[processes]]
+        name = "migrate"
+        pre = [ "/bin/sleep", "0.2" ]
+
+        [[processes]]
+        name = "app"
+        depends-on = [ "migrate" ]
+        run = [ "/bin/sh", "-c", "echo app >> {result_path}" ]
+        "##;
+
+    let profile_dir = tempfile::TempDir::new().unwrap();
+    let profile_path = profile_dir.path().join("profile.json");
+
+    let (mut config, dir) = common::prepare(config).await;
+    config.startup_profile = Some(profile_path.clone());
+
+    let (_tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let gc = groundcontrol::run(config, rx);
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(result, Ok(ShutdownReason::DaemonExited { .. })));
+    assert_eq!("app\n", output);
+
+    let profile = std::fs::read_to_string(profile_path).unwrap();
+    for field in ["\"total-ms\"", "\"name\": \"certs\"", "\"duration-ms\""] {
+        assert!(profile.contains(field), "{field} missing from {profile}");
+    }
+    assert!(
+        profile.contains("\"critical-path\": [\n    \"migrate\",\n    \"app\"\n  ]"),
+        "{profile}"
+    );
+}
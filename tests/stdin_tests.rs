@@ -0,0 +1,64 @@
+//! Tests that verify per-process standard input.
+
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+use crate::common::{start, stop};
+
+mod common;
+
+/// Standard input is read from the configured file by the `pre` command
+/// of a one-shot process and the `run` command of a daemon process;
+/// every other command gets an empty standard input.
+#[test_log::test(tokio::test)]
+async fn stdin_from_file() {
+    let config = r##"
+        [[processes]]
+        name = "oneshot"
+        stdin = { file = "{temp_path}/input.txt" }
+        pre = [ "/bin/sh", "-c", "sed 's/^/oneshot: /' >> {result_path}" ]
+
+        [[processes]]
+        name = "daemon"
+        stdin = { file = "{temp_path}/input.txt" }
+        pre = [ "/bin/sh", "-c", "cat >> {result_path}; echo pre >> {result_path}" ]
+        run = [ "/bin/sh", "-c", "sed 's/^/daemon: /' >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    tokio::fs::write(dir.path().join("input.txt"), "seed input\n")
+        .await
+        .unwrap();
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    assert_eq!(
+        indoc! {r#"
+            oneshot: seed input
+            pre
+            daemon: seed input
+        "#},
+        output
+    );
+}
+
+/// A missing stdin file prevents the process from starting.
+#[test_log::test(tokio::test)]
+async fn missing_stdin_file_aborts_startup() {
+    let config = r##"
+        [[processes]]
+        name = "oneshot"
+        stdin = { file = "{temp_path}/missing.txt" }
+        pre = [ "/bin/sh", "-c", "echo oneshot >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::StartupAborted(_))
+    ));
+    assert_eq!("", output);
+}
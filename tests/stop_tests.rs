@@ -1,7 +1,15 @@
 //! Tests the verify different aspects of the `stop` configurations that
 //! stop long-running daemons.
 
+use std::time::{Duration, Instant};
+
 use indoc::indoc;
+use nix::sys::signal::{kill, Signal};
+use pretty_assertions::assert_eq;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
 
 use crate::common::{spawn_daemon_waiter, start, stop};
 
@@ -129,12 +137,11 @@ async fn stop_command() {
 /// a normal thing to do and should not be replicated outside of testing
 /// scenarios)
 ///
-/// Note that Ground Control *could* be changed to kill every processes
-/// in the daemon's process group as an attempt to clean up after this
-/// situation. *But,* it is also worth noting that this is issue is
-/// effectively a bug in the Ground Control specification, since `stop`
-/// commands should not fail in their *attempt* to shut down their
-/// target daemon.
+/// Set `on-stop-failure = "kill-group"` to have Ground Control kill
+/// every process in the daemon's process group in this situation. *But,*
+/// it is also worth noting that this is issue is effectively a bug in
+/// the Ground Control specification, since `stop` commands should not
+/// fail in their *attempt* to shut down their target daemon.
 #[test_log::test(tokio::test)]
 async fn failed_stop_command_continues_shutdown() {
     let config = r##"
@@ -207,12 +214,11 @@ async fn failed_stop_command_continues_shutdown() {
 /// a normal thing to do and should not be replicated outside of testing
 /// scenarios)
 ///
-/// Note that Ground Control *could* be changed to kill every processes
-/// in the daemon's process group as an attempt to clean up after this
-/// situation. *But,* it is also worth noting that this is issue is
-/// effectively a bug in the Ground Control specification, since `stop`
-/// commands should not fail in their *attempt* to shut down their
-/// target daemon.
+/// Set `on-stop-failure = "kill-group"` to have Ground Control kill
+/// every process in the daemon's process group in this situation. *But,*
+/// it is also worth noting that this is issue is effectively a bug in
+/// the Ground Control specification, since `stop` commands should not
+/// fail in their *attempt* to shut down their target daemon.
 #[test_log::test(tokio::test)]
 async fn killed_stop_command_continues_shutdown() {
     let config = r##"
@@ -285,12 +291,11 @@ async fn killed_stop_command_continues_shutdown() {
 /// a normal thing to do and should not be replicated outside of testing
 /// scenarios)
 ///
-/// Note that Ground Control *could* be changed to kill every processes
-/// in the daemon's process group as an attempt to clean up after this
-/// situation. *But,* it is also worth noting that this is issue is
-/// effectively a bug in the Ground Control specification, since `stop`
-/// commands should not fail in their *attempt* to shut down their
-/// target daemon.
+/// Set `on-stop-failure = "kill-group"` to have Ground Control kill
+/// every process in the daemon's process group in this situation. *But,*
+/// it is also worth noting that this is issue is effectively a bug in
+/// the Ground Control specification, since `stop` commands should not
+/// fail in their *attempt* to shut down their target daemon.
 #[test_log::test(tokio::test)]
 async fn not_found_stop_command_continues_shutdown() {
     let config = r##"
@@ -351,3 +356,422 @@ async fn not_found_stop_command_continues_shutdown() {
         output
     );
 }
+
+/// `on-stop-failure = "kill-group"` kills the daemon (and everything
+/// else in its process group) with `SIGKILL` if the `stop` command
+/// fails, then continues the shutdown as usual.
+#[test_log::test(tokio::test)]
+async fn failed_stop_command_kills_group() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        stop = [ "/bin/sh", "-c", "exit 1" ]
+        on-stop-failure = "kill-group"
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+
+    // The daemon never saw a `SIGTERM`, so it did not log its graceful
+    // shutdown.
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon-post
+        "#},
+        output
+    );
+}
+
+/// `on-stop-failure = "abort"` stops the shutdown as soon as the `stop`
+/// command fails: neither the daemon's `post` command nor any of the
+/// remaining processes are run/stopped, and Ground Control returns an
+/// error.
+#[test_log::test(tokio::test)]
+async fn failed_stop_command_aborts_shutdown() {
+    let config = r##"
+        [[processes]]
+        name = "one-shot"
+        post = [ "/bin/sh", "-c", "echo one-shot-post >> {result_path}" ]
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        # Kill the daemon so that the test does not leave it running,
+        # but still fail the `stop` command.
+        stop = [ "/bin/sh", "-c", "kill -KILL `cat {temp_path}/daemon.pid`; exit 1" ]
+        on-stop-failure = "abort"
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::ShutdownAborted(_))
+    ));
+    assert_eq!(
+        groundcontrol::exit_code::SHUTDOWN_ABORTED,
+        result.unwrap_err().exit_code()
+    );
+    assert_eq!("daemon:started\n", output);
+}
+
+/// Daemons that exit on their own while Ground Control is shutting down
+/// are not stopped (their `stop` command is not run), but their `post`
+/// command is still run.
+#[test_log::test(tokio::test)]
+async fn exited_daemon_is_not_stopped() {
+    let config = r##"
+        [[processes]]
+        name = "daemon1"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon1", "{result_path}", "{temp_path}" ]
+        stop = [ "/bin/sh", "-c", "echo daemon1-stop >> {result_path}" ]
+        post = [ "/bin/sh", "-c", "echo daemon1-post >> {result_path}" ]
+
+        [[processes]]
+        name = "wait-daemon1-start"
+        pre = [ "/bin/sh", "{wait-daemon-start.sh}", "daemon1", "{temp_path}" ]
+
+        [[processes]]
+        name = "daemon2"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon2", "{result_path}", "{temp_path}" ]
+        # Shut down daemon1 (and wait for it to exit) while Ground Control
+        # is stopping daemon2.
+        post = [ "/bin/sh", "-c", "PID=`cat {temp_path}/daemon1.pid`; kill -TERM $PID; while kill -0 $PID 2>/dev/null; do sleep 0.05; done" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon2");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!(
+        indoc! {r#"
+            daemon1:started
+            daemon2:started
+            daemon2:shutdown-requested
+            daemon2:stopped
+            daemon1:shutdown-requested
+            daemon1:stopped
+            daemon1-post
+        "#},
+        output
+    );
+}
+
+/// `stop` can be an HTTP request to the daemon's admin endpoint, which
+/// asks the daemon to quiesce. Here, the endpoint stops the daemon with
+/// SIGINT (so that the daemon does not log the shutdown message that it
+/// would log if it received the fallback SIGTERM).
+#[test_log::test(tokio::test)]
+async fn http_stop_quiesces_daemon() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let config = format!(
+        r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{{test-daemon.sh}}", "daemon", "{{result_path}}", "{{temp_path}}" ]
+        stop = {{ http = "http://{addr}/quitquitquit", then-signal = "SIGTERM" }}
+        post = [ "/bin/sh", "-c", "echo daemon-post >> {{result_path}}" ]
+        "##
+    );
+
+    let (gc, tx, dir) = start(&config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    let admin = tokio::task::spawn(async move {
+        let daemon_pid = daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await.unwrap();
+
+        kill(daemon_pid, Signal::SIGINT).unwrap();
+        reader
+            .into_inner()
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .await
+            .unwrap();
+
+        request_line
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!("POST /quitquitquit HTTP/1.1\r\n", admin.await.unwrap());
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon-post
+        "#},
+        output
+    );
+}
+
+/// The `then-signal` is sent to the daemon if the quiesce request fails
+/// (here, because nothing is listening on the port).
+#[test_log::test(tokio::test)]
+async fn failed_http_stop_sends_signal() {
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let config = format!(
+        r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{{test-daemon.sh}}", "daemon", "{{result_path}}", "{{temp_path}}" ]
+        stop = {{ http = "http://{addr}/quitquitquit" }}
+        "##
+    );
+
+    let (gc, tx, dir) = start(&config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon:shutdown-requested
+            daemon:stopped
+        "#},
+        output
+    );
+}
+
+/// `pre-stop-delay` waits before stopping the daemon (which gives load
+/// balancers time to take the daemon out of rotation).
+#[test_log::test(tokio::test)]
+async fn pre_stop_delay_waits_before_stopping() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        pre-stop-delay = "500ms"
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    let shutdown_requested = tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+        Instant::now()
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert!(shutdown_requested.await.unwrap().elapsed() >= Duration::from_millis(500));
+    assert_eq!(
+        indoc! {r#"
+            daemon:started
+            daemon:shutdown-requested
+            daemon:stopped
+        "#},
+        output
+    );
+}
+
+/// `shutdown-timeout` bounds the entire shutdown: once it expires, the
+/// daemons that have not yet been stopped are killed (including the one
+/// whose `stop` command is hanging) and Ground Control returns an error.
+#[test_log::test(tokio::test)]
+async fn shutdown_timeout_kills_remaining_daemons() {
+    let config = r##"
+        shutdown-timeout = "1s"
+
+        [[processes]]
+        name = "daemon1"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon1", "{result_path}", "{temp_path}" ]
+        post = [ "/bin/sh", "-c", "echo daemon1-post >> {result_path}" ]
+
+        [[processes]]
+        name = "daemon2"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon2", "{result_path}", "{temp_path}" ]
+        stop = [ "/bin/sh", "-c", "exec sleep 30" ]
+        post = [ "/bin/sh", "-c", "echo daemon2-post >> {result_path}" ]
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let daemon1_waiter = spawn_daemon_waiter(&dir, "daemon1");
+    let daemon2_waiter = spawn_daemon_waiter(&dir, "daemon2");
+    tokio::task::spawn(async move {
+        daemon1_waiter.await.unwrap();
+        daemon2_waiter.await.unwrap();
+        tx.send(()).unwrap();
+    });
+
+    let started = Instant::now();
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(started.elapsed() < Duration::from_secs(10));
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::ShutdownAborted(_))
+    ));
+    assert_eq!(
+        groundcontrol::exit_code::SHUTDOWN_TIMEOUT,
+        result.unwrap_err().exit_code()
+    );
+
+    // Neither daemon was asked to shut down gracefully, and neither
+    // `post` command was run.
+    assert_eq!(
+        indoc! {r#"
+            daemon1:started
+            daemon2:started
+        "#},
+        output
+    );
+}
+
+/// `shutdown-class` kills the daemons that take too long to stop (after
+/// a second for best-effort daemons, and before the last half of the
+/// `shutdown-timeout` for normal daemons), which leaves the rest of the
+/// time for the critical daemons, and the shutdown continues.
+#[test_log::test(tokio::test)]
+async fn shutdown_class_preserves_time_for_critical_daemons() {
+    let config = r##"
+        shutdown-timeout = "4s"
+
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "{test-daemon.sh}", "db", "{result_path}", "{temp_path}" ]
+        shutdown-class = "critical"
+
+        [[processes]]
+        name = "worker"
+        run = [ "/bin/sh", "-c", "trap '' TERM; echo $$ > {temp_path}/worker.pid; while true; do sleep 0.1; done" ]
+        post = [ "/bin/sh", "-c", "echo worker-post >> {result_path}" ]
+
+        [[processes]]
+        name = "cache"
+        run = [ "/bin/sh", "-c", "trap '' TERM; echo $$ > {temp_path}/cache.pid; while true; do sleep 0.1; done" ]
+        post = [ "/bin/sh", "-c", "echo cache-post >> {result_path}" ]
+        shutdown-class = "best-effort"
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let db_waiter = spawn_daemon_waiter(&dir, "db");
+    let worker_waiter = spawn_daemon_waiter(&dir, "worker");
+    let cache_waiter = spawn_daemon_waiter(&dir, "cache");
+    let shutdown_requested = tokio::task::spawn(async move {
+        db_waiter.await.unwrap();
+        worker_waiter.await.unwrap();
+        cache_waiter.await.unwrap();
+        tx.send(()).unwrap();
+        Instant::now()
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    // The cache is killed after a second, and the worker once half of
+    // the `shutdown-timeout` has passed.
+    let elapsed = shutdown_requested.await.unwrap().elapsed();
+    assert!(elapsed >= Duration::from_secs(2));
+    assert!(elapsed < Duration::from_secs(4));
+    assert_eq!(
+        groundcontrol::ShutdownReason::GracefulShutdown,
+        result.unwrap()
+    );
+    assert_eq!(
+        indoc! {r#"
+            db:started
+            cache-post
+            worker-post
+            db:shutdown-requested
+            db:stopped
+        "#},
+        output
+    );
+}
+
+/// Quiesce requests can be sent to IPv6 addresses (which are enclosed in
+/// brackets in the URL).
+#[test_log::test(tokio::test)]
+async fn http_stop_over_ipv6() {
+    let listener = TcpListener::bind("[::1]:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let config = format!(
+        r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{{test-daemon.sh}}", "daemon", "{{result_path}}", "{{temp_path}}" ]
+        stop = {{ http = "http://{addr}/quitquitquit", then-signal = "SIGTERM" }}
+        "##
+    );
+
+    let (gc, tx, dir) = start(&config).await;
+
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    let admin = tokio::task::spawn(async move {
+        let daemon_pid = daemon_waiter.await.unwrap();
+        tx.send(()).unwrap();
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await.unwrap();
+        let mut host_line = String::new();
+        reader.read_line(&mut host_line).await.unwrap();
+
+        kill(daemon_pid, Signal::SIGINT).unwrap();
+        reader
+            .into_inner()
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .await
+            .unwrap();
+
+        host_line
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!(format!("Host: {addr}\r\n"), admin.await.unwrap());
+    assert_eq!("daemon:started\n", output);
+}
@@ -0,0 +1,64 @@
+//! Tests that verify one-shot success markers.
+
+use pretty_assertions::assert_eq;
+
+use crate::common::{start, stop};
+
+mod common;
+
+/// The `pre` command of a one-shot process with a success marker only
+/// runs until it succeeds once; later starts skip it (but still run the
+/// process's `post` command).
+#[test_log::test(tokio::test)]
+async fn success_marker_skips_pre_after_success() {
+    let marker_dir = tempfile::TempDir::new().unwrap();
+    let marker = marker_dir.path().join("state/migrated-v42");
+    let config = format!(
+        r##"
+        [[processes]]
+        name = "migrate"
+        pre = [ "/bin/sh", "-c", "echo pre >> {{result_path}}" ]
+        post = [ "/bin/sh", "-c", "echo post >> {{result_path}}" ]
+        success-marker = "{}"
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo daemon >> {{result_path}}" ]
+        "##,
+        marker.display()
+    );
+
+    let (gc, _tx, dir) = start(&config).await;
+    let (result, output) = stop(gc, dir).await;
+    assert!(result.is_ok());
+    assert_eq!("pre\ndaemon\npost\n", output);
+    assert!(marker.is_file());
+
+    let (gc, _tx, dir) = start(&config).await;
+    let (result, output) = stop(gc, dir).await;
+    assert!(result.is_ok());
+    assert_eq!("daemon\npost\n", output);
+}
+
+/// Failed `pre` commands do not create the success marker, and so are
+/// run again on the next start.
+#[test_log::test(tokio::test)]
+async fn failed_pre_does_not_create_success_marker() {
+    let marker_dir = tempfile::TempDir::new().unwrap();
+    let marker = marker_dir.path().join("migrated");
+    let config = format!(
+        r##"
+        [[processes]]
+        name = "migrate"
+        pre = [ "/bin/sh", "-c", "echo pre >> {{result_path}}; exit 1" ]
+        success-marker = "{}"
+        "##,
+        marker.display()
+    );
+
+    let (gc, _tx, dir) = start(&config).await;
+    let (result, output) = stop(gc, dir).await;
+    assert!(result.is_err());
+    assert_eq!("pre\n", output);
+    assert!(!marker.exists());
+}
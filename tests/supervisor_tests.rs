@@ -0,0 +1,294 @@
+//! Tests that verify running multiple specs in one supervisor.
+
+use std::time::Duration;
+
+use groundcontrol::{history::ExitSnapshot, ShutdownReason, Supervisor};
+use pretty_assertions::assert_eq;
+use tempfile::TempDir;
+
+use crate::common::{prepare, process_state, spawn_daemon_waiter, stop};
+
+mod common;
+
+/// Every spec runs concurrently, and shutting down one spec does not
+/// affect the others.
+#[test_log::test(tokio::test)]
+async fn specs_shut_down_independently() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "{test-daemon.sh}", "daemon", "{result_path}", "{temp_path}" ]
+        "##;
+
+    let (config_a, dir_a) = prepare(config).await;
+    let (config_b, dir_b) = prepare(config).await;
+    let daemon_a = spawn_daemon_waiter(&dir_a, "daemon");
+    let daemon_b = spawn_daemon_waiter(&dir_b, "daemon");
+
+    let mut supervisor = Supervisor::new();
+    supervisor.spawn_spec("tenant-a", config_a).unwrap();
+    supervisor.spawn_spec("tenant-b", config_b).unwrap();
+    assert_eq!(vec!["tenant-a", "tenant-b"], supervisor.spec_names());
+
+    daemon_a.await.unwrap();
+    daemon_b.await.unwrap();
+
+    assert!(supervisor.shutdown_spec("tenant-a"));
+    let (result, output) = stop(
+        async { supervisor.wait_spec("tenant-a").await.unwrap() },
+        dir_a,
+    )
+    .await;
+    assert_eq!(ShutdownReason::GracefulShutdown, result.unwrap());
+    assert_eq!(
+        "daemon:started\ndaemon:shutdown-requested\ndaemon:stopped\n",
+        output
+    );
+
+    // The other spec is still running until the supervisor shuts down.
+    assert_eq!(vec!["tenant-b"], supervisor.spec_names());
+    let (result, output) = stop(
+        async {
+            let mut results = supervisor.shutdown().await;
+            assert_eq!(1, results.len());
+            let (name, result) = results.remove(0);
+            assert_eq!("tenant-b", name);
+            result
+        },
+        dir_b,
+    )
+    .await;
+    assert_eq!(ShutdownReason::GracefulShutdown, result.unwrap());
+    assert_eq!(
+        "daemon:started\ndaemon:shutdown-requested\ndaemon:stopped\n",
+        output
+    );
+}
+
+/// The `env` table of each spec only applies to that spec's processes
+/// (and not to the shared process environment).
+#[test_log::test(tokio::test)]
+async fn spec_env_is_not_shared() {
+    let config = |tenant: &str| {
+        format!(
+            r##"
+            [env]
+            GC_TEST_TENANT = "{tenant}"
+
+            [[processes]]
+            name = "env"
+            pre = [ "/bin/sh", "-c", "echo $GC_TEST_TENANT >> {{result_path}}" ]
+            run = [ "/bin/sh", "{{test-daemon.sh}}", "daemon", "{{result_path}}", "{{temp_path}}" ]
+            "##
+        )
+    };
+
+    let (config_a, dir_a) = prepare(&config("a")).await;
+    let (config_b, dir_b) = prepare(&config("b")).await;
+    let daemon_a = spawn_daemon_waiter(&dir_a, "daemon");
+    let daemon_b = spawn_daemon_waiter(&dir_b, "daemon");
+
+    let mut supervisor = Supervisor::new();
+    supervisor.spawn_spec("tenant-a", config_a).unwrap();
+    supervisor.spawn_spec("tenant-b", config_b).unwrap();
+
+    daemon_a.await.unwrap();
+    daemon_b.await.unwrap();
+    assert!(std::env::var("GC_TEST_TENANT").is_err());
+
+    assert!(supervisor.shutdown_spec("tenant-a"));
+    let (result, output) = stop(
+        async { supervisor.wait_spec("tenant-a").await.unwrap() },
+        dir_a,
+    )
+    .await;
+    assert_eq!(ShutdownReason::GracefulShutdown, result.unwrap());
+    assert_eq!(
+        "a\ndaemon:started\ndaemon:shutdown-requested\ndaemon:stopped\n",
+        output
+    );
+
+    let (result, output) = stop(async { supervisor.shutdown().await.remove(0).1 }, dir_b).await;
+    assert_eq!(ShutdownReason::GracefulShutdown, result.unwrap());
+    assert_eq!(
+        "b\ndaemon:started\ndaemon:shutdown-requested\ndaemon:stopped\n",
+        output
+    );
+}
+
+/// Spec names must be unique, and specs cannot forward signals (which
+/// are delivered to the whole process).
+#[test_log::test(tokio::test)]
+async fn rejects_conflicting_specs() {
+    let (config, dir) = prepare(
+        r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo run >> {result_path}" ]
+        "##,
+    )
+    .await;
+    let (forwarding, _forwarding_dir) = prepare(
+        r##"
+        forward-signals = true
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sleep", "1" ]
+        "##,
+    )
+    .await;
+
+    let mut supervisor = Supervisor::new();
+    supervisor.spawn_spec("tenant", config.clone()).unwrap();
+    assert_spawn_error(
+        "Spec \"tenant\" is already running",
+        supervisor.spawn_spec("tenant", config),
+    );
+    assert_spawn_error(
+        "Spec \"forwarding\" uses `forward-signals`, which is not supported when running multiple specs",
+        supervisor.spawn_spec("forwarding", forwarding),
+    );
+    assert_eq!(vec!["tenant"], supervisor.spec_names());
+
+    let (result, output) = stop(async { supervisor.wait_spec("tenant").await.unwrap() }, dir).await;
+    assert_eq!(
+        ShutdownReason::DaemonExited {
+            process: "daemon".into()
+        },
+        result.unwrap()
+    );
+    assert_eq!("run\n", output);
+}
+
+fn assert_spawn_error(expected: &str, result: Result<(), groundcontrol::Error>) {
+    match result {
+        Err(groundcontrol::Error::StartupAborted(report)) => {
+            assert_eq!(expected, report.to_string());
+        }
+        Ok(()) | Err(_) => panic!("Expected StartupAborted error."),
+    }
+}
+
+/// Pausing a spec stops every one of its daemons until the spec is
+/// resumed.
+#[test_log::test(tokio::test)]
+async fn spec_pauses_and_resumes() {
+    let config = r##"
+        [[processes]]
+        name = "db"
+        run = [ "/bin/sh", "-c", "echo $$ > {temp_path}/db.pid; while true; do sleep 0.1; done" ]
+
+        [[processes]]
+        name = "app"
+        run = [ "/bin/sh", "-c", "echo $$ > {temp_path}/app.pid; while true; do sleep 0.1; done" ]
+        post = [ "/bin/sh", "-c", "echo post >> {result_path}" ]
+        "##;
+
+    let (config, dir) = prepare(config).await;
+    let db = spawn_daemon_waiter(&dir, "db");
+    let app = spawn_daemon_waiter(&dir, "app");
+
+    let mut supervisor = Supervisor::new();
+    supervisor.spawn_spec("appliance", config).unwrap();
+    let db = db.await.unwrap();
+    let app = app.await.unwrap();
+
+    // Wait for startup to complete, since requests are only handled
+    // once the spec is running.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    assert!(supervisor.pause_spec("appliance"));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(('T', 'T'), (process_state(db), process_state(app)));
+
+    assert!(supervisor.resume_spec("appliance"));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_ne!('T', process_state(db));
+    assert_ne!('T', process_state(app));
+
+    assert!(!supervisor.pause_spec("unknown"));
+
+    assert!(supervisor.shutdown_spec("appliance"));
+    let (result, output) = stop(
+        async { supervisor.wait_spec("appliance").await.unwrap() },
+        dir,
+    )
+    .await;
+    assert_eq!(ShutdownReason::GracefulShutdown, result.unwrap());
+    assert_eq!("post\n", output);
+}
+
+/// Holds on the startup of a process in a spec can be released through
+/// the supervisor.
+#[test_log::test(tokio::test)]
+async fn spec_hold_is_released() {
+    let config = r##"
+        [[processes]]
+        name = "app"
+        hold = {}
+        run = [ "/bin/sh", "-c", "echo app >> {result_path}" ]
+        "##;
+
+    let (config, dir) = prepare(config).await;
+
+    let mut supervisor = Supervisor::new();
+    supervisor.spawn_spec("tenant", config).unwrap();
+    assert!(supervisor.release_hold("tenant", "app"));
+    assert!(!supervisor.release_hold("other", "app"));
+
+    let (result, output) = stop(async { supervisor.wait_spec("tenant").await.unwrap() }, dir).await;
+    assert_eq!(
+        ShutdownReason::DaemonExited {
+            process: "app".into()
+        },
+        result.unwrap()
+    );
+    assert_eq!("app\n", output);
+}
+
+/// The failure snapshot of a daemon only ends up in the exit history of
+/// its own spec, even if a spec without an exit history has a process
+/// with the same name.
+#[test_log::test(tokio::test)]
+async fn failure_snapshots_are_not_shared() {
+    let (config_a, _dir_a) = prepare(
+        r##"
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo 'tenant a'; exit 1" ]
+        "##,
+    )
+    .await;
+
+    let state_dir = TempDir::new().unwrap();
+    let config_b = r##"
+        state-dir = "{state_dir}"
+
+        [[processes]]
+        name = "daemon"
+        run = [ "/bin/sh", "-c", "echo 'tenant b'; exit 0" ]
+        "##
+    .replace("{state_dir}", state_dir.path().to_str().unwrap());
+    let (config_b, _dir_b) = prepare(&config_b).await;
+
+    let mut supervisor = Supervisor::new();
+    supervisor.spawn_spec("tenant-a", config_a).unwrap();
+    assert!(supervisor.wait_spec("tenant-a").await.unwrap().is_err());
+
+    supervisor.spawn_spec("tenant-b", config_b).unwrap();
+    assert_eq!(
+        ShutdownReason::DaemonExited {
+            process: String::from("daemon")
+        },
+        supervisor.wait_spec("tenant-b").await.unwrap().unwrap()
+    );
+
+    let history: Vec<(String, Option<ExitSnapshot>)> =
+        groundcontrol::history::read(state_dir.path(), "daemon")
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.status, entry.snapshot))
+            .collect();
+    assert_eq!(vec![(String::from("exited cleanly"), None)], history);
+}
@@ -0,0 +1,51 @@
+//! Tests that verify per-process time zones.
+
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+use crate::common::{assert_startup_aborted, start, stop};
+
+mod common;
+
+/// Every one of the process's commands sees the process's time zone as
+/// `TZ`, even if every other environment variable has been filtered
+/// out.
+#[test_log::test(tokio::test)]
+async fn timezone_sets_tz() {
+    let config = r##"
+        [[processes]]
+        name = "app"
+        timezone = "America/New_York"
+        pre = { only-env = [], command = [ "/bin/sh", "-c", "echo pre $TZ >> {result_path}" ] }
+        run = [ "/bin/sh", "-c", "echo run $TZ >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(result.is_ok());
+    assert_eq!("pre America/New_York\nrun America/New_York\n", output);
+}
+
+/// Time zones that are not in the tz database prevent the process from
+/// starting.
+#[test_log::test(tokio::test)]
+async fn unknown_timezone_aborts_startup() {
+    let config = r##"
+        [[processes]]
+        name = "app"
+        timezone = "Mars/Olympus_Mons"
+        pre = [ "/bin/sh", "-c", "echo pre >> {result_path}" ]
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_startup_aborted(
+        indoc! {r#"
+            Time zone "Mars/Olympus_Mons" of process "app" is not in the tz database ("/usr/share/zoneinfo/Mars/Olympus_Mons" does not exist)
+        "#},
+        result,
+    );
+    assert_eq!("", output);
+}
@@ -0,0 +1,130 @@
+//! Tests that verify daemon watchdogs.
+
+use std::time::Duration;
+
+use groundcontrol::ShutdownReason;
+use pretty_assertions::assert_eq;
+
+use crate::common::{spawn_daemon_waiter, start, stop};
+
+mod common;
+
+/// Daemons that never ping their watchdog are killed once the watchdog
+/// timeout expires, which shuts down Ground Control (since the daemon
+/// failed).
+#[test_log::test(tokio::test)]
+async fn unresponsive_daemon_is_killed() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        runtime-dir = "{temp_path}/daemon-run"
+        run = [ "/bin/sh", "-c", "echo run >> {result_path}; exec sleep 30" ]
+        post = [ "/bin/sh", "-c", "echo post >> {result_path}" ]
+        watchdog = "1s"
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(
+        result,
+        Err(groundcontrol::Error::AbnormalShutdown(reasons)) if reasons == [ShutdownReason::DaemonFailed {
+            process: "daemon".into(),
+            exit_code: None,
+            signal: Some(9),
+        }]
+    ));
+    assert_eq!("run\npost\n", output);
+}
+
+/// Touching the watchdog file keeps the daemon alive for longer than the
+/// watchdog timeout.
+#[test_log::test(tokio::test)]
+async fn file_pings_keep_daemon_alive() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        runtime-dir = "{temp_path}/daemon-run"
+        run = [ "/bin/sh", "-c", "for i in 1 2 3 4 5 6 7 8; do touch $GC_WATCHDOG_FILE; sleep 0.3; done; echo done >> {result_path}" ]
+        watchdog = "1s"
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+    let (result, output) = stop(gc, dir).await;
+
+    assert_eq!(
+        ShutdownReason::DaemonExited {
+            process: "daemon".into()
+        },
+        result.unwrap()
+    );
+    assert_eq!("done\n", output);
+}
+
+/// Sending datagrams to the watchdog socket keeps the daemon alive for
+/// longer than the watchdog timeout.
+#[test_log::test(tokio::test)]
+async fn datagram_pings_keep_daemon_alive() {
+    let config = r##"
+        [[processes]]
+        name = "daemon"
+        runtime-dir = "{temp_path}/daemon-run"
+        run = [ "/bin/sh", "-c", "sleep 2.5; echo done >> {result_path}" ]
+        watchdog = "1s"
+        "##;
+
+    let (gc, _tx, dir) = start(config).await;
+
+    // Ping the watchdog from the test (since the shell cannot send
+    // datagrams), starting once the socket has been created.
+    let socket_path = dir.path().join("daemon-run/watchdog.sock");
+    tokio::spawn(async move {
+        let socket = tokio::net::UnixDatagram::unbound().unwrap();
+        for _ in 0..100 {
+            let _ = socket.send_to(b"ping", &socket_path).await;
+            tokio::time::sleep(Duration::from_millis(300)).await;
+        }
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert_eq!(
+        ShutdownReason::DaemonExited {
+            process: "daemon".into()
+        },
+        result.unwrap()
+    );
+    assert_eq!("done\n", output);
+}
+
+/// Stopped (paused) daemons cannot ping their watchdog, and are not
+/// killed for missing their watchdog deadline.
+#[test_log::test(tokio::test)]
+async fn paused_daemon_is_not_killed() {
+    let config = r##"
+        control-dir = "{temp_path}/control"
+
+        [[processes]]
+        name = "daemon"
+        runtime-dir = "{temp_path}/daemon-run"
+        run = [ "/bin/sh", "-c", "echo $$ > {temp_path}/daemon.pid; echo run >> {result_path}; while true; do sleep 0.1; done" ]
+        post = [ "/bin/sh", "-c", "echo post >> {result_path}" ]
+        watchdog = "2s"
+        "##;
+
+    let (gc, tx, dir) = start(config).await;
+
+    let pause_file = dir.path().join("control/pause.daemon");
+    let daemon_waiter = spawn_daemon_waiter(&dir, "daemon");
+    tokio::task::spawn(async move {
+        daemon_waiter.await.unwrap();
+        std::fs::write(pause_file, "").unwrap();
+        tokio::time::sleep(Duration::from_millis(3500)).await;
+        tx.send(()).unwrap();
+    });
+
+    let (result, output) = stop(gc, dir).await;
+
+    assert!(matches!(result, Ok(ShutdownReason::GracefulShutdown)));
+    assert_eq!("run\npost\n", output);
+}